@@ -0,0 +1,559 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs::{self, File, FileTimes};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use thiserror::Error;
+
+use disk_scanner::formatting::Formatter;
+use disk_scanner::tags::{self, TagDatabase};
+
+use crate::scanner::{MatchedFile, TrashUsage};
+
+mod protect;
+mod relocate;
+mod rename;
+
+pub use protect::ProtectedPaths;
+pub use relocate::Relocation;
+pub use rename::{undo_renames, RenameTemplate};
+use rename::{Renamer, UndoLog};
+
+/// What to do with the files picked after a scan.
+#[derive(Debug, Clone)]
+pub enum FileAction {
+    /// Permanently remove the files.
+    Delete,
+    /// Move the files to the platform trash / recycle bin.
+    Trash,
+    /// Pack the files into a `.tar.gz` archive and remove the originals.
+    Archive { destination: PathBuf },
+    /// Change the permission bits of the files.
+    #[cfg(unix)]
+    Chmod(ModeChange),
+    /// Change the owner and/or group of the files.
+    #[cfg(unix)]
+    Chown(OwnerChange),
+    /// Set the access and modification times of the files to now, so age-based cleanup
+    /// leaves them alone.
+    Touch,
+    /// Give the files new names within their directories, recording each rename in
+    /// `undo_log` when given.
+    Rename { template: RenameTemplate, undo_log: Option<PathBuf> },
+    /// Copy or move the files below another directory, keeping their paths relative to
+    /// the scan root.
+    Relocate(Relocation),
+    /// Record `tag` on the files, in the tag database at `database` if given and on the
+    /// files themselves otherwise.
+    Tag { tag: String, database: Option<PathBuf> },
+}
+
+impl FileAction {
+    /// Whether the action can lose data or lock people out, and so stays out of
+    /// `ProtectedPaths`. Only `Touch`, `Tag` and copying are harmless.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            FileAction::Touch | FileAction::Tag { .. } => false,
+            FileAction::Relocate(relocation) => relocation.remove_source,
+            _ => true,
+        }
+    }
+}
+
+/// New permission bits for `--chmod`: octal (`640`) or comma-separated symbolic clauses
+/// as `chmod(1)` takes them (`o-w`, `u=rw,go=r`), minus `X`, `s` and `t`.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub enum ModeChange {
+    Absolute(u32),
+    Symbolic(Vec<ModeClause>),
+}
+
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct ModeClause {
+    /// Bits of the classes the clause applies to, e.g. 0o070 for `g`
+    who: u32,
+    op: char,
+    /// `rwx` bits, 0 to 7
+    perms: u32,
+}
+
+#[cfg(unix)]
+impl ModeChange {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            return match u32::from_str_radix(value, 8) {
+                Ok(mode) if mode <= 0o7777 => Ok(ModeChange::Absolute(mode)),
+                _ => Err(format!("'{}' is not an octal mode between 0 and 7777", value)),
+            };
+        }
+        value.split(',').map(parse_clause).collect::<Option<_>>()
+            .map(ModeChange::Symbolic)
+            .ok_or_else(|| format!("'{}' is neither an octal mode (640) nor symbolic (o-w, u=rw,go=r)", value))
+    }
+
+    /// The permission bits (including setuid, setgid and sticky) for a file that has `mode`.
+    pub fn apply(&self, mode: u32) -> u32 {
+        match self {
+            ModeChange::Absolute(new) => *new,
+            ModeChange::Symbolic(clauses) => clauses.iter().fold(mode & 0o7777, |mode, clause| {
+                let bits = clause.who & (clause.perms * 0o111);
+                match clause.op {
+                    '+' => mode | bits,
+                    '-' => mode & !bits,
+                    _ => (mode & !clause.who) | bits,
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn parse_clause(clause: &str) -> Option<ModeClause> {
+    let op_at = clause.find(['+', '-', '='])?;
+    let (who, rest) = clause.split_at(op_at);
+    let mut who_bits = 0;
+    for c in who.chars() {
+        who_bits |= match c {
+            'u' => 0o700,
+            'g' => 0o070,
+            'o' => 0o007,
+            'a' => 0o777,
+            _ => return None,
+        };
+    }
+    let mut perms = 0;
+    for c in rest[1..].chars() {
+        perms |= match c {
+            'r' => 4,
+            'w' => 2,
+            'x' => 1,
+            _ => return None,
+        };
+    }
+    Some(ModeClause { who: if who_bits == 0 { 0o777 } else { who_bits }, op: rest.chars().next()?, perms })
+}
+
+/// New owner for `--chown`: `USER`, `USER:GROUP` or `:GROUP`, as names or numeric ids.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct OwnerChange {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+#[cfg(unix)]
+impl OwnerChange {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (user, group) = match value.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (value, None),
+        };
+        let uid = match user {
+            "" => None,
+            user => Some(user.parse().ok()
+                .or_else(|| uzers::get_user_by_name(user).map(|u| u.uid()))
+                .ok_or_else(|| format!("no such user '{}'", user))?),
+        };
+        let gid = match group {
+            None | Some("") => None,
+            Some(group) => Some(group.parse().ok()
+                .or_else(|| uzers::get_group_by_name(group).map(|g| g.gid()))
+                .ok_or_else(|| format!("no such group '{}'", group))?),
+        };
+        if uid.is_none() && gid.is_none() {
+            return Err(format!("'{}' names neither a user nor a group", value));
+        }
+        Ok(OwnerChange { uid, gid })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ActionError {
+    #[error("Failed to delete {path:?}: {source}")]
+    Delete { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to move {path:?} to trash: {source}")]
+    Trash { path: PathBuf, source: trash::Error },
+
+    #[error("Failed to write archive {path:?}: {source}")]
+    Archive { path: PathBuf, source: std::io::Error },
+
+    #[cfg(unix)]
+    #[error("Failed to change permissions of {path:?}: {source}")]
+    Chmod { path: PathBuf, source: std::io::Error },
+
+    #[cfg(unix)]
+    #[error("Failed to change owner of {path:?}: {source}")]
+    Chown { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to touch {path:?}: {source}")]
+    Touch { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to rename {path:?}: {source}")]
+    Rename { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to write undo log {path:?}: {source}")]
+    UndoLog { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to relocate {path:?}: {source}")]
+    Relocate { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to tag {path:?}: {source}")]
+    Tag { path: PathBuf, source: std::io::Error },
+
+    #[error("Failed to use journal {path:?}: {source}")]
+    Journal { path: PathBuf, source: std::io::Error },
+
+    #[error("Refusing to touch {path:?}: {protected:?} is protected (--force overrides)")]
+    Protected { path: PathBuf, protected: PathBuf },
+}
+
+#[derive(Debug, Default)]
+pub struct ActionSummary {
+    pub processed_files: u64,
+    pub processed_bytes: u64,
+    pub errors: Vec<ActionError>,
+}
+
+/// Applies `action` to `files`, leaving out those `protected` covers when the action is
+/// destructive (`None` protects nothing, for `--force`).
+pub fn apply_action(action: &FileAction, files: &[MatchedFile], protected: Option<&ProtectedPaths>) -> ActionSummary {
+    let (files, refused) = guard(action, files, protected);
+    let mut summary = apply_unguarded(action, &files);
+    summary.errors.extend(refused);
+    summary
+}
+
+/// The files `action` may touch, and errors for those it may not.
+fn guard<'a>(action: &FileAction, files: &'a [MatchedFile], protected: Option<&ProtectedPaths>) -> (Cow<'a, [MatchedFile]>, Vec<ActionError>) {
+    let Some(protected) = protected.filter(|_| action.is_destructive()) else {
+        return (Cow::Borrowed(files), Vec::new());
+    };
+    let mut allowed = Vec::with_capacity(files.len());
+    let mut refused = Vec::new();
+    for file in files {
+        match protected.protecting(&file.path) {
+            Some(dir) => refused.push(ActionError::Protected { path: file.path.clone(), protected: dir.to_path_buf() }),
+            None => allowed.push(file.clone()),
+        }
+    }
+    (Cow::Owned(allowed), refused)
+}
+
+fn apply_unguarded(action: &FileAction, files: &[MatchedFile]) -> ActionSummary {
+    match action {
+        FileAction::Delete => for_each_file(files, |path| {
+            fs::remove_file(path).map_err(|e| ActionError::Delete { path: path.to_path_buf(), source: e })
+        }),
+        FileAction::Trash => for_each_file(files, |path| {
+            trash::delete(path).map_err(|e| ActionError::Trash { path: path.to_path_buf(), source: e })
+        }),
+        FileAction::Archive { destination } => archive_files(destination, files),
+        #[cfg(unix)]
+        FileAction::Chmod(change) => for_each_file(files, |path| {
+            let chmod = || {
+                let mode = current_mode(path)?;
+                let new_mode = change.apply(mode);
+                if new_mode == mode { Ok(()) } else { fs::set_permissions(path, fs::Permissions::from_mode(new_mode)) }
+            };
+            chmod().map_err(|e| ActionError::Chmod { path: path.to_path_buf(), source: e })
+        }),
+        #[cfg(unix)]
+        FileAction::Chown(change) => for_each_file(files, |path| {
+            // lchown: a file swapped for a symlink since the scan must not hand over its target
+            std::os::unix::fs::lchown(path, change.uid, change.gid)
+                .map_err(|e| ActionError::Chown { path: path.to_path_buf(), source: e })
+        }),
+        FileAction::Touch => for_each_file(files, |path| {
+            let touch = || {
+                regular_file(path)?;
+                let now = SystemTime::now();
+                File::options().write(true).open(path)?
+                    .set_times(FileTimes::new().set_accessed(now).set_modified(now))
+            };
+            touch().map_err(|e| ActionError::Touch { path: path.to_path_buf(), source: e })
+        }),
+        FileAction::Rename { template, undo_log } => rename_files(template, undo_log.as_deref(), files),
+        FileAction::Relocate(relocation) => relocation.apply(files),
+        FileAction::Tag { tag, database: None } => for_each_file(files, |path| {
+            let tag_file = || {
+                regular_file(path)?;
+                let mut tags: BTreeSet<String> = tags::read_native(path)?.into_iter().collect();
+                if tags.insert(tag.clone()) { tags::write_native(path, &tags) } else { Ok(()) }
+            };
+            tag_file().map_err(|e| ActionError::Tag { path: path.to_path_buf(), source: e })
+        }),
+        FileAction::Tag { tag, database: Some(database) } => tag_in_database(tag, database, files),
+    }
+}
+
+/// Tags `files` in the database at `path`, which is only written once all of them are in.
+fn tag_in_database(tag: &str, path: &Path, files: &[MatchedFile]) -> ActionSummary {
+    let mut database = match TagDatabase::open(path) {
+        Ok(database) => database,
+        Err(e) => {
+            let mut summary = ActionSummary::default();
+            summary.errors.push(ActionError::Tag { path: path.to_path_buf(), source: e });
+            return summary;
+        }
+    };
+    let mut summary = for_each_file(files, |file| {
+        database.insert(file, tag);
+        Ok(())
+    });
+    if let Err(e) = database.save() {
+        summary.processed_files = 0;
+        summary.processed_bytes = 0;
+        summary.errors.push(ActionError::Tag { path: path.to_path_buf(), source: e });
+    }
+    summary
+}
+
+fn rename_files(template: &RenameTemplate, undo_log: Option<&Path>, files: &[MatchedFile]) -> ActionSummary {
+    let mut log = None;
+    if let Some(path) = undo_log {
+        match UndoLog::open(path) {
+            Ok(opened) => log = Some(opened),
+            Err(e) => {
+                // Without the log the renames could not be undone, so don't start them
+                let mut summary = ActionSummary::default();
+                summary.errors.push(ActionError::UndoLog { path: path.to_path_buf(), source: e });
+                return summary;
+            }
+        }
+    }
+    let mut renamer = Renamer::new(template);
+    for_each_file(files, |path| {
+        let mut rename = || {
+            regular_file(path)?;
+            match (renamer.target(path)?, &mut log) {
+                (None, _) => Ok(()),
+                (Some(target), Some(log)) => log.rename(path, &target),
+                (Some(target), None) => fs::rename(path, target),
+            }
+        };
+        rename().map_err(|e| ActionError::Rename { path: path.to_path_buf(), source: e })
+    })
+}
+
+/// What `action` would change about each of `files`, e.g. `mode 0666 -> 0644`, leaving
+/// out those already as requested. Only `Chmod`, `Chown`, `Touch`, `Rename`, `Relocate`
+/// and `Tag` are described, with timestamps as `formatter` shows them. Files the action has
+/// to leave alone, as `protected` covers them, come with an error.
+pub fn pending_changes<'a>(
+    action: &FileAction,
+    files: &'a [MatchedFile],
+    protected: Option<&ProtectedPaths>,
+    formatter: Formatter,
+) -> Vec<(&'a Path, std::io::Result<String>)> {
+    let protected = protected.filter(|_| action.is_destructive());
+    let mut renamer = match action {
+        FileAction::Rename { template, .. } => Some(Renamer::new(template)),
+        _ => None,
+    };
+    // Opened once rather than for every file; `None` means tags are on the files
+    let database = match action {
+        FileAction::Tag { database: Some(path), .. } => Some(TagDatabase::open(path)
+            .map_err(|e| format!("tag database {:?}: {}", path, e))),
+        _ => None,
+    };
+    files.iter()
+        .filter_map(|file| {
+            if let Some(dir) = protected.and_then(|p| p.protecting(&file.path)) {
+                let refusal = format!("{:?} is protected (--force overrides)", dir);
+                return Some((file.path.as_path(), Err(std::io::Error::other(refusal))));
+            }
+            let change = match &mut renamer {
+                Some(renamer) => regular_file(&file.path)
+                    .and_then(|_| renamer.target(&file.path))
+                    .map(|target| target.map(|t| format!("-> {}", t.display()))),
+                None => match (action, &database) {
+                    (FileAction::Tag { tag, .. }, Some(database)) => database.as_ref()
+                        .map_err(|e| std::io::Error::other(e.clone()))
+                        .map(|database| tag_change(tag, database.tags(&file.path))),
+                    _ => pending_change(action, &file.path, formatter),
+                },
+            };
+            change.transpose().map(|change| (file.path.as_path(), change))
+        })
+        .collect()
+}
+
+fn pending_change(action: &FileAction, path: &Path, formatter: Formatter) -> std::io::Result<Option<String>> {
+    match action {
+        FileAction::Touch => {
+            let modified = regular_file(path)?.modified()?;
+            Ok(Some(format!("modified {} -> now", formatter.timestamp(modified))))
+        }
+        #[cfg(unix)]
+        FileAction::Chmod(change) => {
+            let mode = current_mode(path)?;
+            let new_mode = change.apply(mode);
+            Ok((new_mode != mode).then(|| format!("mode {:04o} -> {:04o}", mode, new_mode)))
+        }
+        #[cfg(unix)]
+        FileAction::Chown(change) => {
+            let metadata = fs::symlink_metadata(path)?;
+            let (uid, gid) = (change.uid.unwrap_or(metadata.uid()), change.gid.unwrap_or(metadata.gid()));
+            Ok(((uid, gid) != (metadata.uid(), metadata.gid()))
+                .then(|| format!("owner {}:{} -> {}:{}", metadata.uid(), metadata.gid(), uid, gid)))
+        }
+        FileAction::Relocate(relocation) => {
+            regular_file(path)?;
+            Ok(Some(format!("-> {}", relocation.target(path)?.display())))
+        }
+        FileAction::Tag { tag, .. } => {
+            regular_file(path)?;
+            Ok(tag_change(tag, tags::read_native(path)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `tags a -> a,reviewed`, or `None` for a file that already has `tag`.
+fn tag_change(tag: &str, current: Vec<String>) -> Option<String> {
+    if current.iter().any(|t| t == tag) {
+        return None;
+    }
+    let mut tags: BTreeSet<String> = current.into_iter().collect();
+    let before = if tags.is_empty() { "(none)".to_string() } else { tags::join(&tags) };
+    tags.insert(tag.to_string());
+    Some(format!("tags {} -> {}", before, tags::join(&tags)))
+}
+
+/// Metadata of the regular file at `path`; anything else (say, a symlink that took its
+/// place since the scan) is refused.
+fn regular_file(path: &Path) -> std::io::Result<fs::Metadata> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::other("no longer a regular file"));
+    }
+    Ok(metadata)
+}
+
+#[cfg(unix)]
+fn current_mode(path: &Path) -> std::io::Result<u32> {
+    Ok(regular_file(path)?.permissions().mode() & 0o7777)
+}
+
+/// Removes the invoking user's trashed items from `trash`, leaving the trash directories
+/// themselves in place; items `protected` covers stay. The files and bytes of the items
+/// removed count as processed.
+pub fn empty_trash(trash: &[TrashUsage], protected: Option<&ProtectedPaths>) -> ActionSummary {
+    let mut summary = ActionSummary::default();
+    for item in trash.iter().flat_map(trashed_items) {
+        if let Some(dir) = protected.and_then(|protected| protected.protecting(&item)) {
+            summary.errors.push(ActionError::Protected { protected: dir.to_path_buf(), path: item });
+            continue;
+        }
+        let (files, bytes) = disk_usage(&item);
+        // Not following symlinks: a link in the trash goes, not what it points to
+        let removed = match fs::symlink_metadata(&item) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&item),
+            Ok(_) => fs::remove_file(&item),
+            Err(e) => Err(e),
+        };
+        match removed {
+            Ok(()) => {
+                summary.processed_files += files;
+                summary.processed_bytes += bytes;
+            }
+            Err(e) => summary.errors.push(ActionError::Delete { path: item, source: e }),
+        }
+    }
+    summary
+}
+
+/// Files and bytes at or below `path`, not following symlinks; unreadable entries are
+/// left out.
+pub fn disk_usage(path: &Path) -> (u64, u64) {
+    let (mut files, mut bytes) = (0, 0);
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            pending.extend(fs::read_dir(&path).into_iter().flatten().filter_map(Result::ok).map(|entry| entry.path()));
+        } else {
+            files += 1;
+            bytes += metadata.len();
+        }
+    }
+    (files, bytes)
+}
+
+/// What emptying `trash` removes: the entries of its bins, sorted.
+pub fn trashed_items(trash: &TrashUsage) -> Vec<PathBuf> {
+    let mut items: Vec<PathBuf> = trash.bins().iter()
+        .filter_map(|bin| fs::read_dir(bin).ok())
+        .flat_map(|listing| listing.filter_map(Result::ok).map(|entry| entry.path()))
+        .collect();
+    items.sort();
+    items
+}
+
+fn for_each_file<F>(files: &[MatchedFile], mut op: F) -> ActionSummary
+where
+    F: FnMut(&Path) -> Result<(), ActionError>,
+{
+    let mut summary = ActionSummary::default();
+    for file in files {
+        match op(&file.path) {
+            Ok(()) => {
+                summary.processed_files += 1;
+                summary.processed_bytes += file.size;
+            }
+            Err(e) => summary.errors.push(e),
+        }
+    }
+    summary
+}
+
+fn archive_files(destination: &Path, files: &[MatchedFile]) -> ActionSummary {
+    let mut summary = ActionSummary::default();
+
+    let archive_file = match File::create(destination) {
+        Ok(f) => f,
+        Err(e) => {
+            summary.errors.push(ActionError::Archive { path: destination.to_path_buf(), source: e });
+            return summary;
+        }
+    };
+    let mut builder = tar::Builder::new(GzEncoder::new(archive_file, Compression::default()));
+
+    let mut archived = Vec::new();
+    for file in files {
+        // Store entries relative to the filesystem root so the layout can be restored later
+        let entry_name: PathBuf = file.path.components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .collect();
+        match builder.append_path_with_name(&file.path, &entry_name) {
+            Ok(()) => archived.push(file),
+            Err(e) => summary.errors.push(ActionError::Archive { path: file.path.clone(), source: e }),
+        }
+    }
+
+    // Only remove originals once the archive has been fully written
+    if let Err(e) = builder.into_inner().and_then(|encoder| encoder.finish()) {
+        summary.errors.push(ActionError::Archive { path: destination.to_path_buf(), source: e });
+        return summary;
+    }
+
+    for file in archived {
+        match fs::remove_file(&file.path) {
+            Ok(()) => {
+                summary.processed_files += 1;
+                summary.processed_bytes += file.size;
+            }
+            Err(e) => summary.errors.push(ActionError::Delete { path: file.path.clone(), source: e }),
+        }
+    }
+    summary
+}
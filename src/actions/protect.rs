@@ -0,0 +1,94 @@
+//! Places destructive actions refuse to touch, however the files there were matched, so a
+//! pattern mistake like `-p '.'` on `/` can't take the system down with it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Protected places: a directory protects everything below it, and `DIR/*` only the files
+/// directly in `DIR` (for `/` and home directories, whose subdirectories are fair game).
+#[derive(Debug, Clone)]
+pub struct ProtectedPaths {
+    entries: Vec<Protected>,
+}
+
+#[derive(Debug, Clone)]
+struct Protected {
+    path: PathBuf,
+    contents_only: bool,
+}
+
+impl Default for ProtectedPaths {
+    /// The root and the home directory (their files only), and the system: its
+    /// configuration, programs, libraries, boot files and `/var/lib`.
+    fn default() -> Self {
+        let mut protected = Self { entries: Vec::new() };
+        #[cfg(unix)]
+        {
+            protected.add("/*");
+            for dir in ["/etc", "/usr", "/bin", "/sbin", "/boot", "/var/lib"] {
+                protected.add(dir);
+            }
+            protected.add_named("/", "lib");
+        }
+        #[cfg(windows)]
+        {
+            protected.add(r"C:\*");
+            protected.add(r"C:\Windows");
+            protected.add_named(r"C:\", "Program Files");
+        }
+        if let Some(home) = std::env::home_dir() {
+            protected.entries.push(Protected { path: resolve(&home), contents_only: true });
+        }
+        protected
+    }
+}
+
+impl ProtectedPaths {
+    /// Adds `DIR` or `DIR/*`; empty entries are ignored.
+    pub fn add(&mut self, entry: &str) {
+        let entry = entry.trim();
+        // The separator stays: `/` is the root, and `C:` alone the current directory on C:
+        let (path, contents_only) = match entry.strip_suffix('*').filter(|dir| dir.ends_with(['/', '\\'])) {
+            Some(dir) => (dir, true),
+            None => (entry, false),
+        };
+        if !path.is_empty() {
+            self.entries.push(Protected { path: resolve(Path::new(path)), contents_only });
+        }
+    }
+
+    /// Adds the directories in `parent` whose names start with `prefix` (`/lib64`,
+    /// `C:\Program Files (x86)`), with everything below them.
+    fn add_named(&mut self, parent: &str, prefix: &str) {
+        let Ok(listing) = fs::read_dir(parent) else {
+            return;
+        };
+        for entry in listing.filter_map(Result::ok) {
+            if entry.file_name().to_string_lossy().starts_with(prefix) && entry.path().is_dir() {
+                self.entries.push(Protected { path: resolve(&entry.path()), contents_only: false });
+            }
+        }
+    }
+
+    /// The protected place `file` is in, if any. The file's directory is resolved first, so
+    /// a symlink or `..` leading into a protected place doesn't get past.
+    pub fn protecting(&self, file: &Path) -> Option<&Path> {
+        let file = match (file.parent(), file.file_name()) {
+            (Some(parent), Some(name)) => resolve(parent).join(name),
+            _ => file.to_path_buf(),
+        };
+        self.entries.iter()
+            .find(|entry| if entry.contents_only {
+                file.parent() == Some(&entry.path) || file == entry.path
+            } else {
+                file.starts_with(&entry.path)
+            })
+            .map(|entry| entry.path.as_path())
+    }
+}
+
+/// `path` with symlinks resolved, or as given when it doesn't exist.
+fn resolve(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
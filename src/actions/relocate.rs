@@ -0,0 +1,187 @@
+//! `--copy-to`/`--move-to`: copies or moves matched files below another directory,
+//! keeping their paths relative to the scan root, with a journal so an interrupted run
+//! can be repeated without redoing or clobbering anything.
+
+use std::collections::HashSet;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::{Deserialize, Serialize};
+
+use super::{regular_file, ActionError, ActionSummary};
+use crate::scanner::MatchedFile;
+
+/// Suffix of the file a copy is written to before it is renamed into place, so an
+/// interrupted copy never looks like a complete one.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Where and how matched files are relocated.
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    /// Remove the originals once copied
+    pub remove_source: bool,
+    pub destination: PathBuf,
+    /// Scan root; files keep their path relative to it below `destination`
+    pub root: PathBuf,
+    /// Records finished files; files already in it are skipped
+    pub journal: Option<PathBuf>,
+    /// Files copied at once
+    pub workers: usize,
+}
+
+/// One line of the journal.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalRecord {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl Relocation {
+    /// Where the file at `path` goes.
+    pub(super) fn target(&self, path: &Path) -> io::Result<PathBuf> {
+        let relative = path.strip_prefix(&self.root)
+            .map_err(|_| io::Error::other(format!("not below the scan root {:?}", self.root)))?;
+        Ok(self.destination.join(relative))
+    }
+
+    pub(super) fn apply(&self, files: &[MatchedFile]) -> ActionSummary {
+        let mut summary = ActionSummary::default();
+        let (done, journal) = match self.open_journal() {
+            Ok(opened) => opened,
+            Err(e) => {
+                // Without the journal a rerun could not tell finished files apart, so don't start
+                let path = self.journal.clone().unwrap_or_default();
+                summary.errors.push(ActionError::Journal { path, source: e });
+                return summary;
+            }
+        };
+        let pending: Vec<&MatchedFile> = files.iter().filter(|f| !done.contains(&f.path)).collect();
+
+        let progress = ProgressBar::new(pending.iter().map(|f| f.size).sum());
+        if !io::stderr().is_terminal() {
+            progress.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})").unwrap()
+                .progress_chars("=> ")
+        );
+        progress.set_message(if self.remove_source { "Moving" } else { "Copying" });
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(&mut summary);
+        let journal_error = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..self.workers.clamp(1, pending.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(file) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let outcome = self.relocate(&file.path).map(|target| {
+                            if let Some(journal) = &mut *journal.lock().unwrap_or_else(PoisonError::into_inner) {
+                                let record = JournalRecord { from: file.path.clone(), to: target };
+                                if let Err(e) = write_record(journal, &record) {
+                                    journal_error.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert(e);
+                                }
+                            }
+                        });
+                        progress.inc(file.size);
+                        let mut summary = results.lock().unwrap_or_else(PoisonError::into_inner);
+                        match outcome {
+                            Ok(()) => {
+                                summary.processed_files += 1;
+                                summary.processed_bytes += file.size;
+                            }
+                            Err(e) => summary.errors.push(ActionError::Relocate { path: file.path.clone(), source: e }),
+                        }
+                    }
+                });
+            }
+        });
+        progress.finish_and_clear();
+        if let (Some(path), Some(e)) = (&self.journal, journal_error.into_inner().unwrap_or_else(PoisonError::into_inner)) {
+            summary.errors.push(ActionError::Journal { path: path.clone(), source: e });
+        }
+        summary
+    }
+
+    /// Reads the files the journal lists as done and opens it for appending.
+    fn open_journal(&self) -> io::Result<(HashSet<PathBuf>, Mutex<Option<File>>)> {
+        let Some(path) = &self.journal else {
+            return Ok((HashSet::new(), Mutex::new(None)));
+        };
+        let mut done = HashSet::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    // A line cut short by an interruption is skipped; its file gets redone
+                    if let Ok(record) = serde_json::from_str::<JournalRecord>(&line) {
+                        done.insert(record.from);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok((done, Mutex::new(Some(file))))
+    }
+
+    /// Copies or moves one file, returning where it went.
+    fn relocate(&self, path: &Path) -> io::Result<PathBuf> {
+        let metadata = regular_file(path)?;
+        let target = self.target(path)?;
+        if let Ok(existing) = fs::symlink_metadata(&target) {
+            // A copy finished just before an interruption kept it out of the journal
+            if !self.remove_source && same_contents_likely(&metadata, &existing) {
+                return Ok(target);
+            }
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} already exists", target)));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if self.remove_source {
+            match fs::rename(path, &target) {
+                Ok(()) => return Ok(target),
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut partial = target.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+        fs::copy(path, &partial)?;
+        let mut times = FileTimes::new();
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        File::options().write(true).open(&partial)?.set_times(times)?;
+        fs::rename(&partial, &target)?;
+        if self.remove_source {
+            fs::remove_file(path)?;
+        }
+        Ok(target)
+    }
+}
+
+/// Whether `existing` is a finished copy of the file with `original`'s metadata: copies
+/// get the original's size and modification time.
+fn same_contents_likely(original: &fs::Metadata, existing: &fs::Metadata) -> bool {
+    existing.is_file()
+        && existing.len() == original.len()
+        && matches!((existing.modified(), original.modified()), (Ok(a), Ok(b)) if a == b)
+}
+
+fn write_record(journal: &mut File, record: &JournalRecord) -> io::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    journal.write_all(line.as_bytes())
+}
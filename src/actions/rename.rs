@@ -0,0 +1,184 @@
+//! `--rename`: new names for matched files from a template, and the undo log that lets
+//! `undo-rename` put them back.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use disk_scanner::t;
+
+/// A file name template such as `archive_{date}_{name}`. Placeholders are `{name}` (the
+/// whole file name), `{stem}` (without the last extension), `{ext}` (the last extension,
+/// without the dot) and `{date}` (the modification date, `YYYY-MM-DD` in UTC).
+#[derive(Debug, Clone)]
+pub struct RenameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Name,
+    Stem,
+    Ext,
+    Date,
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.contains(['/', '\\']) {
+            return Err(format!("'{}' must be a file name; files are renamed within their directory", template));
+        }
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..].find('}')
+                .ok_or_else(|| format!("unclosed '{{' in '{}'", template))? + open;
+            parts.push(match &rest[open + 1..close] {
+                "name" => Part::Name,
+                "stem" => Part::Stem,
+                "ext" => Part::Ext,
+                "date" => Part::Date,
+                other => return Err(format!("unknown placeholder {{{}}}; use {{name}}, {{stem}}, {{ext}} or {{date}}", other)),
+            });
+            rest = &rest[close + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("stray '}}' in '{}'", template));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(RenameTemplate { parts })
+    }
+
+    /// The new file name for the file at `path`. Names that aren't valid UTF-8 are refused
+    /// rather than renamed to a mangled copy of themselves.
+    fn render(&self, path: &Path) -> io::Result<String> {
+        let name = path.file_name().unwrap_or_default().to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the file name is not valid UTF-8"))?;
+        let (stem, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+            _ => (name, ""),
+        };
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => rendered.push_str(text),
+                Part::Name => rendered.push_str(name),
+                Part::Stem => rendered.push_str(stem),
+                Part::Ext => rendered.push_str(ext),
+                Part::Date => {
+                    let modified = fs::symlink_metadata(path)?.modified()?;
+                    rendered.push_str(&humantime::format_rfc3339_seconds(modified).to_string()[..10]);
+                }
+            }
+        }
+        if rendered.is_empty() || rendered == "." || rendered == ".." {
+            return Err(io::Error::other(format!("template gives the invalid name {:?}", rendered)));
+        }
+        Ok(rendered)
+    }
+}
+
+/// Picks target paths for a batch of renames, never one that exists or that an earlier
+/// file of the batch was given: a taken `name.ext` becomes `name_1.ext`, `name_2.ext`, ...
+pub(super) struct Renamer<'a> {
+    template: &'a RenameTemplate,
+    claimed: HashSet<PathBuf>,
+}
+
+impl<'a> Renamer<'a> {
+    pub(super) fn new(template: &'a RenameTemplate) -> Self {
+        Self { template, claimed: HashSet::new() }
+    }
+
+    /// Where the file at `path` goes, or `None` when it already has the name asked for.
+    pub(super) fn target(&mut self, path: &Path) -> io::Result<Option<PathBuf>> {
+        let name = self.template.render(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut target = directory.join(&name);
+        if target == path {
+            return Ok(None);
+        }
+        let (stem, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+            _ => (&name[..], ""),
+        };
+        let mut suffix = 0;
+        while self.claimed.contains(&target) || fs::symlink_metadata(&target).is_ok() {
+            suffix += 1;
+            target = directory.join(format!("{}_{}{}", stem, suffix, ext));
+        }
+        self.claimed.insert(target.clone());
+        Ok(Some(target))
+    }
+}
+
+/// One line of the undo log.
+#[derive(Debug, Serialize, Deserialize)]
+struct RenameRecord {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// Appends a JSON line per rename to the `--undo-log` file.
+pub(super) struct UndoLog {
+    file: File,
+}
+
+impl UndoLog {
+    pub(super) fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self { file: OpenOptions::new().create(true).append(true).open(path)? })
+    }
+
+    /// Renames `from` to `to`, logging it once it succeeded. Paths the log cannot hold
+    /// (not valid UTF-8) are refused before anything is renamed.
+    pub(super) fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let record = RenameRecord { from: from.to_path_buf(), to: to.to_path_buf() };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        fs::rename(from, to)?;
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// What `undo_renames` did.
+#[derive(Debug, Default)]
+pub struct UndoSummary {
+    pub restored: u64,
+    pub errors: Vec<String>,
+}
+
+/// Renames the files in an undo log back, latest first. A file is left where it is when its
+/// original name has been taken since; with `dry_run` nothing is renamed.
+pub fn undo_renames(log: &Path, dry_run: bool) -> io::Result<UndoSummary> {
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(log)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str::<RenameRecord>(&line)?);
+        }
+    }
+    let mut summary = UndoSummary::default();
+    for record in records.iter().rev() {
+        if fs::symlink_metadata(&record.from).is_ok() {
+            summary.errors.push(format!("{:?} exists again; leaving {:?} as it is", record.from, record.to));
+            continue;
+        }
+        if dry_run {
+            eprintln!("{}", t!("Would rename {} back to {}", record.to.display(), record.from.display()));
+        } else if let Err(e) = fs::rename(&record.to, &record.from) {
+            summary.errors.push(format!("Failed to rename {:?} back to {:?}: {}", record.to, record.from, e));
+            continue;
+        }
+        summary.restored += 1;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,139 @@
+//! Threshold rules for `--alert-if`, such as `dir("/var/log") > 10GB` or `free < 5GiB`,
+//! checked against each scan. With `--watch` the scanner rescans until one is breached.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::formatting::Formatter;
+use crate::scanner::ScanResult;
+use crate::units::parse_size;
+
+/// What a rule measures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertSubject {
+    /// Bytes in the whole tree
+    Total,
+    /// Files in the whole tree
+    Files,
+    /// Bytes the current user can still write to the volume
+    Free,
+    /// Bytes in the directory, absolute or relative to the scan root
+    Dir(PathBuf),
+}
+
+/// One `--alert-if` rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertRule {
+    pub subject: AlertSubject,
+    /// Breached above `limit` when set, below it otherwise
+    pub above: bool,
+    pub limit: u64,
+    /// The rule as written, for alerts
+    pub source: String,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid alert rule: {0}")]
+pub struct AlertRuleError(String);
+
+/// A rule a scan breached, with what the scan measured.
+#[derive(Debug, Clone)]
+pub struct Breach {
+    pub rule: AlertRule,
+    pub value: u64,
+}
+
+impl AlertRule {
+    /// Parses `SUBJECT > LIMIT` or `SUBJECT < LIMIT`, where the subject is `total`, `files`,
+    /// `free` or `dir("PATH")` and the limit a size such as `10GB` (a count for `files`).
+    pub fn parse(source: &str) -> Result<Self, AlertRuleError> {
+        // The last one, as the path of a `dir` may hold either
+        let (at, above) = source.char_indices()
+            .rfind(|&(_, c)| c == '>' || c == '<')
+            .map(|(at, c)| (at, c == '>'))
+            .ok_or_else(|| AlertRuleError(format!("'{}' has no > or <", source)))?;
+        let (subject, limit) = (source[..at].trim(), source[at + 1..].trim());
+        let subject = match subject {
+            "total" => AlertSubject::Total,
+            "files" => AlertSubject::Files,
+            "free" => AlertSubject::Free,
+            _ => {
+                let path = subject.strip_prefix("dir(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .map(str::trim)
+                    .and_then(|quoted| quoted.strip_prefix('"')?.strip_suffix('"'))
+                    .ok_or_else(|| {
+                        AlertRuleError(format!("unknown subject '{}'; expected total, files, free or dir(\"PATH\")", subject))
+                    })?;
+                AlertSubject::Dir(PathBuf::from(path))
+            }
+        };
+        let limit = match subject {
+            AlertSubject::Files => limit.parse().map_err(|_| AlertRuleError(format!("'{}' is not a file count", limit)))?,
+            _ => parse_size(limit).map_err(AlertRuleError)?,
+        };
+        Ok(AlertRule { subject, above, limit, source: source.trim().to_string() })
+    }
+
+    /// Whether the rule needs the scan's entries, to add up a directory.
+    pub fn needs_entries(&self) -> bool {
+        matches!(self.subject, AlertSubject::Dir(_))
+    }
+
+    /// `value` as what the rule measures: a count or a size.
+    pub fn format_value(&self, value: u64, formatter: Formatter) -> String {
+        match self.subject {
+            AlertSubject::Files => formatter.count(value),
+            _ => formatter.size(value),
+        }
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// The `rules` a scan of `root` breached, in the order given. `free` is never breached when
+/// the platform doesn't report the volume's free space, and `dir` counts nothing outside
+/// `root`.
+pub fn check(rules: &[AlertRule], root: &Path, result: &ScanResult) -> io::Result<Vec<Breach>> {
+    let dirs: Vec<PathBuf> = rules.iter()
+        .filter_map(|rule| match &rule.subject {
+            AlertSubject::Dir(dir) => Some(root.join(dir)),
+            _ => None,
+        })
+        .collect();
+    let mut dir_sizes = vec![0u64; dirs.len()];
+    if !dirs.is_empty() {
+        for entry in result.entries.iter() {
+            let entry = entry?;
+            for (dir, size) in dirs.iter().zip(&mut dir_sizes) {
+                if entry.path.starts_with(dir) {
+                    *size += entry.size;
+                }
+            }
+        }
+    }
+
+    let mut dir_sizes = dir_sizes.into_iter();
+    let mut breaches = Vec::new();
+    for rule in rules {
+        let value = match rule.subject {
+            AlertSubject::Total => Some(result.total_size),
+            AlertSubject::Files => Some(result.total_files),
+            AlertSubject::Free => result.volume.map(|volume| volume.available),
+            AlertSubject::Dir(_) => dir_sizes.next(),
+        };
+        if let Some(value) = value
+            && (if rule.above { value > rule.limit } else { value < rule.limit })
+        {
+            breaches.push(Breach { rule: rule.clone(), value });
+        }
+    }
+    Ok(breaches)
+}
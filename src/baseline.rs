@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::export::{self, SnapshotReader, SCHEMA_VERSION, SNAPSHOT_MAGIC};
+use crate::path_tree::{NodeId, PathTree};
+use crate::scanner::{FileEntry, SpillList};
+
+/// Per-directory sizes from an earlier `--json` report or binary snapshot, used by `--baseline` to spot
+/// directories that grew since, and when loaded with `load_with_files` its files too, for
+/// `--export-delta`. Keys are relative to the scan root, so a snapshot still applies when
+/// the same tree is mounted somewhere else.
+#[derive(Debug, Clone, Default)]
+pub struct Baseline {
+    directories: PathTree<u64>,
+    /// Size and modification time (Unix seconds) of every file; `None` for the directories
+    /// between them
+    files: PathTree<Option<(u64, Option<u64>)>>,
+    /// Of the report loaded, if it has a header
+    pub scan_id: Option<String>,
+    /// Whether the report was cut off while it was written, or is missing chunks, so it
+    /// only covers part of the tree
+    pub truncated: bool,
+}
+
+/// The parts of a report (any schema version up to `SCHEMA_VERSION`) a baseline needs.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportFile {
+    /// Missing in v1 reports
+    #[serde(default = "schema_v1")]
+    schema_version: u32,
+    /// Missing before reports had headers
+    #[serde(default)]
+    pub(crate) header: Option<ReportIds>,
+    /// Only in reports written with `--chunk-size`
+    #[serde(default)]
+    pub(crate) chunk: Option<ReportChunk>,
+    /// Missing only on the trailer line
+    #[serde(default)]
+    pub(crate) summary: Option<ReportSummary>,
+    #[serde(default)]
+    pub(crate) entries: Vec<ReportEntry>,
+    /// Only on the last line of a chunked report written to stdout
+    #[serde(default)]
+    pub(crate) trailer: Option<ReportTrailer>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportChunk {
+    pub(crate) index: usize,
+    pub(crate) count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportTrailer {
+    pub(crate) entries: u64,
+}
+
+/// A report that ends in the middle of a document, as when the process writing it died.
+#[derive(Debug, Error)]
+#[error("Report {0:?} ends early, cut off while it was written")]
+pub(crate) struct TruncatedReport(PathBuf);
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportIds {
+    pub(crate) scan_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportSummary {
+    pub(crate) root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+    #[serde(default)]
+    pub(crate) modified: Option<u64>,
+}
+
+/// The documents of the report read from `reader` (one, or one per chunk), refusing
+/// schema versions newer than `SCHEMA_VERSION`. A document cut off at the end of the input
+/// is a `TruncatedReport` error. `path` is only for messages.
+pub(crate) fn report_documents<'a>(reader: impl Read + 'a, path: &'a Path) -> impl Iterator<Item = Result<ReportFile>> + 'a {
+    serde_json::Deserializer::from_reader(reader).into_iter::<ReportFile>().map(move |document| {
+        let report = match document {
+            Err(e) if e.is_eof() || e.io_error_kind() == Some(io::ErrorKind::UnexpectedEof) => {
+                return Err(TruncatedReport(path.to_path_buf()).into());
+            }
+            document => document.with_context(|| format!("Failed to parse report {:?}", path))?,
+        };
+        // v1 kept `root` and entry paths as given on the command line, possibly
+        // relative; sizes are keyed relative to the root either way
+        if report.schema_version > SCHEMA_VERSION {
+            bail!(
+                "Report {:?} uses schema v{}, newer than the supported v{}",
+                path,
+                report.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        Ok(report)
+    })
+}
+
+fn schema_v1() -> u32 {
+    1
+}
+
+/// A directory whose size grew by more than the alert threshold.
+#[derive(Debug, Clone)]
+pub struct DirectoryGrowth {
+    pub path: PathBuf,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl DirectoryGrowth {
+    /// Growth relative to the baseline size, e.g. 0.25 for +25%.
+    pub fn fraction(&self) -> f64 {
+        (self.after - self.before) as f64 / self.before as f64
+    }
+}
+
+/// How the files of a scan differ from those of the baseline, for `--export-delta`.
+#[derive(Debug, Clone, Default)]
+pub struct Delta {
+    /// Files the baseline didn't have, in the order of the scan's entries
+    pub added: Vec<FileEntry>,
+    /// Files whose size or modification time differ, in the order of the scan's entries
+    pub changed: Vec<ChangedFile>,
+    /// Files of the baseline the scan didn't find, below the scan's root, sorted
+    pub removed: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangedFile {
+    pub entry: FileEntry,
+    pub before_size: u64,
+    /// Unix seconds; `None` where the baseline has no modification time
+    pub before_modified: Option<u64>,
+}
+
+impl Baseline {
+    /// Reads a report written by `--json`/`--output`. Line-delimited chunks from
+    /// `--chunk-size` on stdout are accepted too and merged, and so are reports
+    /// written with `--compress` and binary snapshots (`--format snapshot`).
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::read(path, false)
+    }
+
+    /// Like `load`, also keeping every file's size and modification time for `delta`.
+    pub fn load_with_files(path: &Path) -> Result<Self> {
+        Self::read(path, true)
+    }
+
+    fn read(path: &Path, with_files: bool) -> Result<Self> {
+        let context = || format!("Failed to read baseline {:?}", path);
+        let mut reader = BufReader::new(export::open_report(path).with_context(context)?);
+        let mut baseline = Self::default();
+        if reader.fill_buf().with_context(context)?.starts_with(SNAPSHOT_MAGIC) {
+            let mut snapshot = SnapshotReader::new(reader).with_context(context)?;
+            let root = snapshot.root().to_path_buf();
+            baseline.scan_id = snapshot.scan_id().map(str::to_string);
+            let mut error = None;
+            for entry in &mut snapshot {
+                match entry {
+                    Ok(entry) => baseline.add_file(&root, &entry.path, entry.size, entry.modified, with_files),
+                    Err(e) => error = Some(e),
+                }
+            }
+            // What was read before the cut is still usable
+            match error {
+                Some(_) if snapshot.truncated() => baseline.truncated = true,
+                Some(e) => return Err(e).with_context(context),
+                None => {}
+            }
+            return Ok(baseline);
+        }
+
+        // Chunked reports are complete with every chunk, or as many entries as the
+        // trailer says when written to stdout
+        let (mut chunks, mut chunk_count, mut entries, mut trailer) = (HashSet::new(), None, 0u64, None);
+        for report in report_documents(reader, path) {
+            let report = match report {
+                // Unless that leaves nothing, as with a report that isn't chunked
+                Err(e) if e.is::<TruncatedReport>() && !chunks.is_empty() => {
+                    baseline.truncated = true;
+                    break;
+                }
+                report => report?,
+            };
+            if report.trailer.is_some() {
+                trailer = report.trailer;
+                continue;
+            }
+            if let Some(header) = report.header {
+                baseline.scan_id = Some(header.scan_id);
+            }
+            if let Some(chunk) = report.chunk {
+                chunks.insert(chunk.index);
+                chunk_count = Some(chunk.count);
+            }
+            let Some(summary) = &report.summary else {
+                continue;
+            };
+            for entry in &report.entries {
+                baseline.add_file(&summary.root, &entry.path, entry.size, entry.modified, with_files);
+            }
+            entries += report.entries.len() as u64;
+        }
+        baseline.truncated |= match (trailer, chunk_count) {
+            (Some(trailer), _) => trailer.entries != entries,
+            (None, Some(count)) => chunks.len() != count,
+            (None, None) => false,
+        };
+        Ok(baseline)
+    }
+
+    /// Adds a file of a report to the directory sizes, and to the files `with_files`.
+    fn add_file(&mut self, root: &Path, file: &Path, size: u64, modified: Option<u64>, with_files: bool) {
+        self.add(root, file, size);
+        if with_files && let Ok(relative) = file.strip_prefix(root) {
+            let file = self.files.insert(relative);
+            *self.files.get_mut(file) = Some((size, modified));
+        }
+    }
+
+    /// Sizes of every directory under `root`, computed from the entries of a scan.
+    pub fn from_entries(root: &Path, entries: &SpillList<FileEntry>) -> io::Result<Self> {
+        let mut baseline = Self::default();
+        for entry in entries.iter() {
+            let entry = entry?;
+            baseline.add(root, &entry.path, entry.size);
+        }
+        Ok(baseline)
+    }
+
+    /// Adds a file's size to every directory between `root` and the file.
+    fn add(&mut self, root: &Path, file: &Path, size: u64) {
+        let Some(parent) = file.strip_prefix(root).ok().and_then(Path::parent) else {
+            return;
+        };
+        let mut dir = Some(self.directories.insert(parent));
+        while let Some(node) = dir {
+            *self.directories.get_mut(node) += size;
+            dir = self.directories.parent(node);
+        }
+    }
+
+    /// The files in `entries`, of a scan of `root`, that were added or changed since the
+    /// baseline, and the baseline's files that are gone. Needs a baseline loaded with
+    /// `load_with_files`; modification times are only compared where both sides have one.
+    pub fn delta(&self, root: &Path, entries: &SpillList<FileEntry>) -> io::Result<Delta> {
+        let mut delta = Delta::default();
+        let mut seen = vec![false; self.files.len()];
+        for entry in entries.iter() {
+            let entry = entry?;
+            let Ok(relative) = entry.path.strip_prefix(root) else {
+                continue;
+            };
+            let Some((known, (before_size, before_modified))) = self.files.find(relative)
+                .and_then(|file| Some((file, (*self.files.get(file))?)))
+            else {
+                delta.added.push(entry.into_owned());
+                continue;
+            };
+            seen[known as usize] = true;
+            let modified = entry.modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+            let retouched = matches!((modified, before_modified), (Some(now), Some(before)) if now != before);
+            if entry.size != before_size || retouched {
+                delta.changed.push(ChangedFile { entry: entry.into_owned(), before_size, before_modified });
+            }
+        }
+        delta.removed = self.files.iter()
+            .filter(|&(file, known)| known.is_some() && !seen[file as usize])
+            .map(|(file, _)| root.join(self.files.path(file)))
+            .collect();
+        delta.removed.sort();
+        Ok(delta)
+    }
+
+    /// Size of the directory at `dir`, relative to the root, when the baseline was taken.
+    pub fn directory_size(&self, dir: &Path) -> Option<u64> {
+        self.directories.find(dir).map(|dir| *self.directories.get(dir))
+    }
+
+    /// Directories of the baseline that grew by more than `threshold` (0.1 = 10%) in
+    /// `current`, largest growth in bytes first. Directories that did not exist in the
+    /// baseline count towards their parents only.
+    pub fn growth(&self, root: &Path, current: &Baseline, threshold: f64) -> Vec<DirectoryGrowth> {
+        // The same directory in `current`, filled in parents first as nodes are numbered
+        let mut in_current: Vec<Option<NodeId>> = Vec::with_capacity(self.directories.len());
+        let mut grown = Vec::new();
+        for (dir, &before) in self.directories.iter() {
+            let matching = match self.directories.parent(dir) {
+                None => Some(PathTree::<u64>::ROOT),
+                Some(parent) => in_current[parent as usize]
+                    .and_then(|parent| current.directories.child(parent, self.directories.name(dir))),
+            };
+            in_current.push(matching);
+            let after = matching.map_or(0, |matching| *current.directories.get(matching));
+            if before > 0 && after as f64 > before as f64 * (1.0 + threshold) {
+                grown.push(DirectoryGrowth { path: join_relative(root, &self.directories.path(dir)), before, after });
+            }
+        }
+        grown.sort_by(|a, b| (b.after - b.before).cmp(&(a.after - a.before)).then_with(|| a.path.cmp(&b.path)));
+        grown
+    }
+}
+
+/// `root.join(dir)` without the trailing separator `join` adds for an empty `dir`.
+fn join_relative(root: &Path, dir: &Path) -> PathBuf {
+    if dir.as_os_str().is_empty() { root.to_path_buf() } else { root.join(dir) }
+}
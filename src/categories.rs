@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Built-in extension lists, by category.
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("documents", &[
+        "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "rst", "tex", "xls", "xlsx", "ods", "csv", "ppt",
+        "pptx", "odp", "epub",
+    ]),
+    ("images", &[
+        "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "svg", "raw", "cr2", "nef", "psd",
+    ]),
+    ("video", &["mp4", "mkv", "mov", "avi", "wmv", "webm", "m4v", "mpg", "mpeg", "flv"]),
+    ("audio", &["mp3", "flac", "wav", "aac", "ogg", "m4a", "opus", "wma"]),
+    ("archives", &[
+        "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "tar.gz", "tar.bz2", "tar.xz", "tar.zst",
+    ]),
+    ("disk images", &["iso", "img", "dmg", "vmdk", "vdi", "vhd", "vhdx", "qcow2"]),
+    ("code", &[
+        "rs", "c", "h", "cc", "cpp", "hpp", "cs", "go", "java", "kt", "py", "rb", "js", "jsx", "ts", "tsx",
+        "php", "swift", "sh", "ps1", "sql", "html", "css", "json", "yaml", "yml", "toml", "xml",
+    ]),
+    ("build artifacts", &[
+        "o", "obj", "a", "lib", "so", "dll", "dylib", "exe", "class", "jar", "pyc", "rlib", "rmeta", "pdb",
+        "whl",
+    ]),
+];
+
+/// Files whose extension is in no category.
+pub const OTHER: &str = "other";
+
+/// Maps file extensions to categories for `--by-category`. Starts from the built-in
+/// lists and can be extended from a TOML file:
+///
+/// ```toml
+/// [categories]
+/// datasets = ["parquet", "arrow", "h5"]
+/// video = ["braw"]   # adds to a built-in category
+/// ```
+#[derive(Debug, Clone)]
+pub struct Categories {
+    /// Lower-case extension (without the dot) to category
+    by_extension: HashMap<String, Arc<str>>,
+    other: Arc<str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryFile {
+    #[serde(default)]
+    categories: HashMap<String, Vec<String>>,
+}
+
+impl Categories {
+    pub fn builtin() -> Self {
+        let mut categories = Self { by_extension: HashMap::new(), other: OTHER.into() };
+        for (category, extensions) in BUILTIN {
+            categories.extend(category, extensions.iter().copied());
+        }
+        categories
+    }
+
+    /// The built-in categories plus those of the file at `path`, whose extensions take
+    /// precedence over the built-in ones.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read category file {:?}", path))?;
+        let file: CategoryFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse category file {:?}", path))?;
+        let mut categories = Self::builtin();
+        for (category, extensions) in &file.categories {
+            categories.extend(category, extensions.iter().map(String::as_str));
+        }
+        Ok(categories)
+    }
+
+    /// Assigns `extensions` (with or without the leading dot) to `category`.
+    pub fn extend<'a>(&mut self, category: &str, extensions: impl IntoIterator<Item = &'a str>) {
+        let category: Arc<str> = category.into();
+        for ext in extensions {
+            let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+            if !ext.is_empty() {
+                self.by_extension.insert(ext, Arc::clone(&category));
+            }
+        }
+    }
+
+    /// Category of the file at `path`, preferring the longest matching extension
+    /// (`tar.gz` over `gz`); `OTHER` when none matches.
+    pub fn classify(&self, path: &Path) -> &Arc<str> {
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+            return &self.other;
+        };
+        // Skip the first character so dot files such as `.bashrc` have no extension
+        name.char_indices()
+            .skip(1)
+            .filter(|&(_, c)| c == '.')
+            .find_map(|(i, _)| self.by_extension.get(&name[i + 1..].to_ascii_lowercase()))
+            .unwrap_or(&self.other)
+    }
+
+    /// Every extension with its category, sorted by extension.
+    pub fn assignments(&self) -> Vec<(&str, &str)> {
+        let mut assignments: Vec<_> = self.by_extension.iter().map(|(ext, category)| (&ext[..], &category[..])).collect();
+        assignments.sort_unstable();
+        assignments
+    }
+}
+
+impl Default for Categories {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
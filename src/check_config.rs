@@ -0,0 +1,282 @@
+//! `check-config`: a scan's options taken through the same setup as the scan (rule files
+//! loaded, patterns compiled, the `ScannerConfig` built), then where it would write probed
+//! and the settings it would run with, including those from the environment, printed.
+//! Nothing below the scan root is read.
+
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use disk_scanner::export::{self, ExportFormat};
+use disk_scanner::i18n;
+use disk_scanner::scanner::ScannerConfig;
+use disk_scanner::t;
+
+use crate::cli::CliArgs;
+use crate::system_log::SystemLogger;
+
+/// Environment variables the scan reads: the locale for messages and numbers, `NO_COLOR`
+/// for the summary and `XDG_DATA_HOME` for where the home trash is.
+const ENVIRONMENT: [&str; 6] = ["LC_ALL", "LC_MESSAGES", "LC_NUMERIC", "LANG", "NO_COLOR", "XDG_DATA_HOME"];
+
+/// How a scan writes one of its outputs.
+#[derive(Clone, Copy)]
+enum Target {
+    /// Created in an existing directory, replacing any file of the name
+    File,
+    /// Appended to, or created in an existing directory
+    #[cfg(feature = "native")]
+    Append,
+    /// Appended to, or created along with any missing directories (--history)
+    AppendCreatingParents,
+    /// A directory files are put below, created if missing
+    #[cfg(feature = "native")]
+    Directory,
+    /// A Unix socket bound in an existing directory, replacing a stale one, or a Windows
+    /// named pipe (--progress-socket)
+    #[cfg(feature = "native")]
+    Socket,
+}
+
+/// Prints the effective settings of the scan `cli_args` describes and checks its outputs,
+/// failing if that or the setup before it (`problems`) found anything to fix.
+pub fn report(cli_args: &CliArgs, config: &ScannerConfig, mut problems: Vec<String>) -> Result<()> {
+    println!("{}", t!("Settings for a scan of {}", format!("{:?}", config.target_path)));
+
+    println!("\n{}", t!("How the tree is walked:"));
+    let walk = [
+        ("engine", format!("{:?}", config.engine)),
+        ("serial", config.serial.to_string()),
+        ("max_concurrent_tasks", config.max_concurrent_tasks.to_string()),
+        ("per_device", config.per_device.to_string()),
+        ("largest_first", config.largest_first.to_string()),
+        ("recent_first", config.recent_first.to_string()),
+        ("rescan_changed", config.rescan_changed.to_string()),
+        ("memory_limit", format!("{:?}", config.memory_limit)),
+    ];
+    for (name, value) in walk {
+        println!("  {}={}", name, value);
+    }
+
+    println!("\n{}", t!("What is counted (config hash {}):", config.config_hash()));
+    for line in config.counted_settings().lines() {
+        println!("  {}", line);
+    }
+
+    println!("\n{}", t!("From the environment:"));
+    println!("  language={:?}", i18n::language());
+    for name in ENVIRONMENT {
+        if let Some(value) = std::env::var_os(name) {
+            println!("  {}={:?}", name, value);
+        }
+    }
+
+    println!("\n{}", t!("Read:"));
+    let inputs = inputs(cli_args);
+    if inputs.is_empty() {
+        println!("  {}", t!("no files besides the tree"));
+    }
+    for (flag, path) in inputs {
+        println!("  {} {:?}", flag, path);
+    }
+
+    println!("\n{}", t!("Written:"));
+    let outputs = outputs(cli_args);
+    let destinations = destinations(cli_args);
+    if outputs.is_empty() && destinations.is_empty() {
+        println!("  {}", t!("standard output only"));
+    }
+    let outputs = outputs.into_iter().map(|(flag, path, target)| (flag, format!("{:?}", path), probe(&path, target)));
+    for (flag, name, check) in outputs.chain(destinations) {
+        match check {
+            Ok(()) => println!("  {} {}", flag, name),
+            Err(e) => {
+                println!("  {} {}: {}", flag, name, e);
+                problems.push(t!("{} {} can't be written: {}", flag, name, e));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("\n{}", t!("Configuration OK; nothing was scanned"));
+        return Ok(());
+    }
+    eprintln!();
+    for problem in &problems {
+        eprintln!("{}", t!("Problem: {}", problem));
+    }
+    anyhow::bail!("Found {} problem(s) in the configuration", problems.len())
+}
+
+/// The files the setup read, which got this far only because all of them loaded.
+fn inputs(cli_args: &CliArgs) -> Vec<(&'static str, &Path)> {
+    let mut inputs: Vec<(&'static str, &Path)> = [
+        ("--quota-file", cli_args.quota_file.as_deref()),
+        ("--cost-model-file", cli_args.cost_model_file.as_deref()),
+        ("--category-file", cli_args.category_file.as_deref()),
+        ("--regenerable-rules", cli_args.regenerable_rules.as_deref()),
+        ("--baseline", cli_args.baseline.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, path)| Some((flag, path?)))
+    .collect();
+    inputs.extend(cli_args.filter_file.iter().map(|path| ("--filter-file", path.as_path())));
+    #[cfg(feature = "encrypt")]
+    inputs.extend(cli_args.passphrase_file.as_deref().map(|path| ("--passphrase-file", path)));
+    inputs
+}
+
+/// Every file and directory the scan writes to, under the names it would use.
+fn outputs(cli_args: &CliArgs) -> Vec<(&'static str, PathBuf, Target)> {
+    let mut outputs = Vec::new();
+    if let Some(output) = &cli_args.output {
+        outputs.push(("--output", report_path(cli_args, output), Target::File));
+    }
+    #[cfg(feature = "native")]
+    {
+        let native = [
+            ("--errors-out", &cli_args.errors_out, Target::File),
+            ("--undo-log", &cli_args.undo_log, Target::Append),
+            ("--copy-to", &cli_args.copy_to, Target::Directory),
+            ("--move-to", &cli_args.move_to, Target::Directory),
+            ("--journal", &cli_args.journal, Target::Append),
+            ("--progress-socket", &cli_args.progress_socket, Target::Socket),
+        ];
+        outputs.extend(native.into_iter().filter_map(|(flag, path, target)| Some((flag, path.clone()?, target))));
+    }
+    let common = [
+        ("--excludes-file", &cli_args.excludes_file, Target::File),
+        ("--history", &cli_args.history, Target::AppendCreatingParents),
+        ("--tag-db", &cli_args.tag_db, Target::File),
+    ];
+    outputs.extend(common.into_iter().filter_map(|(flag, path, target)| Some((flag, path.clone()?, target))));
+    outputs
+}
+
+/// Where the scan sends things other than files, each checked as far as it can be without
+/// sending anything: the URLs were parsed along with the arguments, and the system log is
+/// connected to. Credentials in URLs are left out.
+fn destinations(cli_args: &CliArgs) -> Vec<(&'static str, String, Result<(), String>)> {
+    let mut destinations = Vec::new();
+    #[cfg(feature = "elasticsearch")]
+    if let Some(url) = &cli_args.es_url {
+        destinations.push(("--es-url", without_credentials(url), Ok(())));
+    }
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    if let Some(target) = &cli_args.stream_to {
+        let url = match target {
+            #[cfg(feature = "kafka")]
+            crate::event_stream::StreamTarget::Kafka { brokers, topic } => format!("kafka://{}/{}", brokers, topic),
+            #[cfg(feature = "nats")]
+            crate::event_stream::StreamTarget::Nats { server, subject } => format!("{}/{}", server, subject),
+        };
+        destinations.push(("--stream-to", without_credentials(&url), Ok(())));
+    }
+    if let Some(target) = cli_args.log_target {
+        let connected = SystemLogger::connect(target).map(drop).map_err(|e| e.to_string());
+        destinations.push(("--log-target", format!("{:?}", target), connected));
+    }
+    destinations
+}
+
+/// `url` with the `user:pass@` before its host, if any, masked.
+#[cfg(any(feature = "elasticsearch", feature = "kafka", feature = "nats"))]
+fn without_credentials(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let host_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..host_end].rfind('@') {
+        Some(at) => format!("{}://***@{}", scheme, &rest[at + 1..]),
+        None => url.to_string(),
+    }
+}
+
+/// The file `--output` names once the report's extensions are added; the first one when
+/// it's written in chunks.
+fn report_path(cli_args: &CliArgs, output: &Path) -> PathBuf {
+    let chunked = cli_args.chunk_size.is_some() && cli_args.format == ExportFormat::Json;
+    let path = if chunked { export::chunk_path(output, 1) } else { output.to_path_buf() };
+    let path = match cli_args.compress {
+        Some(compression) if cli_args.format != ExportFormat::Parquet => compression.output_path(&path),
+        _ => path,
+    };
+    #[cfg(feature = "encrypt")]
+    if cli_args.passphrase_file.is_some() {
+        return export::encrypted_path(&path);
+    }
+    path
+}
+
+/// Whether the scan could write `path` as `target`. Files that exist are opened for
+/// appending without writing anything; otherwise a file is created in the directory that
+/// would hold it and removed again.
+fn probe(path: &Path, target: Target) -> Result<(), String> {
+    match target {
+        Target::File if path.is_dir() => Err(t!("it is a directory").to_string()),
+        Target::File => probe_directory(parent(path)),
+        #[cfg(feature = "native")]
+        Target::Append => probe_append(path).unwrap_or_else(|| probe_directory(parent(path))),
+        Target::AppendCreatingParents => probe_append(path).unwrap_or_else(|| probe_directory(existing_ancestor(path))),
+        #[cfg(feature = "native")]
+        Target::Directory if path.is_dir() => probe_directory(path),
+        #[cfg(feature = "native")]
+        Target::Directory if path.exists() => Err(t!("it is not a directory").to_string()),
+        #[cfg(feature = "native")]
+        Target::Directory => probe_directory(existing_ancestor(path)),
+        #[cfg(feature = "native")]
+        Target::Socket => probe_socket(path),
+    }
+}
+
+/// Opens `path` for appending if it exists; `None` if it doesn't.
+fn probe_append(path: &Path) -> Option<Result<(), String>> {
+    if path.is_dir() {
+        return Some(Err(t!("it is a directory").to_string()));
+    }
+    path.exists().then(|| OpenOptions::new().append(true).open(path).map(drop).map_err(|e| e.to_string()))
+}
+
+/// A socket that exists is replaced by the scan; anything else at `path` is left alone and
+/// fails it.
+#[cfg(all(feature = "native", unix))]
+fn probe_socket(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(()),
+        Ok(_) => Err(t!("it exists and is not a socket").to_string()),
+        Err(_) => probe_directory(parent(path)),
+    }
+}
+
+#[cfg(all(feature = "native", windows))]
+fn probe_socket(path: &Path) -> Result<(), String> {
+    match path.to_str() {
+        Some(name) if name.to_ascii_lowercase().starts_with(r"\\.\pipe\") => Ok(()),
+        _ => Err(t!("it is not a named pipe such as {}", r"\\.\pipe\disk_scanner")),
+    }
+}
+
+fn probe_directory(directory: &Path) -> Result<(), String> {
+    if !directory.is_dir() {
+        return Err(t!("directory {} doesn't exist", format!("{:?}", directory)));
+    }
+    let probe = directory.join(format!(".disk_scanner-check-{}", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe).map_err(|e| e.to_string())?;
+    fs::remove_file(&probe).map_err(|e| e.to_string())
+}
+
+/// The closest of `path` and its ancestors that exists, where missing directories would be
+/// created.
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors().map(nonempty).find(|ancestor| ancestor.exists()).unwrap_or(Path::new("."))
+}
+
+/// The directory holding `path`, `.` for a bare file name.
+fn parent(path: &Path) -> &Path {
+    nonempty(path.parent().unwrap_or(path))
+}
+
+fn nonempty(path: &Path) -> &Path {
+    if path.as_os_str().is_empty() { Path::new(".") } else { path }
+}
@@ -0,0 +1,126 @@
+//! What keeping a scanned tree in object storage would cost, for `--cost-model`: storage
+//! per month, plus the one-time requests to upload every file as an object and the egress
+//! to download it all again.
+
+use std::path::Path;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::scanner::ScanResult;
+
+/// Bytes in the gigabyte providers bill by, which is a GiB.
+const BILLED_GB: f64 = (1u64 << 30) as f64;
+
+/// List prices in USD of the first tier in a US region, for storage classes without a
+/// minimum object size or retrieval fees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CostPreset {
+    /// Amazon S3 Standard
+    S3Standard,
+    /// Google Cloud Storage Standard
+    GcsStandard,
+    /// Azure Blob Storage, hot tier (LRS)
+    AzureHot,
+    /// Backblaze B2
+    B2,
+    /// Cloudflare R2
+    R2,
+}
+
+/// Prices of an object store. Loaded from TOML for `--cost-model-file`, where a preset
+/// can fill in whatever the file leaves out:
+///
+/// ```toml
+/// preset = "s3-standard"
+/// name = "S3 with our discount"
+/// storage_per_gb_month = 0.019
+/// per_1000_requests = 0.005
+/// egress_per_gb = 0.05
+/// currency = "USD"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostModel {
+    pub name: String,
+    pub storage_per_gb_month: f64,
+    /// What uploading 1000 objects costs (PUT or class A requests)
+    pub per_1000_requests: f64,
+    /// What downloading a GB to the internet costs
+    pub egress_per_gb: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostFile {
+    preset: Option<String>,
+    name: Option<String>,
+    storage_per_gb_month: Option<f64>,
+    per_1000_requests: Option<f64>,
+    egress_per_gb: Option<f64>,
+    currency: Option<String>,
+}
+
+/// A `CostModel` applied to a scan.
+#[derive(Debug, Clone)]
+pub struct CostEstimate {
+    pub bytes: u64,
+    pub objects: u64,
+    pub storage_per_month: f64,
+    pub upload: f64,
+    pub egress: f64,
+}
+
+impl CostModel {
+    pub fn preset(preset: CostPreset) -> Self {
+        let (name, storage_per_gb_month, per_1000_requests, egress_per_gb) = match preset {
+            CostPreset::S3Standard => ("S3 Standard", 0.023, 0.005, 0.09),
+            CostPreset::GcsStandard => ("Cloud Storage Standard", 0.020, 0.005, 0.12),
+            CostPreset::AzureHot => ("Azure Blob hot tier", 0.018, 0.0065, 0.087),
+            CostPreset::B2 => ("Backblaze B2", 0.006, 0.0, 0.01),
+            CostPreset::R2 => ("Cloudflare R2", 0.015, 0.0045, 0.0),
+        };
+        CostModel { name: name.to_string(), storage_per_gb_month, per_1000_requests, egress_per_gb, currency: "USD".to_string() }
+    }
+
+    /// The model in the TOML file at `path`, starting from its `preset` (or `preset` here
+    /// when it names none). Without either, every price must be given.
+    pub fn load(path: &Path, preset: Option<CostPreset>) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cost model {:?}", path))?;
+        let file: CostFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse cost model {:?}", path))?;
+        let preset = match &file.preset {
+            Some(name) => Some(CostPreset::from_str(name, true)
+                .map_err(|_| anyhow::anyhow!("Unknown cost model preset '{}' in {:?}", name, path))?),
+            None => preset,
+        };
+        let base = preset.map(Self::preset);
+        let price = |value: Option<f64>, field: &str, base: Option<f64>| -> Result<f64> {
+            match value.or(base) {
+                Some(price) if price.is_finite() && price >= 0.0 => Ok(price),
+                Some(price) => anyhow::bail!("Invalid {} {} in {:?}", field, price, path),
+                None => anyhow::bail!("Cost model {:?} needs {} or a preset", path, field),
+            }
+        };
+        Ok(CostModel {
+            storage_per_gb_month: price(file.storage_per_gb_month, "storage_per_gb_month", base.as_ref().map(|b| b.storage_per_gb_month))?,
+            per_1000_requests: price(file.per_1000_requests, "per_1000_requests", base.as_ref().map(|b| b.per_1000_requests))?,
+            egress_per_gb: price(file.egress_per_gb, "egress_per_gb", base.as_ref().map(|b| b.egress_per_gb))?,
+            name: file.name.or_else(|| base.as_ref().map(|b| b.name.clone())).unwrap_or_else(|| "custom".to_string()),
+            currency: file.currency.or_else(|| base.map(|b| b.currency)).unwrap_or_else(|| "USD".to_string()),
+        })
+    }
+
+    /// The cost of storing every file of `result` as one object.
+    pub fn estimate(&self, result: &ScanResult) -> CostEstimate {
+        let gb = result.total_size as f64 / BILLED_GB;
+        CostEstimate {
+            bytes: result.total_size,
+            objects: result.total_files,
+            storage_per_month: gb * self.storage_per_gb_month,
+            upload: result.total_files as f64 / 1000.0 * self.per_1000_requests,
+            egress: gb * self.egress_per_gb,
+        }
+    }
+}
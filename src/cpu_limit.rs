@@ -0,0 +1,77 @@
+//! How many CPU cores the process may use, which sizes the runtime's worker threads and
+//! the default number of concurrent walker tasks. In a container limited by a cgroup CPU
+//! quota the machine's core count overcommits badly, so the quota wins where it's lower.
+
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+/// Cores the process may run on: the CPUs available to it, lowered to its cgroup CPU quota
+/// rounded up, and at least one.
+pub fn available_cpus() -> usize {
+    let cpus = num_cpus::get();
+    match cgroup_quota() {
+        Some(quota) => cpus.min(quota.ceil() as usize).max(1),
+        None => cpus.max(1),
+    }
+}
+
+/// The CPU quota of the process's cgroup, in cores (`1.5` for 150ms per 100ms period),
+/// if it has one. With cgroups v1 (or a hybrid setup) that of the `cpu` controller
+/// applies; with v2 the lowest quota of the cgroup and its ancestors.
+#[cfg(target_os = "linux")]
+pub fn cgroup_quota() -> Option<f64> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mut unified = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if id == "0" && controllers.is_empty() {
+            unified = Some(path);
+        } else if controllers.split(',').any(|c| c == "cpu") {
+            return ["/sys/fs/cgroup/cpu,cpuacct", "/sys/fs/cgroup/cpu"].iter()
+                .find_map(|mount| quota_v1(Path::new(mount), path));
+        }
+    }
+    // Without a v1 `cpu` controller, a pure v2 hierarchy is mounted at /sys/fs/cgroup
+    let mount = Path::new("/sys/fs/cgroup");
+    unified.filter(|_| mount.join("cgroup.controllers").exists()).and_then(|path| quota_v2(mount, path))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cgroup_quota() -> Option<f64> {
+    None
+}
+
+/// The lowest `cpu.max` from the cgroup at `path` below `mount` up to the root. Inside a
+/// container the path is often the host's, which isn't mounted; the root then stands in.
+#[cfg(target_os = "linux")]
+fn quota_v2(mount: &Path, path: &str) -> Option<f64> {
+    let relative = path.trim_start_matches('/');
+    let dir = if mount.join(relative).is_dir() { mount.join(relative) } else { mount.to_path_buf() };
+    dir.ancestors()
+        .take_while(|dir| dir.starts_with(mount))
+        .filter_map(|dir| {
+            let max = std::fs::read_to_string(dir.join("cpu.max")).ok()?;
+            let (quota, period) = max.trim().split_once(' ')?;
+            cores(quota.parse().ok()?, period.parse().ok()?)
+        })
+        .min_by(f64::total_cmp)
+}
+
+/// `cpu.cfs_quota_us` over `cpu.cfs_period_us` of the cgroup at `path` below `mount`, or
+/// of `mount` itself when that path isn't mounted; a quota of -1 means none.
+#[cfg(target_os = "linux")]
+fn quota_v1(mount: &Path, path: &str) -> Option<f64> {
+    let relative = path.trim_start_matches('/');
+    let dir = if mount.join(relative).is_dir() { mount.join(relative) } else { mount.to_path_buf() };
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok()?.trim().parse::<i64>().ok();
+    let quota = u64::try_from(read("cpu.cfs_quota_us")?).ok()?;
+    cores(quota, u64::try_from(read("cpu.cfs_period_us")?).ok()?)
+}
+
+#[cfg(target_os = "linux")]
+fn cores(quota: u64, period: u64) -> Option<f64> {
+    (quota > 0 && period > 0).then(|| quota as f64 / period as f64)
+}
@@ -0,0 +1,187 @@
+//! `--es-url`: bulk-indexes every scanned file into an Elasticsearch or OpenSearch index
+//! while the scan runs.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use disk_scanner::scanner::FileEntry;
+
+/// Documents sent per `_bulk` request.
+const BATCH_DOCUMENTS: usize = 1000;
+
+/// Tries per batch before its documents are counted as failed.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled for every further one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One scanned file. `@timestamp` is when the scan started, so the documents of one
+/// scan can be told apart from earlier ones in the same index.
+#[derive(Debug, Serialize)]
+struct FileDocument<'a> {
+    #[serde(rename = "@timestamp")]
+    timestamp: &'a str,
+    root: &'a str,
+    path: Cow<'a, str>,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
+}
+
+/// The parts of a `_bulk` response needed to find rejected documents.
+#[derive(Debug, Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    #[serde(default)]
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkItem {
+    status: u16,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+/// What happened to the documents sent to the index.
+#[derive(Debug, Default)]
+pub struct IndexStats {
+    pub indexed: u64,
+    pub failed: u64,
+    /// Why the first failed document or batch was rejected
+    pub first_error: Option<String>,
+}
+
+impl IndexStats {
+    fn fail(&mut self, documents: u64, reason: String) {
+        self.failed += documents;
+        self.first_error.get_or_insert(reason);
+    }
+}
+
+/// Background thread sending batches to the index; see `start`.
+pub struct BulkIndexer {
+    handle: JoinHandle<IndexStats>,
+}
+
+impl BulkIndexer {
+    /// Waits until every file sent so far is indexed, which is once all senders are gone.
+    pub fn finish(self) -> IndexStats {
+        self.handle.join().unwrap_or_else(|_| IndexStats {
+            first_error: Some("indexing thread panicked".to_string()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Starts indexing files of the scan of `root` into the index at `url` (e.g.
+/// `http://localhost:9200/disk-usage`; credentials may be given as `user:pass@`).
+/// Files sent to the returned channel are indexed in batches of `BATCH_DOCUMENTS`;
+/// failed requests are retried with exponential backoff on connection errors, 429 and
+/// 5xx responses.
+pub fn start(url: &str, root: &Path) -> (mpsc::UnboundedSender<FileEntry>, BulkIndexer) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<FileEntry>();
+    let bulk_url = format!("{}/_bulk", url.trim_end_matches('/'));
+    let root = root.to_string_lossy().into_owned();
+    let timestamp = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let handle = thread::spawn(move || {
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        let mut stats = IndexStats::default();
+        let mut body = String::new();
+        let mut pending = 0;
+        while let Some(entry) = rx.blocking_recv() {
+            append_document(&mut body, &timestamp, &root, &entry);
+            pending += 1;
+            if pending == BATCH_DOCUMENTS {
+                send_batch(&agent, &bulk_url, &body, pending as u64, &mut stats);
+                body.clear();
+                pending = 0;
+            }
+        }
+        if pending > 0 {
+            send_batch(&agent, &bulk_url, &body, pending as u64, &mut stats);
+        }
+        stats
+    });
+    (tx, BulkIndexer { handle })
+}
+
+/// Appends the action line and the document for `entry` to a `_bulk` body.
+fn append_document(body: &mut String, timestamp: &str, root: &str, entry: &FileEntry) {
+    let document = FileDocument {
+        timestamp,
+        root,
+        path: entry.path.to_string_lossy(),
+        size: entry.size,
+        modified: entry.modified.map(|t| humantime::format_rfc3339_seconds(t).to_string()),
+        uid: entry.owner,
+        extension: entry.path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()),
+    };
+    body.push_str("{\"index\":{}}\n");
+    // A struct of strings and numbers always serializes
+    body.push_str(&serde_json::to_string(&document).unwrap_or_default());
+    body.push('\n');
+}
+
+fn send_batch(agent: &ureq::Agent, url: &str, body: &str, documents: u64, stats: &mut IndexStats) {
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let reason = match agent.post(url).set("Content-Type", "application/x-ndjson").send_string(body) {
+            Ok(response) => {
+                match response.into_string().map_err(|e| e.to_string())
+                    .and_then(|text| serde_json::from_str::<BulkResponse>(&text).map_err(|e| e.to_string()))
+                {
+                    Ok(response) => record_response(&response, documents, stats),
+                    Err(e) => stats.fail(documents, format!("unreadable _bulk response: {}", e)),
+                }
+                return;
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let reason = format!("HTTP {}: {}", status, response.into_string().unwrap_or_default().trim());
+                if status != 429 && status < 500 {
+                    stats.fail(documents, reason);
+                    return;
+                }
+                reason
+            }
+            Err(ureq::Error::Transport(e)) => e.to_string(),
+        };
+        if attempt == MAX_ATTEMPTS {
+            stats.fail(documents, reason);
+            return;
+        }
+        thread::sleep(backoff);
+        backoff *= 2;
+    }
+}
+
+/// Counts the documents of a successful `_bulk` request, some of which may still have
+/// been rejected one by one (mapping conflicts, a full write queue, ...).
+fn record_response(response: &BulkResponse, documents: u64, stats: &mut IndexStats) {
+    if !response.errors {
+        stats.indexed += documents;
+        return;
+    }
+    for item in response.items.iter().flat_map(HashMap::values) {
+        if item.status < 300 {
+            stats.indexed += 1;
+        } else {
+            let reason = item.error.as_ref()
+                .and_then(|e| e.get("reason").and_then(|r| r.as_str()).map(str::to_string))
+                .unwrap_or_else(|| format!("status {}", item.status));
+            stats.fail(1, reason);
+        }
+    }
+}
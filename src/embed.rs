@@ -0,0 +1,138 @@
+//! Scan options accepted by the C ABI, the Python bindings and `agent` jobs.
+
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::categories::Categories;
+use crate::scanner::{ConfigError, Engine, IgnoredErrors, ScannerConfig};
+
+/// Options as JSON/keyword arguments, with the CLI's defaults for anything left out.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmbedOptions {
+    path: PathBuf,
+    #[serde(default)]
+    threads: Option<usize>,
+    /// "async" or "sync"
+    #[serde(default)]
+    engine: Option<Engine>,
+    /// A concurrency budget per device
+    #[serde(default)]
+    per_device: bool,
+    /// Read the biggest directories first
+    #[serde(default)]
+    largest_first: bool,
+    /// Read the most recently modified directories first
+    #[serde(default)]
+    recent_first: bool,
+    #[serde(default)]
+    follow_symlinks: bool,
+    #[serde(default = "default_true")]
+    include_hidden: bool,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    by_repo: bool,
+    #[serde(default)]
+    by_owner: bool,
+    /// Aggregate usage per built-in file category
+    #[serde(default)]
+    by_category: bool,
+    /// Directories with the most entries
+    #[serde(default)]
+    inode_report: bool,
+    /// Histogram of immediate children per directory
+    #[serde(default)]
+    fan_out: bool,
+    /// Largest files per extension, for the heaviest extensions
+    #[serde(default)]
+    top_per_ext: Option<NonZeroUsize>,
+    #[serde(default)]
+    cold_data_days: Option<u64>,
+    #[serde(default)]
+    locked_files: bool,
+    /// Files and directories with access control lists not inherited from their parent
+    #[serde(default)]
+    acls: bool,
+    /// Files that can't be deleted or rewritten because of their attributes
+    #[serde(default)]
+    immutable: bool,
+    /// Encodings of text files per directory
+    #[serde(default)]
+    encodings: bool,
+    /// LF, CRLF and mixed line endings of text files per directory
+    #[serde(default)]
+    line_endings: bool,
+    /// Labels of directories from their .dirinfo or README files
+    #[serde(default)]
+    dir_labels: bool,
+    /// Collect SELinux types, expecting one of these (any, if empty)
+    #[serde(default)]
+    selinux: Option<Vec<String>>,
+    /// Paths or patterns whose errors are only counted
+    #[serde(default)]
+    ignore_errors_under: Vec<String>,
+    /// Peak memory, CPU time and filesystem calls of the scan
+    #[serde(default)]
+    resource_usage: bool,
+    /// Bytes the collected lists may use before they are spilled to temporary files
+    #[serde(default)]
+    memory_limit: Option<u64>,
+    /// Include every scanned file in the report
+    #[serde(default)]
+    entries: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl EmbedOptions {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_scanner_config(self) -> Result<ScannerConfig, ConfigError> {
+        let cold_data_age = match self.cold_data_days {
+            Some(days) => Some(days.checked_mul(24 * 60 * 60).ok_or(ConfigError::ColdDataAgeTooLong(days))?),
+            None => None,
+        };
+        let mut builder = ScannerConfig::builder(self.path)
+            .per_device(self.per_device)
+            .largest_first(self.largest_first)
+            .recent_first(self.recent_first)
+            .follow_symlinks(self.follow_symlinks)
+            .include_hidden(self.include_hidden)
+            .by_repo(self.by_repo)
+            .collect_entries(self.entries)
+            .cold_data_age(cold_data_age.map(Duration::from_secs))
+            .by_owner(self.by_owner)
+            .categories(self.by_category.then(Categories::builtin))
+            .top_per_extension(self.top_per_ext)
+            .inode_report(self.inode_report)
+            .fan_out(self.fan_out)
+            .detect_locked_files(self.locked_files)
+            .acls(self.acls)
+            .detect_immutable(self.immutable)
+            .sample_encodings(self.encodings)
+            .line_endings(self.line_endings)
+            .directory_labels(self.dir_labels)
+            .selinux(self.selinux)
+            .ignore_errors_under(Some(IgnoredErrors::new(&self.ignore_errors_under)))
+            .resource_usage(self.resource_usage)
+            .memory_limit(self.memory_limit);
+        if let Some(engine) = self.engine {
+            builder = builder.engine(engine);
+        }
+        if let Some(threads) = self.threads {
+            builder = builder.max_concurrent_tasks(threads);
+        }
+        if let Some(pattern) = self.pattern {
+            builder = builder.pattern(pattern);
+        }
+        builder.build()
+    }
+}
@@ -0,0 +1,72 @@
+//! `--errors-out`: writes every scan error as a JSON line to its own file as it happens,
+//! so the full detail is kept for follow-up (e.g. fixing permissions) without cluttering
+//! the report.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use disk_scanner::scanner::ErrorEvent;
+
+#[derive(Debug, Serialize)]
+struct ErrorRecord<'a> {
+    time: String,
+    path: String,
+    kind: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    io_kind: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os_error: Option<i32>,
+    message: &'a str,
+}
+
+/// The thread writing the error file.
+pub struct ErrorLog {
+    path: PathBuf,
+    handle: JoinHandle<io::Result<u64>>,
+}
+
+impl ErrorLog {
+    /// Waits for the remaining errors to be written, returning how many there were. Call
+    /// after the sender has been dropped, i.e. once the scan is over.
+    pub fn finish(self) -> Result<u64> {
+        self.handle.join()
+            .unwrap_or_else(|_| Err(io::Error::other("writer thread panicked")))
+            .with_context(|| format!("Failed to write error file {:?}", self.path))
+    }
+}
+
+/// Creates (or truncates) `path` and starts writing the errors sent to the returned sender.
+pub fn start(path: &Path) -> Result<(mpsc::UnboundedSender<ErrorEvent>, ErrorLog)> {
+    let file = File::create(path).with_context(|| format!("Failed to create error file {:?}", path))?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<ErrorEvent>();
+    let handle = thread::spawn(move || {
+        let mut out = BufWriter::new(file);
+        let mut written = 0;
+        while let Some(event) = rx.blocking_recv() {
+            let record = ErrorRecord {
+                time: humantime::format_rfc3339_millis(event.time).to_string(),
+                path: event.path.to_string_lossy().into_owned(),
+                kind: event.kind,
+                io_kind: event.io_kind.as_deref(),
+                os_error: event.os_error,
+                message: &event.message,
+            };
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+            // Complete lines for anyone tailing the file, as errors come in
+            if rx.is_empty() {
+                out.flush()?;
+            }
+            written += 1;
+        }
+        out.flush()?;
+        Ok(written)
+    });
+    Ok((tx, ErrorLog { path: path.to_path_buf(), handle }))
+}
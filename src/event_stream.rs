@@ -0,0 +1,292 @@
+//! `--stream-to`: publishes every scanned file, then the scan summary, to a Kafka topic or
+//! a NATS subject while the scan runs.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[cfg(feature = "nats")]
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use disk_scanner::scanner::{FileEntry, ScanResult};
+
+use disk_scanner::export::host_name;
+
+/// Where `--stream-to` publishes, parsed from `kafka://broker[,broker...]/topic` or
+/// `nats://host[:port]/subject`.
+#[derive(Debug, Clone)]
+pub enum StreamTarget {
+    #[cfg(feature = "kafka")]
+    Kafka { brokers: String, topic: String },
+    #[cfg(feature = "nats")]
+    Nats { server: String, subject: String },
+}
+
+impl StreamTarget {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (scheme, rest) = value.split_once("://")
+            .ok_or_else(|| format!("'{}' is not a kafka:// or nats:// URL", value))?;
+        let (host, name) = rest.split_once('/')
+            .filter(|(host, name)| !host.is_empty() && !name.is_empty())
+            .ok_or_else(|| format!("'{}' needs a host and a topic or subject, e.g. {}://localhost/disk-scans", value, scheme))?;
+        match scheme {
+            #[cfg(feature = "kafka")]
+            "kafka" => Ok(StreamTarget::Kafka { brokers: host.to_string(), topic: name.to_string() }),
+            #[cfg(feature = "nats")]
+            "nats" => Ok(StreamTarget::Nats { server: format!("nats://{}", host), subject: name.to_string() }),
+            // Reachable when one of the two is compiled out
+            #[allow(unreachable_patterns)]
+            "kafka" | "nats" => Err(format!("{} is not available in this build (enable the `{}` feature)", scheme, scheme)),
+            _ => Err(format!("unknown scheme '{}', expected kafka:// or nats://", scheme)),
+        }
+    }
+}
+
+/// Fields shared by every message of one scan, so consumers can group messages from
+/// many hosts and scans.
+#[derive(Debug, Clone, Serialize)]
+struct ScanIdentity {
+    host: String,
+    root: String,
+    /// When the scan started
+    started: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a> {
+    Entry {
+        #[serde(flatten)]
+        scan: &'a ScanIdentity,
+        path: Cow<'a, str>,
+        size: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        modified: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        uid: Option<u32>,
+    },
+    Summary {
+        #[serde(flatten)]
+        scan: &'a ScanIdentity,
+        total_files: u64,
+        total_directories: u64,
+        total_size: u64,
+        errors: usize,
+        duration_ms: u64,
+        cancelled: bool,
+    },
+}
+
+/// What happened to the messages published during a scan.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub published: u64,
+    pub failed: u64,
+    /// Why the first failed message was not delivered
+    pub first_error: Option<String>,
+}
+
+impl StreamStats {
+    fn record(&mut self, outcome: Result<(), String>) {
+        match outcome {
+            Ok(()) => self.published += 1,
+            Err(e) => {
+                self.failed += 1;
+                self.first_error.get_or_insert(e);
+            }
+        }
+    }
+}
+
+/// A connection to the broker; see `EventStream::connect`.
+enum Publisher {
+    #[cfg(feature = "kafka")]
+    Kafka(kafka::Publisher),
+    #[cfg(feature = "nats")]
+    Nats { client: async_nats::Client, subject: String },
+}
+
+impl Publisher {
+    async fn connect(target: &StreamTarget) -> Result<Self> {
+        match target {
+            #[cfg(feature = "kafka")]
+            StreamTarget::Kafka { brokers, topic } => Ok(Publisher::Kafka(kafka::Publisher::new(brokers, topic)?)),
+            #[cfg(feature = "nats")]
+            StreamTarget::Nats { server, subject } => {
+                let client = async_nats::connect(server.as_str()).await
+                    .with_context(|| format!("Failed to connect to NATS server {}", server))?;
+                Ok(Publisher::Nats { client, subject: subject.clone() })
+            }
+        }
+    }
+
+    async fn publish(&mut self, key: &str, payload: Vec<u8>, stats: &mut StreamStats) {
+        match self {
+            #[cfg(feature = "kafka")]
+            Publisher::Kafka(kafka) => kafka.publish(key, payload, stats).await,
+            #[cfg(feature = "nats")]
+            Publisher::Nats { client, subject } => {
+                let _ = key;
+                stats.record(client.publish(subject.clone(), payload.into()).await.map_err(|e| e.to_string()));
+            }
+        }
+    }
+
+    /// Waits until everything published so far is delivered (or has failed).
+    async fn flush(&mut self, stats: &mut StreamStats) {
+        match self {
+            #[cfg(feature = "kafka")]
+            Publisher::Kafka(kafka) => kafka.flush(stats).await,
+            #[cfg(feature = "nats")]
+            Publisher::Nats { client, .. } => {
+                if let Err(e) = client.flush().await {
+                    stats.first_error.get_or_insert(e.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Publishes the files of a running scan as they are found; see `connect`.
+pub struct EventStream {
+    task: JoinHandle<(Publisher, StreamStats)>,
+    identity: ScanIdentity,
+}
+
+impl EventStream {
+    /// Connects to `target` and starts publishing every file sent to the returned channel
+    /// as an `entry` message, keyed by host name.
+    pub async fn connect(target: &StreamTarget, root: &Path) -> Result<(mpsc::UnboundedSender<FileEntry>, Self)> {
+        let mut publisher = Publisher::connect(target).await?;
+        let identity = ScanIdentity {
+            host: host_name(),
+            root: root.to_string_lossy().into_owned(),
+            started: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel::<FileEntry>();
+        let scan = identity.clone();
+        let task = tokio::spawn(async move {
+            let mut stats = StreamStats::default();
+            while let Some(entry) = rx.recv().await {
+                let message = Message::Entry {
+                    scan: &scan,
+                    path: entry.path.to_string_lossy(),
+                    size: entry.size,
+                    modified: entry.modified.map(|t| humantime::format_rfc3339_seconds(t).to_string()),
+                    uid: entry.owner,
+                };
+                // A struct of strings and numbers always serializes
+                let payload = serde_json::to_vec(&message).unwrap_or_default();
+                publisher.publish(&scan.host, payload, &mut stats).await;
+            }
+            (publisher, stats)
+        });
+        Ok((tx, EventStream { task, identity }))
+    }
+
+    /// Waits until every file sent so far is published, which is once all senders are
+    /// gone, then publishes a `summary` message for a completed scan.
+    pub async fn finish(self, result: Option<&ScanResult>) -> StreamStats {
+        let (mut publisher, mut stats) = match self.task.await {
+            Ok(done) => done,
+            Err(e) => return StreamStats { first_error: Some(e.to_string()), ..Default::default() },
+        };
+        if let Some(result) = result {
+            let message = Message::Summary {
+                scan: &self.identity,
+                total_files: result.total_files,
+                total_directories: result.total_directories,
+                total_size: result.total_size,
+                errors: result.errors.len(),
+                duration_ms: result.scan_duration.as_millis() as u64,
+                cancelled: result.cancelled,
+            };
+            let payload = serde_json::to_vec(&message).unwrap_or_default();
+            publisher.publish(&self.identity.host, payload, &mut stats).await;
+        }
+        publisher.flush(&mut stats).await;
+        stats
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+    use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
+    use rdkafka::util::Timeout;
+    use rdkafka::ClientConfig;
+
+    use super::StreamStats;
+
+    /// Messages handed to librdkafka before waiting for their delivery reports.
+    const IN_FLIGHT: usize = 10_000;
+
+    /// How long librdkafka keeps retrying a message before reporting it failed.
+    const MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub(super) struct Publisher {
+        producer: FutureProducer,
+        topic: String,
+        pending: Vec<DeliveryFuture>,
+    }
+
+    impl Publisher {
+        pub(super) fn new(brokers: &str, topic: &str) -> Result<Self> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("message.timeout.ms", MESSAGE_TIMEOUT.as_millis().to_string())
+                .set("compression.type", "lz4")
+                .create()
+                .with_context(|| format!("Failed to create a Kafka producer for {}", brokers))?;
+            Ok(Publisher { producer, topic: topic.to_string(), pending: Vec::new() })
+        }
+
+        pub(super) async fn publish(&mut self, key: &str, payload: Vec<u8>, stats: &mut StreamStats) {
+            loop {
+                let record = FutureRecord::to(&self.topic).key(key).payload(&payload);
+                match self.producer.send_result(record) {
+                    Ok(delivery) => {
+                        self.pending.push(delivery);
+                        break;
+                    }
+                    // librdkafka's own queue is full; wait for deliveries and try again
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) if !self.pending.is_empty() => {
+                        self.collect(stats).await;
+                    }
+                    Err((e, _)) => {
+                        stats.record(Err(e.to_string()));
+                        return;
+                    }
+                }
+            }
+            if self.pending.len() >= IN_FLIGHT {
+                self.collect(stats).await;
+            }
+        }
+
+        pub(super) async fn flush(&mut self, stats: &mut StreamStats) {
+            self.collect(stats).await;
+            if let Err(e) = self.producer.flush(Timeout::After(MESSAGE_TIMEOUT)) {
+                stats.first_error.get_or_insert(e.to_string());
+            }
+        }
+
+        /// Waits for the delivery reports of all messages in flight.
+        async fn collect(&mut self, stats: &mut StreamStats) {
+            for delivery in self.pending.drain(..) {
+                stats.record(match delivery.await {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err((e, _))) => Err(e.to_string()),
+                    Err(_) => Err("producer went away before delivery".to_string()),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Exclude files for backup tools, from what a scan found not worth backing up: regenerable
+//! data (caches, dependencies, build outputs, see `Regenerable`) and files over a size
+//! limit. Written in the pattern syntax of borg, restic or rsync for `--emit-excludes`.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+
+use crate::formatting::Formatter;
+use crate::regenerable::{Matched, Regenerable};
+use crate::scanner::{FileEntry, SpillList};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExcludeTool {
+    /// Patterns for `borg create --exclude-from`
+    Borg,
+    /// Patterns for `restic backup --exclude-file`
+    Restic,
+    /// Patterns for `rsync --exclude-from`, relative to the scan root as the source
+    Rsync,
+}
+
+/// What one line of the exclude file leaves out.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExcludeTarget {
+    Directory(PathBuf),
+    File(PathBuf),
+    /// Files with this extension (lower-case, without the dot) anywhere
+    Extension(String),
+}
+
+impl ExcludeTarget {
+    fn path(&self) -> Option<&Path> {
+        match self {
+            ExcludeTarget::Directory(path) | ExcludeTarget::File(path) => Some(path),
+            ExcludeTarget::Extension(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Exclusion {
+    pub target: ExcludeTarget,
+    /// The class of regenerable data, or "large files"
+    pub reason: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// The exclusions for the collected `entries` of a scan of `root`: each regenerable file
+/// by the directory its rule matched where there is one, otherwise by its extension or
+/// itself, and each other file of at least `min_size` bytes. Directories below another
+/// excluded one are folded into it.
+pub fn collect(
+    root: &Path,
+    entries: &SpillList<FileEntry>,
+    regenerable: &Regenerable,
+    min_size: Option<u64>,
+) -> io::Result<Vec<Exclusion>> {
+    let mut found: BTreeMap<ExcludeTarget, Exclusion> = BTreeMap::new();
+    for entry in entries.iter() {
+        let entry = entry?;
+        let (target, reason) = match regenerable.find(&entry.path) {
+            // A rule matching the root or above it would exclude the whole backup
+            Some((class, Matched::Path(path))) if path.starts_with(root) && path != root => {
+                let target = if path == entry.path { ExcludeTarget::File(path) } else { ExcludeTarget::Directory(path) };
+                (target, class.to_string())
+            }
+            Some((class, Matched::Extension(ext))) => (ExcludeTarget::Extension(ext.to_string()), class.to_string()),
+            _ if min_size.is_some_and(|min| entry.size >= min) => {
+                (ExcludeTarget::File(entry.path.to_path_buf()), "large files".to_string())
+            }
+            _ => continue,
+        };
+        let exclusion = found.entry(target.clone()).or_insert_with(|| Exclusion { target, reason, files: 0, size: 0 });
+        exclusion.files += 1;
+        exclusion.size += entry.size;
+    }
+
+    // Sorted by path, so a directory comes right before what lies below it
+    let (mut by_path, extensions): (Vec<Exclusion>, Vec<Exclusion>) = found.into_values()
+        .partition(|e| !matches!(e.target, ExcludeTarget::Extension(_)));
+    by_path.sort_by(|a, b| a.target.path().cmp(&b.target.path()));
+    let mut exclusions: Vec<Exclusion> = Vec::with_capacity(by_path.len() + extensions.len());
+    let mut outer: Option<usize> = None;
+    for exclusion in by_path {
+        if let Some(i) = outer
+            && let (Some(dir), Some(path)) = (exclusions[i].target.path(), exclusion.target.path())
+            && path.starts_with(dir)
+        {
+            exclusions[i].files += exclusion.files;
+            exclusions[i].size += exclusion.size;
+            continue;
+        }
+        if matches!(exclusion.target, ExcludeTarget::Directory(_)) {
+            outer = Some(exclusions.len());
+        }
+        exclusions.push(exclusion);
+    }
+    exclusions.extend(extensions);
+
+    // Largest reason first, then largest exclusion first within it
+    let mut by_reason: BTreeMap<String, u64> = BTreeMap::new();
+    for exclusion in &exclusions {
+        *by_reason.entry(exclusion.reason.clone()).or_default() += exclusion.size;
+    }
+    exclusions.sort_by(|a, b| {
+        by_reason[&b.reason].cmp(&by_reason[&a.reason])
+            .then_with(|| a.reason.cmp(&b.reason))
+            .then_with(|| b.size.cmp(&a.size))
+            .then_with(|| a.target.cmp(&b.target))
+    });
+    Ok(exclusions)
+}
+
+/// Writes `exclusions` from a scan of `root` as an exclude file for `tool`, with a comment
+/// above each group saying what it holds. Paths with line breaks, which no exclude file
+/// can hold, are left out.
+pub fn write(
+    tool: ExcludeTool,
+    root: &Path,
+    exclusions: &[Exclusion],
+    formatter: Formatter,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    match tool {
+        ExcludeTool::Borg => writeln!(out, "# borg create --exclude-from patterns for {}", root.display())?,
+        ExcludeTool::Restic => writeln!(out, "# restic backup --exclude-file patterns for {}", root.display())?,
+        ExcludeTool::Rsync => writeln!(out, "# rsync --exclude-from patterns for copying {}/", root.display())?,
+    }
+    for (i, exclusion) in exclusions.iter().enumerate() {
+        if exclusions[..i].last().is_none_or(|previous| previous.reason != exclusion.reason) {
+            let (files, size) = exclusions[i..].iter()
+                .take_while(|e| e.reason == exclusion.reason)
+                .fold((0, 0), |(files, size), e| (files + e.files, size + e.size));
+            writeln!(out, "\n# {}: {} file(s), {}", exclusion.reason, formatter.count(files), formatter.size(size))?;
+        }
+        if let Some(pattern) = pattern(tool, root, &exclusion.target) {
+            writeln!(out, "{}", pattern)?;
+        }
+    }
+    Ok(())
+}
+
+fn pattern(tool: ExcludeTool, root: &Path, target: &ExcludeTarget) -> Option<String> {
+    let path = match target {
+        ExcludeTarget::Extension(ext) => {
+            let ext = escape(ext, tool);
+            return Some(if tool == ExcludeTool::Borg { format!("sh:**/*.{}", ext) } else { format!("*.{}", ext) });
+        }
+        _ => target.path()?.to_str()?,
+    };
+    if path.contains(['\n', '\r']) {
+        return None;
+    }
+    Some(match tool {
+        // A path prefix, matched literally
+        ExcludeTool::Borg => format!("pp:{}", path),
+        ExcludeTool::Restic => escape(path, tool),
+        // Anchored at the root of the transfer, with a trailing slash only matching directories
+        ExcludeTool::Rsync => {
+            let relative = Path::new(path).strip_prefix(root).ok()?.to_str()?;
+            let slash = if matches!(target, ExcludeTarget::Directory(_)) { "/" } else { "" };
+            format!("/{}{}", escape(relative, tool), slash)
+        }
+    })
+}
+
+/// Escapes the wildcard characters of `text` for `tool` (backslashes too, where they
+/// aren't path separators); restic also expands environment variables in exclude files.
+fn escape(text: &str, tool: ExcludeTool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '*' | '?' | '[' if tool != ExcludeTool::Borg => escaped.push('\\'),
+            '\\' if tool != ExcludeTool::Borg && cfg!(unix) => escaped.push('\\'),
+            '$' if tool == ExcludeTool::Restic => escaped.push('$'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,1389 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+
+use crate::baseline::Baseline;
+use crate::formatting::Formatter;
+use crate::quota::QuotaViolation;
+use crate::scanner::{ColdDirectory, FileEntry, MatchedFile, RepoUsage, ScanResult, Spill, SpillList, FAN_OUT_BOUNDS};
+
+/// Version of the JSON report layout. Within a version fields are only ever added, so
+/// parsers should ignore fields they do not know; renaming or removing a field, or
+/// changing its meaning, bumps the version. Reports without `schema_version` are v1,
+/// which differs in that `root` and entry paths were kept as given on the command line
+/// instead of being made absolute. v3 ends a chunked report on stdout with a trailer
+/// document, which has no summary; v2 streams were chunks only.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Version of the XML report layout (`--format xml`), with the same rules as
+/// `SCHEMA_VERSION`: elements and attributes are only ever added within a version.
+pub const XML_SCHEMA_VERSION: u32 = 1;
+
+mod anonymize;
+#[cfg(feature = "encrypt")]
+pub mod encrypt;
+mod header;
+#[cfg(feature = "parquet")]
+mod inventory;
+mod markdown;
+mod snapshot;
+mod tree;
+mod xml;
+
+pub use anonymize::Anonymizer;
+pub use header::{host_name, ReportHeader};
+pub use snapshot::{json_to_snapshot, snapshot_to_json, SnapshotEntry, SnapshotReader, SnapshotWriter, SNAPSHOT_MAGIC};
+#[cfg(feature = "encrypt")]
+pub use encrypt::Encryption;
+
+/// Stands in for `encrypt::Encryption` in builds without the `encrypt` feature; it has no
+/// values, so nothing gets encrypted.
+#[cfg(not(feature = "encrypt"))]
+pub enum Encryption {}
+
+/// First bytes of a report written with encryption.
+pub const ENCRYPTED_MAGIC: &[u8; 8] = b"DSENCv1\n";
+
+/// Extension appended to the names of encrypted reports, without the dot.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Extension of a report file while it is written, without the dot; it gets its real name
+/// only once complete.
+pub const PARTIAL_EXTENSION: &str = "partial";
+
+#[derive(Debug, Serialize)]
+struct SummaryRecord<'a> {
+    root: Cow<'a, Path>,
+    total_files: u64,
+    total_directories: u64,
+    total_size: u64,
+    scan_duration_ms: u64,
+    errors: Vec<String>,
+    ignored_errors: u64,
+    matching_files: Records<'a, MatchedFile>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    repositories: Vec<RepoRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cold_data: Option<ColdDataRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placeholders: Option<PlaceholderRecord>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    owners: Vec<OwnerRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    categories: Vec<CategoryRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    regenerable: Option<RegenerableRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dedup: Option<DedupRecord>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    target_usage: Vec<TargetUsageRecord>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    busiest_directories: Vec<DirectoryCountRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fan_out: Option<FanOutRecord>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    extensions: Vec<ExtensionRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    quota_violations: Vec<QuotaViolationRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locked_files: Vec<LockedFileRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    immutable_files: Vec<ImmutableFileRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    encodings: Vec<EncodingRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    line_endings: Vec<LineEndingRecord<'a>>,
+    /// Left out with an anonymizer, as labels describe what the names would have
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    directory_labels: Vec<DirectoryLabelRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    acls: Vec<AclRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selinux: Option<SelinuxRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_caches: Vec<CacheRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_mounts: Vec<MountRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_snapshots: Vec<Cow<'a, Path>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    subvolumes: Vec<SubvolumeRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fragmentation: Option<FragmentationRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trash: Vec<CacheRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    volume: Option<VolumeRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<DeviceRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshots: Option<SnapshotsRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    open_files: Option<OpenFilesRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    churn: Option<ChurnRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared_links: Option<SharedLinksRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_usage: Option<ResourceUsageRecord>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cancelled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct VolumeRecord {
+    total: u64,
+    used: u64,
+    free: u64,
+    available: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inodes: Option<InodeRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceRecord<'a> {
+    source: Cow<'a, Path>,
+    disk: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial: Option<&'a str>,
+    capacity: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotational: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotsRecord<'a> {
+    mount_point: Cow<'a, Path>,
+    snapshots: Vec<SnapshotRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    volume_used: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unaccounted: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotRecord<'a> {
+    /// Left out with an anonymizer, as snapshot names often carry dates and host names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    purgeable: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct InodeRecord {
+    total: u64,
+    used: u64,
+    free: u64,
+    available: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DirectoryCountRecord<'a> {
+    path: Cow<'a, Path>,
+    children: u64,
+    files: u64,
+    directories: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct FanOutRecord {
+    directories: u64,
+    max_children: u64,
+    mean_children: f64,
+    buckets: Vec<FanOutBucketRecord>,
+}
+
+/// Directories with `min..=max` children; `max` is absent for the last, open bucket.
+#[derive(Debug, Serialize)]
+struct FanOutBucketRecord {
+    min: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<u64>,
+    directories: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenFilesRecord {
+    limit: Option<u64>,
+    requested_tasks: usize,
+    tasks: usize,
+    unreadable_directories: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ChurnRecord<'a> {
+    vanished: u64,
+    rescans: u64,
+    recovered: u64,
+    directories: Vec<ChurnedDirectoryRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChurnedDirectoryRecord<'a> {
+    path: Cow<'a, Path>,
+    vanished: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedLinksRecord<'a> {
+    counted_again: bool,
+    files: u64,
+    file_size: u64,
+    directories: u64,
+    links: Vec<SharedLinkRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedLinkRecord<'a> {
+    link: Cow<'a, Path>,
+    counted_at: Cow<'a, Path>,
+    is_dir: bool,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ResourceUsageRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak_rss: Option<u64>,
+    user_cpu_ms: u64,
+    system_cpu_ms: u64,
+    read_dir_calls: u64,
+    metadata_calls: u64,
+    open_calls: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tasks_spawned: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct LockedFileRecord<'a> {
+    path: Cow<'a, Path>,
+    size: u64,
+    reason: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct EncodingRecord<'a> {
+    path: Cow<'a, Path>,
+    files: u64,
+    ascii: u64,
+    utf8: u64,
+    utf16: u64,
+    utf32: u64,
+    legacy: u64,
+    with_bom: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DirectoryLabelRecord<'a> {
+    path: Cow<'a, Path>,
+    label: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct LineEndingRecord<'a> {
+    path: Cow<'a, Path>,
+    files: u64,
+    lf: u64,
+    crlf: u64,
+    mixed: u64,
+    none: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ImmutableFileRecord<'a> {
+    path: Cow<'a, Path>,
+    size: u64,
+    attributes: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct AclRecord<'a> {
+    path: Cow<'a, Path>,
+    is_dir: bool,
+    acl: Cow<'a, str>,
+}
+
+#[derive(Debug, Serialize)]
+struct SelinuxRecord<'a> {
+    types: Vec<LabelRecord<'a>>,
+    mislabeled_files: u64,
+    mislabeled: Vec<MislabeledRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct LabelRecord<'a> {
+    #[serde(rename = "type")]
+    label_type: &'a str,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct MislabeledRecord<'a> {
+    path: Cow<'a, Path>,
+    /// Absent for unlabeled files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct FragmentationRecord<'a> {
+    min_size: u64,
+    files: u64,
+    extents: u64,
+    fragmented: u64,
+    most_fragmented: Vec<FragmentedFileRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct FragmentedFileRecord<'a> {
+    path: Cow<'a, Path>,
+    size: u64,
+    extents: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct SubvolumeRecord<'a> {
+    root: Cow<'a, Path>,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct MountRecord<'a> {
+    path: Cow<'a, Path>,
+    fs_type: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheRecord<'a> {
+    path: Cow<'a, Path>,
+    kind: &'a str,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct OwnerRecord<'a> {
+    uid: u32,
+    name: Cow<'a, str>,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ExtensionRecord<'a> {
+    extension: &'a str,
+    files: u64,
+    size: u64,
+    largest: Vec<EntryRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct CategoryRecord<'a> {
+    name: &'a str,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct RegenerableRecord<'a> {
+    files: u64,
+    size: u64,
+    user_data_files: u64,
+    user_data_size: u64,
+    classes: Vec<CategoryRecord<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DedupRecord {
+    chunk_size: u64,
+    files: u64,
+    bytes: u64,
+    chunks: u64,
+    unique_chunks: u64,
+    unique_bytes: u64,
+    ratio: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct PlaceholderRecord {
+    files: u64,
+    logical_size: u64,
+    local_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetUsageRecord {
+    block_size: u64,
+    allocated: u64,
+    overhead: u64,
+    small_files: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct QuotaViolationRecord<'a> {
+    owner: Cow<'a, str>,
+    used: u64,
+    limit: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ColdDataRecord<'a> {
+    age_days: u64,
+    files: u64,
+    size: u64,
+    directories: Records<'a, ColdDirectory>,
+}
+
+#[derive(Debug, Serialize)]
+struct ColdDirectoryRecord<'a> {
+    path: Cow<'a, Path>,
+    files: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoRecord<'a> {
+    root: Cow<'a, Path>,
+    working_tree_size: u64,
+    git_dir_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct EntryRecord<'a> {
+    path: Cow<'a, Path>,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkInfo {
+    /// 1-based index of this chunk
+    index: usize,
+    count: usize,
+}
+
+/// The last line of a chunked report on stdout, so readers can tell a complete stream from
+/// one cut off between chunks.
+#[derive(Debug, Serialize)]
+struct TrailerDocument {
+    schema_version: u32,
+    trailer: TrailerRecord,
+}
+
+#[derive(Debug, Serialize)]
+struct TrailerRecord {
+    chunks: usize,
+    entries: u64,
+}
+
+/// A `--export-delta` document: the scan summary plus how the files differ from the
+/// baseline's, instead of all of them.
+#[derive(Debug, Serialize)]
+struct DeltaDocument<'a> {
+    schema_version: u32,
+    header: &'a ReportHeader,
+    summary: &'a SummaryRecord<'a>,
+    delta: DeltaRecord<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeltaRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline_scan_id: Option<&'a str>,
+    added: Vec<EntryRecord<'a>>,
+    changed: Vec<ChangedRecord<'a>>,
+    removed: Vec<Cow<'a, Path>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangedRecord<'a> {
+    #[serde(flatten)]
+    entry: EntryRecord<'a>,
+    before_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_modified: Option<u64>,
+}
+
+/// One JSON document: the scan summary plus all (or one chunk of) the file entries.
+#[derive(Debug, Serialize)]
+struct ReportDocument<'a, E> {
+    schema_version: u32,
+    header: &'a ReportHeader,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<ChunkInfo>,
+    summary: &'a SummaryRecord<'a>,
+    entries: E,
+}
+
+/// How a collected item appears in the report, with its paths anonymized by `anonymizer`
+/// if given.
+trait Record {
+    type Out<'a>: Serialize where Self: 'a;
+    fn record(&self, anonymizer: Option<&Anonymizer>) -> Self::Out<'_>;
+}
+
+impl Record for FileEntry {
+    type Out<'a> = EntryRecord<'a>;
+    fn record(&self, anonymizer: Option<&Anonymizer>) -> EntryRecord<'_> {
+        entry_record(self, anonymizer)
+    }
+}
+
+impl Record for MatchedFile {
+    type Out<'a> = EntryRecord<'a>;
+    fn record(&self, anonymizer: Option<&Anonymizer>) -> EntryRecord<'_> {
+        EntryRecord { path: report_path(&self.path, anonymizer), size: self.size, modified: None }
+    }
+}
+
+impl Record for ColdDirectory {
+    type Out<'a> = ColdDirectoryRecord<'a>;
+    fn record(&self, anonymizer: Option<&Anonymizer>) -> ColdDirectoryRecord<'_> {
+        ColdDirectoryRecord { path: report_path(&self.path, anonymizer), files: self.files, size: self.size }
+    }
+}
+
+/// A list serialized as an array, reading items spilled to disk back one at a time.
+#[derive(Debug)]
+struct Records<'a, T>(&'a SpillList<T>, Option<&'a Anonymizer>);
+
+impl<T: Spill + Record> Serialize for Records<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for item in self.0.iter() {
+            let item = item.map_err(serde::ser::Error::custom)?;
+            seq.serialize_element(&item.record(self.1))?;
+        }
+        seq.end()
+    }
+}
+
+/// `path` as the report shows it.
+fn report_path<'a>(path: &'a Path, anonymizer: Option<&Anonymizer>) -> Cow<'a, Path> {
+    match anonymizer {
+        Some(anonymizer) => Cow::Owned(anonymizer.path(path)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// A user name or other text naming something, as the report shows it: hashed whole by
+/// `anonymizer`, so equal texts still get equal hashes.
+fn report_text<'a>(text: impl Into<Cow<'a, str>>, anonymizer: Option<&Anonymizer>) -> Cow<'a, str> {
+    let text = text.into();
+    match anonymizer {
+        Some(anonymizer) => Cow::Owned(anonymizer.hash(text.as_ref().as_ref())),
+        None => text,
+    }
+}
+
+fn unix_seconds(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs())
+}
+
+fn entry_record<'a>(entry: &'a FileEntry, anonymizer: Option<&Anonymizer>) -> EntryRecord<'a> {
+    EntryRecord { path: report_path(&entry.path, anonymizer), size: entry.size, modified: unix_seconds(entry.modified) }
+}
+
+fn summary_record<'a>(
+    root: &'a Path,
+    result: &'a ScanResult,
+    quota_violations: &'a [QuotaViolation],
+    anonymizer: Option<&'a Anonymizer>,
+) -> SummaryRecord<'a> {
+    let path = |path| report_path(path, anonymizer);
+    SummaryRecord {
+        root: path(root),
+        total_files: result.total_files,
+        total_directories: result.total_directories,
+        total_size: result.total_size,
+        scan_duration_ms: result.scan_duration.as_millis() as u64,
+        errors: result.errors.iter()
+            .map(|e| match anonymizer {
+                // The path is the only part of the message that names anything
+                Some(_) => e.to_string().replace(&format!("{:?}", e.path()), &format!("{:?}", path(e.path()))),
+                None => e.to_string(),
+            })
+            .collect(),
+        ignored_errors: result.ignored_errors,
+        matching_files: Records(&result.matching_files, anonymizer),
+        repositories: result.repositories.iter()
+            .map(|r: &RepoUsage| RepoRecord { root: path(&r.root), working_tree_size: r.working_tree_size, git_dir_size: r.git_dir_size })
+            .collect(),
+        cold_data: result.cold_data.as_ref().map(|cold| ColdDataRecord {
+            age_days: cold.age.as_secs() / (24 * 60 * 60),
+            files: cold.files,
+            size: cold.size,
+            directories: Records(&cold.directories, anonymizer),
+        }),
+        placeholders: result.placeholders.as_ref()
+            .map(|p| PlaceholderRecord { files: p.files, logical_size: p.logical_size, local_size: p.local_size }),
+        owners: result.owners.iter()
+            .map(|o| OwnerRecord { uid: o.uid, name: report_text(o.display_name(), anonymizer), files: o.files, size: o.size })
+            .collect(),
+        categories: result.categories.iter()
+            .map(|c| CategoryRecord { name: &c.name, files: c.files, size: c.size })
+            .collect(),
+        regenerable: result.regenerable.as_ref().map(|r| RegenerableRecord {
+            files: r.files,
+            size: r.size,
+            user_data_files: result.total_files.saturating_sub(r.files),
+            user_data_size: result.total_size.saturating_sub(r.size),
+            classes: r.classes.iter().map(|c| CategoryRecord { name: &c.name, files: c.files, size: c.size }).collect(),
+        }),
+        dedup: result.dedup.as_ref().map(|d| DedupRecord {
+            chunk_size: d.chunk_size,
+            files: d.files,
+            bytes: d.bytes,
+            chunks: d.chunks,
+            unique_chunks: d.unique_chunks,
+            unique_bytes: d.unique_bytes,
+            ratio: d.ratio(),
+        }),
+        target_usage: result.target_usage.iter()
+            .map(|u| TargetUsageRecord {
+                block_size: u.block_size,
+                allocated: u.allocated,
+                overhead: u.overhead(result.total_size),
+                small_files: u.small_files,
+            })
+            .collect(),
+        busiest_directories: result.busiest_directories.iter()
+            .map(|d| DirectoryCountRecord { path: path(&d.path), children: d.children, files: d.files, directories: d.directories })
+            .collect(),
+        fan_out: result.fan_out.as_ref().map(|f| FanOutRecord {
+            directories: f.directories,
+            max_children: f.max_children,
+            mean_children: f.mean_children(),
+            buckets: f.buckets.iter().enumerate()
+                .map(|(i, &directories)| FanOutBucketRecord {
+                    min: if i == 0 { 0 } else { FAN_OUT_BOUNDS[i - 1] },
+                    max: FAN_OUT_BOUNDS.get(i).map(|upper| upper - 1),
+                    directories,
+                })
+                .collect(),
+        }),
+        extensions: result.extensions.iter()
+            .map(|e| ExtensionRecord {
+                extension: &e.extension,
+                files: e.files,
+                size: e.size,
+                largest: e.largest.iter().map(|entry| entry_record(entry, anonymizer)).collect(),
+            })
+            .collect(),
+        quota_violations: quota_violations.iter()
+            .map(|v| QuotaViolationRecord { owner: report_text(v.owner.as_str(), anonymizer), used: v.used, limit: v.limit })
+            .collect(),
+        locked_files: result.locked_files.iter()
+            .map(|l| LockedFileRecord { path: path(&l.path), size: l.size, reason: &l.reason })
+            .collect(),
+        encodings: result.encodings.iter()
+            .map(|d| EncodingRecord {
+                path: path(&d.path),
+                files: d.counts.files(),
+                ascii: d.counts.ascii,
+                utf8: d.counts.utf8,
+                utf16: d.counts.utf16,
+                utf32: d.counts.utf32,
+                legacy: d.counts.legacy,
+                with_bom: d.counts.with_bom,
+            })
+            .collect(),
+        line_endings: result.line_endings.iter()
+            .map(|d| LineEndingRecord {
+                path: path(&d.path),
+                files: d.counts.files(),
+                lf: d.counts.lf,
+                crlf: d.counts.crlf,
+                mixed: d.counts.mixed,
+                none: d.counts.none,
+            })
+            .collect(),
+        directory_labels: match anonymizer {
+            Some(_) => Vec::new(),
+            None => result.directory_labels.iter()
+                .map(|(dir, label)| DirectoryLabelRecord { path: path(dir), label })
+                .collect(),
+        },
+        immutable_files: result.immutable_files.iter()
+            .map(|f| ImmutableFileRecord {
+                path: path(&f.path),
+                size: f.size,
+                attributes: f.attributes.iter().map(|a| a.label()).collect(),
+            })
+            .collect(),
+        acls: result.acls.iter()
+            .map(|a| AclRecord { path: path(&a.path), is_dir: a.is_dir, acl: report_text(a.description.as_str(), anonymizer) })
+            .collect(),
+        selinux: result.selinux.as_ref().map(|s| SelinuxRecord {
+            types: s.types.iter()
+                .map(|t| LabelRecord { label_type: &t.label_type, files: t.files, size: t.size })
+                .collect(),
+            mislabeled_files: s.mislabeled_files,
+            mislabeled: s.mislabeled.iter()
+                .map(|m| MislabeledRecord { path: path(&m.path), context: m.context.as_deref() })
+                .collect(),
+        }),
+        skipped_caches: result.skipped_caches.iter()
+            .map(|c| CacheRecord { path: path(&c.path), kind: &c.kind, files: c.files, size: c.size })
+            .collect(),
+        skipped_mounts: result.skipped_mounts.iter()
+            .map(|m| MountRecord { path: path(&m.path), fs_type: &m.fs_type })
+            .collect(),
+        skipped_snapshots: result.skipped_snapshots.iter().map(|p| path(p)).collect(),
+        subvolumes: result.subvolumes.iter()
+            .map(|s| SubvolumeRecord { root: path(&s.root), files: s.files, size: s.size })
+            .collect(),
+        fragmentation: result.fragmentation.as_ref().map(|f| FragmentationRecord {
+            min_size: f.min_size,
+            files: f.files,
+            extents: f.extents,
+            fragmented: f.fragmented,
+            most_fragmented: f.most_fragmented.iter()
+                .map(|file| FragmentedFileRecord { path: path(&file.path), size: file.size, extents: file.extents })
+                .collect(),
+        }),
+        trash: result.trash.iter()
+            .map(|t| CacheRecord { path: path(&t.path), kind: &t.kind, files: t.files, size: t.size })
+            .collect(),
+        volume: result.volume.map(|v| VolumeRecord {
+            total: v.total,
+            used: v.used(),
+            free: v.free,
+            available: v.available,
+            inodes: v.inodes.map(|i| InodeRecord { total: i.total, used: i.used(), free: i.free, available: i.available }),
+        }),
+        device: result.device.as_ref().map(|d| DeviceRecord {
+            source: path(&d.source),
+            disk: &d.disk,
+            model: d.model.as_deref(),
+            serial: d.serial.as_deref().filter(|_| anonymizer.is_none()),
+            capacity: d.capacity,
+            rotational: d.rotational,
+        }),
+        snapshots: result.snapshots.as_ref().map(|s| SnapshotsRecord {
+            mount_point: path(&s.mount_point),
+            snapshots: s.snapshots.iter().map(|snapshot| SnapshotRecord { name: anonymizer.is_none().then_some(snapshot.name.as_str()), purgeable: snapshot.purgeable }).collect(),
+            volume_used: s.volume_used,
+            unaccounted: s.unaccounted,
+        }),
+        open_files: result.open_files.as_ref().map(|o| OpenFilesRecord {
+            limit: o.limit,
+            requested_tasks: o.requested_tasks,
+            tasks: o.tasks,
+            unreadable_directories: o.unreadable_directories,
+        }),
+        shared_links: result.shared_links.as_ref().filter(|s| s.files + s.directories > 0).map(|s| SharedLinksRecord {
+            counted_again: s.counted_again,
+            files: s.files,
+            file_size: s.file_size,
+            directories: s.directories,
+            links: s.links.iter()
+                .map(|l| SharedLinkRecord { link: path(&l.link), counted_at: path(&l.counted_at), is_dir: l.is_dir, size: l.size })
+                .collect(),
+        }),
+        churn: (result.churn.vanished > 0).then(|| ChurnRecord {
+            vanished: result.churn.vanished,
+            rescans: result.churn.rescans,
+            recovered: result.churn.recovered,
+            directories: result.churn.directories.iter()
+                .map(|d| ChurnedDirectoryRecord { path: path(&d.path), vanished: d.vanished })
+                .collect(),
+        }),
+        resource_usage: result.resource_usage.as_ref().map(|r| ResourceUsageRecord {
+            peak_rss: r.peak_rss,
+            user_cpu_ms: r.user_cpu.as_millis() as u64,
+            system_cpu_ms: r.system_cpu.as_millis() as u64,
+            read_dir_calls: r.read_dir_calls,
+            metadata_calls: r.metadata_calls,
+            open_calls: r.open_calls,
+            tasks_spawned: r.tasks_spawned,
+        }),
+        cancelled: result.cancelled,
+    }
+}
+
+/// Renders the whole report as a single compact JSON document.
+pub fn report_json(
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+) -> serde_json::Result<String> {
+    let header = ReportHeader::new(root, result, None);
+    let summary = summary_record(root, result, quota_violations, None);
+    serde_json::to_string(&ReportDocument {
+        schema_version: SCHEMA_VERSION,
+        header: &header,
+        chunk: None,
+        summary: &summary,
+        entries: Records(&result.entries, None),
+    })
+}
+
+/// Layout of exported reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// The summary plus every file, as JSON
+    #[default]
+    Json,
+    /// Only the per-file inventory (path, size, mtime, owner, extension) as a Parquet
+    /// file; needs the `parquet` feature
+    Parquet,
+    /// An indented tree of directories and files with their sizes, like `tree --du -h`
+    Tree,
+    /// The summary, largest files, largest directories and usage per extension as
+    /// Markdown tables
+    Markdown,
+    /// The core of the summary plus every file, as XML
+    Xml,
+    /// Only every file's path, size and mtime, in a compact binary format that --baseline
+    /// reads far faster than JSON; needs --output
+    Snapshot,
+}
+
+/// Compression applied to exported reports as they are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    /// Only available when built with the `zstd` feature
+    Zstd,
+}
+
+impl Compression {
+    /// File name extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// `path` with this compression's extension appended, unless it already ends in it.
+    pub fn output_path(self, path: &Path) -> PathBuf {
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(self.extension())) {
+            return path.to_path_buf();
+        }
+        let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".");
+        name.push(self.extension());
+        path.with_file_name(name)
+    }
+}
+
+/// `path` with `ENCRYPTED_EXTENSION` appended, as encrypted reports are named.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// `path` with `PARTIAL_EXTENSION` appended, as report files are named while written.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(PARTIAL_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// A report file written under its `partial_path` and renamed to its name by `commit`, so a
+/// scan killed mid-export (out of memory, power loss) never leaves a truncated file where a
+/// complete report is expected.
+struct PartialFile {
+    writer: BufWriter<File>,
+    partial: PathBuf,
+    path: PathBuf,
+}
+
+impl PartialFile {
+    fn create(path: PathBuf) -> io::Result<Self> {
+        let partial = partial_path(&path);
+        Ok(Self { writer: BufWriter::new(File::create(&partial)?), partial, path })
+    }
+
+    /// Makes sure the data is on disk before the file takes its name, then renames it.
+    fn commit(self) -> io::Result<PathBuf> {
+        self.writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        std::fs::rename(&self.partial, &self.path)?;
+        Ok(self.path)
+    }
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Opens a report written by `write_json_report`, decompressing it if it starts with a
+/// gzip or zstd header. Encrypted reports are refused; see `encrypt::decrypt`.
+pub fn open_report(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = reader.fill_buf()?;
+    if header.starts_with(ENCRYPTED_MAGIC) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the report is encrypted; decrypt it first (disk_scanner decrypt)",
+        ));
+    }
+    if header.starts_with(&[0x1f, 0x8b]) {
+        return Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))));
+    }
+    if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        #[cfg(feature = "zstd")]
+        return Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)));
+        #[cfg(not(feature = "zstd"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd compression is not available in this build (enable the `zstd` feature)",
+        ));
+    }
+    Ok(Box::new(reader))
+}
+
+/// Where the bytes of a report go in the end: to the file (or stdout) as they are, or
+/// encrypted first.
+enum Layer<W: Write> {
+    Plain(W),
+    #[cfg(feature = "encrypt")]
+    Encrypted(Box<encrypt::EncryptingWriter<W>>),
+}
+
+impl<W: Write> Layer<W> {
+    fn new(writer: W, encryption: Option<&Encryption>) -> io::Result<Self> {
+        match encryption {
+            None => Ok(Layer::Plain(writer)),
+            #[cfg(feature = "encrypt")]
+            Some(encryption) => Ok(Layer::Encrypted(Box::new(encryption.writer(writer)?))),
+            #[cfg(not(feature = "encrypt"))]
+            Some(encryption) => match *encryption {},
+        }
+    }
+
+    /// Seals the last encrypted chunk, if any, and flushes.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Layer::Plain(mut writer) => writer.flush().map(|()| writer),
+            #[cfg(feature = "encrypt")]
+            Layer::Encrypted(writer) => {
+                let mut writer = writer.finish()?;
+                writer.flush().map(|()| writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Layer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Layer::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "encrypt")]
+            Layer::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Layer::Plain(writer) => writer.flush(),
+            #[cfg(feature = "encrypt")]
+            Layer::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+/// A report destination, compressing (and encrypting) on the fly when asked to.
+enum Sink<W: Write> {
+    Plain(Layer<W>),
+    Gzip(GzEncoder<Layer<W>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, Layer<W>>),
+}
+
+impl<W: Write> Sink<W> {
+    fn new(writer: W, compression: Option<Compression>, encryption: Option<&Encryption>) -> io::Result<Self> {
+        let writer = Layer::new(writer, encryption)?;
+        match compression {
+            None => Ok(Sink::Plain(writer)),
+            Some(Compression::Gzip) => Ok(Sink::Gzip(GzEncoder::new(writer, flate2::Compression::default()))),
+            #[cfg(feature = "zstd")]
+            Some(Compression::Zstd) => Ok(Sink::Zstd(zstd::Encoder::new(writer, 0)?)),
+            #[cfg(not(feature = "zstd"))]
+            Some(Compression::Zstd) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "zstd compression is not available in this build (enable the `zstd` feature)",
+            )),
+        }
+    }
+
+    /// Writes any buffered compressed data and the format's trailer.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Sink::Plain(writer) => writer.finish(),
+            Sink::Gzip(encoder) => encoder.finish()?.finish(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.finish()?.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(writer) => writer.write(buf),
+            Sink::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.flush(),
+            Sink::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Name of the `index`-th chunk file, e.g. `report.json` -> `report.0003.json`.
+pub fn chunk_path(output: &Path, index: usize) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let file_name = match output.extension() {
+        Some(ext) => format!("{}.{:04}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}.{:04}", stem, index),
+    };
+    output.with_file_name(file_name)
+}
+
+/// Where and how `write_json_report` writes a report.
+#[derive(Clone, Copy, Default)]
+pub struct JsonOptions<'a> {
+    /// File to write; stdout when `None`
+    pub output: Option<&'a Path>,
+    /// Entries per document: numbered files, or one JSON document per line on stdout
+    pub chunk_size: Option<usize>,
+    /// Appends its extension to file names (`report.json.gz`)
+    pub compression: Option<Compression>,
+    /// Appends `.enc` to file names, after any compression extension
+    pub encryption: Option<&'a Encryption>,
+    /// Hashes every path in the report
+    pub anonymizer: Option<&'a Anonymizer>,
+}
+
+/// Writes the JSON report as `options` say. Compressed or encrypted data is not written
+/// to a terminal. Returns the paths of the files written.
+pub fn write_json_report(
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    options: &JsonOptions<'_>,
+) -> io::Result<Vec<PathBuf>> {
+    let JsonOptions { output, chunk_size, compression, encryption, anonymizer } = *options;
+    if output.is_none() {
+        check_terminal(compression, encryption)?;
+    }
+    let header = ReportHeader::new(root, result, anonymizer);
+    let summary = summary_record(root, result, quota_violations, anonymizer);
+    let mut written = Vec::new();
+    let create = |path: PathBuf| -> io::Result<Sink<PartialFile>> {
+        let path = match compression {
+            Some(compression) => compression.output_path(&path),
+            None => path,
+        };
+        let path = if encryption.is_some() { encrypted_path(&path) } else { path };
+        Sink::new(PartialFile::create(path)?, compression, encryption)
+    };
+
+    let Some(chunk_size) = chunk_size else {
+        let document = ReportDocument {
+            schema_version: SCHEMA_VERSION,
+            header: &header,
+            chunk: None,
+            summary: &summary,
+            entries: Records(&result.entries, anonymizer),
+        };
+        match output {
+            Some(path) => {
+                let mut sink = create(path.to_path_buf())?;
+                write_document(&mut sink, &document, false)?;
+                written.push(sink.finish()?.commit()?);
+            }
+            None => {
+                let mut sink = Sink::new(io::stdout().lock(), compression, encryption)?;
+                write_document(&mut sink, &document, true)?;
+                sink.finish().map(drop)?;
+            }
+        }
+        return Ok(written);
+    };
+
+    let count = result.entries.len().div_ceil(chunk_size).max(1);
+    let mut entries = result.entries.iter();
+    let mut stdout = match output {
+        Some(_) => None,
+        None => Some(Sink::new(io::stdout().lock(), compression, encryption)?),
+    };
+
+    for i in 0..count {
+        let chunk = entries.by_ref().take(chunk_size).collect::<io::Result<Vec<_>>>()?;
+        let document = ReportDocument {
+            schema_version: SCHEMA_VERSION,
+            header: &header,
+            chunk: Some(ChunkInfo { index: i + 1, count }),
+            summary: &summary,
+            entries: chunk.iter().map(|entry| entry_record(entry, anonymizer)).collect::<Vec<_>>(),
+        };
+        match (output, &mut stdout) {
+            (Some(path), _) => {
+                let mut sink = create(chunk_path(path, i + 1))?;
+                write_document(&mut sink, &document, false)?;
+                written.push(sink.finish()?.commit()?);
+            }
+            (None, Some(sink)) => {
+                write_document(sink, &document, false)?;
+                // Each chunk as a whole, so whatever reads the stream gets every complete
+                // chunk even if the process dies before the next one
+                sink.flush()?;
+            }
+            (None, None) => unreachable!("stdout sink exists when there is no output file"),
+        }
+    }
+    if let Some(mut sink) = stdout {
+        let trailer = TrailerDocument {
+            schema_version: SCHEMA_VERSION,
+            trailer: TrailerRecord { chunks: count, entries: result.entries.len() as u64 },
+        };
+        serde_json::to_writer(&mut sink, &trailer)?;
+        sink.write_all(b"\n")?;
+        sink.finish().map(drop)?;
+    }
+    Ok(written)
+}
+
+/// Refuses to write compressed or encrypted data to stdout when it is a terminal.
+fn check_terminal(compression: Option<Compression>, encryption: Option<&Encryption>) -> io::Result<()> {
+    if (compression.is_some() || encryption.is_some()) && io::stdout().is_terminal() {
+        let what = if encryption.is_some() { "encrypted" } else { "compressed" };
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Refusing to write {} data to a terminal; use --output or redirect stdout", what),
+        ));
+    }
+    Ok(())
+}
+
+/// Settings for `write_tree_report`.
+#[derive(Clone, Copy, Default)]
+pub struct TreeOptions<'a> {
+    /// File to write; stdout when `None`
+    pub output: Option<&'a Path>,
+    /// Leave files out, showing only directories
+    pub dirs_only: bool,
+    /// Levels shown below the root; deeper ones still count towards their ancestors' sizes
+    pub max_depth: Option<usize>,
+    /// Appends its extension to the file name (`tree.txt.gz`)
+    pub compression: Option<Compression>,
+    /// Appends `.enc` to the file name, after any compression extension
+    pub encryption: Option<&'a Encryption>,
+    /// Hashes every name in the tree
+    pub anonymizer: Option<&'a Anonymizer>,
+    pub formatter: Formatter,
+}
+
+/// Writes the files of `result` below `root` as an indented tree with per-node sizes,
+/// after the report header as `# name: value` lines. Returns the path written, if any.
+pub fn write_tree_report(root: &Path, result: &ScanResult, options: &TreeOptions<'_>) -> io::Result<Vec<PathBuf>> {
+    let TreeOptions { output, dirs_only, max_depth, compression, encryption, anonymizer, formatter } = *options;
+    write_text_report(output, compression, encryption, |writer| {
+        for (name, value) in ReportHeader::new(root, result, anonymizer).fields() {
+            writeln!(writer, "# {}: {}", name, value)?;
+        }
+        writeln!(writer)?;
+        tree::write(writer, root, &result.entries, dirs_only, max_depth, anonymizer, formatter).map(drop)
+    })
+}
+
+/// Writes a Markdown report of `result` below `root`, to paste into tickets and wiki pages.
+/// Sizes, counts and the scan time are shown as `formatter` says; `options.chunk_size`
+/// doesn't apply. Returns the path written, if any.
+pub fn write_markdown_report(
+    root: &Path,
+    result: &ScanResult,
+    formatter: Formatter,
+    options: &JsonOptions<'_>,
+) -> io::Result<Vec<PathBuf>> {
+    let JsonOptions { output, compression, encryption, anonymizer, .. } = *options;
+    write_text_report(output, compression, encryption, |writer| {
+        let header = ReportHeader::new(root, result, anonymizer);
+        markdown::write(writer, &header, root, result, anonymizer, formatter).map(drop)
+    })
+}
+
+/// Writes the totals, errors and every file of `result` as an XML document, for importers
+/// that take nothing else. `options.chunk_size` doesn't apply. Returns the path written,
+/// if any.
+pub fn write_xml_report(
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    options: &JsonOptions<'_>,
+) -> io::Result<Vec<PathBuf>> {
+    let JsonOptions { output, compression, encryption, anonymizer, .. } = *options;
+    write_text_report(output, compression, encryption, |writer| {
+        let header = ReportHeader::new(root, result, anonymizer);
+        xml::write(writer, &header, root, result, quota_violations, anonymizer).map(drop)
+    })
+}
+
+/// Writes the JSON report with only the files added, changed or removed since `baseline`
+/// (loaded with `Baseline::load_with_files`) in place of the entries, for ingestion
+/// pipelines that already have the rest. `options.chunk_size` doesn't apply. Returns the
+/// path written, if any.
+pub fn write_delta_report(
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    baseline: &Baseline,
+    options: &JsonOptions<'_>,
+) -> io::Result<Vec<PathBuf>> {
+    let JsonOptions { output, compression, encryption, anonymizer, .. } = *options;
+    let delta = baseline.delta(root, &result.entries)?;
+    let header = ReportHeader::new(root, result, anonymizer);
+    let summary = summary_record(root, result, quota_violations, anonymizer);
+    let document = DeltaDocument {
+        schema_version: SCHEMA_VERSION,
+        header: &header,
+        summary: &summary,
+        delta: DeltaRecord {
+            baseline_scan_id: baseline.scan_id.as_deref(),
+            added: delta.added.iter().map(|entry| entry_record(entry, anonymizer)).collect(),
+            changed: delta.changed.iter()
+                .map(|changed| ChangedRecord {
+                    entry: entry_record(&changed.entry, anonymizer),
+                    before_size: changed.before_size,
+                    before_modified: changed.before_modified,
+                })
+                .collect(),
+            removed: delta.removed.iter().map(|path| report_path(path, anonymizer)).collect(),
+        },
+    };
+    write_text_report(output, compression, encryption, |writer| {
+        if output.is_none() {
+            serde_json::to_writer_pretty(&mut *writer, &document)?;
+        } else {
+            serde_json::to_writer(&mut *writer, &document)?;
+        }
+        writer.write_all(b"\n")
+    })
+}
+
+/// Writes every file of `result` below `root` as a binary snapshot (see `SnapshotWriter`)
+/// to `output`, compressing and encrypting it as asked. Returns the path written.
+pub fn write_snapshot_report(
+    root: &Path,
+    result: &ScanResult,
+    output: &Path,
+    compression: Option<Compression>,
+    encryption: Option<&Encryption>,
+    anonymizer: Option<&Anonymizer>,
+) -> io::Result<Vec<PathBuf>> {
+    write_text_report(Some(output), compression, encryption, |writer| {
+        let header = ReportHeader::new(root, result, anonymizer);
+        let mut snapshot = SnapshotWriter::new(writer, &report_path(root, anonymizer), Some(&header.scan_id))?;
+        for entry in result.entries.iter() {
+            let entry = entry?;
+            snapshot.add(&report_path(&entry.path, anonymizer), entry.size, unix_seconds(entry.modified))?;
+        }
+        snapshot.finish().map(drop)
+    })
+}
+
+/// Runs `write` on `output` (stdout when `None`), compressing and encrypting what it
+/// writes as asked. Returns the path written, if any.
+fn write_text_report<F>(
+    output: Option<&Path>,
+    compression: Option<Compression>,
+    encryption: Option<&Encryption>,
+    write: F,
+) -> io::Result<Vec<PathBuf>>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    match output {
+        Some(path) => {
+            let path = match compression {
+                Some(compression) => compression.output_path(path),
+                None => path.to_path_buf(),
+            };
+            let path = if encryption.is_some() { encrypted_path(&path) } else { path };
+            let mut sink = Sink::new(PartialFile::create(path)?, compression, encryption)?;
+            write(&mut sink)?;
+            Ok(vec![sink.finish()?.commit()?])
+        }
+        None => {
+            check_terminal(compression, encryption)?;
+            let mut sink = Sink::new(io::stdout().lock(), compression, encryption)?;
+            write(&mut sink)?;
+            sink.finish().map(drop)?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+fn write_document<W: Write, E: Serialize>(writer: &mut W, document: &ReportDocument<'_, E>, pretty: bool) -> io::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(&mut *writer, document)?;
+    } else {
+        serde_json::to_writer(&mut *writer, document)?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Writes the per-file inventory of `result` below `root` to `output` as Parquet, one row
+/// per file with its path, size, modification time, owner and extension, and the report
+/// header in the file's key-value metadata. `row_group_size` caps
+/// the rows per row group; `compression` picks the column codec instead of Snappy, so
+/// no extension is appended for it; `encryption` appends `.enc`. `anonymizer` hashes the
+/// paths. Returns the path written.
+pub fn write_parquet_report(
+    root: &Path,
+    result: &ScanResult,
+    output: &Path,
+    row_group_size: Option<usize>,
+    compression: Option<Compression>,
+    encryption: Option<&Encryption>,
+    anonymizer: Option<&Anonymizer>,
+) -> io::Result<PathBuf> {
+    #[cfg(feature = "parquet")]
+    {
+        let output = if encryption.is_some() { encrypted_path(output) } else { output.to_path_buf() };
+        let file = Layer::new(PartialFile::create(output)?, encryption)?;
+        let header = ReportHeader::new(root, result, anonymizer);
+        inventory::write(&header, &result.entries, file, row_group_size, compression, anonymizer)?.finish()?.commit()
+    }
+    #[cfg(not(feature = "parquet"))]
+    {
+        let _ = (root, result, output, row_group_size, compression, encryption, anonymizer);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Parquet export is not available in this build (enable the `parquet` feature)",
+        ))
+    }
+}
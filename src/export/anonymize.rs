@@ -0,0 +1,68 @@
+use std::ffi::{OsStr, OsString};
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// Longest extension kept as it is; anything longer is more likely part of the name.
+const MAX_EXTENSION_LEN: usize = 8;
+
+/// Replaces every path component in a report with a keyed hash for `--anonymize`, so
+/// usage breakdowns can be shared without the names in them. The same name always gets
+/// the same hash within one `Anonymizer`, keeping the tree's structure; extensions (up
+/// to `MAX_EXTENSION_LEN` alphanumerics) and a leading dot are kept, and so are drive
+/// letters. The key is random, so hashes differ between runs and can't be looked up.
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+    key: RandomState,
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `path` with each component replaced: `/home/alice/notes.txt` becomes
+    /// `/<hash>/<hash>/<hash>.txt`, with 16 hex digits per hash.
+    pub fn path(&self, path: &Path) -> PathBuf {
+        let mut anonymized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => anonymized.push(self.name(name)),
+                Component::Prefix(prefix) => match prefix.kind() {
+                    // The server and share of \\server\share\... are names like any other
+                    Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                        let mut unc = OsString::from(r"\\");
+                        unc.push(self.hash(server));
+                        unc.push(r"\");
+                        unc.push(self.hash(share));
+                        anonymized.push(unc);
+                    }
+                    _ => anonymized.push(component),
+                },
+                other => anonymized.push(other),
+            }
+        }
+        anonymized
+    }
+
+    fn name(&self, name: &OsStr) -> String {
+        let name = name.to_string_lossy();
+        let (dot, rest) = match name.strip_prefix('.') {
+            Some(rest) if !rest.is_empty() => (".", rest),
+            _ => ("", &name[..]),
+        };
+        match rest.rfind('.') {
+            Some(i) if i > 0 && is_extension(&rest[i + 1..]) => {
+                format!("{}{}.{}", dot, self.hash(OsStr::new(&rest[..i])), &rest[i + 1..])
+            }
+            _ => format!("{}{}", dot, self.hash(OsStr::new(rest))),
+        }
+    }
+
+    pub(super) fn hash(&self, name: &OsStr) -> String {
+        format!("{:016x}", self.key.hash_one(name.as_encoded_bytes()))
+    }
+}
+
+fn is_extension(ext: &str) -> bool {
+    (1..=MAX_EXTENSION_LEN).contains(&ext.len()) && ext.bytes().all(|b| b.is_ascii_alphanumeric())
+}
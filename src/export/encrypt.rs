@@ -0,0 +1,199 @@
+//! Passphrase encryption of exported reports (`--encrypt-output`), so inventories of
+//! sensitive shares don't sit in plain text wherever they are written.
+//!
+//! Files start with `ENCRYPTED_MAGIC`, the PBKDF2-HMAC-SHA256 salt and iteration count, and a
+//! per-file salt; the key for the file is derived from the passphrase key and that salt
+//! with HKDF. The data follows in AES-256-GCM sealed chunks of up to `CHUNK_SIZE` bytes,
+//! each prefixed with its sealed length (u32, big endian). Chunk nonces are the chunk's
+//! index plus a flag set on the last one, so reordered, dropped or truncated chunks fail
+//! to decrypt.
+
+use std::io::{self, Read, Write};
+use std::num::NonZeroU32;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{hkdf, pbkdf2};
+
+use super::ENCRYPTED_MAGIC;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+/// PBKDF2 rounds for new files, as OWASP recommends for HMAC-SHA256.
+const ITERATIONS: u32 = 600_000;
+/// Files asking for more rounds than this are refused rather than hanging the reader.
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+/// A key derived from a passphrase, to encrypt any number of files with. The slow key
+/// derivation happens once, in `new`.
+pub struct Encryption {
+    key: [u8; 32],
+    salt: [u8; SALT_LEN],
+}
+
+impl Encryption {
+    pub fn new(passphrase: &[u8]) -> io::Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        random(&mut salt)?;
+        Ok(Self { key: derive_key(passphrase, &salt, ITERATIONS)?, salt })
+    }
+
+    /// Starts an encrypted file on `inner`. Call `EncryptingWriter::finish` when done, or the
+    /// file can't be decrypted.
+    pub fn writer<W: Write>(&self, mut inner: W) -> io::Result<EncryptingWriter<W>> {
+        let mut file_salt = [0; SALT_LEN];
+        random(&mut file_salt)?;
+        inner.write_all(ENCRYPTED_MAGIC)?;
+        inner.write_all(&self.salt)?;
+        inner.write_all(&ITERATIONS.to_be_bytes())?;
+        inner.write_all(&file_salt)?;
+        Ok(EncryptingWriter { inner, key: file_key(&self.key, &file_salt), buffer: Vec::new(), chunk: 0 })
+    }
+}
+
+/// Encrypts what is written to it in chunks.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    key: LessSafeKey,
+    buffer: Vec<u8>,
+    chunk: u64,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Seals the rest as the last chunk and returns the inner writer, flushed.
+    pub fn finish(mut self) -> io::Result<W> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.seal(rest, true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn seal(&mut self, mut data: Vec<u8>, last: bool) -> io::Result<()> {
+        self.key.seal_in_place_append_tag(nonce(self.chunk, last), Aad::empty(), &mut data)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.chunk += 1;
+        self.inner.write_all(&(data.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&data)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() > CHUNK_SIZE {
+            let rest = self.buffer.split_off(CHUNK_SIZE);
+            let full = std::mem::replace(&mut self.buffer, rest);
+            self.seal(full, false)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Only flushes the chunks sealed so far; the rest waits for more data or `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a file written through `Encryption::writer`, reading the header from `inner`.
+/// A wrong passphrase shows as an `InvalidData` error on the first read.
+pub fn decrypt<R: Read>(mut inner: R, passphrase: &[u8]) -> io::Result<DecryptingReader<R>> {
+    let mut magic = [0; ENCRYPTED_MAGIC.len()];
+    inner.read_exact(&mut magic)?;
+    if magic != *ENCRYPTED_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an encrypted report"));
+    }
+    let mut salt = [0; SALT_LEN];
+    let mut iterations = [0; 4];
+    let mut file_salt = [0; SALT_LEN];
+    inner.read_exact(&mut salt)?;
+    inner.read_exact(&mut iterations)?;
+    inner.read_exact(&mut file_salt)?;
+    let key = derive_key(passphrase, &salt, u32::from_be_bytes(iterations))?;
+    Ok(DecryptingReader { inner, key: file_key(&key, &file_salt), plain: Vec::new(), position: 0, chunk: 0, done: false })
+}
+
+/// The plain text of an encrypted file, chunk by chunk.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    key: LessSafeKey,
+    plain: Vec<u8>,
+    position: usize,
+    chunk: u64,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let mut length = [0; 4];
+        self.inner.read_exact(&mut length).map_err(truncated)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if !(TAG_LEN..=CHUNK_SIZE + TAG_LEN).contains(&length) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "encrypted report is corrupted"));
+        }
+        let mut sealed = vec![0; length];
+        self.inner.read_exact(&mut sealed).map_err(truncated)?;
+        for last in [false, true] {
+            let mut data = sealed.clone();
+            if let Ok(plain) = self.key.open_in_place(nonce(self.chunk, last), Aad::empty(), &mut data) {
+                let plain_len = plain.len();
+                data.truncate(plain_len);
+                self.plain = data;
+                self.position = 0;
+                self.chunk += 1;
+                self.done = last;
+                return Ok(());
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "wrong passphrase, or the encrypted report is corrupted"))
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plain.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.position);
+        buf[..n].copy_from_slice(&self.plain[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+fn truncated(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::InvalidData, "encrypted report is truncated"),
+        _ => e,
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> io::Result<[u8; 32]> {
+    let iterations = NonZeroU32::new(iterations)
+        .filter(|n| n.get() <= MAX_ITERATIONS)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "encrypted report has an invalid key derivation"))?;
+    let mut key = [0; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase, &mut key);
+    Ok(key)
+}
+
+fn file_key(key: &[u8; 32], file_salt: &[u8]) -> LessSafeKey {
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, file_salt).extract(key);
+    let okm = prk.expand(&[b"disk_scanner report"], &AES_256_GCM)
+        .expect("AES-256 key length is valid for HKDF-SHA256");
+    LessSafeKey::new(UnboundKey::from(okm))
+}
+
+fn nonce(chunk: u64, last: bool) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&chunk.to_be_bytes());
+    nonce[11] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn random(buf: &mut [u8]) -> io::Result<()> {
+    SystemRandom::new().fill(buf).map_err(|_| io::Error::other("no secure random numbers available"))
+}
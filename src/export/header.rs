@@ -0,0 +1,81 @@
+//! The block at the top of every report saying what it was made from, so an archived
+//! report can still be interpreted and two reports can be checked to come from comparable
+//! scans before they are diffed.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use super::{report_path, Anonymizer};
+use crate::scanner::{stable_hash, ScanResult};
+
+/// Who made a report, of what and with which settings. `scan_id` is a hash of the real
+/// host, root, configuration hash and start time, so the same scan always gets the same ID
+/// and anyone holding the unanonymized values can recompute it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportHeader {
+    pub scan_id: String,
+    pub tool_version: &'static str,
+    pub hostname: String,
+    pub root: PathBuf,
+    /// `ScannerConfig::config_hash`; reports with different hashes counted differently
+    pub config_hash: String,
+    /// UTC in ISO-8601, with milliseconds
+    pub started: String,
+    pub finished: String,
+}
+
+impl ReportHeader {
+    /// The header of the report on `result`, a scan of `root`, with the host name and root
+    /// hashed by `anonymizer` if given.
+    pub fn new(root: &Path, result: &ScanResult, anonymizer: Option<&Anonymizer>) -> Self {
+        let hostname = host_name();
+        let started_nanos = result.started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let id_source = format!("{}\n{}\n{}\n{}", hostname, root.display(), result.config_hash, started_nanos);
+        ReportHeader {
+            scan_id: format!("{:016x}", stable_hash(id_source.as_bytes())),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            hostname: match anonymizer {
+                Some(anonymizer) => anonymizer.hash(hostname.as_ref()),
+                None => hostname,
+            },
+            root: report_path(root, anonymizer).into_owned(),
+            config_hash: result.config_hash.clone(),
+            started: humantime::format_rfc3339_millis(result.started).to_string(),
+            finished: humantime::format_rfc3339_millis(result.started + result.scan_duration).to_string(),
+        }
+    }
+
+    /// The fields as `(name, value)` pairs, in the order they're serialized.
+    pub fn fields(&self) -> [(&'static str, String); 7] {
+        [
+            ("scan_id", self.scan_id.clone()),
+            ("tool_version", self.tool_version.to_string()),
+            ("hostname", self.hostname.clone()),
+            ("root", self.root.display().to_string()),
+            ("config_hash", self.config_hash.clone()),
+            ("started", self.started.clone()),
+            ("finished", self.finished.clone()),
+        ]
+    }
+}
+
+/// Name of this machine; empty if it can't be found.
+#[cfg(unix)]
+pub fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the length passed is the buffer's
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn host_name() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
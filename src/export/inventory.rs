@@ -0,0 +1,110 @@
+//! The per-file inventory as a Parquet file, so scans load straight into Spark, DuckDB
+//! and friends.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use arrow_array::builder::{StringBuilder, TimestampSecondBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression as Codec, GzipLevel};
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+
+use super::{report_path, report_text, unix_seconds, Anonymizer, Compression, ReportHeader};
+use crate::owners;
+use crate::scanner::{last_extension, FileEntry, SpillList};
+
+/// Entries converted to Arrow arrays at a time.
+const BATCH_ROWS: usize = 64 * 1024;
+
+const TIMEZONE: &str = "UTC";
+
+fn schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("path", DataType::Utf8, false),
+        Field::new("size", DataType::UInt64, false),
+        Field::new("modified", DataType::Timestamp(TimeUnit::Second, Some(TIMEZONE.into())), true),
+        Field::new("uid", DataType::UInt32, true),
+        Field::new("owner", DataType::Utf8, true),
+        Field::new("extension", DataType::Utf8, true),
+    ]))
+}
+
+fn codec(compression: Option<Compression>) -> io::Result<Codec> {
+    match compression {
+        None => Ok(Codec::SNAPPY),
+        Some(Compression::Gzip) => Ok(Codec::GZIP(GzipLevel::default())),
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Ok(Codec::ZSTD(parquet::basic::ZstdLevel::default())),
+        #[cfg(not(feature = "zstd"))]
+        Some(Compression::Zstd) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd compression is not available in this build (enable the `zstd` feature)",
+        )),
+    }
+}
+
+/// Writes the inventory to `file`, with each field of `header` as key-value metadata
+/// named `disk_scanner.<field>`, returning it once the footer is written.
+pub(super) fn write<W: Write + Send>(
+    header: &ReportHeader,
+    entries: &SpillList<FileEntry>,
+    file: W,
+    row_group_size: Option<usize>,
+    compression: Option<Compression>,
+    anonymizer: Option<&Anonymizer>,
+) -> io::Result<W> {
+    let metadata = header.fields()
+        .into_iter()
+        .map(|(name, value)| KeyValue::new(format!("disk_scanner.{}", name), value))
+        .collect();
+    let mut properties = WriterProperties::builder()
+        .set_compression(codec(compression)?)
+        .set_key_value_metadata(Some(metadata));
+    if let Some(rows) = row_group_size {
+        properties = properties.set_max_row_group_size(rows);
+    }
+    let schema = schema();
+    let mut writer = ArrowWriter::try_new(file, Arc::clone(&schema), Some(properties.build()))
+        .map_err(io::Error::other)?;
+    // Most files share a handful of owners, and each lookup reads the user database
+    let mut names: HashMap<u32, Option<String>> = HashMap::new();
+
+    let mut entries = entries.iter().peekable();
+    while entries.peek().is_some() {
+        let batch = entries.by_ref().take(BATCH_ROWS).collect::<io::Result<Vec<_>>>()?;
+        let mut paths = StringBuilder::new();
+        let mut sizes = UInt64Builder::with_capacity(batch.len());
+        let mut modified = TimestampSecondBuilder::with_capacity(batch.len()).with_timezone(TIMEZONE);
+        let mut uids = UInt32Builder::with_capacity(batch.len());
+        let mut owner_names = StringBuilder::new();
+        let mut extensions = StringBuilder::new();
+        for entry in &batch {
+            paths.append_value(report_path(&entry.path, anonymizer).to_string_lossy());
+            sizes.append_value(entry.size);
+            modified.append_option(unix_seconds(entry.modified).map(|secs| secs as i64));
+            uids.append_option(entry.owner);
+            owner_names.append_option(
+                entry.owner.and_then(|uid| {
+                    names.entry(uid).or_insert_with(|| owners::user_name(uid).map(|name| report_text(name, anonymizer).into_owned())).as_deref()
+                }),
+            );
+            let extension = last_extension(&entry.path);
+            extensions.append_option((!extension.is_empty()).then_some(extension));
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(paths.finish()),
+            Arc::new(sizes.finish()),
+            Arc::new(modified.finish()),
+            Arc::new(uids.finish()),
+            Arc::new(owner_names.finish()),
+            Arc::new(extensions.finish()),
+        ];
+        let batch = RecordBatch::try_new(Arc::clone(&schema), columns).map_err(io::Error::other)?;
+        writer.write(&batch).map_err(io::Error::other)?;
+    }
+    writer.into_inner().map_err(io::Error::other)
+}
@@ -0,0 +1,141 @@
+//! A Markdown report of a scan, with the summary, largest files, largest directories and
+//! usage per extension as tables, to paste into tickets and wiki pages.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::{report_path, Anonymizer, ReportHeader};
+use crate::formatting::Formatter;
+use crate::path_tree::PathTree;
+use crate::scanner::{last_extension, ScanResult};
+
+/// Rows in each of the largest files, largest directories and extension tables.
+const TOP_ROWS: usize = 20;
+
+#[derive(Default)]
+struct Usage {
+    files: u64,
+    size: u64,
+}
+
+/// Keeps the `TOP_ROWS` largest items pushed, largest first.
+struct Largest<T: Ord>(BinaryHeap<Reverse<(u64, T)>>);
+
+impl<T: Ord> Largest<T> {
+    fn push(&mut self, size: u64, item: T) {
+        self.0.push(Reverse((size, item)));
+        if self.0.len() > TOP_ROWS {
+            self.0.pop();
+        }
+    }
+
+    fn into_sorted(self) -> Vec<(u64, T)> {
+        self.0.into_sorted_vec().into_iter().map(|Reverse(item)| item).collect()
+    }
+}
+
+/// `text` as a code span that can sit in a table cell.
+fn code(text: &str) -> String {
+    format!("`{}`", text.replace('|', "\\|").replace('`', "'"))
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+/// Writes the report on the files of `result` below `root`. Paths in the tables are
+/// relative to `root`.
+pub(super) fn write<W: Write>(
+    mut writer: W,
+    header: &ReportHeader,
+    root: &Path,
+    result: &ScanResult,
+    anonymizer: Option<&Anonymizer>,
+    formatter: Formatter,
+) -> io::Result<W> {
+    let mut largest_files = Largest(BinaryHeap::new());
+    let mut directories: PathTree<Usage> = PathTree::default();
+    let mut extensions: HashMap<String, Usage> = HashMap::new();
+    for entry in result.entries.iter() {
+        let entry = entry?;
+        let Ok(relative) = entry.path.strip_prefix(root) else {
+            continue;
+        };
+        largest_files.push(entry.size, relative.to_path_buf());
+        let mut dir = relative.parent().map(|parent| directories.insert(parent));
+        while let Some(node) = dir.filter(|&node| node != PathTree::<Usage>::ROOT) {
+            let usage = directories.get_mut(node);
+            usage.files += 1;
+            usage.size += entry.size;
+            dir = directories.parent(node);
+        }
+        let usage = extensions.entry(last_extension(&entry.path)).or_default();
+        usage.files += 1;
+        usage.size += entry.size;
+    }
+    let total = result.total_size;
+
+    writeln!(writer, "# Disk usage of {}", code(&report_path(root, anonymizer).display().to_string()))?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "Scanned {} in {:.2?} on {} with disk_scanner {}.",
+        formatter.timestamp(result.started),
+        result.scan_duration,
+        code(&header.hostname),
+        header.tool_version,
+    )?;
+    writeln!(writer)?;
+    writeln!(writer, "Scan ID {}, configuration {}.", code(&header.scan_id), code(&header.config_hash))?;
+    if result.cancelled {
+        writeln!(writer, "\n> **Note:** the scan was cancelled, so these numbers are incomplete.")?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "| | |")?;
+    writeln!(writer, "|---|---:|")?;
+    writeln!(writer, "| Files | {} |", formatter.count(result.total_files))?;
+    writeln!(writer, "| Directories | {} |", formatter.count(result.total_directories))?;
+    writeln!(writer, "| Size | {} |", formatter.size(total))?;
+    writeln!(writer, "| Errors | {} |", formatter.count(result.errors.len() as u64))?;
+
+    writeln!(writer, "\n## Largest files\n")?;
+    writeln!(writer, "| Size | Share | File |")?;
+    writeln!(writer, "|---:|---:|---|")?;
+    for (size, path) in largest_files.into_sorted() {
+        let path = report_path(&path, anonymizer);
+        writeln!(writer, "| {} | {:.1}% | {} |", formatter.size(size), percent(size, total), code(&path.display().to_string()))?;
+    }
+
+    let mut largest_directories = Largest(BinaryHeap::new());
+    for (dir, usage) in directories.iter().skip(1) {
+        largest_directories.push(usage.size, (dir, usage.files));
+    }
+    writeln!(writer, "\n## Largest directories\n")?;
+    writeln!(writer, "| Size | Share | Files | Directory |")?;
+    writeln!(writer, "|---:|---:|---:|---|")?;
+    for (size, (dir, files)) in largest_directories.into_sorted() {
+        let dir = directories.path(dir);
+        let mut cell = code(&report_path(&dir, anonymizer).display().to_string());
+        if anonymizer.is_none()
+            && let Some(label) = result.directory_label(&root.join(&dir))
+        {
+            cell = format!("{} ({})", cell, label.replace('|', "\\|"));
+        }
+        writeln!(writer, "| {} | {:.1}% | {} | {} |", formatter.size(size), percent(size, total), formatter.count(files), cell)?;
+    }
+
+    let mut heaviest_extensions = Largest(BinaryHeap::new());
+    for (extension, usage) in extensions {
+        heaviest_extensions.push(usage.size, (extension, usage.files));
+    }
+    writeln!(writer, "\n## By extension\n")?;
+    writeln!(writer, "| Extension | Files | Size | Share |")?;
+    writeln!(writer, "|---|---:|---:|---:|")?;
+    for (size, (extension, files)) in heaviest_extensions.into_sorted() {
+        let extension = if extension.is_empty() { "(none)".to_string() } else { code(&format!(".{}", extension)) };
+        writeln!(writer, "| {} | {} | {} | {:.1}% |", extension, formatter.count(files), formatter.size(size), percent(size, total))?;
+    }
+    Ok(writer)
+}
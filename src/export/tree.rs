@@ -0,0 +1,122 @@
+//! The scanned tree as indented text in the style of `tree --du -h`, each directory
+//! annotated with the size of everything below it.
+
+use std::io::{self, Write};
+use std::path::{Component, Path};
+
+use super::{report_path, Anonymizer};
+use crate::formatting::Formatter;
+use crate::path_tree::{NodeId, PathTree};
+use crate::scanner::{FileEntry, SpillList};
+
+#[derive(Debug, Default)]
+struct Node {
+    size: u64,
+    directory: bool,
+}
+
+/// The built tree, with each node's children in the order they're shown.
+struct Tree {
+    nodes: PathTree<Node>,
+    children: Vec<Vec<NodeId>>,
+}
+
+/// Builds the tree below `root` from the scanned files. Levels below `max_depth` are never
+/// stored, only added to the sizes of their ancestors, so a shallow tree of a big scan
+/// stays small.
+fn build(root: &Path, entries: &SpillList<FileEntry>, dirs_only: bool, max_depth: Option<usize>) -> io::Result<Tree> {
+    let mut tree = PathTree::<Node>::default();
+    tree.get_mut(PathTree::<Node>::ROOT).directory = true;
+    for entry in entries.iter() {
+        let entry = entry?;
+        let Ok(relative) = entry.path.strip_prefix(root) else {
+            continue;
+        };
+        let names: Vec<_> = relative.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        let Some((file, dirs)) = names.split_last() else {
+            continue;
+        };
+        let mut node = PathTree::<Node>::ROOT;
+        tree.get_mut(node).size += entry.size;
+        let mut depth = 0;
+        for dir in dirs {
+            if max_depth.is_some_and(|max| depth == max) {
+                break;
+            }
+            node = tree.insert_child(node, dir);
+            let dir = tree.get_mut(node);
+            dir.directory = true;
+            dir.size += entry.size;
+            depth += 1;
+        }
+        if !dirs_only && depth == dirs.len() && max_depth.is_none_or(|max| depth < max) {
+            let file = tree.insert_child(node, file);
+            tree.get_mut(file).size += entry.size;
+        }
+    }
+    let children = tree.sorted_children();
+    Ok(Tree { nodes: tree, children })
+}
+
+/// Directories and files shown, not counting the root.
+#[derive(Default)]
+struct Counts {
+    directories: u64,
+    files: u64,
+}
+
+fn write_children<W: Write>(
+    writer: &mut W,
+    tree: &Tree,
+    node: NodeId,
+    prefix: &str,
+    anonymizer: Option<&Anonymizer>,
+    formatter: Formatter,
+    counts: &mut Counts,
+) -> io::Result<()> {
+    let children = &tree.children[node as usize];
+    let last = children.len().saturating_sub(1);
+    for (i, &child) in children.iter().enumerate() {
+        let (branch, indent) = if i == last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        let name = report_path(Path::new(tree.nodes.name(child)), anonymizer);
+        writeln!(writer, "{}{}[{:>10}]  {}", prefix, branch, formatter.size(tree.nodes.get(child).size), name.display())?;
+        if tree.nodes.get(child).directory {
+            counts.directories += 1;
+            write_children(writer, tree, child, &format!("{}{}", prefix, indent), anonymizer, formatter, counts)?;
+        } else {
+            counts.files += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the tree of `entries` below `root`: only directories with `dirs_only`, and no
+/// more than `max_depth` levels below the root. Directories without files in them aren't
+/// shown, as `entries` holds only files.
+pub(super) fn write<W: Write>(
+    mut writer: W,
+    root: &Path,
+    entries: &SpillList<FileEntry>,
+    dirs_only: bool,
+    max_depth: Option<usize>,
+    anonymizer: Option<&Anonymizer>,
+    formatter: Formatter,
+) -> io::Result<W> {
+    let tree = build(root, entries, dirs_only, max_depth)?;
+    let root_node = PathTree::<Node>::ROOT;
+    writeln!(writer, "[{:>10}]  {}", formatter.size(tree.nodes.get(root_node).size), report_path(root, anonymizer).display())?;
+    let mut counts = Counts::default();
+    write_children(&mut writer, &tree, root_node, "", anonymizer, formatter, &mut counts)?;
+    let plural = |n: u64, one: &'static str, many: &'static str| if n == 1 { one } else { many };
+    write!(writer, "\n{} {}", formatter.count(counts.directories), plural(counts.directories, "directory", "directories"))?;
+    if !dirs_only {
+        write!(writer, ", {} {}", formatter.count(counts.files), plural(counts.files, "file", "files"))?;
+    }
+    writeln!(writer)?;
+    Ok(writer)
+}
@@ -0,0 +1,86 @@
+//! The report as XML, for importers that take nothing else (CMDBs, older asset
+//! inventories). Only the core of the summary and the per-file entries are included;
+//! element and attribute names are those of the JSON report.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::{entry_record, summary_record, Anonymizer, ReportHeader, XML_SCHEMA_VERSION};
+use crate::quota::QuotaViolation;
+use crate::scanner::ScanResult;
+
+/// `text` with the XML special characters escaped, tabs and line breaks as character
+/// references (attribute values would lose them otherwise) and the control characters
+/// XML 1.0 can't carry at all replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push_str(&format!("&#{};", c as u32)),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn path(path: &Path) -> String {
+    escape(&path.to_string_lossy())
+}
+
+pub(super) fn write<W: Write>(
+    mut writer: W,
+    header: &ReportHeader,
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    anonymizer: Option<&Anonymizer>,
+) -> io::Result<W> {
+    let summary = summary_record(root, result, quota_violations, anonymizer);
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<report schema_version="{}">"#, XML_SCHEMA_VERSION)?;
+    writeln!(writer, "  <header>")?;
+    for (name, value) in header.fields() {
+        writeln!(writer, "    <{}>{}</{}>", name, escape(&value), name)?;
+    }
+    writeln!(writer, "  </header>")?;
+    writeln!(writer, "  <summary>")?;
+    writeln!(writer, "    <root>{}</root>", path(&summary.root))?;
+    writeln!(writer, "    <total_files>{}</total_files>", summary.total_files)?;
+    writeln!(writer, "    <total_directories>{}</total_directories>", summary.total_directories)?;
+    writeln!(writer, "    <total_size>{}</total_size>", summary.total_size)?;
+    writeln!(writer, "    <scan_duration_ms>{}</scan_duration_ms>", summary.scan_duration_ms)?;
+    writeln!(writer, "    <ignored_errors>{}</ignored_errors>", summary.ignored_errors)?;
+    if let Some(churn) = &summary.churn {
+        writeln!(
+            writer,
+            r#"    <churn vanished="{}" rescans="{}" recovered="{}"/>"#,
+            churn.vanished, churn.rescans, churn.recovered
+        )?;
+    }
+    writeln!(writer, "    <cancelled>{}</cancelled>", summary.cancelled)?;
+    writeln!(writer, r#"    <errors count="{}">"#, summary.errors.len())?;
+    for error in &summary.errors {
+        writeln!(writer, "      <error>{}</error>", escape(error))?;
+    }
+    writeln!(writer, "    </errors>")?;
+    writeln!(writer, "  </summary>")?;
+    writeln!(writer, r#"  <entries count="{}">"#, result.entries.len())?;
+    for entry in result.entries.iter() {
+        let entry = entry?;
+        let record = entry_record(&entry, anonymizer);
+        write!(writer, r#"    <file path="{}" size="{}""#, path(&record.path), record.size)?;
+        if let Some(modified) = record.modified {
+            write!(writer, r#" modified="{}""#, modified)?;
+        }
+        writeln!(writer, "/>")?;
+    }
+    writeln!(writer, "  </entries>")?;
+    writeln!(writer, "</report>")?;
+    Ok(writer)
+}
@@ -0,0 +1,58 @@
+//! Per-process limit on open file descriptors, which bounds how many directories the
+//! concurrent walker can have open at once.
+
+use std::io;
+
+/// Descriptors left for stdio, the runtime, sockets and report files.
+const RESERVED_DESCRIPTORS: u64 = 64;
+
+/// Soft limit on open files (`ulimit -n`), if the platform has a finite one.
+#[cfg(unix)]
+pub fn open_file_limit() -> Option<u64> {
+    let mut limit = std::mem::MaybeUninit::<libc::rlimit>::uninit();
+    // SAFETY: `limit` is a valid out-param
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrlimit succeeded, so it filled in the struct
+    let limit = unsafe { limit.assume_init() };
+    // The field type differs between platforms
+    #[allow(clippy::unnecessary_cast)]
+    (limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+}
+
+/// Windows has no per-process handle limit low enough to matter.
+#[cfg(not(unix))]
+pub fn open_file_limit() -> Option<u64> {
+    None
+}
+
+/// How many walker tasks can run under `limit`. Each holds a directory handle while
+/// listing it and one more while probing a file with `--locked-files`.
+pub fn max_tasks(limit: u64) -> usize {
+    usize::try_from(limit.saturating_sub(RESERVED_DESCRIPTORS) / 2).unwrap_or(usize::MAX).max(1)
+}
+
+/// `requested` concurrent tasks, lowered to what the open file limit allows.
+pub fn cap_tasks(requested: usize) -> usize {
+    open_file_limit().map_or(requested, |limit| requested.min(max_tasks(limit)))
+}
+
+/// Whether opening something failed because the process (or the whole system) ran
+/// out of file descriptors, rather than because of the file itself.
+pub fn is_exhausted(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+    }
+    #[cfg(windows)]
+    {
+        const ERROR_TOO_MANY_OPEN_FILES: i32 = 4;
+        e.raw_os_error() == Some(ERROR_TOO_MANY_OPEN_FILES)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = e;
+        false
+    }
+}
@@ -0,0 +1,98 @@
+//! C ABI for embedding the scanner in other applications.
+//!
+//! ```c
+//! typedef void (*scanner_progress_fn)(uint64_t items, uint64_t bytes, uint64_t errors, void *user_data);
+//!
+//! char *scanner_run(const char *config_json, scanner_progress_fn progress, void *user_data);
+//! void scanner_free_string(char *result_json);
+//! ```
+//!
+//! `config_json` is an object such as `{"path": "/data", "pattern": "\\.log$", "by_owner": true}`;
+//! only `path` is required. The returned report has the same layout as `--json`, or is
+//! `{"error": "..."}` if the scan could not run. It must be released with `scanner_free_string`.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use tokio::sync::watch;
+
+use crate::embed::EmbedOptions;
+use crate::export;
+use crate::progress::ProgressSnapshot;
+use crate::scanner::{self, ScanHooks};
+
+/// Called with running totals while the scan is in progress.
+pub type ProgressCallback = Option<unsafe extern "C" fn(items: u64, bytes: u64, errors: u64, user_data: *mut c_void)>;
+
+fn run(config_json: &str, progress: ProgressCallback, user_data: *mut c_void) -> Result<String, anyhow::Error> {
+    let config = serde_json::from_str::<EmbedOptions>(config_json)?.into_scanner_config()?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+
+    runtime.block_on(async {
+        let (observer, mut snapshots) = match progress {
+            Some(_) => {
+                let (tx, rx) = watch::channel(ProgressSnapshot::default());
+                (Some(tx), Some(rx))
+            }
+            None => (None, None),
+        };
+        // Polled on the calling thread alongside the scan, so callbacks arrive there too
+        let forward = async {
+            let (Some(callback), Some(snapshots)) = (progress, snapshots.as_mut()) else {
+                return;
+            };
+            while snapshots.changed().await.is_ok() {
+                let snapshot = snapshots.borrow_and_update().clone();
+                // SAFETY: the host passed a valid function pointer together with its user_data
+                unsafe { callback(snapshot.items, snapshot.bytes, snapshot.errors, user_data) };
+                if snapshot.done {
+                    break;
+                }
+            }
+        };
+        let hooks = ScanHooks { progress: observer, ..Default::default() };
+        let (result, ()) = tokio::join!(scanner::run_scan_with(&config, hooks), forward);
+        Ok(export::report_json(&config.target_path, &result?, &[])?)
+    })
+}
+
+fn into_c_string(json: String) -> *mut c_char {
+    // serde_json escapes control characters, so the output never contains NUL
+    CString::new(json).map(CString::into_raw).unwrap_or(std::ptr::null_mut())
+}
+
+/// Runs a scan described by `config_json` and returns the JSON report.
+///
+/// # Safety
+///
+/// `config_json` must be a valid NUL-terminated string. `progress`, if not null, is
+/// called on the calling thread with `user_data` until `scanner_run` returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scanner_run(
+    config_json: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    let outcome = if config_json.is_null() {
+        Err(anyhow::anyhow!("config_json is null"))
+    } else {
+        // SAFETY: checked for null above; validity is the caller's contract
+        match unsafe { CStr::from_ptr(config_json) }.to_str() {
+            Ok(config_json) => run(config_json, progress, user_data),
+            Err(e) => Err(e.into()),
+        }
+    };
+    let json = outcome.unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() }).to_string());
+    into_c_string(json)
+}
+
+/// Releases a string returned by `scanner_run`.
+///
+/// # Safety
+///
+/// `result_json` must come from `scanner_run` and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scanner_free_string(result_json: *mut c_char) {
+    if !result_json.is_null() {
+        // SAFETY: the pointer was produced by `CString::into_raw` in `scanner_run`
+        drop(unsafe { CString::from_raw(result_json) });
+    }
+}
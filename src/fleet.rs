@@ -0,0 +1,324 @@
+//! `serve` and `agent`: a coordinator that hands one scan job to every registered agent
+//! and aggregates their reports. Both ends speak JSON lines over plain TCP, token and
+//! reports unencrypted, so the protocol is only for a trusted network or a TLS tunnel.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{timeout, Instant};
+
+use disk_scanner::embed::EmbedOptions;
+use disk_scanner::formatting::Formatter;
+use disk_scanner::{export, scanner};
+
+use crate::cli::{AgentArgs, ServeArgs};
+
+/// How long a new connection gets to register before it is dropped.
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait between two attempts of an agent to reach its coordinator.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest message either end reads, so a peer can't make it buffer without end. Reports
+/// with every file listed can be large; anything beyond this is cut off as an error.
+const MAX_MESSAGE_LEN: u64 = 256 * 1024 * 1024;
+
+/// Messages from an agent to the coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AgentMessage {
+    Register {
+        name: String,
+        version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// The report of a finished job, as written by `--json`
+    Result { id: u64, report: serde_json::Value },
+    Failed { id: u64, message: String },
+}
+
+/// Messages from the coordinator to an agent.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CoordinatorMessage {
+    Welcome,
+    Rejected { reason: String },
+    /// Scan options as accepted by the C ABI and the Python bindings
+    Job { id: u64, options: serde_json::Value },
+}
+
+/// One end of a JSON lines connection.
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Connection { reader: BufReader::new(reader), writer }
+    }
+
+    async fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// The next message, or `None` once the other end hung up.
+    async fn receive<T: for<'de> Deserialize<'de>>(&mut self) -> Result<Option<T>> {
+        let mut line = Vec::new();
+        (&mut self.reader).take(MAX_MESSAGE_LEN + 1).read_until(b'\n', &mut line).await?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if line.len() as u64 > MAX_MESSAGE_LEN {
+            bail!("Message longer than {} bytes", MAX_MESSAGE_LEN);
+        }
+        Ok(Some(serde_json::from_slice(&line).context("Malformed message")?))
+    }
+}
+
+/// What one agent reported back for the job.
+#[derive(Debug, Serialize)]
+struct AgentOutcome {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The aggregated result printed by `serve --json`.
+#[derive(Debug, Serialize)]
+struct FleetReport {
+    path: PathBuf,
+    total_files: u64,
+    total_directories: u64,
+    total_size: u64,
+    failed_agents: usize,
+    agents: Vec<AgentOutcome>,
+}
+
+/// Waits for agents to register, sends every one of them the scan job and prints the
+/// aggregated result.
+pub async fn serve(args: &ServeArgs) -> Result<()> {
+    let mut options = match &args.options {
+        Some(json) => serde_json::from_str::<serde_json::Value>(json).context("Invalid --options")?,
+        None => serde_json::json!({}),
+    };
+    let Some(fields) = options.as_object_mut() else {
+        bail!("--options must be a JSON object");
+    };
+    fields.insert("path".to_string(), serde_json::json!(args.path));
+    // Catch unknown or mistyped options here rather than once per agent
+    serde_json::from_value::<EmbedOptions>(options.clone()).context("Invalid --options")?;
+
+    let listener = TcpListener::bind(&args.listen).await
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    eprintln!("Waiting up to {}s for agents on {}", args.wait, listener.local_addr()?);
+    let deadline = Instant::now() + Duration::from_secs(args.wait);
+    let mut agents: BTreeMap<String, Connection> = BTreeMap::new();
+    while args.agents.is_none_or(|wanted| agents.len() < wanted.get()) {
+        let (stream, address) = match tokio::time::timeout_at(deadline, listener.accept()).await {
+            Ok(accepted) => accepted?,
+            Err(_) => break,
+        };
+        match register(Connection::new(stream), args.token.as_deref()).await {
+            Ok((name, connection)) => {
+                eprintln!("Agent {} registered from {}", name, address);
+                if agents.insert(name.clone(), connection).is_some() {
+                    eprintln!("Warning: agent {} registered twice; using the newer connection", name);
+                }
+            }
+            Err(e) => eprintln!("Warning: rejected connection from {}: {:#}", address, e),
+        }
+    }
+    if agents.is_empty() {
+        bail!("No agent registered within {}s", args.wait);
+    }
+    if let Some(wanted) = args.agents
+        && agents.len() < wanted.get()
+    {
+        eprintln!("Warning: only {} of {} agents registered; scanning with those", agents.len(), wanted);
+    }
+
+    let mut jobs = JoinSet::new();
+    for (id, (name, mut connection)) in (1..).zip(agents) {
+        let options = options.clone();
+        jobs.spawn(async move {
+            let outcome = run_job(&mut connection, id, options).await;
+            let (report, error) = match outcome {
+                Ok(report) => (Some(report), None),
+                Err(e) => (None, Some(format!("{:#}", e))),
+            };
+            AgentOutcome { name, report, error }
+        });
+    }
+    let mut outcomes = jobs.join_all().await;
+    outcomes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let summary = |outcome: &AgentOutcome, field: &str| {
+        outcome.report.as_ref().and_then(|r| r["summary"][field].as_u64()).unwrap_or(0)
+    };
+    let report = FleetReport {
+        path: args.path.clone(),
+        total_files: outcomes.iter().map(|o| summary(o, "total_files")).sum(),
+        total_directories: outcomes.iter().map(|o| summary(o, "total_directories")).sum(),
+        total_size: outcomes.iter().map(|o| summary(o, "total_size")).sum(),
+        failed_agents: outcomes.iter().filter(|o| o.error.is_some()).count(),
+        agents: outcomes,
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let formatter = Formatter::default();
+    println!("\nScan of {:?} on {} agents", report.path, report.agents.len());
+    for agent in &report.agents {
+        match &agent.error {
+            Some(error) => println!("  {:<24} failed: {}", agent.name, error),
+            None => println!(
+                "  {:<24} {:>12} {:>10} files {:>8} errors",
+                agent.name,
+                formatter.size(summary(agent, "total_size")),
+                summary(agent, "total_files"),
+                agent.report.as_ref().and_then(|r| r["summary"]["errors"].as_array()).map_or(0, Vec::len),
+            ),
+        }
+    }
+    println!(
+        "  {:<24} {:>12} {:>10} files",
+        "Total",
+        formatter.size(report.total_size),
+        report.total_files,
+    );
+    Ok(())
+}
+
+/// Reads the registration of a new connection and answers it.
+async fn register(mut connection: Connection, token: Option<&str>) -> Result<(String, Connection)> {
+    let message = timeout(REGISTER_TIMEOUT, connection.receive::<AgentMessage>()).await
+        .context("No registration within the timeout")??;
+    let Some(AgentMessage::Register { name, version, token: offered }) = message else {
+        bail!("Expected a registration");
+    };
+    if let Some(token) = token
+        && !same_token(offered.as_deref().unwrap_or_default(), token)
+    {
+        let reason = "wrong or missing token".to_string();
+        connection.send(&CoordinatorMessage::Rejected { reason: reason.clone() }).await?;
+        bail!("Agent {} sent a {}", name, reason);
+    }
+    if version != env!("CARGO_PKG_VERSION") {
+        eprintln!("Warning: agent {} runs version {}, this coordinator {}", name, version, env!("CARGO_PKG_VERSION"));
+    }
+    connection.send(&CoordinatorMessage::Welcome).await?;
+    Ok((name, connection))
+}
+
+/// Whether `offered` is `expected`, compared in a time that doesn't tell how much of it
+/// matched (only whether the lengths do).
+fn same_token(offered: &str, expected: &str) -> bool {
+    let difference = offered.bytes().zip(expected.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b));
+    offered.len() == expected.len() && std::hint::black_box(difference) == 0
+}
+
+async fn run_job(connection: &mut Connection, id: u64, options: serde_json::Value) -> Result<serde_json::Value> {
+    connection.send(&CoordinatorMessage::Job { id, options }).await?;
+    match connection.receive::<AgentMessage>().await? {
+        Some(AgentMessage::Result { id: done, report }) if done == id => Ok(report),
+        Some(AgentMessage::Failed { id: done, message }) if done == id => bail!(message),
+        Some(_) => bail!("Unexpected answer to job {}", id),
+        None => bail!("Agent disconnected during the scan"),
+    }
+}
+
+/// How an agent's session with its coordinator ended.
+enum SessionEnd {
+    /// The coordinator hung up, e.g. after its job was done
+    Disconnected,
+    /// The coordinator refused this agent; reconnecting will not help
+    Rejected(String),
+}
+
+/// Registers with the coordinator and runs the scan jobs it sends, reconnecting with
+/// backoff whenever the connection is lost.
+pub async fn run_agent(args: &AgentArgs) -> Result<()> {
+    let name = args.name.clone().unwrap_or_else(export::host_name);
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match session(args, &name).await {
+            Ok((SessionEnd::Rejected(reason), _)) => bail!("Coordinator {} rejected this agent: {}", args.coordinator, reason),
+            Ok((SessionEnd::Disconnected, jobs)) => {
+                if args.once && jobs > 0 {
+                    return Ok(());
+                }
+                delay = Duration::from_secs(1);
+            }
+            Err(e) => eprintln!("Warning: coordinator {}: {:#}; retrying in {}s", args.coordinator, e, delay.as_secs()),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// One connection to the coordinator; returns how it ended and how many jobs ran.
+async fn session(args: &AgentArgs, name: &str) -> Result<(SessionEnd, u64)> {
+    let stream = TcpStream::connect(&args.coordinator).await?;
+    let mut connection = Connection::new(stream);
+    connection.send(&AgentMessage::Register {
+        name: name.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        token: args.token.clone(),
+    }).await?;
+    match connection.receive::<CoordinatorMessage>().await? {
+        Some(CoordinatorMessage::Welcome) => eprintln!("Registered with {} as {}", args.coordinator, name),
+        Some(CoordinatorMessage::Rejected { reason }) => return Ok((SessionEnd::Rejected(reason), 0)),
+        Some(CoordinatorMessage::Job { .. }) => bail!("Job before registration was answered"),
+        None => return Ok((SessionEnd::Disconnected, 0)),
+    }
+
+    let mut jobs = 0;
+    while let Some(message) = connection.receive::<CoordinatorMessage>().await? {
+        let CoordinatorMessage::Job { id, options } = message else {
+            continue;
+        };
+        eprintln!("Running job {}", id);
+        let answer = match run_scan_job(options, &args.allow).await {
+            Ok(report) => AgentMessage::Result { id, report },
+            Err(e) => AgentMessage::Failed { id, message: format!("{:#}", e) },
+        };
+        connection.send(&answer).await?;
+        jobs += 1;
+    }
+    Ok((SessionEnd::Disconnected, jobs))
+}
+
+async fn run_scan_job(options: serde_json::Value, allow: &[PathBuf]) -> Result<serde_json::Value> {
+    let options: EmbedOptions = serde_json::from_value(options).context("Invalid job")?;
+    if !allow.iter().any(|root| is_within(options.path(), root)) {
+        bail!("{:?} is outside the directories this agent allows", options.path());
+    }
+    let config = options.into_scanner_config()?;
+    let result = scanner::run_scan(&config).await?;
+    Ok(serde_json::from_str(&export::report_json(&config.target_path, &result, &[])?)?)
+}
+
+/// Whether `path` is `root` or below it, after resolving `..` and symlinks.
+fn is_within(path: &Path, root: &Path) -> bool {
+    match (path.canonicalize(), root.canonicalize()) {
+        (Ok(path), Ok(root)) => path.starts_with(root),
+        _ => false,
+    }
+}
@@ -0,0 +1,177 @@
+//! How sizes, counts and timestamps are shown to people: binary (KiB, MiB) or SI (kB, MB)
+//! units, digit grouping, and ISO-8601 or local timestamps. Machine-readable reports keep
+//! plain numbers and UTC ISO-8601 timestamps whatever is picked here.
+
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+use humansize::{format_size, BINARY, DECIMAL};
+
+/// Units of human-readable sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeUnits {
+    /// Powers of 1024: KiB, MiB, GiB
+    #[default]
+    Binary,
+    /// Powers of 1000: kB, MB, GB
+    Si,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimeStyle {
+    /// UTC in ISO-8601, e.g. 2024-05-01T12:30:00Z
+    #[default]
+    Iso,
+    /// Local time, e.g. 2024-05-01 14:30:00
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Separators {
+    pub thousands: char,
+    pub decimal: char,
+}
+
+impl Separators {
+    /// The separators of the locale in `LC_ALL`, `LC_NUMERIC` or `LANG`, by its language
+    /// (and territory for Switzerland); English ones for any locale not known here.
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::for_locale(&locale)
+    }
+
+    /// The separators of `locale`, such as `de_DE.UTF-8`.
+    pub fn for_locale(locale: &str) -> Self {
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let (language, territory) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        let (thousands, decimal) = match (language, territory) {
+            ("de" | "it" | "fr", "CH") => ('\'', '.'),
+            ("de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr" | "sl", _) => ('.', ','),
+            ("fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "uk" | "hu" | "bg" | "et" | "lv" | "lt", _) => {
+                ('\u{a0}', ',')
+            }
+            _ => (',', '.'),
+        };
+        Separators { thousands, decimal }
+    }
+}
+
+/// Formats numbers and times for reports people read. The default matches what was shown
+/// before any of this was configurable: binary units, no digit grouping, ISO-8601 UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Formatter {
+    pub units: SizeUnits,
+    /// Groups digits in thousands and sets the decimal mark when set
+    pub separators: Option<Separators>,
+    pub time_style: TimeStyle,
+}
+
+impl Formatter {
+    /// `bytes` in the chosen units, e.g. "1.50 GiB".
+    pub fn size(&self, bytes: u64) -> String {
+        let text = match self.units {
+            SizeUnits::Binary => format_size(bytes, BINARY),
+            SizeUnits::Si => format_size(bytes, DECIMAL),
+        };
+        match self.separators {
+            Some(separators) if separators.decimal != '.' => text.replace('.', &separators.decimal.to_string()),
+            _ => text,
+        }
+    }
+
+    /// `n` with its digits grouped in thousands when separators are set.
+    pub fn count(&self, n: u64) -> String {
+        let digits = n.to_string();
+        let Some(separators) = self.separators else {
+            return digits;
+        };
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(separators.thousands);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// `amount` with two decimals and `currency` after it, e.g. "1,234.50 USD".
+    pub fn money(&self, amount: f64, currency: &str) -> String {
+        let cents = (amount * 100.0).round() as u64;
+        let decimal = self.separators.map_or('.', |separators| separators.decimal);
+        format!("{}{}{:02} {}", self.count(cents / 100), decimal, cents % 100, currency)
+    }
+
+    /// `time` in the chosen style.
+    pub fn timestamp(&self, time: SystemTime) -> String {
+        match self.time_style {
+            TimeStyle::Iso => humantime::format_rfc3339_seconds(time).to_string(),
+            TimeStyle::Local => local_time(time),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, negative before it.
+#[cfg(any(unix, windows))]
+fn epoch_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+#[cfg(unix)]
+fn local_time(time: SystemTime) -> String {
+    let seconds = epoch_seconds(time) as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm it's given
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut tm) }.is_null() {
+        return utc_time(time);
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+    )
+}
+
+#[cfg(windows)]
+fn local_time(time: SystemTime) -> String {
+    use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+    use windows_sys::Win32::System::Time::{FileTimeToSystemTime, SystemTimeToTzSpecificLocalTime};
+
+    /// 100 ns intervals between 1601-01-01, where FILETIME starts, and the Unix epoch.
+    const EPOCH_DIFFERENCE: i64 = 116_444_736_000_000_000;
+    let ticks = epoch_seconds(time) * 10_000_000 + EPOCH_DIFFERENCE;
+    let file_time = FILETIME { dwLowDateTime: ticks as u32, dwHighDateTime: (ticks >> 32) as u32 };
+    // SAFETY: both calls only write to the SYSTEMTIMEs they're given
+    let mut utc: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    let mut local: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    if unsafe { FileTimeToSystemTime(&file_time, &mut utc) } == 0
+        || unsafe { SystemTimeToTzSpecificLocalTime(std::ptr::null(), &utc, &mut local) } == 0
+    {
+        return utc_time(time);
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        local.wYear, local.wMonth, local.wDay, local.wHour, local.wMinute, local.wSecond,
+    )
+}
+
+/// Without a time zone database, local time is UTC.
+#[cfg(not(any(unix, windows)))]
+fn local_time(time: SystemTime) -> String {
+    utc_time(time)
+}
+
+/// `time` in UTC, laid out like local times.
+fn utc_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string().trim_end_matches('Z').replacen('T', " ", 1)
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::scanner::{FileEntry, ScanResult, SpillList};
+
+/// Aggregates of one scan as stored by `--history`: one JSON object per line, appended
+/// after every scan so the file can be tailed, grepped or trimmed with ordinary tools.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// Seconds since the Unix epoch when the scan finished
+    pub timestamp: u64,
+    pub root: PathBuf,
+    pub total_files: u64,
+    pub total_directories: u64,
+    pub total_size: u64,
+    /// Size of each top-level directory under `root`, by name
+    pub directories: BTreeMap<String, u64>,
+}
+
+impl HistoryRecord {
+    /// Summarizes `result`; the per-directory sizes come from its collected entries,
+    /// which fails only if entries spilled to disk cannot be read back.
+    pub fn from_scan(root: &Path, result: &ScanResult) -> io::Result<Self> {
+        let mut directories = BTreeMap::new();
+        for entry in result.entries.iter() {
+            let entry = entry?;
+            let Ok(relative) = entry.path.strip_prefix(root) else {
+                continue;
+            };
+            let mut components = relative.components();
+            let (Some(top), Some(_)) = (components.next(), components.next()) else {
+                // Files directly in the root only count towards the total
+                continue;
+            };
+            *directories.entry(top.as_os_str().to_string_lossy().into_owned()).or_default() += entry.size;
+        }
+        Ok(Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            root: root.to_path_buf(),
+            total_files: result.total_files,
+            total_directories: result.total_directories,
+            total_size: result.total_size,
+            directories,
+        })
+    }
+}
+
+/// Appends `record` to the history file at `path`, creating it and its parent
+/// directories on first use. A record cut off by a crash during an earlier append is
+/// ended first, so `load` can skip it instead of it running into this one.
+pub fn append(path: &Path, record: &HistoryRecord) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut append = || -> io::Result<()> {
+        let _lock = lock(path)?;
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    };
+    append().with_context(|| format!("Failed to append to history {:?}", path))
+}
+
+/// Takes the lock that appending to and pruning the history file at `path` hold, so a
+/// prune can't replace the file under an append, waiting while another process has it.
+/// It is on a file of its own, `<path>.lock`, as pruning replaces the history file.
+/// Platforms without file locks (WASI) go without. `TagDatabase::save` locks its database
+/// the same way.
+pub(crate) fn lock(path: &Path) -> io::Result<File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new().create(true).write(true).truncate(false).open(lock_path)?;
+    match file.lock() {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(file),
+        locked => locked.map(|()| file),
+    }
+}
+
+/// Reads every record of the history file at `path`, oldest first. Records cut off by a
+/// crash while they were appended are skipped.
+pub fn load(path: &Path) -> Result<Vec<HistoryRecord>> {
+    let file = File::open(path).with_context(|| format!("Failed to read history {:?}", path))?;
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read history {:?}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = match serde_json::from_str(&line) {
+            Err(e) if e.is_eof() => continue,
+            record => record.with_context(|| format!("Invalid record on line {} of history {:?}", number + 1, path))?,
+        };
+        records.push(record);
+    }
+    records.sort_by_key(|r: &HistoryRecord| r.timestamp);
+    Ok(records)
+}
+
+/// Which records of a history file `prune` keeps, for each scan root on its own: a record
+/// any of the rules keeps stays. Periods are UTC days and Monday-to-Sunday weeks, and a
+/// period is represented by its latest record. With no rules set, everything stays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// The most recent records
+    pub keep_last: Option<usize>,
+    /// The latest record of each of the most recent days with records
+    pub keep_daily: Option<usize>,
+    /// The latest record of each of the most recent weeks with records
+    pub keep_weekly: Option<usize>,
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+impl Retention {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.keep_daily.is_none() && self.keep_weekly.is_none()
+    }
+
+    /// Which of `records`, all of one root and oldest first, to keep.
+    fn keeps(&self, records: &[&HistoryRecord]) -> Vec<bool> {
+        if self.is_empty() {
+            return vec![true; records.len()];
+        }
+        let mut keep = vec![false; records.len()];
+        // Periods as (days, offset in days); the Unix epoch was a Thursday, so weeks start
+        // 3 days later. `keep_last` has every record count on its own.
+        let rules = [(self.keep_last, None), (self.keep_daily, Some((1, 0))), (self.keep_weekly, Some((7, 3)))];
+        for (count, period) in rules {
+            let Some(count) = count else {
+                continue;
+            };
+            let mut seen = HashSet::new();
+            for (i, record) in records.iter().enumerate().rev() {
+                if seen.len() == count {
+                    break;
+                }
+                let key = match period {
+                    Some((days, offset)) => (record.timestamp / DAY + offset) / days,
+                    None => i as u64,
+                };
+                if seen.insert(key) {
+                    keep[i] = true;
+                }
+            }
+        }
+        keep
+    }
+}
+
+/// What `prune` did, or would do with `dry_run`.
+#[derive(Debug, Clone, Default)]
+pub struct PruneSummary {
+    pub kept: usize,
+    pub removed: usize,
+    /// `--whats-new` snapshots of roots none of the kept records are of
+    pub snapshots_removed: usize,
+    /// Of the history file and the snapshots together
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Compacts the history file at `path` to the records `retention` keeps, replacing it
+/// only once the new one is complete, and removes the snapshots of roots no kept record
+/// is of (and leftovers of interrupted snapshot writes). Appends wait until it's done.
+/// With `dry_run` nothing changes.
+pub fn prune(path: &Path, retention: &Retention, dry_run: bool) -> Result<PruneSummary> {
+    let _lock = if dry_run { None } else { Some(lock(path).with_context(|| format!("Failed to lock history {:?}", path))?) };
+    let records = load(path)?;
+    let mut by_root: BTreeMap<&Path, Vec<&HistoryRecord>> = BTreeMap::new();
+    for record in &records {
+        by_root.entry(&record.root).or_default().push(record);
+    }
+    let mut kept = Vec::new();
+    for root_records in by_root.values() {
+        kept.extend(root_records.iter().zip(retention.keeps(root_records)).filter(|&(_, keep)| keep).map(|(record, _)| *record));
+    }
+    kept.sort_by_key(|r| r.timestamp);
+
+    let mut summary = PruneSummary { kept: kept.len(), removed: records.len() - kept.len(), ..Default::default() };
+    summary.bytes_before = fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    let mut compacted = Vec::new();
+    for record in &kept {
+        serde_json::to_writer(&mut compacted, record)?;
+        compacted.push(b'\n');
+    }
+    summary.bytes_after = compacted.len() as u64;
+    if !dry_run && summary.removed > 0 {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let rewrite = || -> io::Result<()> {
+            let mut file = File::create(&temp)?;
+            file.write_all(&compacted)?;
+            // On disk before the rename, or a crash could leave the history empty
+            file.sync_all()?;
+            fs::rename(&temp, path)
+        };
+        rewrite().with_context(|| format!("Failed to rewrite history {:?}", path))?;
+    }
+
+    let wanted: HashSet<PathBuf> = kept.iter().map(|r| snapshot_path(path, &r.root)).collect();
+    let mut dir = path.as_os_str().to_owned();
+    dir.push(".snapshots");
+    let Ok(snapshots) = fs::read_dir(&dir) else {
+        return Ok(summary);
+    };
+    for snapshot in snapshots.filter_map(Result::ok) {
+        let snapshot_path = snapshot.path();
+        let size = snapshot.metadata().map(|m| m.len()).unwrap_or_default();
+        summary.bytes_before += size;
+        if wanted.contains(&snapshot_path) {
+            summary.bytes_after += size;
+            continue;
+        }
+        if !dry_run {
+            fs::remove_file(&snapshot_path).with_context(|| format!("Failed to remove snapshot {:?}", snapshot_path))?;
+        }
+        summary.snapshots_removed += 1;
+    }
+    Ok(summary)
+}
+
+/// A file that is new or larger than in the previous scan of the same root.
+#[derive(Debug, Clone)]
+pub struct FileGrowth {
+    pub path: PathBuf,
+    /// `None` for files that did not exist in the previous scan
+    pub before: Option<u64>,
+    pub after: u64,
+}
+
+impl FileGrowth {
+    pub fn delta(&self) -> u64 {
+        self.after - self.before.unwrap_or(0)
+    }
+}
+
+/// Files responsible for the growth since the previous scan, for `--whats-new`.
+#[derive(Debug, Clone)]
+pub struct WhatsNew {
+    /// When the snapshot compared against was written
+    pub previous_scan: Option<SystemTime>,
+    /// Largest contribution first
+    pub files: Vec<FileGrowth>,
+    /// Sum of all growth, which the contributions are shares of
+    pub total_growth: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotEntry<'a> {
+    /// Relative to the scan root
+    path: std::borrow::Cow<'a, Path>,
+    size: u64,
+}
+
+/// Where `--whats-new` keeps the file listing of the latest scan of `root`: a directory
+/// next to the history file with one snapshot per root, replaced on every scan.
+pub fn snapshot_path(history: &Path, root: &Path) -> PathBuf {
+    let mut dir = history.as_os_str().to_owned();
+    dir.push(".snapshots");
+    let name: String = root.to_string_lossy().bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' => (b as char).to_string(),
+            _ => format!("_{:02x}", b),
+        })
+        .collect();
+    PathBuf::from(dir).join(format!("{}.jsonl", name))
+}
+
+/// Writes the sizes of all `entries` (one JSON object per line) to `path`, replacing the
+/// previous snapshot only once the new one is complete.
+pub fn save_snapshot(path: &Path, root: &Path, entries: &SpillList<FileEntry>) -> Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    let temp = path.with_extension("jsonl.tmp");
+    let write = || -> Result<()> {
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        for entry in entries.iter() {
+            let entry = entry?;
+            let Ok(relative) = entry.path.strip_prefix(root) else {
+                continue;
+            };
+            serde_json::to_writer(&mut writer, &SnapshotEntry { path: relative.into(), size: entry.size })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    };
+    write().with_context(|| format!("Failed to write snapshot {:?}", path))
+}
+
+/// Compares `entries` of the current scan of `root` with the snapshot at `path`.
+/// Returns `None` when there is no snapshot yet.
+pub fn whats_new(path: &Path, root: &Path, entries: &SpillList<FileEntry>) -> Result<Option<WhatsNew>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read snapshot {:?}", path)),
+    };
+    let previous_scan = file.metadata().and_then(|m| m.modified()).ok();
+    let mut previous: HashMap<PathBuf, u64> = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read snapshot {:?}", path))?;
+        let entry: SnapshotEntry<'_> = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid snapshot {:?}", path))?;
+        previous.insert(entry.path.into_owned(), entry.size);
+    }
+
+    let mut files = Vec::new();
+    for entry in entries.iter() {
+        let entry = entry.context("Failed to read back entries spilled to disk")?;
+        let before = entry.path.strip_prefix(root).ok().and_then(|relative| previous.get(relative)).copied();
+        if entry.size > before.unwrap_or(0) {
+            files.push(FileGrowth { path: entry.path.clone(), before, after: entry.size });
+        }
+    }
+    files.sort_by(|a, b| b.delta().cmp(&a.delta()).then_with(|| a.path.cmp(&b.path)));
+    let total_growth = files.iter().map(FileGrowth::delta).sum();
+    Ok(Some(WhatsNew { previous_scan, files, total_growth }))
+}
@@ -0,0 +1,91 @@
+//! Keyboard controls for scans running on a terminal with `--hotkeys`: `p` pauses and
+//! resumes, `q` (or Esc / Ctrl+C, which raw mode no longer turns into a signal) cancels.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use tokio::sync::mpsc;
+
+use crate::progress::ProgressUpdate;
+use crate::scanner::ScanControl;
+
+/// How often the listener checks whether the scan has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Puts the terminal in raw mode and forwards key presses to a `ScanControl` until dropped.
+pub struct HotkeyListener {
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotkeyListener {
+    pub fn start(control: ScanControl, progress_tx: Option<mpsc::UnboundedSender<ProgressUpdate>>) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        #[cfg(unix)]
+        if let Err(e) = keep_output_processing() {
+            let _ = terminal::disable_raw_mode();
+            return Err(e);
+        }
+        let finished = Arc::new(AtomicBool::new(false));
+        let thread_finished = Arc::clone(&finished);
+        // A plain thread rather than `spawn_blocking`, so a pending poll never delays runtime shutdown
+        let thread = std::thread::spawn(move || {
+            while !thread_finished.load(Ordering::Relaxed) && !control.is_cancelled() {
+                match event::poll(POLL_INTERVAL) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(_) => break,
+                }
+                let Ok(Event::Key(key)) = event::read() else { continue };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('p') => {
+                        let paused = control.toggle_pause();
+                        if let Some(tx) = &progress_tx {
+                            let _ = tx.send(ProgressUpdate::Paused(paused));
+                        }
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => control.cancel(),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => control.cancel(),
+                    _ => {}
+                }
+            }
+        });
+        Ok(Self { finished, thread: Some(thread) })
+    }
+}
+
+/// Turns output processing back on after raw mode turned it off, so the `\n` of warnings
+/// printed during the scan still returns the cursor instead of staircasing the lines.
+#[cfg(unix)]
+fn keep_output_processing() -> io::Result<()> {
+    // SAFETY: `termios` is plain data, filled in by tcgetattr before it is read
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        termios.c_oflag |= libc::OPOST;
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+impl Drop for HotkeyListener {
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = terminal::disable_raw_mode();
+    }
+}
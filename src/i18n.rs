@@ -0,0 +1,103 @@
+//! Translations of the messages shown to people: the summary, warnings and progress
+//! output. Messages are written in English at their call sites with `t!`, and the English
+//! text is what each catalog is keyed by, like gettext's message ids; a catalog without a
+//! translation for a message falls back to the English one. The language comes from
+//! `--lang`, or else from `LC_ALL`, `LC_MESSAGES` or `LANG`.
+//!
+//! Placeholders are `{}` for the next argument or `{0}`, `{1}`, ... for a given one, so a
+//! translation can reorder them. Arguments are formatted before they are passed in.
+
+mod de;
+
+use std::fmt::{Display, Write};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    /// The language of the locale in `LC_ALL`, `LC_MESSAGES` or `LANG`, English for any
+    /// without a catalog.
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        match locale.get(..2) {
+            Some("de") => Language::De,
+            _ => Language::En,
+        }
+    }
+}
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+/// Picks the language for the rest of the process; only the first call has an effect, and
+/// only before any message was translated.
+pub fn set_language(language: Language) {
+    let _ = LANGUAGE.set(language);
+}
+
+pub fn language() -> Language {
+    *LANGUAGE.get_or_init(Language::from_env)
+}
+
+/// `message` in the current language.
+pub fn translate(message: &'static str) -> &'static str {
+    match language() {
+        Language::En => message,
+        Language::De => de::lookup(message).unwrap_or(message),
+    }
+}
+
+/// `template` with its placeholders replaced by `args`. `{{` and `}}` stand for braces.
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            filled.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let Some(end) = rest.find('}').filter(|_| rest.starts_with('{')) else {
+            filled.push_str(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        };
+        let index = match rest[1..end].parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => {
+                next += 1;
+                next - 1
+            }
+        };
+        if let Some(arg) = args.get(index) {
+            let _ = write!(filled, "{}", arg);
+        }
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Translates a message: `t!("Summary")` gives a `&'static str`, and with arguments,
+/// `t!("Owners ({})", count)`, the filled in `String`.
+#[macro_export]
+macro_rules! t {
+    ($message:literal) => {
+        $crate::i18n::translate($message)
+    };
+    ($message:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::translate($message), &[$(&$arg as &dyn ::std::fmt::Display),+])
+    };
+}
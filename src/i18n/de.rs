@@ -0,0 +1,302 @@
+//! German catalog. Table headers that read the same in German (UTF-8, LF, ACL, ...) are
+//! left out and fall back to the English text.
+
+pub(super) fn lookup(message: &str) -> Option<&'static str> {
+    Some(match message {
+        // Summary
+        "Summary" => "Zusammenfassung",
+        "Scan cancelled; the results below cover only part of the tree." => {
+            "Scan abgebrochen; die folgenden Ergebnisse umfassen nur einen Teil des Baums."
+        }
+        "Files" => "Dateien",
+        "Directories" => "Verzeichnisse",
+        "Size" => "Größe",
+        "Duration" => "Dauer",
+        "Volume used" => "Volume belegt",
+        "Device" => "Gerät",
+        "Serial number" => "Seriennummer",
+        "Disk capacity" => "Kapazität des Datenträgers",
+        "{}, spinning" => "{}, Festplatte",
+        "{}, solid state" => "{}, SSD",
+        "Volume free" => "Volume frei",
+        "Share of volume" => "Anteil am Volume",
+        "Errors" => "Fehler",
+        "Ignored errors" => "Ignorierte Fehler",
+        "Vanished during scan" => "Während des Scans verschwunden",
+        "Matching files ({})" => "Passende Dateien ({})",
+        "SIZE" => "GRÖSSE",
+        "PATH" => "PFAD",
+        "Warning: failed to read back matching files spilled to disk: {}" => {
+            "Warnung: auf die Festplatte ausgelagerte passende Dateien konnten nicht gelesen werden: {}"
+        }
+        "Repositories ({})" => "Repositorys ({})",
+        "TOTAL" => "GESAMT",
+        "WORKING TREE" => "ARBEITSBAUM",
+        "FILES" => "DATEIEN",
+        "Owners ({})" => "Besitzer ({})",
+        "OWNER" => "BESITZER",
+        "Categories ({})" => "Kategorien ({})",
+        "SHARE" => "ANTEIL",
+        "CATEGORY" => "KATEGORIE",
+        "Regenerable data: {} reclaimable if deleted, {} of user data" => {
+            "Wiederherstellbare Daten: {} durch Löschen freigebbar, {} Benutzerdaten"
+        }
+        "CLASS" => "KLASSE",
+        "user data" => "Benutzerdaten",
+        "Dedup estimate: {} of file data would store as {} ({}x)" => {
+            "Deduplizierung geschätzt: {} Dateidaten würden als {} gespeichert ({}x)"
+        }
+        "Files read" => "Gelesene Dateien",
+        "Average chunk size" => "Mittlere Blockgröße",
+        "Chunks" => "Blöcke",
+        "Distinct chunks" => "Verschiedene Blöcke",
+        "{} ({}%)" => "{} ({} %)",
+        "Saved by deduplication" => "Durch Deduplizierung gespart",
+        "Estimated cost on {}: {} per month" => "Geschätzte Kosten bei {}: {} pro Monat",
+        "Stored data" => "Gespeicherte Daten",
+        "Objects" => "Objekte",
+        "Storage per month" => "Speicher pro Monat",
+        "Uploading every object (once)" => "Hochladen aller Objekte (einmalig)",
+        "Downloading everything (once)" => "Herunterladen von allem (einmalig)",
+        "Local snapshots on {} ({})" => "Lokale Snapshots auf {} ({})",
+        "PURGEABLE" => "ENTFERNBAR",
+        "SNAPSHOT" => "SNAPSHOT",
+        "yes" => "ja",
+        "no" => "nein",
+        "  The volume takes up {} more than its files, much of it possibly held by these snapshots." => {
+            "  Das Volume belegt {} mehr als seine Dateien, vermutlich großteils durch diese Snapshots."
+        }
+        "  Deleting files frees no space these snapshots still hold; tmutil thinlocalsnapshots or deletelocalsnapshots releases it." => {
+            "  Löschen gibt keinen Platz frei, den diese Snapshots noch belegen; tmutil thinlocalsnapshots oder deletelocalsnapshots gibt ihn frei."
+        }
+        "Fragmentation: {} of {} files of {} or more in extents averaging under {}" => {
+            "Fragmentierung: {} von {} Dateien ab {} in Extents von im Schnitt unter {}"
+        }
+        "EXTENTS" => "EXTENTS",
+        "AVERAGE" => "DURCHSCHNITT",
+        "Subvolumes ({})" => "Subvolumes ({})",
+        "SUBVOLUME" => "SUBVOLUME",
+        "Skipped snapshot directories ({})" => "Übersprungene Snapshot-Verzeichnisse ({})",
+        "Cloud placeholders" => "Cloud-Platzhalter",
+        "{} files, {} in all" => "{} Dateien, zusammen {}",
+        "Size stored locally" => "Lokal gespeichert",
+        "Size on target: {} of file data in {} files" => "Größe auf dem Ziel: {} Dateidaten in {} Dateien",
+        "BLOCK SIZE" => "BLOCKGRÖSSE",
+        "ALLOCATED" => "BELEGT",
+        "OVERHEAD" => "MEHRBEDARF",
+        "FILES UNDER ONE BLOCK" => "DATEIEN UNTER EINEM BLOCK",
+        "{} of {} ({}%)" => "{} von {} ({} %)",
+        "Volume inodes used" => "Belegte Inodes des Volumes",
+        "Inodes in this tree" => "Inodes in diesem Baum",
+        "{} ({}% of the volume)" => "{} ({} % des Volumes)",
+        "Volume inodes" => "Inodes des Volumes",
+        "no fixed limit" => "keine feste Grenze",
+        "Fan-out: {} children per directory on average, at most {}" => {
+            "Verzweigung: im Schnitt {} Einträge pro Verzeichnis, höchstens {}"
+        }
+        "CHILDREN" => "EINTRÄGE",
+        "DIRECTORIES" => "VERZEICHNISSE",
+        "Directories with the most entries ({})" => "Verzeichnisse mit den meisten Einträgen ({})",
+        "ENTRIES" => "EINTRÄGE",
+        "DIRS" => "VERZ.",
+        "  {} directories hold {} or more entries, or 1% of the volume's inodes; expect slow listings and backups." => {
+            "  {} Verzeichnisse enthalten {} oder mehr Einträge oder 1 % der Inodes des Volumes; Auflistungen und Backups werden langsam sein."
+        }
+        "Largest files per extension ({} heaviest extensions)" => {
+            "Größte Dateien je Endung ({} Endungen mit dem meisten Platz)"
+        }
+        "FILE" => "DATEI",
+        "(no extension)" => "(keine Endung)",
+        "{} in {} files" => "{} in {} Dateien",
+        "All owners are within quota." => "Alle Besitzer liegen innerhalb ihres Kontingents.",
+        "Over quota ({})" => "Kontingent überschritten ({})",
+        "USED" => "BELEGT",
+        "LIMIT" => "GRENZE",
+        "OVER" => "DARÜBER",
+        "No directory grew beyond the alert threshold." => "Kein Verzeichnis ist über die Warnschwelle gewachsen.",
+        "Grown since baseline ({})" => "Gewachsen seit der Baseline ({})",
+        "BEFORE" => "VORHER",
+        "AFTER" => "NACHHER",
+        "GROWTH" => "WACHSTUM",
+        "DIRECTORY" => "VERZEICHNIS",
+        "What's new since the previous scan{}: {} in {} files" => "Neu seit dem letzten Scan{}: {} in {} Dateien",
+        "new" => "neu",
+        "  ... and {} more" => "  ... und {} weitere",
+        "Locked files ({})" => "Gesperrte Dateien ({})",
+        "REASON" => "GRUND",
+        "Text encodings ({} files sampled)" => "Textkodierungen ({} Dateien untersucht)",
+        "  ASCII {}, UTF-8 {}, UTF-16 {}, UTF-32 {}, legacy code pages {}; {} with a byte order mark" => {
+            "  ASCII {}, UTF-8 {}, UTF-16 {}, UTF-32 {}, alte Codepages {}; {} mit Byte-Order-Mark"
+        }
+        "LEGACY" => "ALT",
+        "  ... and {} more directories" => "  ... und {} weitere Verzeichnisse",
+        "Line endings ({} files)" => "Zeilenenden ({} Dateien)",
+        "  LF {}, CRLF {}, mixed {}, no line break {}" => "  LF {}, CRLF {}, gemischt {}, ohne Zeilenumbruch {}",
+        "MIXED" => "GEMISCHT",
+        "Immutable files ({})" => "Unveränderliche Dateien ({})",
+        "ATTRIBUTES" => "ATTRIBUTE",
+        "Explicit ACLs ({})" => "Explizite ACLs ({})",
+        "TYPE" => "TYP",
+        "dir" => "Verz.",
+        "file" => "Datei",
+        "SELinux types ({})" => "SELinux-Typen ({})",
+        "Mislabeled files ({})" => "Falsch beschriftete Dateien ({})",
+        "CONTEXT" => "KONTEXT",
+        "Skipped caches ({}): {} reclaimable" => "Übersprungene Caches ({}): {} freigebbar",
+        "Skipped mounts ({})" => "Übersprungene Einhängepunkte ({})",
+        "Trash ({}): {} reclaimable by emptying it" => "Papierkorb ({}): {} durch Leeren freigebbar",
+        "TRASH" => "PAPIERKORB",
+        "Cold data (unused for {} days): {} files, {}" => "Kalte Daten (seit {} Tagen unbenutzt): {} Dateien, {}",
+        "Warning: failed to read back cold directories spilled to disk: {}" => {
+            "Warnung: auf die Festplatte ausgelagerte kalte Verzeichnisse konnten nicht gelesen werden: {}"
+        }
+        "Changed during scan ({})" => "Während des Scans geändert ({})",
+        "Symlinks counted again ({} directories, {} files, {})" => {
+            "Erneut gezählte symbolische Links ({} Verzeichnisse, {} Dateien, {})"
+        }
+        "Symlinks to data counted elsewhere ({} directories, {} files, {})" => {
+            "Symbolische Links auf anderswo gezählte Daten ({} Verzeichnisse, {} Dateien, {})"
+        }
+        "COUNTED AT" => "GEZÄHLT UNTER",
+        "(directory)" => "(Verzeichnis)",
+        "VANISHED" => "VERSCHWUNDEN",
+        "Runtime metrics" => "Laufzeitmetriken",
+        "Tasks spawned" => "Gestartete Tasks",
+        "Peak tasks in flight" => "Höchstzahl laufender Tasks",
+        "Peak semaphore waiters" => "Höchstzahl wartender Tasks am Semaphor",
+        "Peak progress channel depth" => "Höchste Länge des Fortschrittskanals",
+        "Resource usage" => "Ressourcenverbrauch",
+        "Peak memory (RSS)" => "Höchster Speicherverbrauch (RSS)",
+        "CPU time (user)" => "CPU-Zeit (Benutzer)",
+        "CPU time (system)" => "CPU-Zeit (System)",
+        "Directory listings" => "Verzeichnisauflistungen",
+        "Metadata calls" => "Metadatenabfragen",
+        "Files opened" => "Geöffnete Dateien",
+        "Errors encountered ({})" => "Aufgetretene Fehler ({})",
+        "Timings (cumulative across tasks, wall clock {})" => "Zeiten (über alle Tasks summiert, Gesamtdauer {})",
+        "TIME" => "ZEIT",
+        "CALLS" => "AUFRUFE",
+        "Output writing" => "Ausgabe schreiben",
+        "Open file limit{} allows reading only {} directories at once instead of {}; raise it with `ulimit -n` for a faster scan." => {
+            "Das Limit offener Dateien{} erlaubt nur {} statt {} gleichzeitig gelesene Verzeichnisse; für einen schnelleren Scan mit `ulimit -n` erhöhen."
+        }
+        "{} directories were not read because the process ran out of file descriptors{}; raise the limit with `ulimit -n` and scan again." => {
+            "{} Verzeichnisse wurden nicht gelesen, weil dem Prozess die Dateideskriptoren ausgingen{}; das Limit mit `ulimit -n` erhöhen und erneut scannen."
+        }
+        "{} entries vanished while the scan ran; {} more listings of their directories found {} new entries, but the totals may still mix the tree from before and after the changes." => {
+            "{} Einträge sind während des Scans verschwunden; {} weitere Auflistungen ihrer Verzeichnisse fanden {} neue Einträge, doch die Summen können den Baum vor und nach den Änderungen noch vermischen."
+        }
+        "{} entries vanished while the scan ran, so the totals mix the tree from before and after the changes; --rescan-changed N lists the changing directories again." => {
+            "{} Einträge sind während des Scans verschwunden, daher vermischen die Summen den Baum vor und nach den Änderungen; --rescan-changed N listet die sich ändernden Verzeichnisse erneut auf."
+        }
+
+        // Progress
+        "Scanning..." => "Scanne...",
+        "Paused - press p to resume, q to cancel" => "Pausiert - p zum Fortsetzen, q zum Abbrechen",
+        "Scanning... Items: {}, Size: {}, Dirs queued: {}{}" => "Scanne... Einträge: {}, Größe: {}, Verzeichnisse in der Warteschlange: {}{}",
+        " (errors encountered)" => " (Fehler aufgetreten)",
+        "Scan finished! Total Items: {}, Total Size: {}" => "Scan beendet! Einträge gesamt: {}, Größe gesamt: {}",
+        "Items: {}, Size: {}" => "Einträge: {}, Größe: {}",
+        "Items: {}, Size: {} (done)" => "Einträge: {}, Größe: {} (fertig)",
+        "Largest files so far:" => "Bisher größte Dateien:",
+        "Largest directories so far:" => "Bisher größte Verzeichnisse:",
+
+        // Warnings
+        "Warning: {}" => "Warnung: {}",
+        "Warning: progress socket {} failed: {}" => "Warnung: Fortschritts-Socket {} fehlgeschlagen: {}",
+        "Warning: Cannot read hotkeys from the terminal: {}" => "Warnung: Tastenkürzel können nicht vom Terminal gelesen werden: {}",
+        "Warning: cannot read the mount table ({}); no filesystem types are skipped" => {
+            "Warnung: die Einhängetabelle kann nicht gelesen werden ({}); es werden keine Dateisystemtypen übersprungen"
+        }
+        "Warning: cannot start scan threads ({}); scanning on one thread" => {
+            "Warnung: Scan-Threads können nicht gestartet werden ({}); es wird mit einem Thread gescannt"
+        }
+        "Warning: Invalid regex pattern '{}': {}. Proceeding without pattern matching." => {
+            "Warnung: Ungültiger regulärer Ausdruck '{}': {}. Es wird ohne Mustersuche fortgefahren."
+        }
+        "Warning: Cannot connect to {} log target: {}. Continuing without it." => {
+            "Warnung: Verbindung zum Log-Ziel {} nicht möglich: {}. Es wird ohne fortgefahren."
+        }
+        "Warning: {} files could not be indexed: {}" => "Warnung: {} Dateien konnten nicht indiziert werden: {}",
+        "unknown error" => "unbekannter Fehler",
+        "Warning: {} messages could not be published: {}" => "Warnung: {} Nachrichten konnten nicht veröffentlicht werden: {}",
+        "Warning: --interactive requires a terminal; skipping file selection." => {
+            "Warnung: --interactive braucht ein Terminal; die Dateiauswahl wird übersprungen."
+        }
+        "Warning: Failed to write to system log: {}" => "Warnung: Schreiben ins Systemprotokoll fehlgeschlagen: {}",
+
+        // Scan and sampling results
+        "Sampled {}% ({} files stat'ed in {} directories)" => "{} % untersucht ({} Dateien in {} Verzeichnissen abgefragt)",
+        "Estimated total size: {} ± {} (95% confidence)" => "Geschätzte Gesamtgröße: {} ± {} (95 % Konfidenz)",
+        "Estimated files: {} ± {}" => "Geschätzte Dateien: {} ± {}",
+        "Estimated directories: {} ± {}" => "Geschätzte Verzeichnisse: {} ± {}",
+        "Scan duration: {}" => "Scandauer: {}",
+        "Errors encountered: {}" => "Aufgetretene Fehler: {}",
+        "An error occurred during sampling: {}" => "Beim Stichprobenscan ist ein Fehler aufgetreten: {}",
+        "An error occurred during scanning: {}" => "Beim Scannen ist ein Fehler aufgetreten: {}",
+        "Wrote {} errors to {}" => "{} Fehler nach {} geschrieben",
+        "Wrote {} files to {} ({})" => "{} Dateien nach {} geschrieben ({})",
+        "Warning: baseline {} was cut off while it was written; using the part that is complete" => "Warnung: Die Baseline {} wurde beim Schreiben abgeschnitten; verwendet wird der vollständige Teil",
+        "Invalid regex pattern '{}': {}" => "Ungültiges Regex-Muster '{}': {}",
+        "Settings for a scan of {}" => "Einstellungen für einen Scan von {}",
+        "How the tree is walked:" => "Wie der Baum durchlaufen wird:",
+        "What is counted (config hash {}):" => "Was gezählt wird (Konfigurations-Hash {}):",
+        "From the environment:" => "Aus der Umgebung:",
+        "Read:" => "Gelesen:",
+        "no files besides the tree" => "keine Dateien außer dem Baum",
+        "Written:" => "Geschrieben:",
+        "standard output only" => "nur die Standardausgabe",
+        "{} {} can't be written: {}" => "{} {} kann nicht geschrieben werden: {}",
+        "it is a directory" => "es ist ein Verzeichnis",
+        "it is not a directory" => "es ist kein Verzeichnis",
+        "it exists and is not a socket" => "es existiert und ist kein Socket",
+        "it is not a named pipe such as {}" => "es ist keine Named Pipe wie {}",
+        "directory {} doesn't exist" => "Verzeichnis {} existiert nicht",
+        "Configuration OK; nothing was scanned" => "Konfiguration in Ordnung; es wurde nichts gescannt",
+        "Problem: {}" => "Problem: {}",
+        "Indexed {} files into Elasticsearch" => "{} Dateien in Elasticsearch indiziert",
+        "Published {} messages to {}" => "{} Nachrichten an {} veröffentlicht",
+        "No earlier snapshot of {} yet; --whats-new will compare against this scan next time." => {
+            "Noch kein früherer Snapshot von {}; --whats-new vergleicht beim nächsten Mal mit diesem Scan."
+        }
+        "Would keep {} of {} record(s) and remove {} snapshot(s): {} -> {} (dry run)" => {
+            "Würde {} von {} Einträgen behalten und {} Snapshot(s) entfernen: {} -> {} (Probelauf)"
+        }
+        "Kept {} of {} record(s) and removed {} snapshot(s): {} -> {}" => {
+            "{} von {} Einträgen behalten und {} Snapshot(s) entfernt: {} -> {}"
+        }
+        "Report written to {}" => "Bericht nach {} geschrieben",
+        "Exclude file written to {} ({} patterns, {})" => "Ausschlussdatei nach {} geschrieben ({} Muster, {})",
+        "Directory {} grew {}% since the baseline: {} -> {}" => "Verzeichnis {} ist seit der Baseline um {} % gewachsen: {} -> {}",
+        "Alert rule {} breached: {}" => "Alarmregel {} verletzt: {}",
+        "{}: no alert rule breached ({} in {} files); scanning again in {}s" => "{}: keine Alarmregel verletzt ({} in {} Dateien); nächster Scan in {} s",
+        "Grown {}: +{} ({} -> {})" => "Gewachsen {}: +{} ({} -> {})",
+        "New {}: +{}" => "Neu {}: +{}",
+
+        // Actions
+        "Would change {}: {}" => "Würde {} ändern: {}",
+        "Would skip {}: {}" => "Würde {} überspringen: {}",
+        "{}: {} of {} matching file(s) would change (dry run)" => "{}: {} von {} passenden Dateien würden sich ändern (Probelauf)",
+        "{}: updated {} of {} matching file(s)" => "{}: {} von {} passenden Dateien geändert",
+        "{} file(s) would be renamed back (dry run)" => "{} Datei(en) würden zurückbenannt (Probelauf)",
+        "Renamed {} file(s) back" => "{} Datei(en) zurückbenannt",
+        "Would rename {} back to {}" => "Würde {} wieder in {} umbenennen",
+        "Would empty {}: {} item(s), {}" => "Würde {} leeren: {} Einträge, {}",
+        "--empty-trash: {} trash director(ies), {} would be freed (dry run)" => {
+            "--empty-trash: {} Papierkorb-Verzeichnis(se), {} würden frei (Probelauf)"
+        }
+        "--empty-trash: freed {} in {} file(s)" => "--empty-trash: {} in {} Datei(en) freigegeben",
+        "Select files (space to toggle, enter to confirm)" => "Dateien auswählen (Leertaste zum Umschalten, Enter zum Bestätigen)",
+        "No files selected." => "Keine Dateien ausgewählt.",
+        "Action for {} file(s), {}" => "Aktion für {} Datei(en), {}",
+        "Archive path" => "Archivpfad",
+        "{} {} file(s)?" => "{}: {} Datei(en)?",
+        "Aborted." => "Abgebrochen.",
+        "Processed {} file(s), {}" => "{} Datei(en) verarbeitet, {}",
+        "Delete" => "Löschen",
+        "Move to trash" => "In den Papierkorb verschieben",
+        "Archive (.tar.gz)" => "Archivieren (.tar.gz)",
+        "Do nothing" => "Nichts tun",
+        _ => return None,
+    })
+}
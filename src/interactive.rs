@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
+use dialoguer::theme::ColorfulTheme;
+
+use disk_scanner::formatting::Formatter;
+use disk_scanner::{i18n, t};
+
+use crate::actions::{self, FileAction, ProtectedPaths};
+use crate::scanner::MatchedFile;
+
+const ACTION_LABELS: [&str; 4] = ["Delete", "Move to trash", "Archive (.tar.gz)", "Do nothing"];
+
+/// Lets the user pick which matched files to act on and what to do with them. Files in
+/// `protected` places are refused.
+pub fn pick_and_apply(matching_files: &[MatchedFile], protected: Option<&ProtectedPaths>, formatter: Formatter) -> Result<()> {
+    if matching_files.is_empty() {
+        return Ok(());
+    }
+
+    let theme = ColorfulTheme::default();
+    let labels = ACTION_LABELS.map(i18n::translate);
+
+    let mut sorted: Vec<&MatchedFile> = matching_files.iter().collect();
+    sorted.sort_by_key(|f| std::cmp::Reverse(f.size));
+
+    let items: Vec<String> = sorted.iter()
+        .map(|f| format!("{:>10}  {}", formatter.size(f.size), f.path.display()))
+        .collect();
+
+    let picked = MultiSelect::with_theme(&theme)
+        .with_prompt(t!("Select files (space to toggle, enter to confirm)"))
+        .items(&items)
+        .interact()?;
+
+    if picked.is_empty() {
+        println!("{}", t!("No files selected."));
+        return Ok(());
+    }
+
+    let selected: Vec<MatchedFile> = picked.iter().map(|&i| sorted[i].clone()).collect();
+    let selected_size: u64 = selected.iter().map(|f| f.size).sum();
+
+    let choice = Select::with_theme(&theme)
+        .with_prompt(t!("Action for {} file(s), {}", selected.len(), formatter.size(selected_size)))
+        .items(&labels)
+        .default(labels.len() - 1)
+        .interact()?;
+
+    let action = match choice {
+        0 => FileAction::Delete,
+        1 => FileAction::Trash,
+        2 => {
+            let destination: String = Input::with_theme(&theme)
+                .with_prompt(t!("Archive path"))
+                .default("disk_scanner_archive.tar.gz".to_string())
+                .interact_text()?;
+            FileAction::Archive { destination: PathBuf::from(destination) }
+        }
+        _ => return Ok(()),
+    };
+
+    let confirmed = Confirm::with_theme(&theme)
+        .with_prompt(t!("{} {} file(s)?", labels[choice], selected.len()))
+        .default(false)
+        .interact()?;
+    if !confirmed {
+        println!("{}", t!("Aborted."));
+        return Ok(());
+    }
+
+    let summary = actions::apply_action(&action, &selected, protected);
+    println!("{}", t!("Processed {} file(s), {}", summary.processed_files, formatter.size(summary.processed_bytes)));
+    for err in &summary.errors {
+        eprintln!("  - {}", err);
+    }
+    Ok(())
+}
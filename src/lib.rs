@@ -0,0 +1,39 @@
+//! Disk usage scanning engine behind the `disk_scanner` CLI.
+//!
+//! With the `cdylib` feature the crate also exports a C ABI (see [`ffi`]) so other
+//! languages can embed the engine without spawning the CLI; the `python` feature
+//! builds it as the `disk_scanner` Python extension module instead.
+
+pub mod scanner;
+pub mod progress;
+#[cfg(feature = "native")]
+pub mod progress_socket;
+#[cfg(feature = "native")]
+pub mod hotkeys;
+pub mod metrics;
+pub mod timings;
+pub mod export;
+pub mod baseline;
+pub mod path_tree;
+pub mod history;
+pub mod owners;
+pub mod tags;
+pub mod fd_limit;
+pub mod cpu_limit;
+pub mod resources;
+pub mod quota;
+pub mod alerts;
+pub mod categories;
+pub mod regenerable;
+pub mod excludes;
+pub mod cost;
+pub mod units;
+pub mod formatting;
+pub mod i18n;
+pub mod volume;
+#[cfg(feature = "native")]
+pub mod embed;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
@@ -1,63 +1,1082 @@
+mod check_config;
 mod cli;
-mod scanner;
-mod progress;
+#[cfg(feature = "native")]
+mod actions;
+#[cfg(feature = "native")]
+mod interactive;
+#[cfg(feature = "native")]
+mod sampling;
+#[cfg(feature = "native")]
+mod fleet;
+#[cfg(feature = "native")]
+mod error_log;
+#[cfg(feature = "elasticsearch")]
+mod elastic;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+mod event_stream;
+mod summary;
+mod system_log;
+mod trend;
+#[cfg(feature = "self-update")]
+mod self_update;
 
-use scanner::ScannerConfig;
-use anyhow::Result;
+use disk_scanner::{alerts, baseline, excludes, export, history, quota, scanner};
+use disk_scanner::categories::Categories;
+use disk_scanner::regenerable::Regenerable;
+use disk_scanner::cost::CostModel;
+use disk_scanner::formatting::{Formatter, Separators, SizeUnits};
+use disk_scanner::i18n::{self, Language};
+use disk_scanner::t;
+use disk_scanner::tags::{self, TagDatabase, TagFilter};
+use export::ExportFormat;
+use baseline::{Baseline, DirectoryGrowth};
+use history::FileGrowth;
+use scanner::{ExtensionFilter, IgnoredErrors, ScannerConfig};
+use quota::{QuotaConfig, QuotaViolation};
+use alerts::{AlertRule, Breach};
+use scanner::ScanResult;
+use summary::SummaryOptions;
+use system_log::{Priority, SystemLogger};
+use anyhow::{Context, Result};
 use regex::Regex;
+#[cfg(feature = "native")]
+use std::io::IsTerminal;
+#[cfg(feature = "native")]
+use {actions::{FileAction, ProtectedPaths, Relocation}, scanner::{MatchedFile, SpillList}, std::path::PathBuf};
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
+/// Exit code used when at least one owner exceeds their quota.
+const EXIT_OVER_QUOTA: i32 = 3;
+
+/// Exit code used when a directory grew beyond `--alert-growth` since the baseline.
+const EXIT_GROWTH_ALERT: i32 = 4;
+
+/// Exit code used when the scan breached an `--alert-if` rule.
+const EXIT_RULE_ALERT: i32 = 5;
+
+/// Scan errors beyond this many are summarized in a single system log line.
+const MAX_LOGGED_ERRORS: usize = 100;
+
+fn main() -> Result<(), anyhow::Error> {
     let cli_args = cli::parse_args();
+    runtime(&cli_args)?.block_on(run(cli_args))
+}
+
+/// A tokio runtime with one worker thread per core `--cpu-limit` allows, or per available
+/// core. With `--cpu-limit` the blocking pool is also kept to one thread per walker task,
+/// instead of tokio's 512. Without the `native` feature everything runs on the calling thread.
+fn runtime(cli_args: &cli::CliArgs) -> std::io::Result<tokio::runtime::Runtime> {
+    // A serial scan runs on the calling thread, so it needs no workers
+    #[cfg(feature = "native")]
+    if cli_args.serial {
+        return tokio::runtime::Builder::new_current_thread().enable_all().build();
+    }
+    #[cfg(feature = "native")]
+    {
+        let cpus = cli_args.cpu_limit.map_or_else(disk_scanner::cpu_limit::available_cpus, NonZeroUsize::get);
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.worker_threads(cpus).enable_all();
+        if let Some(limit) = cli_args.cpu_limit {
+            builder.max_blocking_threads(cli_args.threads.unwrap_or(limit.get() * 2).max(1));
+        }
+        builder.build()
+    }
+    #[cfg(not(feature = "native"))]
+    {
+        let _ = cli_args;
+        tokio::runtime::Builder::new_current_thread().enable_all().build()
+    }
+}
 
-    let default_concurrent_tasks = num_cpus::get() * 2;
-    let max_concurrent_tasks = cli_args.threads.unwrap_or(default_concurrent_tasks);
+async fn run(cli_args: cli::CliArgs) -> Result<(), anyhow::Error> {
+    #[cfg(feature = "console")]
+    console_subscriber::init();
 
+    i18n::set_language(cli_args.lang.unwrap_or_else(Language::from_env));
+    match &cli_args.command {
+        Some(cli::Command::Trend(args)) => return trend::run(args),
+        Some(cli::Command::History(args)) => return match &args.command {
+            cli::HistoryCommand::Prune(args) => prune_history(args, formatter(&cli_args)),
+        },
+        Some(cli::Command::Snapshot(args)) => return convert_snapshot(&args.command, formatter(&cli_args)),
+        #[cfg(feature = "native")]
+        Some(cli::Command::UndoRename(args)) => return undo_rename(args),
+        #[cfg(feature = "native")]
+        Some(cli::Command::Serve(args)) => return fleet::serve(args).await,
+        #[cfg(feature = "native")]
+        Some(cli::Command::Agent(args)) => return fleet::run_agent(args).await,
+        #[cfg(feature = "encrypt")]
+        Some(cli::Command::Decrypt(args)) => return decrypt(args),
+        #[cfg(feature = "self-update")]
+        Some(cli::Command::SelfUpdate(args)) => return self_update::run(args),
+        Some(cli::Command::CheckConfig(_)) => unreachable!("parse_args turns check-config into the scan it checks"),
+        Some(cli::Command::Completions(args)) => {
+            clap_complete::generate(args.shell, &mut cli::command(), "disk_scanner", &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(cli::Command::Manpage) => {
+            clap_mangen::Man::new(cli::command()).render(&mut std::io::stdout())?;
+            return Ok(());
+        }
+        None => {}
+    }
+    let Some(target_path) = cli_args.path.as_deref() else {
+        unreachable!("clap requires a path unless a subcommand is given");
+    };
+    let formatter = formatter(&cli_args);
+    if cli_args.single_dir_summary && !cli_args.check_only {
+        return single_dir_summary(&cli_args, target_path, formatter).await;
+    }
+
+    // What a scan only warns about, `check-config` reports as problems
+    let mut problems = Vec::new();
     let file_pattern_regex: Option<Regex> = match cli_args.pattern {
         Some(ref pattern_str) => match Regex::new(pattern_str) {
             Ok(re) => Some(re),
+            Err(e) if cli_args.check_only => {
+                problems.push(t!("Invalid regex pattern '{}': {}", pattern_str, e));
+                None
+            }
+            Err(e) => {
+                eprintln!("{}", t!("Warning: Invalid regex pattern '{}': {}. Proceeding without pattern matching.", pattern_str, e));
+                None
+            }
+        },
+        None => None,
+    };
+
+    // check-config connects on its own, listing the log among the outputs
+    let system_logger = match cli_args.log_target {
+        Some(target) if !cli_args.check_only => match SystemLogger::connect(target) {
+            Ok(logger) => Some(logger),
             Err(e) => {
-                eprintln!("Warning: Invalid regex pattern '{}': {}. Proceeding without pattern matching.", pattern_str, e);
+                eprintln!("{}", t!("Warning: Cannot connect to {} log target: {}. Continuing without it.", format!("{:?}", target), e));
                 None
             }
         },
+        _ => None,
+    };
+
+    let quotas = cli_args.quota_file.as_deref().map(QuotaConfig::load).transpose()?;
+    let cost_model = match &cli_args.cost_model_file {
+        Some(path) => Some(CostModel::load(path, cli_args.cost_model)?),
+        None => cli_args.cost_model.map(CostModel::preset),
+    };
+
+    let categories = match &cli_args.category_file {
+        Some(path) => Some(Categories::load(path)?),
+        None => cli_args.by_category.then(Categories::builtin),
+    };
+    let regenerable = match &cli_args.regenerable_rules {
+        Some(path) => Some(Regenerable::load(path)?),
+        None => cli_args.regenerable.then(Regenerable::builtin),
+    };
+
+    let load_baseline = if cli_args.export_delta { Baseline::load_with_files } else { Baseline::load };
+    let baseline = cli_args.baseline.as_deref().map(load_baseline).transpose()?.map(Arc::new);
+    if let (Some(path), Some(baseline)) = (&cli_args.baseline, &baseline)
+        && baseline.truncated
+    {
+        eprintln!("{}", t!("Warning: baseline {} was cut off while it was written; using the part that is complete", format!("{:?}", path)));
+    }
+
+    let exporting = cli_args.json || cli_args.output.is_some();
+    if matches!(cli_args.format, ExportFormat::Tree | ExportFormat::Markdown | ExportFormat::Xml | ExportFormat::Snapshot) && cli_args.chunk_size.is_some() {
+        anyhow::bail!("--chunk-size only applies to --format json and parquet");
+    }
+    if cli_args.format != ExportFormat::Tree && (cli_args.dirs_only || cli_args.depth.is_some()) {
+        anyhow::bail!("--dirs-only and --depth only apply to --format tree");
+    }
+    if cli_args.export_delta && (!exporting || cli_args.format != ExportFormat::Json) {
+        anyhow::bail!("--export-delta only applies to --json and --output with --format json");
+    }
+    if cli_args.emit_excludes.is_some() && cli_args.excludes_file.is_none() && cli_args.json {
+        anyhow::bail!("--emit-excludes needs --excludes-file with --json, which has standard output to itself");
+    }
+    // Set up before the scan, so a bad passphrase file doesn't cost a whole scan
+    #[cfg(feature = "encrypt")]
+    let encryption = match &cli_args.passphrase_file {
+        Some(path) => Some(export::Encryption::new(&read_passphrase(path)?).context("Failed to set up encryption")?),
         None => None,
     };
+    #[cfg(not(feature = "encrypt"))]
+    let encryption: Option<export::Encryption> = None;
+
+    let mut config_builder = ScannerConfig::builder(target_path)
+        .engine(cli_args.engine)
+        .serial(cli_args.serial)
+        .per_device(cli_args.per_device)
+        .largest_first(cli_args.largest_first)
+        .recent_first(cli_args.recent_first)
+        .size_hints(baseline.clone().filter(|_| cli_args.largest_first))
+        .rescan_changed(cli_args.rescan_changed)
+        .follow_symlinks(cli_args.follow_symlinks)
+        .count_links_separately(cli_args.count_links_separately)
+        .include_hidden(!cli_args.no_hidden)
+        .progress_updates(!cli_args.quiet && !cli_args.json)
+        .verbose(cli_args.verbose > 0)
+        .file_pattern(file_pattern_regex)
+        .by_repo(cli_args.by_repo)
+        .debug_metrics(cli_args.debug_metrics)
+        .timings(cli_args.timings)
+        .resource_usage(cli_args.resource_usage)
+        .memory_limit(cli_args.memory_limit)
+        .collect_entries(
+            exporting
+                || cli_args.alert_growth.is_some()
+                || cli_args.alert_if.iter().any(AlertRule::needs_entries)
+                || cli_args.history.is_some()
+                || cli_args.emit_excludes.is_some(),
+        )
+        .cold_data_age(cli_args.cold_data)
+        .by_owner(cli_args.by_owner || quotas.is_some())
+        .categories(categories)
+        .regenerable(regenerable)
+        .top_per_extension(cli_args.top_per_ext)
+        .inode_report(cli_args.inode_report)
+        .fan_out(cli_args.fan_out)
+        .subvolumes(cli_args.by_subvolume)
+        .device_info(cli_args.device_info)
+        .fragmentation(cli_args.fragmentation)
+        .detect_locked_files(cli_args.locked_files)
+        .acls(cli_args.acls)
+        .detect_immutable(cli_args.immutable)
+        .sample_encodings(cli_args.encodings)
+        .line_endings(cli_args.line_endings)
+        .dedup_chunk_size(cli_args.dedup_estimate)
+        .target_block_sizes(cli_args.model_target_blocksize.clone())
+        .directory_labels(cli_args.dir_labels)
+        .selinux(cli_args.selinux.then(|| cli_args.selinux_expect.clone()))
+        .extensions(
+            (!cli_args.ext.is_empty() || !cli_args.not_ext.is_empty())
+                .then(|| ExtensionFilter::new(&cli_args.ext, &cli_args.not_ext)),
+        )
+        .permissions(permission_filter(&cli_args)?)
+        .tags(tag_filter(&cli_args)?)
+        .ignore_errors_under(
+            (!cli_args.ignore_errors_under.is_empty()).then(|| IgnoredErrors::new(&cli_args.ignore_errors_under)),
+        )
+        .skip_caches(cli_args.skip_caches)
+        .skip_snapshots(cli_args.skip_snapshots)
+        .skip_fs_types(cli_args.skip_fs_type.clone())
+        .prune(cli_args.prune_if.clone())
+        .filter_rules(filter_rules(&cli_args)?);
+    #[cfg(feature = "native")]
+    {
+        config_builder = config_builder
+            .hotkeys(cli_args.hotkeys && !cli_args.quiet && !cli_args.json && cli_args.verbose == 0)
+            .progress_socket(cli_args.progress_socket.clone())
+            .live_top(cli_args.live_top);
+    }
+    if let Some(threads) = cli_args.threads.or(cli_args.cpu_limit.map(|cpus| cpus.get() * 2)) {
+        config_builder = config_builder.max_concurrent_tasks(threads);
+    }
+    let scanner_config = config_builder.build()?;
+    if cli_args.check_only {
+        return check_config::report(&cli_args, &scanner_config, problems);
+    }
+
+    if cli_args.verbose >= 2 && !cli_args.json {
+        println!("\nInitialized ScannerConfig: {:#?}", scanner_config);
+    }
 
-    let scanner_config = ScannerConfig {
-        target_path: cli_args.path.clone(),
-        max_concurrent_tasks,
-        follow_symlinks: cli_args.follow_symlinks,
-        include_hidden: !cli_args.no_hidden,
-        progress_updates: !cli_args.quiet && !cli_args.json,
-        verbose: cli_args.verbose,
-        file_pattern: file_pattern_regex,
+    #[cfg(feature = "native")]
+    if let Some(fraction) = cli_args.sample {
+        match sampling::estimate_size(&scanner_config, fraction).await {
+            Ok(estimate) => {
+                println!(
+                    "\n{}",
+                    t!(
+                        "Sampled {}% ({} files stat'ed in {} directories)",
+                        format!("{:.1}", estimate.fraction * 100.0),
+                        estimate.sampled_files,
+                        estimate.visited_directories
+                    )
+                );
+                println!(
+                    "{}",
+                    t!(
+                        "Estimated total size: {} ± {} (95% confidence)",
+                        formatter.size(estimate.total_size as u64),
+                        formatter.size(estimate.size_margin as u64)
+                    )
+                );
+                println!(
+                    "{}",
+                    t!("Estimated files: {} ± {}", format!("{:.0}", estimate.total_files), format!("{:.0}", estimate.files_margin))
+                );
+                println!(
+                    "{}",
+                    t!(
+                        "Estimated directories: {} ± {}",
+                        format!("{:.0}", estimate.total_directories),
+                        format!("{:.0}", estimate.directories_margin)
+                    )
+                );
+                println!("{}", t!("Scan duration: {}", format!("{:?}", estimate.scan_duration)));
+                if estimate.errors > 0 {
+                    println!("{}", t!("Errors encountered: {}", estimate.errors));
+                }
+            }
+            Err(e) => {
+                eprintln!("\n{}", t!("An error occurred during sampling: {}", e));
+            }
+        }
+        return Ok(());
+    }
+
+    // `--watch` reports the scan that breached a rule rather than scanning again, so the
+    // sinks below only follow a scan of their own
+    let watched = match cli_args.watch {
+        Some(interval) => Some(
+            watch_until_breached(&scanner_config, &cli_args.alert_if, Duration::from_secs(interval), cli_args.quiet, formatter)
+                .await?,
+        ),
+        None => None,
     };
 
-    println!("\nInitialized ScannerConfig: {:#?}", scanner_config);
+    #[cfg(feature = "native")]
+    #[cfg_attr(not(any(feature = "elasticsearch", feature = "kafka", feature = "nats")), allow(unused_mut))]
+    let mut entry_sinks = Vec::new();
+    #[cfg(feature = "elasticsearch")]
+    let indexer = cli_args.es_url.as_deref().filter(|_| watched.is_none()).map(|url| {
+        let (tx, indexer) = elastic::start(url, &scanner_config.target_path);
+        entry_sinks.push(tx);
+        indexer
+    });
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let event_stream = match cli_args.stream_to.as_ref().filter(|_| watched.is_none()) {
+        Some(target) => {
+            let (tx, stream) = event_stream::EventStream::connect(target, &scanner_config.target_path).await?;
+            entry_sinks.push(tx);
+            Some(stream)
+        }
+        None => None,
+    };
+    // Places destructive actions keep out of, unless forced
+    #[cfg(feature = "native")]
+    let protected = (!cli_args.force).then(|| {
+        let mut protected = ProtectedPaths::default();
+        for entry in &cli_args.protect {
+            protected.add(entry);
+        }
+        protected
+    });
+    #[cfg(feature = "native")]
+    let (error_tx, error_log) = match cli_args.errors_out.as_ref().filter(|_| watched.is_none()) {
+        Some(path) => error_log::start(path).map(|(tx, log)| (Some(tx), Some(log)))?,
+        None => (None, None),
+    };
+    #[cfg(feature = "native")]
+    let hooks = scanner::ScanHooks { entries: tee_entries(entry_sinks), errors: error_tx, ..Default::default() };
+    #[cfg(feature = "native")]
+    let (scan, watched_breaches) = match watched {
+        Some((scan_result, breaches)) => (Ok(scan_result), Some(breaches)),
+        None => (scanner::run_scan_with(&scanner_config, hooks).await, None),
+    };
+    #[cfg(feature = "native")]
+    if let (Some(error_log), Some(path)) = (error_log, &cli_args.errors_out) {
+        match error_log.finish() {
+            Ok(written) if !cli_args.quiet && !cli_args.json => {
+                eprintln!("{}", t!("Wrote {} errors to {}", written, format!("{:?}", path)));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", t!("Warning: {}", format!("{:#}", e))),
+        }
+    }
+    #[cfg(feature = "elasticsearch")]
+    if let Some(indexer) = indexer {
+        let stats = indexer.finish();
+        if !cli_args.quiet && !cli_args.json {
+            eprintln!("{}", t!("Indexed {} files into Elasticsearch", stats.indexed));
+        }
+        if stats.failed > 0 {
+            eprintln!(
+                "{}",
+                t!(
+                    "Warning: {} files could not be indexed: {}",
+                    stats.failed,
+                    stats.first_error.as_deref().unwrap_or(t!("unknown error")),
+                )
+            );
+        }
+    }
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    if let Some(stream) = event_stream {
+        let stats = stream.finish(scan.as_ref().ok()).await;
+        if !cli_args.quiet && !cli_args.json {
+            eprintln!("{}", t!("Published {} messages to {}", stats.published, stream_name(cli_args.stream_to.as_ref())));
+        }
+        if let Some(error) = &stats.first_error {
+            eprintln!("{}", t!("Warning: {} messages could not be published: {}", stats.failed, error));
+        }
+    }
+    #[cfg(not(feature = "native"))]
+    let (scan, watched_breaches) = match watched {
+        Some((scan_result, breaches)) => (Ok(scan_result), Some(breaches)),
+        None => (scanner::run_scan_sync(&scanner_config), None),
+    };
 
-    match scanner::run_scan(&scanner_config).await {
+    match scan {
         Ok(scan_result) => {
-            println!("\nTotal files: {}", scan_result.total_files);
-            println!("Total directories: {}", scan_result.total_directories);
-            println!("Scan duration: {:?}", scan_result.scan_duration);
-            if !scan_result.matching_files.is_empty() {
-                println!("Matching files ({}):", scan_result.matching_files.len());
-                for f_path in scan_result.matching_files {
-                    println!("  {:?}", f_path);
+            let output_start = Instant::now();
+            let quota_violations = quotas.as_ref()
+                .map(|q| q.violations(&scan_result.owners))
+                .unwrap_or_default();
+            let growth = baseline.as_ref().zip(cli_args.alert_growth)
+                .map(|(b, threshold)| {
+                    Baseline::from_entries(&scanner_config.target_path, &scan_result.entries)
+                        .map(|current| b.growth(&scanner_config.target_path, &current, threshold))
+                })
+                .transpose()
+                .context("Failed to read back entries spilled to disk")?;
+            let breaches = match watched_breaches {
+                Some(breaches) => breaches,
+                None => alerts::check(&cli_args.alert_if, &scanner_config.target_path, &scan_result)
+                    .context("Failed to read back entries spilled to disk")?,
+            };
+            for breach in &breaches {
+                eprintln!("{}", rule_alert(breach, formatter));
+            }
+            let mut whats_new = None;
+            if let Some(path) = &cli_args.history {
+                let root = &scanner_config.target_path;
+                let snapshot = history::snapshot_path(path, root);
+                if cli_args.whats_new {
+                    match history::whats_new(&snapshot, root, &scan_result.entries) {
+                        Ok(Some(changes)) => whats_new = Some(changes),
+                        Ok(None) => eprintln!(
+                            "{}",
+                            t!("No earlier snapshot of {} yet; --whats-new will compare against this scan next time.", format!("{:?}", root))
+                        ),
+                        Err(e) => eprintln!("{}", t!("Warning: {}", format!("{:#}", e))),
+                    }
+                }
+                // A cancelled scan would show up as a sudden drop in the trend
+                if !scan_result.cancelled {
+                    let saved = history::HistoryRecord::from_scan(root, &scan_result)
+                        .context("Failed to read back entries spilled to disk")
+                        .and_then(|record| history::append(path, &record))
+                        .and_then(|()| {
+                        if cli_args.whats_new { history::save_snapshot(&snapshot, root, &scan_result.entries) } else { Ok(()) }
+                    });
+                    if let Err(e) = saved {
+                        eprintln!("{}", t!("Warning: {}", format!("{:#}", e)));
+                    }
+                }
+                let retention = history::Retention {
+                    keep_last: cli_args.keep_last,
+                    keep_daily: cli_args.keep_daily,
+                    keep_weekly: cli_args.keep_weekly,
+                };
+                if !retention.is_empty()
+                    && let Err(e) = history::prune(path, &retention, false)
+                {
+                    eprintln!("{}", t!("Warning: {}", format!("{:#}", e)));
+                }
+            }
+            if let Some(logger) = &system_logger {
+                log_scan_result(logger, &scanner_config.target_path, &scan_result, &quota_violations, &breaches, formatter);
+            }
+            #[cfg(feature = "native")]
+            {
+                let changes = [
+                    #[cfg(unix)]
+                    ("--chmod", cli_args.chmod.clone().map(FileAction::Chmod)),
+                    #[cfg(unix)]
+                    ("--chown", cli_args.chown.clone().map(FileAction::Chown)),
+                    ("--touch", cli_args.touch.then_some(FileAction::Touch)),
+                    ("--copy-to", cli_args.copy_to.clone().map(|destination| relocation(&cli_args, &scanner_config, destination, false))),
+                    ("--tag", cli_args.tag.clone().map(|tag| FileAction::Tag { tag, database: cli_args.tag_db.clone() })),
+                    // Last, as the other actions need the files where the scan found them
+                    // (the two exclude each other)
+                    ("--rename", cli_args.rename.clone().map(|template| {
+                        FileAction::Rename { template, undo_log: cli_args.undo_log.clone() }
+                    })),
+                    ("--move-to", cli_args.move_to.clone().map(|destination| relocation(&cli_args, &scanner_config, destination, true))),
+                ];
+                for (flag, action) in changes {
+                    if let Some(action) = action {
+                        change_matching_files(flag, &action, &scan_result.matching_files, protected.as_ref(), cli_args.dry_run, formatter)?;
+                    }
+                }
+                if cli_args.empty_trash {
+                    empty_trash(&scan_result.trash, protected.as_ref(), cli_args.dry_run, formatter);
+                }
+            }
+            if exporting {
+                let anonymizer = cli_args.anonymize.then(export::Anonymizer::new);
+                let written = match (cli_args.format, cli_args.output.as_deref()) {
+                    (ExportFormat::Parquet, Some(output)) => vec![export::write_parquet_report(
+                        &scanner_config.target_path,
+                        &scan_result,
+                        output,
+                        cli_args.chunk_size.map(NonZeroUsize::get),
+                        cli_args.compress,
+                        encryption.as_ref(),
+                        anonymizer.as_ref(),
+                    )?],
+                    (ExportFormat::Snapshot, Some(output)) => export::write_snapshot_report(
+                        &scanner_config.target_path,
+                        &scan_result,
+                        output,
+                        cli_args.compress,
+                        encryption.as_ref(),
+                        anonymizer.as_ref(),
+                    )?,
+                    (ExportFormat::Tree, output) => export::write_tree_report(
+                        &scanner_config.target_path,
+                        &scan_result,
+                        &export::TreeOptions {
+                            output,
+                            dirs_only: cli_args.dirs_only,
+                            max_depth: cli_args.depth.map(usize::from),
+                            compression: cli_args.compress,
+                            encryption: encryption.as_ref(),
+                            anonymizer: anonymizer.as_ref(),
+                            formatter,
+                        },
+                    )?,
+                    (format, output) => {
+                        let options = export::JsonOptions {
+                            output,
+                            chunk_size: cli_args.chunk_size.map(NonZeroUsize::get),
+                            compression: cli_args.compress,
+                            encryption: encryption.as_ref(),
+                            anonymizer: anonymizer.as_ref(),
+                        };
+                        let root = &scanner_config.target_path;
+                        match format {
+                            ExportFormat::Markdown => export::write_markdown_report(root, &scan_result, formatter, &options)?,
+                            ExportFormat::Xml => export::write_xml_report(root, &scan_result, &quota_violations, &options)?,
+                            _ if cli_args.export_delta => match &baseline {
+                                Some(baseline) => export::write_delta_report(root, &scan_result, &quota_violations, baseline, &options)?,
+                                None => unreachable!("--export-delta requires --baseline"),
+                            },
+                            _ => export::write_json_report(root, &scan_result, &quota_violations, &options)?,
+                        }
+                    }
+                };
+                if !cli_args.quiet {
+                    for path in written {
+                        eprintln!("{}", t!("Report written to {}", format!("{:?}", path)));
+                    }
+                }
+                if cli_args.json {
+                    for warning in scan_result.open_files.iter().flat_map(summary::open_file_warnings).chain(summary::churn_warning(&scan_result.churn)) {
+                        eprintln!("{}", t!("Warning: {}", warning));
+                    }
+                    for grown in growth.iter().flatten() {
+                        eprintln!("{}", growth_alert(grown, formatter));
+                    }
+                    for file in whats_new.iter().flat_map(|w| &w.files).take(summary::WHATS_NEW_SHOWN) {
+                        eprintln!("{}", whats_new_line(file, formatter));
+                    }
+                    exit_for_alerts(&quota_violations, growth.as_deref(), &breaches);
+                    return Ok(());
                 }
             }
-            if !scan_result.errors.is_empty() && cli_args.verbose {
-                println!("Errors encountered ({}) :", scan_result.errors.len());
-                for err in scan_result.errors {
-                    println!("  - {}", err);
+            if let Some(tool) = cli_args.emit_excludes {
+                let target = cli_args.excludes_file.as_deref();
+                emit_excludes(tool, target, &scanner_config, &scan_result, cli_args.exclude_min_size, cli_args.quiet, formatter)?;
+                if target.is_none() {
+                    exit_for_alerts(&quota_violations, growth.as_deref(), &breaches);
+                    return Ok(());
                 }
             }
+            if cli_args.quiet {
+                summary::print_line(&scanner_config.target_path, &scan_result, &quota_violations, growth.as_deref());
+                for warning in scan_result.open_files.iter().flat_map(summary::open_file_warnings).chain(summary::churn_warning(&scan_result.churn)) {
+                    eprintln!("{}", t!("Warning: {}", warning));
+                }
+                for grown in growth.iter().flatten() {
+                    eprintln!("{}", growth_alert(grown, formatter));
+                }
+                for file in whats_new.iter().flat_map(|w| &w.files).take(summary::WHATS_NEW_SHOWN) {
+                    eprintln!("{}", whats_new_line(file, formatter));
+                }
+                exit_for_alerts(&quota_violations, growth.as_deref(), &breaches);
+                return Ok(());
+            }
+            summary::print(
+                &scan_result,
+                &quota_violations,
+                growth.as_deref(),
+                whats_new.as_ref(),
+                &SummaryOptions {
+                    by_repo: cli_args.by_repo,
+                    by_owner: scanner_config.by_owner,
+                    by_category: scanner_config.categories.is_some(),
+                    inodes: cli_args.inode_report,
+                    quotas: quotas.is_some(),
+                    locked_files: cli_args.locked_files,
+                    acls: cli_args.acls,
+                    selinux: cli_args.selinux,
+                    immutable: cli_args.immutable,
+                    encodings: cli_args.encodings,
+                    line_endings: cli_args.line_endings,
+                    cost_model: cost_model.clone(),
+                    verbose: cli_args.verbose > 0,
+                    color: summary::use_color(cli_args.no_color),
+                    formatter,
+                },
+                output_start,
+            );
+            #[cfg(feature = "native")]
+            if cli_args.interactive {
+                if std::io::stdin().is_terminal() {
+                    let matching_files = scan_result.matching_files.to_vec()
+                        .context("Failed to read back matching files spilled to disk")?;
+                    interactive::pick_and_apply(&matching_files, protected.as_ref(), formatter)?;
+                } else {
+                    eprintln!("{}", t!("Warning: --interactive requires a terminal; skipping file selection."));
+                }
+            }
+            exit_for_alerts(&quota_violations, growth.as_deref(), &breaches);
         }
         Err(e) => {
-            eprintln!("\nAn error occurred during scanning: {}", e);
+            eprintln!("\n{}", t!("An error occurred during scanning: {}", e));
+            if let Some(logger) = &system_logger {
+                let _ = logger.log(Priority::Err, &format!("Scan of {:?} failed: {}", scanner_config.target_path, e));
+            }
         }
     }
     
     Ok(())
 }
+
+/// `--single-dir-summary`: a scan with nothing but the totals collected (no progress, no
+/// reports, no actions), printed as one line, to back a folder size tooltip or context
+/// menu entry.
+async fn single_dir_summary(cli_args: &cli::CliArgs, target_path: &std::path::Path, formatter: Formatter) -> Result<()> {
+    let mut config_builder = ScannerConfig::builder(target_path)
+        .engine(cli_args.engine)
+        .serial(cli_args.serial)
+        .rescan_changed(cli_args.rescan_changed)
+        .follow_symlinks(cli_args.follow_symlinks)
+        .count_links_separately(cli_args.count_links_separately)
+        .include_hidden(!cli_args.no_hidden);
+    if let Some(threads) = cli_args.threads.or(cli_args.cpu_limit.map(|cpus| cpus.get() * 2)) {
+        config_builder = config_builder.max_concurrent_tasks(threads);
+    }
+    let config = config_builder.build()?;
+    #[cfg(feature = "native")]
+    let result = scanner::run_scan(&config).await?;
+    #[cfg(not(feature = "native"))]
+    let result = scanner::run_scan_sync(&config)?;
+    summary::print_single_dir(&result, formatter);
+    Ok(())
+}
+
+/// `--copy-to`/`--move-to` of the matching files to `destination`.
+#[cfg(feature = "native")]
+fn relocation(cli_args: &cli::CliArgs, config: &ScannerConfig, destination: PathBuf, remove_source: bool) -> FileAction {
+    FileAction::Relocate(Relocation {
+        remove_source,
+        destination,
+        root: config.target_path.clone(),
+        journal: cli_args.journal.clone(),
+        workers: cli_args.io_workers.into(),
+    })
+}
+
+/// Applies `--chmod`/`--chown`/`--touch`/... to the matching files, or with `--dry-run` lists
+/// the changes. Reports go to stderr, as stdout may carry the JSON report.
+#[cfg(feature = "native")]
+fn change_matching_files(
+    flag: &str,
+    action: &FileAction,
+    files: &SpillList<MatchedFile>,
+    protected: Option<&ProtectedPaths>,
+    dry_run: bool,
+    formatter: Formatter,
+) -> Result<()> {
+    let files = files.to_vec().context("Failed to read back matching files spilled to disk")?;
+    if dry_run {
+        let mut pending = 0;
+        for (path, change) in actions::pending_changes(action, &files, protected, formatter) {
+            match change {
+                Ok(change) => {
+                    eprintln!("{}", t!("Would change {}: {}", path.display(), change));
+                    pending += 1;
+                }
+                Err(e) => eprintln!("  - {}", t!("Would skip {}: {}", format!("{:?}", path), e)),
+            }
+        }
+        eprintln!("{}", t!("{}: {} of {} matching file(s) would change (dry run)", flag, pending, files.len()));
+        return Ok(());
+    }
+    let summary = actions::apply_action(action, &files, protected);
+    eprintln!("{}", t!("{}: updated {} of {} matching file(s)", flag, summary.processed_files, files.len()));
+    for err in &summary.errors {
+        eprintln!("  - {}", err);
+    }
+    Ok(())
+}
+
+/// `--empty-trash`: empties the trash directories the scan found, or with `--dry-run` lists
+/// them. Reports go to stderr, like those of the other actions.
+#[cfg(feature = "native")]
+fn empty_trash(trash: &[scanner::TrashUsage], protected: Option<&ProtectedPaths>, dry_run: bool, formatter: Formatter) {
+    if dry_run {
+        let mut size = 0;
+        for usage in trash {
+            let items = actions::trashed_items(usage);
+            let freed: u64 = items.iter().map(|item| actions::disk_usage(item).1).sum();
+            eprintln!("{}", t!("Would empty {}: {} item(s), {}", usage.path.display(), items.len(), formatter.size(freed)));
+            size += freed;
+        }
+        eprintln!("{}", t!("--empty-trash: {} trash director(ies), {} would be freed (dry run)", trash.len(), formatter.size(size)));
+        return;
+    }
+    let summary = actions::empty_trash(trash, protected);
+    eprintln!(
+        "{}",
+        t!("--empty-trash: freed {} in {} file(s)", formatter.size(summary.processed_bytes), formatter.count(summary.processed_files))
+    );
+    for err in &summary.errors {
+        eprintln!("  - {}", err);
+    }
+}
+
+/// `--emit-excludes`: writes an exclude file for `tool` leaving out the regenerable data
+/// and large files the scan found, to `path` or else standard output.
+fn emit_excludes(
+    tool: excludes::ExcludeTool,
+    path: Option<&Path>,
+    config: &ScannerConfig,
+    result: &ScanResult,
+    min_size: Option<u64>,
+    quiet: bool,
+    formatter: Formatter,
+) -> Result<()> {
+    let regenerable = config.regenerable.clone().unwrap_or_else(Regenerable::builtin);
+    let exclusions = excludes::collect(&config.target_path, &result.entries, &regenerable, min_size)
+        .context("Failed to read back entries spilled to disk")?;
+    let Some(path) = path else {
+        return excludes::write(tool, &config.target_path, &exclusions, formatter, &mut std::io::stdout().lock())
+            .context("Failed to write the exclude file");
+    };
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(path).with_context(|| format!("Failed to create exclude file {:?}", path))?,
+    );
+    excludes::write(tool, &config.target_path, &exclusions, formatter, &mut file)
+        .and_then(|()| file.flush())
+        .with_context(|| format!("Failed to write exclude file {:?}", path))?;
+    if !quiet {
+        let size: u64 = exclusions.iter().map(|e| e.size).sum();
+        eprintln!("{}", t!("Exclude file written to {} ({} patterns, {})", format!("{:?}", path), exclusions.len(), formatter.size(size)));
+    }
+    Ok(())
+}
+
+/// `snapshot from-json` and `snapshot to-json`: converts between JSON reports and binary snapshots.
+fn convert_snapshot(command: &cli::SnapshotCommand, formatter: Formatter) -> Result<()> {
+    let (convert, args): (fn(&Path, &Path, _) -> _, _) = match command {
+        cli::SnapshotCommand::FromJson(args) => (export::json_to_snapshot, args),
+        cli::SnapshotCommand::ToJson(args) => (export::snapshot_to_json, args),
+    };
+    let (written, files) = convert(&args.input, &args.output, args.compress)?;
+    let size = std::fs::metadata(&written).map(|m| m.len()).unwrap_or(0);
+    println!("{}", t!("Wrote {} files to {} ({})", formatter.count(files), format!("{:?}", written), formatter.size(size)));
+    Ok(())
+}
+
+/// `history prune`: compacts a history file to the records its retention rules keep.
+fn prune_history(args: &cli::HistoryPruneArgs, formatter: Formatter) -> Result<()> {
+    let retention = history::Retention { keep_last: args.keep_last, keep_daily: args.keep_daily, keep_weekly: args.keep_weekly };
+    let summary = history::prune(&args.history, &retention, args.dry_run)?;
+    let message = if args.dry_run {
+        t!(
+            "Would keep {} of {} record(s) and remove {} snapshot(s): {} -> {} (dry run)",
+            summary.kept,
+            summary.kept + summary.removed,
+            summary.snapshots_removed,
+            formatter.size(summary.bytes_before),
+            formatter.size(summary.bytes_after)
+        )
+    } else {
+        t!(
+            "Kept {} of {} record(s) and removed {} snapshot(s): {} -> {}",
+            summary.kept,
+            summary.kept + summary.removed,
+            summary.snapshots_removed,
+            formatter.size(summary.bytes_before),
+            formatter.size(summary.bytes_after)
+        )
+    };
+    println!("{}", message);
+    Ok(())
+}
+
+/// `undo-rename`: puts back the files renamed by a `--rename` run.
+#[cfg(feature = "native")]
+fn undo_rename(args: &cli::UndoRenameArgs) -> Result<()> {
+    let summary = actions::undo_renames(&args.log, args.dry_run)
+        .with_context(|| format!("Failed to read undo log {:?}", args.log))?;
+    for error in &summary.errors {
+        eprintln!("  - {}", error);
+    }
+    if args.dry_run {
+        println!("{}", t!("{} file(s) would be renamed back (dry run)", summary.restored));
+    } else {
+        println!("{}", t!("Renamed {} file(s) back", summary.restored));
+    }
+    Ok(())
+}
+
+/// The `--perm` tests, already checked by the argument parser.
+#[cfg(unix)]
+fn permission_filter(cli_args: &cli::CliArgs) -> Result<Option<scanner::PermissionFilter>> {
+    let mut filter = scanner::PermissionFilter::default();
+    for test in &cli_args.perm {
+        filter.add(test)?;
+    }
+    Ok(Some(filter))
+}
+
+#[cfg(not(unix))]
+fn permission_filter(_cli_args: &cli::CliArgs) -> Result<Option<scanner::PermissionFilter>> {
+    Ok(None)
+}
+
+/// The rsync filter rules of the `--filter-file`s, in order.
+fn filter_rules(cli_args: &cli::CliArgs) -> Result<Option<scanner::FilterRules>> {
+    if cli_args.filter_file.is_empty() {
+        return Ok(None);
+    }
+    let mut rules = scanner::FilterRules::default();
+    for path in &cli_args.filter_file {
+        rules.add_file(path)?;
+    }
+    Ok(Some(rules))
+}
+
+/// Number and time formatting from `--si`, `--thousands-sep` and `--time-style`.
+fn formatter(cli_args: &cli::CliArgs) -> Formatter {
+    let separators = cli_args.thousands_sep.as_deref().map(|sep| match sep.chars().next() {
+        None => Separators::from_locale(),
+        Some('.') => Separators { thousands: '.', decimal: ',' },
+        Some(thousands) => Separators { thousands, decimal: '.' },
+    });
+    Formatter {
+        units: if cli_args.si { SizeUnits::Si } else { SizeUnits::Binary },
+        separators,
+        time_style: cli_args.time_style,
+    }
+}
+
+/// The `--filter-tag` tests, already checked by the argument parser, reading tags from
+/// `--tag-db` when given.
+fn tag_filter(cli_args: &cli::CliArgs) -> Result<Option<TagFilter>> {
+    if cli_args.filter_tag.is_empty() {
+        return Ok(None);
+    }
+    let mut filter = TagFilter::default();
+    for test in &cli_args.filter_tag {
+        filter.add(test)?;
+    }
+    match &cli_args.tag_db {
+        Some(path) => {
+            let database = TagDatabase::open(path).with_context(|| format!("Failed to read tag database {:?}", path))?;
+            Ok(Some(filter.with_database(database)))
+        }
+        None if !tags::NATIVE_TAGS => anyhow::bail!("--filter-tag needs --tag-db on this platform"),
+        None => Ok(Some(filter)),
+    }
+}
+
+/// The first line of a passphrase file.
+#[cfg(feature = "encrypt")]
+fn read_passphrase(path: &std::path::Path) -> Result<Vec<u8>> {
+    let contents = std::fs::read(path).with_context(|| format!("Failed to read passphrase file {:?}", path))?;
+    let line = contents.split(|&b| b == b'\n').next().unwrap_or_default();
+    let passphrase = line.strip_suffix(b"\r").unwrap_or(line);
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase file {:?} is empty", path);
+    }
+    Ok(passphrase.to_vec())
+}
+
+/// `decrypt`: writes the plain report of an --encrypt-output file to stdout or --output.
+#[cfg(feature = "encrypt")]
+fn decrypt(args: &cli::DecryptArgs) -> Result<()> {
+    let passphrase = read_passphrase(&args.passphrase_file)?;
+    let file = std::fs::File::open(&args.file).with_context(|| format!("Failed to open {:?}", args.file))?;
+    let mut reader = export::encrypt::decrypt(std::io::BufReader::new(file), &passphrase)
+        .with_context(|| format!("Failed to decrypt {:?}", args.file))?;
+    let copied = match &args.output {
+        Some(path) => {
+            let mut out = std::fs::File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+            std::io::copy(&mut reader, &mut out)
+        }
+        None => std::io::copy(&mut reader, &mut std::io::stdout().lock()),
+    };
+    copied.with_context(|| format!("Failed to decrypt {:?}", args.file))?;
+    Ok(())
+}
+
+/// Exits with `EXIT_OVER_QUOTA`, `EXIT_GROWTH_ALERT` or `EXIT_RULE_ALERT` when the quota,
+/// growth or `--alert-if` check failed, in that order.
+fn exit_for_alerts(quota_violations: &[QuotaViolation], growth: Option<&[DirectoryGrowth]>, breaches: &[Breach]) {
+    if !quota_violations.is_empty() {
+        std::process::exit(EXIT_OVER_QUOTA);
+    }
+    if growth.is_some_and(|g| !g.is_empty()) {
+        std::process::exit(EXIT_GROWTH_ALERT);
+    }
+    if !breaches.is_empty() {
+        std::process::exit(EXIT_RULE_ALERT);
+    }
+}
+
+/// `--watch`: scans every `interval` (without progress output) until one of `rules` is
+/// breached, noting each scan that breaches none unless `quiet`, and returns the breaching
+/// scan with its breaches.
+async fn watch_until_breached(
+    config: &ScannerConfig,
+    rules: &[AlertRule],
+    interval: Duration,
+    quiet: bool,
+    formatter: Formatter,
+) -> Result<(ScanResult, Vec<Breach>)> {
+    let mut config = config.clone();
+    config.progress_updates = false;
+    config.progress_socket = None;
+    config.live_top = None;
+    loop {
+        let started = Instant::now();
+        #[cfg(feature = "native")]
+        let result = scanner::run_scan(&config).await?;
+        #[cfg(not(feature = "native"))]
+        let result = scanner::run_scan_sync(&config)?;
+        let breaches = alerts::check(rules, &config.target_path, &result)
+            .context("Failed to read back entries spilled to disk")?;
+        if !breaches.is_empty() {
+            return Ok((result, breaches));
+        }
+        let wait = interval.saturating_sub(started.elapsed());
+        if !quiet {
+            eprintln!(
+                "{}",
+                t!(
+                    "{}: no alert rule breached ({} in {} files); scanning again in {}s",
+                    formatter.timestamp(SystemTime::now()),
+                    formatter.size(result.total_size),
+                    formatter.count(result.total_files),
+                    wait.as_secs()
+                )
+            );
+        }
+        #[cfg(feature = "native")]
+        tokio::time::sleep(wait).await;
+        #[cfg(not(feature = "native"))]
+        std::thread::sleep(wait);
+    }
+}
+
+fn rule_alert(breach: &Breach, formatter: Formatter) -> String {
+    t!("Alert rule {} breached: {}", breach.rule, breach.rule.format_value(breach.value, formatter))
+}
+
+/// One sender feeding every sink in `sinks`, or none without sinks.
+#[cfg(feature = "native")]
+fn tee_entries(
+    mut sinks: Vec<tokio::sync::mpsc::UnboundedSender<scanner::FileEntry>>,
+) -> Option<tokio::sync::mpsc::UnboundedSender<scanner::FileEntry>> {
+    if sinks.len() <= 1 {
+        return sinks.pop();
+    }
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<scanner::FileEntry>();
+    tokio::spawn(async move {
+        while let Some(entry) = rx.recv().await {
+            for sink in &sinks {
+                let _ = sink.send(entry.clone());
+            }
+        }
+    });
+    Some(tx)
+}
+
+#[cfg(any(feature = "kafka", feature = "nats"))]
+fn stream_name(target: Option<&event_stream::StreamTarget>) -> String {
+    match target {
+        #[cfg(feature = "kafka")]
+        Some(event_stream::StreamTarget::Kafka { topic, .. }) => format!("Kafka topic {}", topic),
+        #[cfg(feature = "nats")]
+        Some(event_stream::StreamTarget::Nats { subject, .. }) => format!("NATS subject {}", subject),
+        None => String::new(),
+    }
+}
+
+fn growth_alert(grown: &DirectoryGrowth, formatter: Formatter) -> String {
+    t!(
+        "Directory {} grew {}% since the baseline: {} -> {}",
+        format!("{:?}", grown.path),
+        format!("{:.1}", grown.fraction() * 100.0),
+        formatter.size(grown.before),
+        formatter.size(grown.after)
+    )
+}
+
+fn whats_new_line(file: &FileGrowth, formatter: Formatter) -> String {
+    match file.before {
+        Some(before) => t!(
+            "Grown {}: +{} ({} -> {})",
+            format!("{:?}", file.path),
+            formatter.size(file.delta()),
+            formatter.size(before),
+            formatter.size(file.after)
+        ),
+        None => t!("New {}: +{}", format!("{:?}", file.path), formatter.size(file.after)),
+    }
+}
+
+fn log_scan_result(
+    logger: &SystemLogger,
+    root: &std::path::Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    breaches: &[Breach],
+    formatter: Formatter,
+) {
+    let summary = format!(
+        "Scan of {:?} complete: {} files, {} directories, {} in {:?} ({} errors)",
+        root,
+        result.total_files,
+        result.total_directories,
+        formatter.size(result.total_size),
+        result.scan_duration,
+        result.errors.len()
+    );
+    let mut messages = vec![(Priority::Info, summary)];
+    for err in result.errors.iter().take(MAX_LOGGED_ERRORS) {
+        messages.push((Priority::Warning, err.to_string()));
+    }
+    if result.errors.len() > MAX_LOGGED_ERRORS {
+        messages.push((Priority::Warning, format!("... and {} more errors", result.errors.len() - MAX_LOGGED_ERRORS)));
+    }
+    for v in quota_violations {
+        messages.push((
+            Priority::Warning,
+            format!("Owner {} over quota: {} used of {}", v.owner, formatter.size(v.used), formatter.size(v.limit)),
+        ));
+    }
+    for breach in breaches {
+        messages.push((
+            Priority::Warning,
+            format!("Alert rule {} breached: {}", breach.rule, breach.rule.format_value(breach.value, formatter)),
+        ));
+    }
+
+    for (priority, message) in messages {
+        if let Err(e) = logger.log(priority, &message) {
+            eprintln!("{}", t!("Warning: Failed to write to system log: {}", e));
+            return;
+        }
+    }
+}
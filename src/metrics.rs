@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Internal counters used to diagnose scheduling problems (`--debug-metrics`).
+#[derive(Debug, Default)]
+pub struct ScanMetrics {
+    tasks_spawned: AtomicU64,
+    tasks_in_flight: AtomicU64,
+    peak_tasks_in_flight: AtomicU64,
+    semaphore_waiters: AtomicU64,
+    peak_semaphore_waiters: AtomicU64,
+    channel_depth: AtomicU64,
+    peak_channel_depth: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetricsSnapshot {
+    pub tasks_spawned: u64,
+    pub tasks_in_flight: u64,
+    pub peak_tasks_in_flight: u64,
+    pub semaphore_waiters: u64,
+    pub peak_semaphore_waiters: u64,
+    pub channel_depth: u64,
+    pub peak_channel_depth: u64,
+}
+
+/// Decrements a gauge when dropped, so early returns are accounted for.
+pub struct GaugeGuard<'a> {
+    gauge: &'a AtomicU64,
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn increment<'a>(gauge: &'a AtomicU64, peak: &AtomicU64) -> GaugeGuard<'a> {
+    let current = gauge.fetch_add(1, Ordering::Relaxed) + 1;
+    peak.fetch_max(current, Ordering::Relaxed);
+    GaugeGuard { gauge }
+}
+
+impl ScanMetrics {
+    pub fn task_started(&self) -> GaugeGuard<'_> {
+        self.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+        increment(&self.tasks_in_flight, &self.peak_tasks_in_flight)
+    }
+
+    pub fn waiting_for_permit(&self) -> GaugeGuard<'_> {
+        increment(&self.semaphore_waiters, &self.peak_semaphore_waiters)
+    }
+
+    pub fn record_channel_depth(&self, depth: usize) {
+        self.channel_depth.store(depth as u64, Ordering::Relaxed);
+        self.peak_channel_depth.fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            tasks_spawned: self.tasks_spawned.load(Ordering::Relaxed),
+            tasks_in_flight: self.tasks_in_flight.load(Ordering::Relaxed),
+            peak_tasks_in_flight: self.peak_tasks_in_flight.load(Ordering::Relaxed),
+            semaphore_waiters: self.semaphore_waiters.load(Ordering::Relaxed),
+            peak_semaphore_waiters: self.peak_semaphore_waiters.load(Ordering::Relaxed),
+            channel_depth: self.channel_depth.load(Ordering::Relaxed),
+            peak_channel_depth: self.peak_channel_depth.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Platform-specific file ownership lookups used by `--by-owner`.
+//!
+//! Unix owners are uids from the file's metadata. Windows owners are security identifiers
+//! (SIDs) in the file's security descriptor, read with `owner_of`; each distinct SID gets
+//! a small id for the rest of the scan, which `user_name` turns back into `DOMAIN\user`.
+
+use std::io;
+use std::path::Path;
+
+/// Numeric owner of a file, where the platform exposes one.
+#[cfg(unix)]
+pub fn owner_id(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+pub fn owner_id(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Owner of the file at `path` where `owner_id` can't tell from its metadata: on Windows,
+/// the id of the SID owning it. Ids are only valid until the process exits.
+#[cfg(windows)]
+pub fn owner_of(path: &Path) -> io::Result<Option<u32>> {
+    use std::ptr;
+    use windows_sys::Win32::Security::{GetLengthSid, GetSecurityDescriptorOwner, OWNER_SECURITY_INFORMATION};
+
+    let mut buffer = security_descriptor(path, OWNER_SECURITY_INFORMATION)?;
+    let descriptor = buffer.as_mut_ptr().cast();
+    let (mut owner, mut defaulted) = (ptr::null_mut(), 0);
+    // SAFETY: `descriptor` was filled in by GetFileSecurityW and the out-params are valid
+    if unsafe { GetSecurityDescriptorOwner(descriptor, &mut owner, &mut defaulted) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if owner.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: `owner` points to a valid SID inside `descriptor`
+    let sid = unsafe { std::slice::from_raw_parts(owner.cast::<u8>(), GetLengthSid(owner) as usize) };
+    Ok(Some(sids::intern(sid)))
+}
+
+#[cfg(not(windows))]
+pub fn owner_of(_path: &Path) -> io::Result<Option<u32>> {
+    Ok(None)
+}
+
+/// The parts of the security descriptor of the file at `path` that `information` asks
+/// for (`OWNER_SECURITY_INFORMATION`, ...), in self-relative form. It is held in u64s to
+/// keep it aligned for the pointers read out of it.
+#[cfg(windows)]
+pub fn security_descriptor(
+    path: &Path,
+    information: windows_sys::Win32::Security::OBJECT_SECURITY_INFORMATION,
+) -> io::Result<Vec<u64>> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use windows_sys::Win32::Security::GetFileSecurityW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut needed = 0u32;
+    // SAFETY: `wide` is NUL-terminated; a null buffer of length 0 only asks for the size
+    unsafe { GetFileSecurityW(wide.as_ptr(), information, ptr::null_mut(), 0, &mut needed) };
+    if needed == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut descriptor = vec![0u64; (needed as usize).div_ceil(8)];
+    // SAFETY: `descriptor` holds at least `needed` bytes
+    if unsafe { GetFileSecurityW(wide.as_ptr(), information, descriptor.as_mut_ptr().cast(), needed, &mut needed) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(descriptor)
+}
+
+/// Resolves a user id to its account name.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    uzers::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
+}
+
+/// Resolves an id from `owner_of` to `DOMAIN\user`, or to the SID in its `S-1-5-...` form
+/// when no account has it (deleted users, other domains).
+#[cfg(windows)]
+pub fn user_name(id: u32) -> Option<String> {
+    let mut sid = sids::get(id)?;
+    Some(sids::account_name(&mut sid).unwrap_or_else(|| sids::to_string(&sid)))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// The SID of the user running the process in its `S-1-5-...` form, which names their bin
+/// in `$RECYCLE.BIN`.
+#[cfg(windows)]
+pub fn current_user_sid() -> Option<String> {
+    use std::ptr;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Security::{GetLengthSid, GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER};
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let mut token = ptr::null_mut();
+    // SAFETY: the pseudo-handle of the current process needs no closing; `token` is an out-param
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+        return None;
+    }
+    let mut needed = 0u32;
+    // SAFETY: `token` is open; a null buffer of length 0 only asks for the size
+    unsafe { GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut needed) };
+    // u64s keep the TOKEN_USER and the SID after it aligned
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    // SAFETY: `buffer` holds at least `needed` bytes
+    let ok = unsafe { GetTokenInformation(token, TokenUser, buffer.as_mut_ptr().cast(), needed, &mut needed) };
+    // SAFETY: `token` was opened above and isn't used after this
+    unsafe { CloseHandle(token) };
+    if ok == 0 {
+        return None;
+    }
+    // SAFETY: GetTokenInformation filled `buffer` with a TOKEN_USER whose SID points into it
+    let sid = unsafe { (*buffer.as_ptr().cast::<TOKEN_USER>()).User.Sid };
+    // SAFETY: `sid` is a valid SID inside `buffer`
+    let sid = unsafe { std::slice::from_raw_parts(sid.cast::<u8>(), GetLengthSid(sid) as usize) };
+    Some(sids::to_string(sid))
+}
+
+#[cfg(windows)]
+mod sids {
+    use std::collections::HashMap;
+    use std::ptr;
+    use std::sync::{LazyLock, Mutex, PoisonError};
+    use windows_sys::Win32::Security::LookupAccountSidW;
+
+    /// Every SID seen so far, by id and the other way round.
+    #[derive(Default)]
+    struct SidTable {
+        ids: HashMap<Vec<u8>, u32>,
+        sids: Vec<Vec<u8>>,
+    }
+
+    static TABLE: LazyLock<Mutex<SidTable>> = LazyLock::new(Default::default);
+
+    pub(super) fn intern(sid: &[u8]) -> u32 {
+        let mut table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(&id) = table.ids.get(sid) {
+            return id;
+        }
+        let id = table.sids.len() as u32;
+        table.sids.push(sid.to_vec());
+        table.ids.insert(sid.to_vec(), id);
+        id
+    }
+
+    pub(super) fn get(id: u32) -> Option<Vec<u8>> {
+        TABLE.lock().unwrap_or_else(PoisonError::into_inner).sids.get(id as usize).cloned()
+    }
+
+    pub(super) fn account_name(sid: &mut [u8]) -> Option<String> {
+        let (mut name_len, mut domain_len, mut kind) = (0u32, 0u32, 0);
+        let sid = sid.as_mut_ptr().cast();
+        // SAFETY: `sid` is a valid SID; null buffers of length 0 only ask for the sizes
+        unsafe { LookupAccountSidW(ptr::null(), sid, ptr::null_mut(), &mut name_len, ptr::null_mut(), &mut domain_len, &mut kind) };
+        if name_len == 0 {
+            return None;
+        }
+        let mut name = vec![0u16; name_len as usize];
+        let mut domain = vec![0u16; domain_len.max(1) as usize];
+        // SAFETY: the buffers hold the lengths passed with them
+        let ok = unsafe {
+            LookupAccountSidW(ptr::null(), sid, name.as_mut_ptr(), &mut name_len, domain.as_mut_ptr(), &mut domain_len, &mut kind)
+        };
+        if ok == 0 {
+            return None;
+        }
+        // On success the lengths leave out the terminating NUL
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        Some(if domain.is_empty() { name } else { format!("{}\\{}", domain, name) })
+    }
+
+    /// The `S-R-A-S1-S2-...` form of a binary SID: revision, a 48-bit big-endian
+    /// authority, then little-endian 32-bit subauthorities.
+    pub(super) fn to_string(sid: &[u8]) -> String {
+        let Some((header, rest)) = sid.split_first_chunk::<8>() else {
+            return String::from("S-?");
+        };
+        let authority = header[2..].iter().fold(0u64, |value, &byte| (value << 8) | u64::from(byte));
+        let mut text = format!("S-{}-{}", header[0], authority);
+        for sub in rest.chunks_exact(4).take(usize::from(header[1])) {
+            text.push_str(&format!("-{}", u32::from_le_bytes([sub[0], sub[1], sub[2], sub[3]])));
+        }
+        text
+    }
+}
@@ -0,0 +1,155 @@
+//! Relative paths stored as a tree of interned names, for the features that keep a value
+//! per directory of a scan (`--baseline`, `--format tree`, the live top list).
+//!
+//! A node is its parent's index, its name's index and the value: a few bytes where a
+//! `PathBuf` key costs its whole path on the heap. Names are stored once however many
+//! directories share them, so a tree of ten million entries takes a fraction of the memory
+//! a map keyed by path would.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Index of a node of a `PathTree`, valid for that tree only.
+pub type NodeId = u32;
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    parent: NodeId,
+    name: u32,
+    value: T,
+}
+
+/// Paths relative to a root, each with a `T`. The root itself is `PathTree::ROOT`, the
+/// empty path; nodes are numbered in the order they were added, so a parent always comes
+/// before its children.
+#[derive(Debug, Clone)]
+pub struct PathTree<T> {
+    nodes: Vec<Node<T>>,
+    children: HashMap<(NodeId, u32), NodeId>,
+    names: Vec<Arc<OsStr>>,
+    name_ids: HashMap<Arc<OsStr>, u32>,
+}
+
+impl<T: Default> Default for PathTree<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node { parent: Self::ROOT, name: 0, value: T::default() }],
+            children: HashMap::new(),
+            // The root's name, never looked up
+            names: vec![Arc::from(OsStr::new(""))],
+            name_ids: HashMap::new(),
+        }
+    }
+}
+
+impl<T> PathTree<T> {
+    pub const ROOT: NodeId = 0;
+
+    /// Nodes in the tree, including the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Always false, as the root is a node.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The node at `path` (relative to the root), if it was added. `.` and the like are
+    /// skipped.
+    pub fn find(&self, path: &Path) -> Option<NodeId> {
+        normal_names(path).try_fold(Self::ROOT, |node, name| self.child(node, name))
+    }
+
+    /// The child `name` of `parent`, if it was added.
+    pub fn child(&self, parent: NodeId, name: &OsStr) -> Option<NodeId> {
+        let name = *self.name_ids.get(name)?;
+        self.children.get(&(parent, name)).copied()
+    }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        (node != Self::ROOT).then(|| self.nodes[node as usize].parent)
+    }
+
+    /// The last component of the node's path; empty for the root.
+    pub fn name(&self, node: NodeId) -> &OsStr {
+        &self.names[self.nodes[node as usize].name as usize]
+    }
+
+    /// The node's path relative to the root, built from its ancestors' names.
+    pub fn path(&self, node: NodeId) -> PathBuf {
+        let mut names: Vec<&OsStr> = self.ancestors(node).map(|ancestor| self.name(ancestor)).collect();
+        names.pop();
+        names.iter().rev().collect()
+    }
+
+    /// `node`, its parent and so on up to and including the root.
+    pub fn ancestors(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(node), |&node| self.parent(node))
+    }
+
+    pub fn get(&self, node: NodeId) -> &T {
+        &self.nodes[node as usize].value
+    }
+
+    pub fn get_mut(&mut self, node: NodeId) -> &mut T {
+        &mut self.nodes[node as usize].value
+    }
+
+    /// Every node with its value, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.nodes.iter().enumerate().map(|(node, n)| (node as NodeId, &n.value))
+    }
+
+    /// The children of every node, indexed by node, each list sorted by name.
+    pub fn sorted_children(&self) -> Vec<Vec<NodeId>> {
+        let mut lists = vec![Vec::new(); self.nodes.len()];
+        for (&(parent, _), &child) in &self.children {
+            lists[parent as usize].push(child);
+        }
+        for list in &mut lists {
+            list.sort_unstable_by(|&a, &b| self.name(a).cmp(self.name(b)));
+        }
+        lists
+    }
+}
+
+impl<T: Default> PathTree<T> {
+    /// The node at `path` (relative to the root), adding it and any missing ancestors
+    /// with default values.
+    pub fn insert(&mut self, path: &Path) -> NodeId {
+        normal_names(path).fold(Self::ROOT, |node, name| self.insert_child(node, name))
+    }
+
+    /// The child `name` of `parent`, added with a default value if it's new.
+    pub fn insert_child(&mut self, parent: NodeId, name: &OsStr) -> NodeId {
+        let name = self.intern(name);
+        if let Some(&child) = self.children.get(&(parent, name)) {
+            return child;
+        }
+        let child = self.nodes.len() as NodeId;
+        self.nodes.push(Node { parent, name, value: T::default() });
+        self.children.insert((parent, name), child);
+        child
+    }
+
+    fn intern(&mut self, name: &OsStr) -> u32 {
+        if let Some(&id) = self.name_ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        let name: Arc<OsStr> = Arc::from(name);
+        self.names.push(Arc::clone(&name));
+        self.name_ids.insert(name, id);
+        id
+    }
+}
+
+fn normal_names(path: &Path) -> impl Iterator<Item = &OsStr> {
+    path.components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    })
+}
@@ -1,75 +1,421 @@
-use indicatif::{ProgressBar, ProgressStyle, HumanBytes};
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+#[cfg(feature = "native")]
+use {
+    indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle},
+    std::collections::BTreeMap,
+    std::path::Path,
+    serde::Serialize,
+    std::sync::Arc,
+    std::sync::atomic::{AtomicU64, Ordering},
+    std::time::{Duration, Instant},
+    tokio::sync::{mpsc, watch},
+    crate::formatting::Formatter,
+    crate::metrics::ScanMetrics,
+    crate::path_tree::{NodeId, PathTree},
+};
+
+/// How often the live top list is redrawn.
+#[cfg(feature = "native")]
+const LIVE_TOP_REFRESH: Duration = Duration::from_millis(250);
 
 #[derive(Debug, Clone)]
 pub enum ProgressUpdate {
-    NewItemFound,
-    BytesProcessed(u64),
+    /// Files and directories found since the last update, and the bytes in those files.
+    /// Walkers send one per directory read (more for very large ones), not one per entry.
+    ItemsFound { items: u64, bytes: u64 },
     ErrorEncountered,
+    /// Directories discovered but not yet read
+    QueueDepth(u64),
+    /// The root's `index`-th sub-directory gets its own progress line
+    SubtreeStarted { index: u32, name: String },
+    /// Items and bytes found in one more directory under that sub-directory
+    SubtreeProgress { index: u32, items: u64, bytes: u64 },
+    SubtreeFinished(u32),
+    /// The scan was paused (`true`) or resumed from the keyboard
+    Paused(bool),
+    /// A file that may be among the largest so far, with `ScannerConfig::live_top`
+    FileFound { path: PathBuf, size: u64 },
+    /// Bytes of the files directly in a directory once it was read, with `ScannerConfig::live_top`
+    DirectoryRead { path: PathBuf, bytes: u64 },
     ScanCompleted,
 }
 
+#[cfg(feature = "native")]
+/// Running totals published to external observers such as the progress socket.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProgressSnapshot {
+    pub items: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub queued_directories: u64,
+    pub elapsed_ms: u64,
+    pub done: bool,
+}
+
+#[cfg(feature = "native")]
 pub struct ProgressReporter {
+    show_spinner: bool,
+    snapshot_tx: Option<watch::Sender<ProgressSnapshot>>,
+    metrics: Option<Arc<ScanMetrics>>,
+    live_top: Option<LiveTop>,
+    formatter: Formatter,
 }
 
+#[cfg(feature = "native")]
 impl ProgressReporter {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        show_spinner: bool,
+        snapshot_tx: Option<watch::Sender<ProgressSnapshot>>,
+        metrics: Option<Arc<ScanMetrics>>,
+    ) -> Self {
+        Self { show_spinner, snapshot_tx, metrics, live_top: None, formatter: Formatter::default() }
+    }
+
+    pub fn with_formatter(mut self, formatter: Formatter) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    /// Also draws the largest files and directories found so far below the spinner.
+    pub fn with_live_top(mut self, live_top: LiveTop) -> Self {
+        self.live_top = Some(live_top);
+        self
     }
 
     pub async fn run(
-        &self,
+        self,
         mut rx: mpsc::UnboundedReceiver<ProgressUpdate>,
     ) {
-        let pb = ProgressBar::new_spinner();
+        let mut display = self.start();
+        while let Some(update) = rx.recv().await {
+            if let Some(metrics) = &display.reporter.metrics {
+                metrics.record_channel_depth(rx.len());
+            }
+            // Whatever queued up while the last redraw ran is applied before the next one
+            if !display.apply(update) || !display.drain(&mut rx) {
+                break;
+            }
+        }
+        display.finish();
+    }
+
+    /// Starts drawing, for callers that feed the updates in themselves with
+    /// `ProgressDisplay::update` instead of handing `run` a channel.
+    pub fn start(self) -> ProgressDisplay {
+        let multi = MultiProgress::new();
+        if !self.show_spinner {
+            multi.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        let pb = multi.add(ProgressBar::new_spinner());
         pb.enable_steady_tick(Duration::from_millis(120));
         pb.set_style(
             ProgressStyle::default_spinner()
                 .tick_strings(&["-", "\\", "|", "/"])
-                .template("{spinner:.red} {msg} [{elapsed_precise}] Items: {pos}").unwrap()
+                .template("{spinner:.red} {msg} [{elapsed_precise}] Items: {pos} {prefix}").unwrap()
         );
-        pb.set_message("Scanning...");
+        pb.set_message(crate::t!("Scanning..."));
+        let live_bars = self.live_top.as_ref().map(|top| LiveTopBars::new(&multi, top.count));
+        ProgressDisplay {
+            reporter: self,
+            multi,
+            pb,
+            start_time: Instant::now(),
+            totals: ProgressSnapshot::default(),
+            paused: false,
+            subtrees: BTreeMap::new(),
+            live_bars,
+            last_drawn: Instant::now(),
+        }
+    }
 
-        let mut total_items = 0u64;
-        let mut total_bytes = 0u64;
+    fn publish(&self, totals: &mut ProgressSnapshot, start_time: Instant, done: bool) {
+        if let Some(tx) = &self.snapshot_tx {
+            totals.elapsed_ms = start_time.elapsed().as_millis() as u64;
+            totals.done = done;
+            tx.send_replace(totals.clone());
+        }
+    }
+}
 
-        while let Some(update) = rx.recv().await {
-            match update {
-                ProgressUpdate::NewItemFound => {
-                    total_items += 1;
-                    pb.set_position(total_items);
-                    pb.set_message(format!(
-                        "Scanning... Items: {}, Size: {}",
-                        total_items,
-                        HumanBytes(total_bytes)
-                    ));
+/// The spinner and progress lines of a running scan, see `ProgressReporter::start`.
+#[cfg(feature = "native")]
+pub struct ProgressDisplay {
+    reporter: ProgressReporter,
+    multi: MultiProgress,
+    pb: ProgressBar,
+    start_time: Instant,
+    totals: ProgressSnapshot,
+    paused: bool,
+    subtrees: BTreeMap<u32, SubtreeBar>,
+    live_bars: Option<LiveTopBars>,
+    last_drawn: Instant,
+}
+
+#[cfg(feature = "native")]
+impl ProgressDisplay {
+    /// Applies `update` to the totals and redraws; false once the scan completed.
+    pub fn update(&mut self, update: ProgressUpdate) -> bool {
+        if !self.apply(update) {
+            return false;
+        }
+        self.redraw();
+        true
+    }
+
+    /// Applies every update waiting in `rx`, then redraws once; false once the scan
+    /// completed.
+    pub fn drain(&mut self, rx: &mut mpsc::UnboundedReceiver<ProgressUpdate>) -> bool {
+        while let Ok(update) = rx.try_recv() {
+            if !self.apply(update) {
+                return false;
+            }
+        }
+        self.redraw();
+        true
+    }
+
+    /// Applies `update` to the totals without redrawing; false once the scan completed.
+    fn apply(&mut self, update: ProgressUpdate) -> bool {
+        let reporter = &mut self.reporter;
+        let totals = &mut self.totals;
+        match update {
+            ProgressUpdate::ItemsFound { items, bytes } => {
+                totals.items += items;
+                totals.bytes += bytes;
+            }
+            ProgressUpdate::ErrorEncountered => totals.errors += 1,
+            ProgressUpdate::QueueDepth(depth) => totals.queued_directories = depth,
+            ProgressUpdate::Paused(now_paused) => self.paused = now_paused,
+            ProgressUpdate::SubtreeStarted { index, name } => {
+                self.subtrees.insert(index, SubtreeBar::new(&self.multi, &name, reporter.formatter));
+            }
+            ProgressUpdate::SubtreeProgress { index, items, bytes } => {
+                if let Some(subtree) = self.subtrees.get_mut(&index) {
+                    subtree.add(items, bytes);
                 }
-                ProgressUpdate::BytesProcessed(bytes) => {
-                    total_bytes += bytes;
-                    pb.set_message(format!(
-                        "Scanning... Items: {}, Size: {}",
-                        total_items,
-                        HumanBytes(total_bytes)
-                    ));
+            }
+            ProgressUpdate::SubtreeFinished(index) => {
+                if let Some(subtree) = self.subtrees.get(&index) {
+                    subtree.finish();
                 }
-                ProgressUpdate::ErrorEncountered => {
-                    pb.set_message(format!(
-                        "Scanning... Items: {}, Size: {} (errors encountered)",
-                        total_items,
-                        HumanBytes(total_bytes)
-                    ));
+            }
+            ProgressUpdate::FileFound { path, size } => {
+                if let Some(top) = &mut reporter.live_top {
+                    top.add_file(path, size);
                 }
-                ProgressUpdate::ScanCompleted => {
-                    break;
+            }
+            ProgressUpdate::DirectoryRead { path, bytes } => {
+                if let Some(top) = &mut reporter.live_top {
+                    top.add_directory(&path, bytes);
                 }
             }
+            ProgressUpdate::ScanCompleted => {
+                return false;
+            }
         }
+        true
+    }
 
-        pb.finish_with_message(format!(
+    fn redraw(&mut self) {
+        let reporter = &self.reporter;
+        let totals = &mut self.totals;
+        if let Some(metrics) = &reporter.metrics {
+            let m = metrics.snapshot();
+            self.pb.set_prefix(format!(
+                "[tasks: {}, waiting: {}, queue: {}]",
+                m.tasks_in_flight, m.semaphore_waiters, m.channel_depth
+            ));
+        }
+        self.pb.set_position(totals.items);
+        if let (Some(top), Some(bars)) = (&reporter.live_top, &self.live_bars)
+            && self.last_drawn.elapsed() >= LIVE_TOP_REFRESH
+        {
+            bars.draw(top, reporter.formatter);
+            self.last_drawn = Instant::now();
+        }
+        if self.paused {
+            self.pb.set_message(crate::t!("Paused - press p to resume, q to cancel"));
+        } else {
+            self.pb.set_message(crate::t!(
+                "Scanning... Items: {}, Size: {}, Dirs queued: {}{}",
+                reporter.formatter.count(totals.items),
+                reporter.formatter.size(totals.bytes),
+                reporter.formatter.count(totals.queued_directories),
+                if totals.errors > 0 { crate::t!(" (errors encountered)") } else { "" }
+            ));
+        }
+        reporter.publish(totals, self.start_time, false);
+    }
+
+    /// Publishes the final totals and finishes every line.
+    pub fn finish(mut self) {
+        let reporter = &self.reporter;
+        let totals = &mut self.totals;
+        totals.queued_directories = 0;
+        reporter.publish(totals, self.start_time, true);
+        self.pb.set_position(totals.items);
+
+        for subtree in self.subtrees.values() {
+            subtree.finish();
+        }
+        if let (Some(top), Some(bars)) = (&reporter.live_top, &self.live_bars) {
+            bars.draw(top, reporter.formatter);
+            bars.finish();
+        }
+
+        self.pb.finish_with_message(crate::t!(
             "Scan finished! Total Items: {}, Total Size: {}",
-            total_items,
-            HumanBytes(total_bytes)
+            reporter.formatter.count(totals.items),
+            reporter.formatter.size(totals.bytes)
         ));
     }
 }
+
+/// Progress line for one of the scan root's sub-directories.
+#[cfg(feature = "native")]
+struct SubtreeBar {
+    bar: ProgressBar,
+    items: u64,
+    bytes: u64,
+    formatter: Formatter,
+}
+
+#[cfg(feature = "native")]
+impl SubtreeBar {
+    fn new(multi: &MultiProgress, name: &str, formatter: Formatter) -> Self {
+        let bar = multi.add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::default_spinner()
+                .tick_strings(&["-", "\\", "|", "/", "="])
+                .template("  {spinner:.blue} {prefix:<24} {msg}").unwrap()
+        );
+        bar.set_prefix(name.to_string());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        Self { bar, items: 0, bytes: 0, formatter }
+    }
+
+    fn add(&mut self, items: u64, bytes: u64) {
+        self.items += items;
+        self.bytes += bytes;
+        self.bar.set_message(crate::t!("Items: {}, Size: {}", self.formatter.count(self.items), self.formatter.size(self.bytes)));
+    }
+
+    fn finish(&self) {
+        if !self.bar.is_finished() {
+            let (items, bytes) = (self.formatter.count(self.items), self.formatter.size(self.bytes));
+            self.bar.finish_with_message(crate::t!("Items: {}, Size: {} (done)", items, bytes));
+        }
+    }
+}
+
+/// The largest files and directories seen so far, for `ScannerConfig::live_top`.
+/// Directory sizes grow as the scan goes on, as they include every file read below them.
+#[cfg(feature = "native")]
+pub struct LiveTop {
+    root: PathBuf,
+    count: usize,
+    /// Largest first, at most `count`
+    files: Vec<(u64, PathBuf)>,
+    /// Every directory below the root with files found in it so far, relative to it
+    directories: PathTree<u64>,
+    /// Size of the smallest of the largest files once there are `count` of them;
+    /// walkers only report files larger than this
+    floor: Arc<AtomicU64>,
+}
+
+#[cfg(feature = "native")]
+impl LiveTop {
+    pub fn new(root: PathBuf, count: usize) -> Self {
+        Self { root, count, files: Vec::new(), directories: PathTree::default(), floor: Arc::default() }
+    }
+
+    /// Shared with the walkers, so they can skip sending files that wouldn't make the list.
+    pub fn floor(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.floor)
+    }
+
+    fn add_file(&mut self, path: PathBuf, size: u64) {
+        let position = self.files.partition_point(|(s, _)| *s >= size);
+        if position >= self.count {
+            return;
+        }
+        self.files.insert(position, (size, path));
+        self.files.truncate(self.count);
+        if self.files.len() == self.count
+            && let Some((smallest, _)) = self.files.last()
+        {
+            self.floor.store(*smallest, Ordering::Relaxed);
+        }
+    }
+
+    fn add_directory(&mut self, path: &Path, bytes: u64) {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return;
+        };
+        let mut dir = self.directories.insert(relative);
+        while dir != PathTree::<u64>::ROOT {
+            *self.directories.get_mut(dir) += bytes;
+            dir = self.directories.parent(dir).unwrap_or(PathTree::<u64>::ROOT);
+        }
+    }
+
+    fn largest_directories(&self) -> Vec<(u64, PathBuf)> {
+        let mut largest: Vec<(u64, NodeId)> = self.directories.iter().skip(1).map(|(dir, &size)| (size, dir)).collect();
+        largest.sort_unstable_by_key(|&(size, _)| std::cmp::Reverse(size));
+        largest.truncate(self.count);
+        // Paths only for the few shown, which is what ties are ordered by
+        let mut largest: Vec<(u64, PathBuf)> = largest.into_iter()
+            .map(|(size, dir)| (size, self.root.join(self.directories.path(dir))))
+            .collect();
+        largest.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        largest
+    }
+}
+
+/// One line per entry of the live top list, under a heading each for files and directories.
+#[cfg(feature = "native")]
+struct LiveTopBars {
+    file_heading: ProgressBar,
+    files: Vec<ProgressBar>,
+    directory_heading: ProgressBar,
+    directories: Vec<ProgressBar>,
+}
+
+#[cfg(feature = "native")]
+impl LiveTopBars {
+    fn new(multi: &MultiProgress, count: usize) -> Self {
+        let line = || {
+            let bar = multi.add(ProgressBar::new_spinner());
+            bar.set_style(ProgressStyle::default_spinner().template("{msg}").unwrap());
+            bar
+        };
+        let file_heading = line();
+        let files = (0..count).map(|_| line()).collect();
+        let directory_heading = line();
+        let directories = (0..count).map(|_| line()).collect();
+        Self { file_heading, files, directory_heading, directories }
+    }
+
+    fn draw(&self, top: &LiveTop, formatter: Formatter) {
+        self.file_heading.set_message(crate::t!("Largest files so far:"));
+        for (index, bar) in self.files.iter().enumerate() {
+            bar.set_message(top.files.get(index).map(|(size, path)| live_line(formatter, *size, path)).unwrap_or_default());
+        }
+        self.directory_heading.set_message(crate::t!("Largest directories so far:"));
+        let directories = top.largest_directories();
+        for (index, bar) in self.directories.iter().enumerate() {
+            bar.set_message(directories.get(index).map(|(size, path)| live_line(formatter, *size, path)).unwrap_or_default());
+        }
+    }
+
+    fn finish(&self) {
+        for bar in [&self.file_heading, &self.directory_heading].into_iter().chain(&self.files).chain(&self.directories) {
+            bar.finish();
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+fn live_line(formatter: Formatter, size: u64, path: &Path) -> String {
+    format!("  {:>10}  {}", formatter.size(size), path.display())
+}
@@ -0,0 +1,114 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::progress::ProgressSnapshot;
+
+/// Minimum delay between two snapshots sent to the same client.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Serves progress snapshots as JSON lines to every client connecting to `path`
+/// until the scan is done. On Unix `path` is a domain socket, on Windows a named
+/// pipe (e.g. `\\.\pipe\disk_scanner`).
+pub async fn serve(path: PathBuf, snapshots: watch::Receiver<ProgressSnapshot>) -> io::Result<()> {
+    imp::serve(path, snapshots).await
+}
+
+async fn stream_to_client<W>(mut writer: W, mut snapshots: watch::Receiver<ProgressSnapshot>) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    loop {
+        let snapshot = snapshots.borrow_and_update().clone();
+        let mut line = serde_json::to_vec(&snapshot)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        if snapshot.done {
+            break;
+        }
+        tokio::time::sleep(UPDATE_INTERVAL).await;
+        if snapshots.changed().await.is_err() {
+            break;
+        }
+    }
+    writer.shutdown().await
+}
+
+/// Waits until the reporter publishes its final snapshot (or goes away).
+async fn wait_until_done(snapshots: &mut watch::Receiver<ProgressSnapshot>) {
+    let _ = snapshots.wait_for(|s| s.done).await;
+}
+
+async fn drain_clients(mut clients: JoinSet<io::Result<()>>) {
+    // Clients that disconnect early are not an error worth reporting
+    while clients.join_next().await.is_some() {}
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::net::UnixListener;
+
+    pub async fn serve(path: PathBuf, mut snapshots: watch::Receiver<ProgressSnapshot>) -> io::Result<()> {
+        // A socket left behind by a killed run would make bind fail; anything else at the
+        // path isn't ours to remove
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{:?} already exists and is not a socket; pick another path", path),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(&path)?;
+        let mut clients = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    if let Ok((stream, _)) = accepted {
+                        clients.spawn(stream_to_client(stream, snapshots.clone()));
+                    }
+                }
+                _ = wait_until_done(&mut snapshots) => break,
+            }
+        }
+
+        drain_clients(clients).await;
+        std::fs::remove_file(&path)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::*;
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    pub async fn serve(path: PathBuf, mut snapshots: watch::Receiver<ProgressSnapshot>) -> io::Result<()> {
+        let mut server = ServerOptions::new().first_pipe_instance(true).create(&path)?;
+        let mut clients = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                connected = server.connect() => {
+                    if connected.is_ok() {
+                        let next = ServerOptions::new().create(&path)?;
+                        let client = std::mem::replace(&mut server, next);
+                        clients.spawn(stream_to_client(client, snapshots.clone()));
+                    }
+                }
+                _ = wait_until_done(&mut snapshots) => break,
+            }
+        }
+
+        drain_clients(clients).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! `disk_scanner` Python extension module.
+//!
+//! ```python
+//! import disk_scanner
+//!
+//! report = disk_scanner.scan("/data", pattern=r"\.parquet$", by_owner=True)
+//! for entry in disk_scanner.iter_entries("/data"):
+//!     print(entry["path"], entry["size"])
+//! ```
+
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::UNIX_EPOCH;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use tokio::sync::mpsc;
+
+use crate::embed::EmbedOptions;
+use crate::export;
+use crate::scanner::{self, FileEntry, ScanHooks, ScannerConfig};
+
+/// Builds the scanner configuration from `path` plus the keyword arguments, which take
+/// the same names as the C ABI's `config_json`.
+fn scanner_config(py: Python<'_>, path: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<ScannerConfig> {
+    let json = py.import("json")?;
+    let options = match options {
+        Some(options) => options.copy()?,
+        None => PyDict::new(py),
+    };
+    options.set_item("path", path)?;
+    let options: String = json.call_method1("dumps", (options,))?.extract()?;
+    serde_json::from_str::<EmbedOptions>(&options)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?
+        .into_scanner_config()
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn run_blocking(config: &ScannerConfig, hooks: ScanHooks) -> anyhow::Result<scanner::ScanResult> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(scanner::run_scan_with(config, hooks))
+}
+
+/// Scans `path` and returns the report as a dict with the same layout as `--json`.
+#[pyfunction]
+#[pyo3(signature = (path, **options))]
+fn scan(py: Python<'_>, path: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<PyObject> {
+    let config = scanner_config(py, path, options)?;
+    let report = py
+        .allow_threads(|| {
+            let result = run_blocking(&config, ScanHooks::default())?;
+            Ok::<_, anyhow::Error>(export::report_json(&config.target_path, &result, &[])?)
+        })
+        .map_err(|e| PyOSError::new_err(e.to_string()))?;
+    Ok(py.import("json")?.call_method1("loads", (report,))?.unbind())
+}
+
+/// Yields `{"path", "size", "modified"}` dicts while the scan is still running.
+#[pyclass]
+struct EntryIterator {
+    entries: mpsc::UnboundedReceiver<FileEntry>,
+    scan: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+#[pymethods]
+impl EntryIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let entries = &mut self.entries;
+        let Some(entry) = py.allow_threads(|| entries.blocking_recv()) else {
+            // The scan only drops its sender once finished, so surface its outcome now
+            if let Some(handle) = self.scan.take() {
+                match handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return Err(PyOSError::new_err(e.to_string())),
+                    Err(_) => return Err(PyOSError::new_err("scan thread panicked")),
+                }
+            }
+            return Ok(None);
+        };
+        let record = PyDict::new(py);
+        record.set_item("path", entry.path)?;
+        record.set_item("size", entry.size)?;
+        record.set_item(
+            "modified",
+            entry.modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()),
+        )?;
+        Ok(Some(record.into_any().unbind()))
+    }
+}
+
+/// Streams every file under `path` as it is scanned; see `scan` for the options.
+#[pyfunction]
+#[pyo3(signature = (path, **options))]
+fn iter_entries(py: Python<'_>, path: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<EntryIterator> {
+    let config = scanner_config(py, path, options)?;
+    let (entry_tx, entries) = mpsc::unbounded_channel();
+    let scan = std::thread::spawn(move || {
+        let hooks = ScanHooks { entries: Some(entry_tx), ..Default::default() };
+        run_blocking(&config, hooks).map(|_| ())
+    });
+    Ok(EntryIterator { entries, scan: Some(scan) })
+}
+
+#[pymodule]
+fn disk_scanner(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(scan, m)?)?;
+    m.add_function(wrap_pyfunction!(iter_entries, m)?)?;
+    m.add_class::<EntryIterator>()?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::scanner::OwnerUsage;
+use crate::units::parse_size;
+
+/// Per-user quotas loaded from a TOML file:
+///
+/// ```toml
+/// default = "10GB"   # optional, applies to users not listed below
+///
+/// [users]
+/// alice = "50GB"
+/// bob = "1.5TiB"
+/// ```
+#[derive(Debug, Clone)]
+pub struct QuotaConfig {
+    pub default: Option<u64>,
+    pub users: HashMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuotaFile {
+    default: Option<String>,
+    #[serde(default)]
+    users: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaViolation {
+    pub owner: String,
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl QuotaConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read quota file {:?}", path))?;
+        let file: QuotaFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse quota file {:?}", path))?;
+
+        let default = file.default.as_deref()
+            .map(parse_size)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid default quota: {}", e))?;
+        let mut users = HashMap::new();
+        for (user, limit) in file.users {
+            let limit = parse_size(&limit).map_err(|e| anyhow::anyhow!("Invalid quota for '{}': {}", user, e))?;
+            users.insert(user, limit);
+        }
+        Ok(Self { default, users })
+    }
+
+    fn limit_for(&self, owner: &str) -> Option<u64> {
+        self.users.get(owner).copied().or(self.default)
+    }
+
+    /// Owners using more than their quota, worst offenders (by bytes over) first.
+    pub fn violations(&self, owners: &[OwnerUsage]) -> Vec<QuotaViolation> {
+        let mut violations: Vec<QuotaViolation> = owners.iter()
+            .filter_map(|usage| {
+                let owner = usage.display_name();
+                let limit = self.limit_for(&owner)?;
+                (usage.size > limit).then_some(QuotaViolation { owner, used: usage.size, limit })
+            })
+            .collect();
+        violations.sort_by_key(|v| std::cmp::Reverse(v.used - v.limit));
+        violations
+    }
+}
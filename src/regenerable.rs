@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Built-in rules, by the class of regenerable data they match.
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("thumbnails", &[".cache/thumbnails", ".thumbnails", "Thumbs.db", "AppData/Local/Microsoft/Windows/Explorer"]),
+    ("package caches", &[
+        ".cache/pip", ".npm/_cacache", ".cache/yarn", ".local/share/pnpm/store", ".cargo/registry", ".cargo/git",
+        "go/pkg/mod", ".gradle/caches", ".m2/repository", ".nuget/packages", "Library/Caches/pip",
+        "AppData/Local/pip/Cache", "AppData/Local/npm-cache",
+    ]),
+    ("application caches", &[".cache", "Library/Caches", "AppData/Local/Temp"]),
+    ("dependencies", &["node_modules", "bower_components", ".venv", "__pycache__", "*.pyc"]),
+    ("build outputs", &[
+        "target/debug", "target/release", "cmake-build-debug", "cmake-build-release", ".gradle", ".next",
+        "*.o", "*.obj",
+    ]),
+];
+
+/// Splits files into regenerable data (thumbnails, package caches, build outputs: what
+/// tools recreate when it's gone) and user data for `--regenerable`. Starts from the
+/// built-in rules and can be extended from a TOML file:
+///
+/// ```toml
+/// # Never regenerable, whatever other rules say
+/// user_data = ["Library/Caches/com.example.offline-maps"]
+///
+/// [regenerable]
+/// "build outputs" = ["out/bin", "*.tmp"]   # adds to a built-in class
+/// renders = ["blender/cache"]
+/// ```
+///
+/// A rule is either `*.EXT`, matching files by extension, or path components such as
+/// `.cache/pip`, matching files with those components in a row anywhere in their path
+/// (including the file name); both are compared case-insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct Regenerable {
+    /// Checked in order, so rules from a file come before the built-in ones
+    rules: Vec<Rule>,
+    user_data: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    class: Arc<str>,
+    pattern: Pattern,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// Lower-case, without the dot
+    Extension(String),
+    Components(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(default)]
+    regenerable: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    user_data: Vec<String>,
+}
+
+impl Pattern {
+    fn parse(rule: &str) -> Option<Self> {
+        let rule = rule.trim();
+        if let Some(ext) = rule.strip_prefix("*.") {
+            return (!ext.is_empty()).then(|| Pattern::Extension(ext.to_ascii_lowercase()));
+        }
+        let components = components(rule);
+        (!components.is_empty()).then_some(Pattern::Components(components))
+    }
+
+    /// Whether the file with path components `names` matches.
+    fn matches(&self, names: &[&OsStr]) -> bool {
+        self.match_end(names).is_some()
+    }
+
+    /// How many of `names` a match takes in, up to the end of the first run of components
+    /// (all of them for an extension), if the file matches.
+    fn match_end(&self, names: &[&OsStr]) -> Option<usize> {
+        match self {
+            // Skip the first character so dot files such as `.bashrc` have no extension
+            Pattern::Extension(ext) => names.last()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.get(1..)?.rsplit_once('.'))
+                .filter(|(_, last)| last.eq_ignore_ascii_case(ext))
+                .map(|_| names.len()),
+            Pattern::Components(components) => find_components(names, components).map(|start| start + components.len()),
+        }
+    }
+}
+
+/// What a rule matched in a file's path, see `Regenerable::find`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matched<'a> {
+    /// The file's extension (lower-case, without the dot), wherever the file is
+    Extension(&'a str),
+    /// The directory (or the file itself) whose path ends with the rule's components
+    Path(PathBuf),
+}
+
+fn components(rule: &str) -> Vec<String> {
+    rule.split(['/', '\\']).filter(|c| !c.is_empty()).map(str::to_string).collect()
+}
+
+/// Whether `expected` appear in a row among `names`.
+fn contains_components(names: &[&OsStr], expected: &[String]) -> bool {
+    find_components(names, expected).is_some()
+}
+
+/// Where `expected` first appear in a row among `names`.
+fn find_components(names: &[&OsStr], expected: &[String]) -> Option<usize> {
+    names.windows(expected.len()).position(|window| {
+        window.iter().zip(expected).all(|(name, expected)| name.to_str().is_some_and(|n| n.eq_ignore_ascii_case(expected)))
+    })
+}
+
+/// The names of the normal components of `path`, leaving out its root.
+fn names(path: &Path) -> Vec<&OsStr> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Regenerable {
+    pub fn builtin() -> Self {
+        let mut regenerable = Self::default();
+        for (class, rules) in BUILTIN {
+            regenerable.extend(class, rules.iter().copied());
+        }
+        regenerable
+    }
+
+    /// The built-in rules plus those of the file at `path`, which take precedence over
+    /// the built-in ones.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read regenerable rules {:?}", path))?;
+        let file: RuleFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse regenerable rules {:?}", path))?;
+        let mut regenerable = Self::default();
+        for (class, rules) in &file.regenerable {
+            regenerable.extend(class, rules.iter().map(String::as_str));
+        }
+        for (class, rules) in BUILTIN {
+            regenerable.extend(class, rules.iter().copied());
+        }
+        regenerable.user_data = file.user_data.iter().map(|rule| components(rule)).filter(|c| !c.is_empty()).collect();
+        Ok(regenerable)
+    }
+
+    /// Adds `rules` for `class`, after those already there.
+    pub fn extend<'a>(&mut self, class: &str, rules: impl IntoIterator<Item = &'a str>) {
+        let class: Arc<str> = class.into();
+        self.rules.extend(rules.into_iter().filter_map(Pattern::parse).map(|pattern| Rule { class: Arc::clone(&class), pattern }));
+    }
+
+    /// The class of regenerable data the file at `path` belongs to, or `None` for user data.
+    pub fn classify(&self, path: &Path) -> Option<&Arc<str>> {
+        self.rule(&names(path)).map(|rule| &rule.class)
+    }
+
+    /// Like `classify`, but also says what made the file regenerable: for `--emit-excludes`,
+    /// which excludes whole directories where it can.
+    pub fn find(&self, path: &Path) -> Option<(&Arc<str>, Matched<'_>)> {
+        let names = names(path);
+        let rule = self.rule(&names)?;
+        let matched = match &rule.pattern {
+            Pattern::Extension(ext) => Matched::Extension(ext),
+            Pattern::Components(_) => {
+                let end = rule.pattern.match_end(&names)?;
+                // Keep the root and prefix `names` left out, then the matched components
+                let mut normal = 0;
+                let matched: PathBuf = path.components()
+                    .take_while(|c| {
+                        let keep = normal < end;
+                        normal += usize::from(matches!(c, Component::Normal(_)));
+                        keep
+                    })
+                    .collect();
+                Matched::Path(matched)
+            }
+        };
+        Some((&rule.class, matched))
+    }
+
+    fn rule(&self, names: &[&OsStr]) -> Option<&Rule> {
+        if self.user_data.iter().any(|components| contains_components(names, components)) {
+            return None;
+        }
+        self.rules.iter().find(|rule| rule.pattern.matches(names))
+    }
+
+    /// Every rule as `class: rule`, in the order they are checked, then the user data ones.
+    pub fn describe(&self) -> Vec<String> {
+        let rules = self.rules.iter().map(|rule| match &rule.pattern {
+            Pattern::Extension(ext) => format!("{}: *.{}", rule.class, ext),
+            Pattern::Components(components) => format!("{}: {}", rule.class, components.join("/")),
+        });
+        rules.chain(self.user_data.iter().map(|components| format!("user data: {}", components.join("/")))).collect()
+    }
+}
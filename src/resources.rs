@@ -0,0 +1,60 @@
+//! The process's own CPU time and memory, reported by `--resource-usage`.
+
+use std::time::Duration;
+
+/// CPU time and peak memory of this process since it started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessUsage {
+    /// Peak resident set size (working set on Windows), in bytes
+    pub peak_rss: Option<u64>,
+    pub user_cpu: Duration,
+    pub system_cpu: Duration,
+}
+
+#[cfg(unix)]
+pub fn process_usage() -> Option<ProcessUsage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `usage` is a valid out-param
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded, so it filled in the struct
+    let usage = unsafe { usage.assume_init() };
+    let duration = |t: libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+    // Bytes on Apple platforms, KiB everywhere else
+    let rss_unit = if cfg!(target_vendor = "apple") { 1 } else { 1024 };
+    Some(ProcessUsage {
+        peak_rss: u64::try_from(usage.ru_maxrss).ok().map(|rss| rss * rss_unit),
+        user_cpu: duration(usage.ru_utime),
+        system_cpu: duration(usage.ru_stime),
+    })
+}
+
+#[cfg(windows)]
+pub fn process_usage() -> Option<ProcessUsage> {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    let zero = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    let (mut created, mut exited, mut kernel, mut user) = (zero, zero, zero, zero);
+    // SAFETY: the pseudo handle of the current process needs no closing; all pointers are
+    // valid out-params
+    if unsafe { GetProcessTimes(GetCurrentProcess(), &mut created, &mut exited, &mut kernel, &mut user) } == 0 {
+        return None;
+    }
+    // FILETIME counts 100ns intervals
+    let duration = |t: FILETIME| Duration::from_nanos(((t.dwHighDateTime as u64) << 32 | t.dwLowDateTime as u64) * 100);
+    // SAFETY: a plain C struct of integers, for which all zeroes is valid
+    let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    // SAFETY: `counters` is a valid out-param of `size` bytes
+    let peak_rss = (unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) } != 0)
+        .then_some(counters.PeakWorkingSetSize as u64);
+    Some(ProcessUsage { peak_rss, user_cpu: duration(user), system_cpu: duration(kernel) })
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn process_usage() -> Option<ProcessUsage> {
+    None
+}
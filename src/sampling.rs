@@ -0,0 +1,229 @@
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::sync::Semaphore;
+
+use disk_scanner::fd_limit;
+use crate::scanner::{self, ScannerConfig};
+
+/// Directories this close to the root are always walked, so the estimate covers the whole top of the tree.
+const FULL_WALK_DEPTH: usize = 2;
+/// Expected number of sub-directories descended into per visited directory, so narrow
+/// chains of directories don't end the sample early.
+const MIN_DESCENDED_SUBDIRS: f64 = 2.0;
+/// z-score for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// Horvitz-Thompson estimate of a total together with its estimated variance.
+#[derive(Debug, Default, Clone, Copy)]
+struct Estimate {
+    value: f64,
+    variance: f64,
+}
+
+impl Estimate {
+    /// Adds a unit (a file, or a whole sub-tree estimate) that was selected with `probability`.
+    fn add_sampled(&mut self, unit: Estimate, probability: f64) {
+        self.value += unit.value / probability;
+        self.variance += (1.0 - probability) / (probability * probability) * unit.value * unit.value
+            + unit.variance / probability;
+    }
+
+    fn margin(&self) -> f64 {
+        Z_95 * self.variance.max(0.0).sqrt()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SubtreeEstimate {
+    size: Estimate,
+    files: Estimate,
+    dirs: Estimate,
+    sampled_files: u64,
+    visited_dirs: u64,
+    errors: u64,
+}
+
+/// Result of a sampled scan. Totals are extrapolated, margins are 95% confidence half-widths.
+#[derive(Debug)]
+pub struct SizeEstimate {
+    pub fraction: f64,
+    pub total_size: f64,
+    pub size_margin: f64,
+    pub total_files: f64,
+    pub files_margin: f64,
+    pub total_directories: f64,
+    pub directories_margin: f64,
+    pub sampled_files: u64,
+    pub visited_directories: u64,
+    pub errors: u64,
+    pub scan_duration: Duration,
+}
+
+/// Deterministic Bernoulli selection, so repeated runs over an unchanged tree give the same answer.
+fn is_selected(path: &Path, fraction: f64) -> bool {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    (hasher.finish() as f64 / u64::MAX as f64) < fraction
+}
+
+fn estimate_directory_recursive(
+    current_path: PathBuf,
+    depth: usize,
+    fraction: f64,
+    config: Arc<ScannerConfig>,
+    semaphore: Arc<Semaphore>,
+) -> Pin<Box<dyn Future<Output = SubtreeEstimate> + Send + 'static>> {
+    Box::pin(async move {
+        let mut estimate = SubtreeEstimate { visited_dirs: 1, ..Default::default() };
+
+        let permit = match semaphore.acquire().await {
+            Ok(p) => p,
+            Err(_) => return estimate,
+        };
+
+        let mut entries_reader = match fs::read_dir(&current_path).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                if config.verbose {
+                    println!("[VERBOSE] Failed to read {:?}: {}", &current_path, e);
+                }
+                estimate.errors += 1;
+                return estimate;
+            }
+        };
+
+        let mut sub_dirs = Vec::new();
+        loop {
+            let entry = match entries_reader.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(_) => {
+                    estimate.errors += 1;
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            if !config.include_hidden
+                && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'))
+            {
+                continue;
+            }
+
+            let file_type = match entry.file_type().await {
+                Ok(ft) => ft,
+                Err(_) => {
+                    estimate.errors += 1;
+                    continue;
+                }
+            };
+
+            if file_type.is_symlink() {
+                if !config.follow_symlinks {
+                    continue;
+                }
+                // The link has to be resolved to classify it, so its size comes for free
+                match fs::metadata(&path).await {
+                    Ok(meta) if meta.is_file() && !config.wanted_file(&path) => {}
+                    Ok(meta) if meta.is_file() => {
+                        estimate.files.value += 1.0;
+                        estimate.size.add_sampled(Estimate { value: meta.len() as f64, variance: 0.0 }, 1.0);
+                    }
+                    Ok(meta) if meta.is_dir() => sub_dirs.push(path),
+                    Ok(_) => {}
+                    Err(_) => estimate.errors += 1,
+                }
+            } else if file_type.is_file() {
+                if !config.wanted_file(&path) {
+                    continue;
+                }
+                estimate.files.value += 1.0;
+                if !is_selected(&path, fraction) {
+                    continue;
+                }
+                match entry.metadata().await {
+                    Ok(meta) => {
+                        estimate.sampled_files += 1;
+                        estimate.size.add_sampled(Estimate { value: meta.len() as f64, variance: 0.0 }, fraction);
+                    }
+                    Err(_) => estimate.errors += 1,
+                }
+            } else if file_type.is_dir() {
+                sub_dirs.push(path);
+            }
+        }
+        drop(permit);
+
+        let mut tasks = Vec::new();
+        let probability = if depth < FULL_WALK_DEPTH {
+            1.0
+        } else {
+            fraction.max(MIN_DESCENDED_SUBDIRS / sub_dirs.len() as f64).min(1.0)
+        };
+        for sub_path in sub_dirs {
+            estimate.dirs.value += 1.0;
+            if probability < 1.0 && !is_selected(&sub_path, probability) {
+                continue;
+            }
+            let task = tokio::spawn(estimate_directory_recursive(
+                sub_path,
+                depth + 1,
+                fraction,
+                Arc::clone(&config),
+                Arc::clone(&semaphore),
+            ));
+            tasks.push((task, probability));
+        }
+
+        for (task, probability) in tasks {
+            match task.await {
+                Ok(sub) => {
+                    estimate.size.add_sampled(sub.size, probability);
+                    estimate.files.add_sampled(sub.files, probability);
+                    estimate.dirs.add_sampled(sub.dirs, probability);
+                    estimate.sampled_files += sub.sampled_files;
+                    estimate.visited_dirs += sub.visited_dirs;
+                    estimate.errors += sub.errors;
+                }
+                Err(_) => estimate.errors += 1,
+            }
+        }
+        estimate
+    })
+}
+
+/// Estimates the size of the tree by stat-ing only `fraction` of the files and
+/// descending into (at least) `fraction` of the directories below the top levels.
+pub async fn estimate_size(config: &ScannerConfig, fraction: f64) -> Result<SizeEstimate, anyhow::Error> {
+    let start_time = Instant::now();
+    scanner::ensure_directory(&config.target_path).await?;
+
+    let semaphore = Arc::new(Semaphore::new(fd_limit::cap_tasks(config.max_concurrent_tasks)));
+    let estimate = estimate_directory_recursive(
+        config.target_path.clone(),
+        0,
+        fraction,
+        Arc::new(config.clone()),
+        semaphore,
+    ).await;
+
+    Ok(SizeEstimate {
+        fraction,
+        total_size: estimate.size.value,
+        size_margin: estimate.size.margin(),
+        total_files: estimate.files.value,
+        files_margin: estimate.files.margin(),
+        // The root itself is always counted
+        total_directories: estimate.dirs.value + 1.0,
+        directories_margin: estimate.dirs.margin(),
+        sampled_files: estimate.sampled_files,
+        visited_directories: estimate.visited_dirs,
+        errors: estimate.errors,
+        scan_duration: start_time.elapsed(),
+    })
+}
@@ -1,25 +1,79 @@
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::fs;
-use tokio::sync::{mpsc, Semaphore};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+#[cfg(feature = "native")]
+use tokio::sync::Semaphore;
 use thiserror::Error;
+#[cfg(feature = "native")]
 use std::future::Future;
-use std::pin::Pin;
-use regex::Regex;
 
-use crate::progress::{ProgressUpdate, ProgressReporter};
+use crate::progress::ProgressUpdate;
+use crate::owners;
+use crate::fd_limit;
+use crate::resources::{self, ProcessUsage};
+use crate::volume::{self, BlockDevice, InodeUsage, LocalSnapshots, VolumeUsage};
+use crate::metrics::MetricsSnapshot;
+#[cfg(feature = "native")]
+use crate::metrics::ScanMetrics;
+use crate::timings::{Phase, PhaseTiming, PhaseTimings};
 
-#[derive(Debug, Clone)]
-pub struct ScannerConfig {
-    pub target_path: PathBuf,
-    pub max_concurrent_tasks: usize,
-    pub follow_symlinks: bool,
-    pub include_hidden: bool,
-    pub progress_updates: bool,
-    pub verbose: bool,
-    pub file_pattern: Option<Regex>,
-}
+mod config;
+mod visitor;
+mod prune;
+mod filter_rules;
+mod extension;
+mod ignore_errors;
+mod permissions;
+mod acl;
+pub(crate) mod xattr;
+mod attributes;
+mod encoding;
+mod line_endings;
+mod chunking;
+mod fiemap;
+mod caches;
+mod mounts;
+mod subvolumes;
+mod trash;
+mod filesystem;
+mod spill;
+#[cfg(feature = "memory-fs")]
+mod memory_fs;
+#[cfg(feature = "native")]
+mod control;
+#[cfg(feature = "native")]
+mod async_engine;
+#[cfg(feature = "native")]
+mod priority;
+mod sync_engine;
+
+pub use config::{ConfigError, Engine, ScannerConfig, ScannerConfigBuilder};
+pub use visitor::{ScanVisitor, VisitAction};
+pub use prune::{DirInfo, PruneParseError, PruneRule};
+pub use filter_rules::{FilterRuleError, FilterRules};
+pub use extension::ExtensionFilter;
+pub use ignore_errors::IgnoredErrors;
+pub use permissions::{PermissionFilter, PermissionParseError};
+pub use attributes::FileAttribute;
+pub use encoding::{EncodingCounts, TextEncoding, SAMPLE_LEN};
+pub use line_endings::{LineEnding, LineEndingCounts, LINE_ENDING_SAMPLE_LEN};
+pub use chunking::{MAX_DEDUP_CHUNK_SIZE, MIN_DEDUP_CHUNK_SIZE};
+use chunking::{ChunkIndex, Chunker};
+pub(crate) use extension::last_extension;
+pub use filesystem::{EntryMetadata, EntryType, FsEntry, RealFs, ScanFs};
+use filesystem::{CountingFs, FsCallCounts};
+pub use spill::{Spill, SpillList};
+use spill::SpillStore;
+#[cfg(feature = "memory-fs")]
+pub use memory_fs::MemoryFs;
+#[cfg(feature = "native")]
+pub use async_engine::{ensure_directory, run_scan, run_scan_with, scan_with_visitor, ScanHooks};
+#[cfg(feature = "native")]
+pub use control::ScanControl;
+pub use sync_engine::run_scan_sync;
 
 #[derive(Debug, Error)]
 pub enum ScanError {
@@ -33,267 +87,1720 @@ pub enum ScanError {
     MetadataError { path: PathBuf, source: std::io::Error },
 }
 
+impl ScanError {
+    pub fn path(&self) -> &Path {
+        match self {
+            ScanError::IoError { path, .. } | ScanError::NotADirectory { path } | ScanError::MetadataError { path, .. } => path,
+        }
+    }
+}
+
+/// A `ScanError` at the moment it happened, for `ScanHooks::errors`.
+#[derive(Debug, Clone)]
+pub struct ErrorEvent {
+    pub path: PathBuf,
+    /// The `ScanError` variant: "io_error", "metadata_error" or "not_a_directory"
+    pub kind: &'static str,
+    /// `std::io::ErrorKind` of the cause, e.g. "PermissionDenied"
+    pub io_kind: Option<String>,
+    /// Raw OS error code of the cause, e.g. 13 (EACCES) on Linux
+    pub os_error: Option<i32>,
+    pub message: String,
+    pub time: SystemTime,
+}
+
+impl ErrorEvent {
+    pub fn new(error: &ScanError) -> Self {
+        let (path, kind, source) = match error {
+            ScanError::IoError { path, source } => (path, "io_error", Some(source)),
+            ScanError::NotADirectory { path } => (path, "not_a_directory", None),
+            ScanError::MetadataError { path, source } => (path, "metadata_error", Some(source)),
+        };
+        Self {
+            path: path.clone(),
+            kind,
+            io_kind: source.map(|e| format!("{:?}", e.kind())),
+            os_error: source.and_then(std::io::Error::raw_os_error),
+            message: error.to_string(),
+            time: SystemTime::now(),
+        }
+    }
+}
+
+/// A file whose name matched `ScannerConfig::file_pattern`.
+#[derive(Debug, Clone)]
+pub struct MatchedFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// A file in the per-file inventory, collected with `ScannerConfig::collect_entries`.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Numeric owner, where the platform has one
+    pub owner: Option<u32>,
+}
+
+/// CPU, memory and filesystem calls a scan cost the process.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// Peak resident set size of the whole process, in bytes
+    pub peak_rss: Option<u64>,
+    /// CPU time spent while scanning
+    pub user_cpu: Duration,
+    pub system_cpu: Duration,
+    pub read_dir_calls: u64,
+    /// `stat`/`lstat` calls
+    pub metadata_calls: u64,
+    /// Files opened, with `--locked-files`
+    pub open_calls: u64,
+    /// Walker tasks spawned; `None` for the synchronous engine
+    pub tasks_spawned: Option<u64>,
+}
+
+/// Disk usage attributed to a single git repository.
+#[derive(Debug, Clone, Default)]
+pub struct RepoUsage {
+    pub root: PathBuf,
+    /// Bytes in checked-out files, excluding the `.git` directory
+    pub working_tree_size: u64,
+    /// Bytes inside the `.git` directory (objects, packs, refs, ...)
+    pub git_dir_size: u64,
+}
+
+impl RepoUsage {
+    pub fn total_size(&self) -> u64 {
+        self.working_tree_size + self.git_dir_size
+    }
+}
+
+/// Repository a directory being walked belongs to.
+#[derive(Debug, Clone)]
+struct RepoContext {
+    root: Arc<PathBuf>,
+    in_git_dir: bool,
+}
+
+impl RepoContext {
+    /// Context for a sub-directory; the repository's own `.git` directory flips `in_git_dir`.
+    fn for_child(&self, sub_path: &Path) -> RepoContext {
+        RepoContext {
+            in_git_dir: self.in_git_dir
+                || (sub_path.parent() == Some(self.root.as_path())
+                    && sub_path.file_name().is_some_and(|n| n == ".git")),
+            root: Arc::clone(&self.root),
+        }
+    }
+}
+
+/// How many directories `ChurnReport::directories` lists.
+pub const CHURNED_DIRECTORIES: usize = 20;
+
+/// Entries that disappeared between their directory's listing and the calls that looked
+/// at them, typically temporary files or files being renamed into place. They're left
+/// out of the totals, which mix the tree from before and after the changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChurnReport {
+    pub vanished: u64,
+    /// The `CHURNED_DIRECTORIES` directories with the most vanished entries, most first
+    pub directories: Vec<ChurnedDirectory>,
+    /// Directories listed again by `ScannerConfig::rescan_changed`, counted per listing
+    pub rescans: u64,
+    /// Entries only those listings found
+    pub recovered: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChurnedDirectory {
+    pub path: PathBuf,
+    pub vanished: u64,
+}
+
+/// How many links `SharedLinkReport::links` lists.
+pub const SHARED_LINKS_SHOWN: usize = 20;
+
+/// Symlinks `ScannerConfig::follow_symlinks` found leading to data the scan counts anyway:
+/// a target inside the scanned tree, or one an earlier link already led to or into.
+#[derive(Debug, Clone, Default)]
+pub struct SharedLinkReport {
+    /// The links were followed and their targets counted again, with
+    /// `ScannerConfig::count_links_separately`; otherwise they were skipped
+    pub counted_again: bool,
+    pub files: u64,
+    /// Bytes of the files the links lead to
+    pub file_size: u64,
+    pub directories: u64,
+    /// The first `SHARED_LINKS_SHOWN` links, those to directories before those to files,
+    /// then the largest files first
+    pub links: Vec<SharedLink>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SharedLink {
+    pub link: PathBuf,
+    /// Where the link leads, with every symlink resolved
+    pub target: PathBuf,
+    /// Where the target is counted: the target itself, or below the first link to it
+    pub counted_at: PathBuf,
+    pub is_dir: bool,
+    /// Bytes of a file target
+    pub size: u64,
+}
+
+/// Keeps the first `SHARED_LINKS_SHOWN` links in the order of `SharedLinkReport::links`.
+fn keep_first_shared_links(links: &mut Vec<SharedLink>) {
+    links.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| b.size.cmp(&a.size)).then_with(|| a.link.cmp(&b.link)));
+    links.truncate(SHARED_LINKS_SHOWN);
+}
+
+/// Cloud placeholder files, see `EntryMetadata::placeholder`. Their full size counts in
+/// the scan's totals, though most of it is only in the cloud.
+#[derive(Debug, Clone, Default)]
+pub struct PlaceholderReport {
+    pub files: u64,
+    /// Their size as listed, as it would be once downloaded
+    pub logical_size: u64,
+    /// What of them is downloaded and stored locally
+    pub local_size: u64,
+}
+
+/// Files not used within `ScannerConfig::cold_data_age`, aggregated per directory.
+#[derive(Debug)]
+pub struct ColdDataReport {
+    pub age: Duration,
+    pub files: u64,
+    pub size: u64,
+    /// Sorted by cold size, largest first
+    pub directories: SpillList<ColdDirectory>,
+}
+
 #[derive(Debug)]
 pub struct ScanResult {
     pub total_files: u64,
     pub total_directories: u64,
     pub total_size: u64,
+    /// When the walk began
+    pub started: SystemTime,
     pub scan_duration: Duration,
+    /// `ScannerConfig::config_hash` of the configuration scanned with
+    pub config_hash: String,
     pub errors: Vec<ScanError>,
-    pub matching_files: Vec<PathBuf>,
+    /// Errors left out of `errors` by `ScannerConfig::ignore_errors_under`
+    pub ignored_errors: u64,
+    /// Entries that changed under the scan
+    pub churn: ChurnReport,
+    /// Only collected with `ScannerConfig::follow_symlinks`
+    pub shared_links: Option<SharedLinkReport>,
+    /// Spilled to disk past `ScannerConfig::memory_limit`, like `entries`
+    pub matching_files: SpillList<MatchedFile>,
+    pub entries: SpillList<FileEntry>,
+    pub cold_data: Option<ColdDataReport>,
+    /// Only set when the scan found placeholders
+    pub placeholders: Option<PlaceholderReport>,
+    pub repositories: Vec<RepoUsage>,
+    /// Sorted by size, largest first; only collected with `ScannerConfig::by_owner`
+    pub owners: Vec<OwnerUsage>,
+    /// Only collected with `ScannerConfig::detect_locked_files`
+    pub locked_files: Vec<LockedFile>,
+    /// Sorted by path; only collected with `ScannerConfig::acls`
+    pub acls: Vec<ExplicitAcl>,
+    /// Only collected with `ScannerConfig::selinux`
+    pub selinux: Option<SelinuxReport>,
+    /// Sorted by path; only collected with `ScannerConfig::detect_immutable`
+    pub immutable_files: Vec<ImmutableFile>,
+    /// Directories with sampled text files, sorted by path; only collected with
+    /// `ScannerConfig::sample_encodings`
+    pub encodings: Vec<DirectoryEncodings>,
+    /// Directories with sampled text files, sorted by path; only collected with
+    /// `ScannerConfig::line_endings`
+    pub line_endings: Vec<DirectoryLineEndings>,
+    /// Labels from the directories' `.dirinfo` or README files; only collected with
+    /// `ScannerConfig::directory_labels`
+    pub directory_labels: BTreeMap<PathBuf, String>,
+    /// Sorted by size, largest first; only collected with `ScannerConfig::categories`
+    pub categories: Vec<CategoryUsage>,
+    /// Only collected with `ScannerConfig::regenerable`
+    pub regenerable: Option<RegenerableReport>,
+    /// Per block size of `ScannerConfig::target_block_sizes`, smallest first
+    pub target_usage: Vec<TargetUsage>,
+    /// Only estimated with `ScannerConfig::dedup_chunk_size`
+    pub dedup: Option<DedupEstimate>,
+    /// Cache directories left out by `ScannerConfig::skip_caches`, largest first
+    pub skipped_caches: Vec<CacheUsage>,
+    /// Mounts left out by `ScannerConfig::skip_fs_types`, sorted by path
+    pub skipped_mounts: Vec<SkippedMount>,
+    /// Snapshot directories left out by `ScannerConfig::skip_snapshots`, sorted
+    pub skipped_snapshots: Vec<PathBuf>,
+    /// Sorted by path; only collected with `ScannerConfig::subvolumes`
+    pub subvolumes: Vec<SubvolumeUsage>,
+    /// Only collected with `ScannerConfig::fragmentation`
+    pub fragmentation: Option<FragmentationReport>,
+    /// Trash and recycle-bin directories, largest first; counted in the totals too
+    pub trash: Vec<TrashUsage>,
+    /// The `BUSIEST_DIRECTORIES` directories with the most entries, most first; only
+    /// collected with `ScannerConfig::inode_report` or `ScannerConfig::fan_out`
+    pub busiest_directories: Vec<DirectoryCount>,
+    /// Only collected with `ScannerConfig::fan_out`
+    pub fan_out: Option<FanOutReport>,
+    /// The `HEAVIEST_EXTENSIONS` extensions by size, largest first; only collected with
+    /// `ScannerConfig::top_per_extension`
+    pub extensions: Vec<ExtensionUsage>,
+    /// Internal counters, only collected with `ScannerConfig::debug_metrics`
+    pub metrics: Option<MetricsSnapshot>,
+    /// Per-phase time breakdown, only collected with `ScannerConfig::timings`
+    pub timings: Option<Vec<PhaseTiming>>,
+    /// What the scan cost, only collected with `ScannerConfig::resource_usage`
+    pub resource_usage: Option<ResourceUsage>,
+    /// Capacity of the volume holding the scan root, if the platform reports it
+    pub volume: Option<VolumeUsage>,
+    /// The disk behind that volume, only looked up with `ScannerConfig::device_info`
+    pub device: Option<BlockDevice>,
+    /// Local snapshots holding space on the volume (APFS on macOS)
+    pub snapshots: Option<LocalSnapshots>,
+    /// The scan was cancelled and the totals cover only part of the tree
+    pub cancelled: bool,
+    /// Set when the open file limit held the scan back
+    pub open_files: Option<OpenFileLimit>,
 }
 
-fn walk_directory_recursive(
-    current_path: PathBuf,
-    config: Arc<ScannerConfig>,
-    semaphore: Arc<Semaphore>,
-    progress_tx: Option<mpsc::UnboundedSender<ProgressUpdate>>,
-) -> Pin<Box<dyn Future<Output = (u64, u64, u64, Vec<ScanError>, Vec<PathBuf>)> + Send + 'static>> {
-    Box::pin(async move {
-        let permit = match semaphore.acquire().await { // Acquire semaphore
-            Ok(p) => p,
-            Err(_) => return (0, 0, 0, vec![], vec![]),
-        };
+/// Files in one directory that were neither accessed nor modified since the cold-data cutoff.
+#[derive(Debug, Clone)]
+pub struct ColdDirectory {
+    pub path: PathBuf,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// Disk usage of a single file owner.
+#[derive(Debug, Clone, Default)]
+pub struct OwnerUsage {
+    pub uid: u32,
+    /// Account name, when the uid resolves on this machine
+    pub name: Option<String>,
+    pub files: u64,
+    pub size: u64,
+}
+
+impl OwnerUsage {
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.uid.to_string())
+    }
+}
+
+/// Disk usage of the files in one category, see `Categories`.
+#[derive(Debug, Clone, Default)]
+pub struct CategoryUsage {
+    pub name: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// Files tools recreate when they're gone, see `Regenerable`; all other files are user data.
+#[derive(Debug, Clone, Default)]
+pub struct RegenerableReport {
+    pub files: u64,
+    /// What deleting all of it would reclaim
+    pub size: u64,
+    /// Per class of regenerable data, largest first
+    pub classes: Vec<CategoryUsage>,
+}
+
+/// How well the files would dedupe in a backup tool storing each distinct content-defined
+/// chunk once, see `ScannerConfig::dedup_chunk_size`.
+#[derive(Debug, Clone, Default)]
+pub struct DedupEstimate {
+    /// Average chunk size the files were split with
+    pub chunk_size: u64,
+    /// Files read, and the bytes they held
+    pub files: u64,
+    pub bytes: u64,
+    pub chunks: u64,
+    pub unique_chunks: u64,
+    /// What storing each distinct chunk once takes, before compression
+    pub unique_bytes: u64,
+}
+
+impl DedupEstimate {
+    /// Bytes read per byte stored; 1.0 when nothing repeats.
+    pub fn ratio(&self) -> f64 {
+        if self.unique_bytes == 0 { 1.0 } else { self.bytes as f64 / self.unique_bytes as f64 }
+    }
+}
+
+/// The tree's usage on a target that allocates space in blocks of `block_size`, such as
+/// a filesystem with that cluster size or an object store billing in those units.
+#[derive(Debug, Clone, Default)]
+pub struct TargetUsage {
+    pub block_size: u64,
+    /// Every file rounded up to whole blocks; empty files take none
+    pub allocated: u64,
+    /// Non-empty files smaller than one block, which waste most of theirs
+    pub small_files: u64,
+}
+
+impl TargetUsage {
+    /// What allocation adds to `size` bytes of file data.
+    pub fn overhead(&self, size: u64) -> u64 {
+        self.allocated.saturating_sub(size)
+    }
+}
+
+/// How many directories `ScannerConfig::inode_report` lists.
+pub const BUSIEST_DIRECTORIES: usize = 20;
+
+/// Directories with at least this many entries are flagged by the inode report; listing,
+/// backing up or deleting them gets slow on most filesystems.
+pub const PATHOLOGICAL_ENTRIES: u64 = 100_000;
+
+/// Entries (and so inodes) directly inside one directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryCount {
+    pub path: PathBuf,
+    /// Every entry the directory listing returned, including hidden and filtered ones,
+    /// symlinks and special files
+    pub children: u64,
+    /// Of those, the files and sub-directories that were counted
+    pub files: u64,
+    pub directories: u64,
+}
+
+impl DirectoryCount {
+    /// Whether the directory holds `PATHOLOGICAL_ENTRIES` entries or more, or at least
+    /// 1% of all inodes of its volume.
+    pub fn is_pathological(&self, inodes: Option<&InodeUsage>) -> bool {
+        self.children >= PATHOLOGICAL_ENTRIES || inodes.is_some_and(|i| self.children * 100 >= i.total)
+    }
+}
+
+/// Upper bounds (exclusive) of the fan-out buckets; the last bucket is open-ended.
+pub const FAN_OUT_BOUNDS: [u64; 7] = [1, 10, 100, 1_000, 10_000, 100_000, 1_000_000];
 
-        if config.verbose {
-            println!("[VERBOSE] Reading directory (permit acquired): {:?}", &current_path);
+/// How many immediate children the directories of a tree have.
+#[derive(Debug, Clone, Default)]
+pub struct FanOutReport {
+    /// Directories per bucket: no children, 1-9, 10-99, ... up to 1,000,000 and more,
+    /// see `FAN_OUT_BOUNDS`
+    pub buckets: [u64; FAN_OUT_BOUNDS.len() + 1],
+    pub directories: u64,
+    pub children: u64,
+    /// Most children of any one directory
+    pub max_children: u64,
+}
+
+impl FanOutReport {
+    fn add(&mut self, children: u64) {
+        self.buckets[FAN_OUT_BOUNDS.partition_point(|&bound| bound <= children)] += 1;
+        self.directories += 1;
+        self.children += children;
+        self.max_children = self.max_children.max(children);
+    }
+
+    fn merge(&mut self, other: &FanOutReport) {
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += count;
+        }
+        self.directories += other.directories;
+        self.children += other.children;
+        self.max_children = self.max_children.max(other.max_children);
+    }
+
+    pub fn mean_children(&self) -> f64 {
+        if self.directories == 0 { 0.0 } else { self.children as f64 / self.directories as f64 }
+    }
+}
+
+/// How many extensions `ScannerConfig::top_per_extension` reports files for.
+pub const HEAVIEST_EXTENSIONS: usize = 10;
+
+/// Disk usage of the files with one extension, with the largest of them.
+#[derive(Debug, Clone)]
+pub struct ExtensionUsage {
+    /// Lower case without the dot; empty for files without an extension
+    pub extension: String,
+    pub files: u64,
+    pub size: u64,
+    /// Largest first, at most `ScannerConfig::top_per_extension` files
+    pub largest: Vec<FileEntry>,
+}
+
+impl ExtensionUsage {
+    /// Keeps the `count` largest files, largest first, breaking ties by path so results
+    /// do not depend on the order tasks finish in.
+    fn keep_largest(&mut self, count: usize) {
+        self.largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        self.largest.truncate(count);
+    }
+}
+
+/// A package-manager, browser or game cache that was measured but left out of the totals.
+#[derive(Debug, Clone)]
+pub struct CacheUsage {
+    pub path: PathBuf,
+    /// Which cache it is, e.g. "pip" or "steam shaders"
+    pub kind: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// A trash or recycle-bin directory and what it held, all of it counted in the totals too.
+#[derive(Debug, Clone)]
+pub struct TrashUsage {
+    pub path: PathBuf,
+    /// Which trash it is, e.g. "freedesktop" or "recycle bin"
+    pub kind: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+impl TrashUsage {
+    /// The directories whose entries are the invoking user's trashed items, which emptying
+    /// the trash removes; the directories themselves are part of the trash's layout and stay.
+    pub fn bins(&self) -> Vec<PathBuf> {
+        trash::bins(&self.path)
+    }
+}
+
+/// How many of the most fragmented files `FragmentationReport::most_fragmented` lists.
+pub const FRAGMENTED_FILES: usize = 20;
+
+/// Files whose extents average less than this are flagged as fragmented.
+pub const FRAGMENTED_EXTENT_SIZE: u64 = 4 << 20;
+
+/// Extent counts of the files of at least `ScannerConfig::fragmentation` bytes, where the
+/// filesystem reports them.
+#[derive(Debug, Clone, Default)]
+pub struct FragmentationReport {
+    pub min_size: u64,
+    pub files: u64,
+    pub extents: u64,
+    /// Files in more than one extent averaging under `FRAGMENTED_EXTENT_SIZE`
+    pub fragmented: u64,
+    /// The `FRAGMENTED_FILES` fragmented files in the most extents, most first
+    pub most_fragmented: Vec<FragmentedFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FragmentedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub extents: u64,
+}
+
+impl FragmentedFile {
+    pub fn mean_extent_size(&self) -> u64 {
+        self.size / self.extents.max(1)
+    }
+}
+
+/// Usage of one subvolume or dataset: the files on one device, by the topmost directory
+/// of the tree on it.
+#[derive(Debug, Clone)]
+pub struct SubvolumeUsage {
+    pub root: PathBuf,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// A mount point the scan didn't descend into, as its filesystem type is skipped.
+#[derive(Debug, Clone)]
+pub struct SkippedMount {
+    pub path: PathBuf,
+    /// As the mount table names it, e.g. `nfs4` or `fuse.sshfs`
+    pub fs_type: String,
+}
+
+/// How the process's open file limit affected a scan.
+#[derive(Debug, Clone)]
+pub struct OpenFileLimit {
+    /// The soft limit, if the platform reports one
+    pub limit: Option<u64>,
+    /// Concurrent tasks asked for by `ScannerConfig::max_concurrent_tasks`
+    pub requested_tasks: usize,
+    /// Concurrent tasks actually used
+    pub tasks: usize,
+    /// Directories left unread because file descriptors ran out anyway
+    pub unreadable_directories: u64,
+}
+
+/// A file that could not be opened for reading because it is in use.
+#[derive(Debug, Clone)]
+pub struct LockedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: String,
+}
+
+/// A file or directory whose access control list differs from what it inherits from its
+/// parent directory.
+#[derive(Debug, Clone)]
+pub struct ExplicitAcl {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// What wasn't inherited, e.g. `user:1000:rwx,group:27:r-x` for POSIX ACLs
+    pub description: String,
+}
+
+/// A file with attributes that stop it from being deleted or rewritten.
+#[derive(Debug, Clone)]
+pub struct ImmutableFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub attributes: Vec<FileAttribute>,
+}
+
+/// Encodings of the text files directly inside one directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryEncodings {
+    pub path: PathBuf,
+    pub counts: EncodingCounts,
+}
+
+impl ScanResult {
+    /// The label `ScannerConfig::directory_labels` found for `dir`, if any.
+    pub fn directory_label(&self, dir: &Path) -> Option<&str> {
+        self.directory_labels.get(dir).map(String::as_str)
+    }
+}
+
+/// Files whose first line labels the directory they are in, most preferred first.
+const LABEL_FILES: [&str; 4] = [".dirinfo", "README", "README.md", "README.txt"];
+
+/// Bytes read from a label file, plenty for its first line.
+pub const LABEL_READ_LEN: usize = 1024;
+
+/// Labels are cut to this many characters.
+const MAX_LABEL_LEN: usize = 80;
+
+/// Line endings of the text files directly inside one directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryLineEndings {
+    pub path: PathBuf,
+    pub counts: LineEndingCounts,
+}
+
+/// How many mislabeled files `ScannerConfig::selinux` lists.
+pub const MISLABELED_SHOWN: usize = 100;
+
+/// The `UNLABELED` type collects files without an SELinux context.
+pub const UNLABELED: &str = "(unlabeled)";
+
+/// SELinux labels of the scanned files.
+#[derive(Debug, Clone, Default)]
+pub struct SelinuxReport {
+    /// Files and bytes per context type, largest first
+    pub types: Vec<LabelUsage>,
+    /// The first `MISLABELED_SHOWN` mislabeled files by path: those without a label, or
+    /// with a type that wasn't expected
+    pub mislabeled: Vec<MislabeledFile>,
+    /// All mislabeled files
+    pub mislabeled_files: u64,
+}
+
+/// Files whose SELinux context has one type, e.g. `httpd_sys_content_t`.
+#[derive(Debug, Clone)]
+pub struct LabelUsage {
+    pub label_type: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MislabeledFile {
+    pub path: PathBuf,
+    /// The full context, `None` for unlabeled files
+    pub context: Option<String>,
+}
+
+/// Totals collected by a single walker task and everything below it.
+#[derive(Debug, Default)]
+struct WalkTotals {
+    files: u64,
+    dirs: u64,
+    size: u64,
+    errors: Vec<ScanError>,
+    matching_files: Vec<MatchedFile>,
+    entries: Vec<FileEntry>,
+    repositories: HashMap<PathBuf, RepoUsage>,
+    cold_files: u64,
+    cold_size: u64,
+    placeholders: PlaceholderReport,
+    cold_directories: Vec<ColdDirectory>,
+    busiest_directories: Vec<DirectoryCount>,
+    fan_out: FanOutReport,
+    /// Entries listed in the directory these totals started from; not merged
+    children: u64,
+    owners: HashMap<u32, OwnerUsage>,
+    /// Files and bytes per category name
+    categories: HashMap<Arc<str>, (u64, u64)>,
+    /// Files and bytes per class of regenerable data
+    regenerable: HashMap<Arc<str>, (u64, u64)>,
+    /// Allocated bytes and files smaller than a block, per modeled block size
+    target_usage: HashMap<u64, (u64, u64)>,
+    /// Per extension, each with at most `top_per_extension` files
+    extensions: HashMap<String, ExtensionUsage>,
+    locked_files: Vec<LockedFile>,
+    acls: Vec<ExplicitAcl>,
+    /// Files and bytes per SELinux type
+    labels: HashMap<String, (u64, u64)>,
+    mislabeled: Vec<MislabeledFile>,
+    mislabeled_files: u64,
+    immutable_files: Vec<ImmutableFile>,
+    /// Per directory of the sampled files
+    encodings: HashMap<PathBuf, EncodingCounts>,
+    line_endings: HashMap<PathBuf, LineEndingCounts>,
+    directory_labels: Vec<(PathBuf, String)>,
+    skipped_caches: Vec<CacheUsage>,
+    skipped_mounts: Vec<SkippedMount>,
+    skipped_snapshots: Vec<PathBuf>,
+    /// The topmost directory seen on each device, with `ScannerConfig::subvolumes`
+    subvolume_roots: HashMap<u64, PathBuf>,
+    /// Files and bytes per device
+    subvolumes: HashMap<u64, (u64, u64)>,
+    /// Files whose extents were counted, and their extents
+    extent_files: u64,
+    extents: u64,
+    fragmented: Vec<FragmentedFile>,
+    trash: Vec<TrashUsage>,
+    /// Directories whose listing failed with "too many open files"
+    descriptors_exhausted: u64,
+    ignored_errors: u64,
+    /// Vanished entries per directory they were listed in
+    churn: HashMap<PathBuf, u64>,
+    vanished: u64,
+    rescans: u64,
+    recovered: u64,
+    /// Entries of the directory these totals started from that vanished, with
+    /// `rescan_changed`, so another listing may find them again; not merged
+    vanished_entries: Vec<PathBuf>,
+    shared_links: Vec<SharedLink>,
+    shared_link_files: u64,
+    shared_link_size: u64,
+    shared_link_directories: u64,
+    /// Items and bytes found since the last progress update; not merged
+    unreported_items: u64,
+    unreported_bytes: u64,
+}
+
+/// Entries of one directory after which walkers send a progress update before the
+/// directory is done, so huge directories still move the counters.
+const PROGRESS_BATCH: u64 = 4096;
+
+impl WalkTotals {
+    fn add_file_size(&mut self, size: u64, repo: Option<&RepoContext>) {
+        self.size += size;
+        if let Some(repo) = repo {
+            let root = repo.root.as_path();
+            let usage = self.repositories.entry(root.to_path_buf())
+                .or_insert_with(|| RepoUsage { root: root.to_path_buf(), ..Default::default() });
+            if repo.in_git_dir {
+                usage.git_dir_size += size;
+            } else {
+                usage.working_tree_size += size;
+            }
+        }
+    }
+
+    /// Notes that the directory at `path` is on `device`, which starts a subvolume there
+    /// unless a directory above it is on that device too.
+    fn subvolume_directory(&mut self, device: u64, path: &Path) {
+        let shallower = |a: &Path, b: &Path| (a.components().count(), a) < (b.components().count(), b);
+        match self.subvolume_roots.get_mut(&device) {
+            Some(root) if shallower(path, root) => *root = path.to_path_buf(),
+            Some(_) => {}
+            None => {
+                self.subvolume_roots.insert(device, path.to_path_buf());
+            }
         }
+    }
 
-        let mut files_count = 0;
-        let mut dirs_count = 0;
-        let mut current_size = 0;
-        let mut errors = Vec::new();
-        let mut sub_task_paths_to_spawn = Vec::new();
-        let mut matching_files_in_dir = Vec::new();
+    /// Registers `path` as the root of a (possibly nested) git repository.
+    fn start_repository(&mut self, path: &Path) -> RepoContext {
+        self.repositories.insert(path.to_path_buf(), RepoUsage { root: path.to_path_buf(), ..Default::default() });
+        RepoContext { root: Arc::new(path.to_path_buf()), in_git_dir: false }
+    }
 
-        let mut entries_reader = match fs::read_dir(&current_path).await {
-            Ok(reader) => reader,
-            Err(e) => {
-                errors.push(ScanError::IoError { path: current_path.clone(), source: e });
-                if let Some(tx) = &progress_tx {
-                    let _ = tx.send(ProgressUpdate::ErrorEncountered);
+    /// Called once a directory's own entries are processed, before its children are merged
+    /// in, so the cold counters and entry counts still only cover what is directly inside it.
+    fn finish_directory(&mut self, path: &Path, ctx: &WalkContext) {
+        if self.cold_files > 0 {
+            let directory = ColdDirectory { path: path.to_path_buf(), files: self.cold_files, size: self.cold_size };
+            match &ctx.spill {
+                Some(store) => {
+                    let added = store.lock().unwrap_or_else(PoisonError::into_inner).add_cold_directory(directory);
+                    ctx.spill_failed(self, added);
                 }
-                return (files_count, dirs_count, current_size, errors, matching_files_in_dir);
+                None => self.cold_directories.push(directory),
+            }
+        }
+        if ctx.config.inode_report || ctx.config.fan_out {
+            self.busiest_directories.push(DirectoryCount {
+                path: path.to_path_buf(),
+                children: self.children,
+                files: self.files,
+                directories: self.dirs,
+            });
+        }
+        if ctx.config.fan_out {
+            self.fan_out.add(self.children);
+        }
+        if ctx.live_top_floor.is_some()
+            && self.size > 0
+            && let Some(tx) = &ctx.progress_tx
+        {
+            let _ = tx.send(ProgressUpdate::DirectoryRead { path: path.to_path_buf(), bytes: self.size });
+        }
+        ctx.report_progress(self);
+    }
+
+    /// Called once the directory at `path` is fully walked, children merged in, so the
+    /// totals cover everything below it; records it if it is a trash directory.
+    fn finish_subtree(&mut self, path: &Path) {
+        if let Some(kind) = trash::trash_kind(path) {
+            // A trash within this one is part of it now
+            self.trash.retain(|inner| !inner.path.starts_with(path));
+            self.trash.push(TrashUsage { path: path.to_path_buf(), kind: kind.to_string(), files: self.files, size: self.size });
+        }
+    }
+
+    fn merge(&mut self, other: WalkTotals, ctx: &WalkContext) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.size += other.size;
+        self.errors.extend(other.errors);
+        self.matching_files.extend(other.matching_files);
+        self.entries.extend(other.entries);
+        self.cold_files += other.cold_files;
+        self.cold_size += other.cold_size;
+        self.placeholders.files += other.placeholders.files;
+        self.placeholders.logical_size += other.placeholders.logical_size;
+        self.placeholders.local_size += other.placeholders.local_size;
+        self.cold_directories.extend(other.cold_directories);
+        self.fan_out.merge(&other.fan_out);
+        if !other.busiest_directories.is_empty() {
+            self.busiest_directories.extend(other.busiest_directories);
+            self.busiest_directories.sort_by(|a, b| b.children.cmp(&a.children).then_with(|| a.path.cmp(&b.path)));
+            self.busiest_directories.truncate(BUSIEST_DIRECTORIES);
+        }
+        self.locked_files.extend(other.locked_files);
+        self.acls.extend(other.acls);
+        self.immutable_files.extend(other.immutable_files);
+        for (dir, counts) in other.encodings {
+            self.encodings.entry(dir).or_default().merge(&counts);
+        }
+        for (dir, counts) in other.line_endings {
+            self.line_endings.entry(dir).or_default().merge(&counts);
+        }
+        self.directory_labels.extend(other.directory_labels);
+        for (label_type, (files, size)) in other.labels {
+            let entry = self.labels.entry(label_type).or_default();
+            entry.0 += files;
+            entry.1 += size;
+        }
+        self.mislabeled.extend(other.mislabeled);
+        self.mislabeled_files += other.mislabeled_files;
+        keep_first_mislabeled(&mut self.mislabeled);
+        self.skipped_caches.extend(other.skipped_caches);
+        self.skipped_mounts.extend(other.skipped_mounts);
+        self.skipped_snapshots.extend(other.skipped_snapshots);
+        for (device, root) in other.subvolume_roots {
+            self.subvolume_directory(device, &root);
+        }
+        self.extent_files += other.extent_files;
+        self.extents += other.extents;
+        self.fragmented.extend(other.fragmented);
+        for (device, (files, size)) in other.subvolumes {
+            let entry = self.subvolumes.entry(device).or_default();
+            entry.0 += files;
+            entry.1 += size;
+        }
+        self.trash.extend(other.trash);
+        self.descriptors_exhausted += other.descriptors_exhausted;
+        self.ignored_errors += other.ignored_errors;
+        for (dir, vanished) in other.churn {
+            *self.churn.entry(dir).or_default() += vanished;
+        }
+        self.vanished += other.vanished;
+        self.rescans += other.rescans;
+        self.recovered += other.recovered;
+        if !other.shared_links.is_empty() {
+            self.shared_links.extend(other.shared_links);
+            keep_first_shared_links(&mut self.shared_links);
+        }
+        self.shared_link_files += other.shared_link_files;
+        self.shared_link_size += other.shared_link_size;
+        self.shared_link_directories += other.shared_link_directories;
+        for (uid, usage) in other.owners {
+            let entry = self.owners.entry(uid).or_insert_with(|| OwnerUsage { uid, ..Default::default() });
+            entry.files += usage.files;
+            entry.size += usage.size;
+        }
+        for (extension, usage) in other.extensions {
+            let entry = self.extensions.entry(extension)
+                .or_insert_with(|| ExtensionUsage { extension: usage.extension.clone(), files: 0, size: 0, largest: Vec::new() });
+            entry.files += usage.files;
+            entry.size += usage.size;
+            entry.largest.extend(usage.largest);
+            if let Some(count) = ctx.config.top_per_extension {
+                entry.keep_largest(count.get());
+            }
+        }
+        for (name, (files, size)) in other.regenerable {
+            let entry = self.regenerable.entry(name).or_default();
+            entry.0 += files;
+            entry.1 += size;
+        }
+        for (block_size, (allocated, small_files)) in other.target_usage {
+            let entry = self.target_usage.entry(block_size).or_default();
+            entry.0 += allocated;
+            entry.1 += small_files;
+        }
+        for (name, (files, size)) in other.categories {
+            let entry = self.categories.entry(name).or_default();
+            entry.0 += files;
+            entry.1 += size;
+        }
+        for (root, usage) in other.repositories {
+            let entry = self.repositories.entry(root.clone())
+                .or_insert_with(|| RepoUsage { root, ..Default::default() });
+            entry.working_tree_size += usage.working_tree_size;
+            entry.git_dir_size += usage.git_dir_size;
+        }
+    }
+
+    fn into_result(
+        self,
+        ctx: &WalkContext,
+        started: SystemTime,
+        scan_duration: Duration,
+        metrics: Option<MetricsSnapshot>,
+    ) -> ScanResult {
+        let (entries, matching_files, cold_directories) = match &ctx.spill {
+            Some(store) => store.lock().unwrap_or_else(PoisonError::into_inner).take(),
+            None => {
+                let mut cold_directories = self.cold_directories;
+                cold_directories.sort_by(spill::larger_first);
+                (self.entries.into(), self.matching_files.into(), cold_directories.into())
             }
         };
 
-        while let Some(entry_result) = match entries_reader.next_entry().await {
-            Ok(Some(entry)) => Some(Ok(entry)),
-            Ok(None) => None,
-            Err(e) => Some(Err(e)),
-        } {
-            let entry = match entry_result {
-                Ok(entry) => entry,
+        let mut owners: Vec<OwnerUsage> = self.owners.into_values()
+            .map(|usage| OwnerUsage { name: owners::user_name(usage.uid), ..usage })
+            .collect();
+        owners.sort_by_key(|o| std::cmp::Reverse(o.size));
+
+        let mut categories: Vec<CategoryUsage> = self.categories.into_iter()
+            .map(|(name, (files, size))| CategoryUsage { name: name.to_string(), files, size })
+            .collect();
+        categories.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        let regenerable = ctx.config.regenerable.is_some().then(|| {
+            let mut classes: Vec<CategoryUsage> = self.regenerable.into_iter()
+                .map(|(name, (files, size))| CategoryUsage { name: name.to_string(), files, size })
+                .collect();
+            classes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+            RegenerableReport {
+                files: classes.iter().map(|c| c.files).sum(),
+                size: classes.iter().map(|c| c.size).sum(),
+                classes,
+            }
+        });
+
+        let target_usage: Vec<TargetUsage> = ctx.config.target_block_sizes.iter()
+            .map(|&block_size| {
+                let (allocated, small_files) = self.target_usage.get(&block_size).copied().unwrap_or_default();
+                TargetUsage { block_size, allocated, small_files }
+            })
+            .collect();
+
+        let mut extensions: Vec<ExtensionUsage> = self.extensions.into_values().collect();
+        extensions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.extension.cmp(&b.extension)));
+        extensions.truncate(HEAVIEST_EXTENSIONS);
+
+        let selinux = ctx.config.selinux.is_some().then(|| {
+            let mut types: Vec<LabelUsage> = self.labels.into_iter()
+                .map(|(label_type, (files, size))| LabelUsage { label_type, files, size })
+                .collect();
+            types.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.label_type.cmp(&b.label_type)));
+            let mut mislabeled = self.mislabeled;
+            keep_first_mislabeled(&mut mislabeled);
+            SelinuxReport { types, mislabeled, mislabeled_files: self.mislabeled_files }
+        });
+
+        let mut skipped_caches = self.skipped_caches;
+        let mut acls = self.acls;
+        acls.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut encodings: Vec<DirectoryEncodings> = self.encodings.into_iter()
+            .map(|(path, counts)| DirectoryEncodings { path, counts })
+            .collect();
+        encodings.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut line_endings: Vec<DirectoryLineEndings> = self.line_endings.into_iter()
+            .map(|(path, counts)| DirectoryLineEndings { path, counts })
+            .collect();
+        line_endings.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut immutable_files = self.immutable_files;
+        immutable_files.sort_by(|a, b| a.path.cmp(&b.path));
+        skipped_caches.sort_by_key(|c| std::cmp::Reverse(c.size));
+        let mut skipped_mounts = self.skipped_mounts;
+        skipped_mounts.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut skipped_snapshots = self.skipped_snapshots;
+        skipped_snapshots.sort();
+        // A file on a device no directory was seen on, such as a bind-mounted file, has
+        // no subvolume to go under
+        let mut subvolumes: Vec<SubvolumeUsage> = self.subvolume_roots.into_iter()
+            .map(|(device, root)| {
+                let (files, size) = self.subvolumes.get(&device).copied().unwrap_or_default();
+                SubvolumeUsage { root, files, size }
+            })
+            .collect();
+        subvolumes.sort_by(|a, b| a.root.cmp(&b.root));
+        let mut fragmented = self.fragmented;
+        fragmented.sort_by(|a, b| b.extents.cmp(&a.extents).then_with(|| a.path.cmp(&b.path)));
+        let fragmentation = ctx.config.fragmentation.map(|min_size| FragmentationReport {
+            min_size,
+            files: self.extent_files,
+            extents: self.extents,
+            fragmented: fragmented.len() as u64,
+            most_fragmented: fragmented.into_iter().take(FRAGMENTED_FILES).collect(),
+        });
+        let mut trash = self.trash;
+        trash.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+        let mut repositories: Vec<RepoUsage> = self.repositories.into_values().collect();
+        repositories.sort_by_key(|r| std::cmp::Reverse(r.total_size()));
+
+        let mut churned: Vec<ChurnedDirectory> = self.churn.into_iter()
+            .map(|(path, vanished)| ChurnedDirectory { path, vanished })
+            .collect();
+        churned.sort_by(|a, b| b.vanished.cmp(&a.vanished).then_with(|| a.path.cmp(&b.path)));
+        churned.truncate(CHURNED_DIRECTORIES);
+
+        ScanResult {
+            total_files: self.files,
+            total_directories: self.dirs + 1,
+            total_size: self.size,
+            started,
+            scan_duration,
+            config_hash: ctx.config.config_hash(),
+            errors: self.errors,
+            ignored_errors: self.ignored_errors,
+            churn: ChurnReport { vanished: self.vanished, directories: churned, rescans: self.rescans, recovered: self.recovered },
+            shared_links: ctx.config.follow_symlinks.then_some(SharedLinkReport {
+                counted_again: ctx.config.count_links_separately,
+                files: self.shared_link_files,
+                file_size: self.shared_link_size,
+                directories: self.shared_link_directories,
+                links: self.shared_links,
+            }),
+            matching_files,
+            entries,
+            cold_data: ctx.config.cold_data_age.map(|age| ColdDataReport {
+                age,
+                files: self.cold_files,
+                size: self.cold_size,
+                directories: cold_directories,
+            }),
+            placeholders: (self.placeholders.files > 0).then_some(self.placeholders),
+            repositories,
+            owners,
+            locked_files: self.locked_files,
+            acls,
+            selinux,
+            immutable_files,
+            encodings,
+            line_endings,
+            directory_labels: self.directory_labels.into_iter().collect(),
+            categories,
+            regenerable,
+            target_usage,
+            dedup: ctx.chunks.as_ref().map(|index| {
+                let index = index.lock().unwrap_or_else(PoisonError::into_inner);
+                DedupEstimate {
+                    chunk_size: ctx.config.dedup_chunk_size.unwrap_or_default(),
+                    files: index.files,
+                    bytes: index.bytes,
+                    chunks: index.chunks,
+                    unique_chunks: index.unique_chunks,
+                    unique_bytes: index.unique_bytes,
+                }
+            }),
+            skipped_caches,
+            skipped_mounts,
+            skipped_snapshots,
+            subvolumes,
+            fragmentation,
+            trash,
+            busiest_directories: self.busiest_directories,
+            fan_out: ctx.config.fan_out.then_some(self.fan_out),
+            extensions,
+            metrics,
+            timings: ctx.timings.as_ref().map(|t| t.snapshot()),
+            resource_usage: ctx.resource_usage(),
+            volume: volume::volume_usage(&ctx.config.target_path).ok(),
+            device: ctx.config.device_info
+                .then(|| mounts::mount_source(&ctx.config.target_path).ok().flatten())
+                .flatten()
+                .and_then(|source| volume::block_device(&source).ok().flatten()),
+            snapshots: volume::local_snapshots(&ctx.config.target_path, self.size).ok().flatten(),
+            cancelled: ctx.cancelled(),
+            open_files: (ctx.tasks < ctx.config.max_concurrent_tasks || self.descriptors_exhausted > 0)
+                .then_some(OpenFileLimit {
+                    limit: ctx.open_file_limit,
+                    requested_tasks: ctx.config.max_concurrent_tasks,
+                    tasks: ctx.tasks,
+                    unreadable_directories: self.descriptors_exhausted,
+                }),
+        }
+    }
+}
+
+/// Symlinks followed by a scan with `ScannerConfig::follow_symlinks`.
+#[derive(Debug, Default)]
+struct FollowedLinks {
+    /// Resolved target of each followed link, with the link
+    by_target: HashMap<PathBuf, PathBuf>,
+    /// The followed links to directories, with their resolved targets
+    by_link: HashMap<PathBuf, PathBuf>,
+}
+
+impl FollowedLinks {
+    /// Where `target` is counted, if it's the target of a followed link or lies below one.
+    fn counted_at(&self, target: &Path) -> Option<PathBuf> {
+        target.ancestors().find_map(|dir| {
+            let link = self.by_target.get(dir)?;
+            Some(match target.strip_prefix(dir) {
+                Ok(rest) if !rest.as_os_str().is_empty() => link.join(rest),
+                _ => link.clone(),
+            })
+        })
+    }
+}
+
+/// State shared by every walker task of one scan.
+struct WalkContext {
+    config: ScannerConfig,
+    fs: Arc<dyn ScanFs>,
+    /// Directories read at once; with `per_device` only the open file limit
+    #[cfg(feature = "native")]
+    semaphore: Semaphore,
+    /// One semaphore of `tasks` permits per device, with `ScannerConfig::per_device`
+    #[cfg(feature = "native")]
+    devices: Option<async_engine::DeviceSemaphores>,
+    /// Takes the place of `semaphore` with `ScannerConfig::largest_first` or `recent_first`, unless `devices`
+    /// are in use; those then hand out their permits by priority instead
+    #[cfg(feature = "native")]
+    gate: Option<Arc<priority::PriorityGate>>,
+    /// Concurrent walker tasks, `max_concurrent_tasks` capped by the open file limit
+    tasks: usize,
+    open_file_limit: Option<u64>,
+    progress_tx: Option<mpsc::UnboundedSender<ProgressUpdate>>,
+    /// Smallest file still on the live top list, with `ScannerConfig::live_top`
+    live_top_floor: Option<Arc<AtomicU64>>,
+    entry_tx: Option<mpsc::UnboundedSender<FileEntry>>,
+    error_tx: Option<mpsc::UnboundedSender<ErrorEvent>>,
+    visitor: Option<Arc<dyn ScanVisitor>>,
+    /// Set once a visitor returns `VisitAction::Stop`
+    stopped: AtomicBool,
+    /// Directories discovered but not yet read, starting with the root
+    queued_directories: AtomicI64,
+    #[cfg(feature = "native")]
+    control: Option<ScanControl>,
+    #[cfg(feature = "native")]
+    metrics: Option<Arc<ScanMetrics>>,
+    timings: Option<PhaseTimings>,
+    /// Calls into `fs`, counted with `ScannerConfig::resource_usage`
+    fs_calls: Option<Arc<FsCallCounts>>,
+    /// Process usage when the scan started, with `ScannerConfig::resource_usage`
+    start_usage: Option<ProcessUsage>,
+    /// Walker tasks spawned by the concurrent engine
+    tasks_spawned: AtomicU64,
+    /// Files last used before this instant count as cold data
+    cold_cutoff: Option<SystemTime>,
+    /// Where entries, matches and cold directories go with `ScannerConfig::memory_limit`
+    spill: Option<Mutex<SpillStore>>,
+    /// The scan root with symlinks resolved, with `ScannerConfig::follow_symlinks`
+    canonical_root: Option<PathBuf>,
+    followed_links: Mutex<FollowedLinks>,
+    /// Mount points of the types in `ScannerConfig::skip_fs_types`
+    skipped_mounts: Option<mounts::SkippedMounts>,
+    /// With `ScannerConfig::dedup_chunk_size`
+    chunker: Option<Chunker>,
+    chunks: Option<Mutex<ChunkIndex>>,
+}
+
+impl WalkContext {
+    fn new(config: &ScannerConfig, progress_tx: Option<mpsc::UnboundedSender<ProgressUpdate>>) -> Self {
+        // A simulated filesystem holds no descriptors
+        let open_file_limit = if config.filesystem.is_none() { fd_limit::open_file_limit() } else { None };
+        let tasks = open_file_limit.map_or(config.max_concurrent_tasks, |limit| {
+            config.max_concurrent_tasks.min(fd_limit::max_tasks(limit))
+        });
+        let mut fs: Arc<dyn ScanFs> = config.filesystem.clone().unwrap_or_else(|| Arc::new(RealFs));
+        let fs_calls = config.resource_usage.then(|| Arc::new(FsCallCounts::default()));
+        if let Some(counts) = &fs_calls {
+            fs = Arc::new(CountingFs { inner: fs, counts: Arc::clone(counts) });
+        }
+        // A simulated filesystem has no mounts
+        let skipped_mounts = (!config.skip_fs_types.is_empty() && config.filesystem.is_none())
+            .then(|| match mounts::SkippedMounts::load(&config.target_path, &config.skip_fs_types) {
+                Ok(mounts) => Some(mounts),
                 Err(e) => {
-                    errors.push(ScanError::IoError { path: current_path.clone(), source: e });
-                    if let Some(tx) = &progress_tx {
-                        let _ = tx.send(ProgressUpdate::ErrorEncountered);
-                    }
-                    continue;
+                    eprintln!("{}", crate::t!("Warning: cannot read the mount table ({}); no filesystem types are skipped", e));
+                    None
                 }
-            };
+            })
+            .flatten();
+        let canonical_root = config.follow_symlinks.then(|| fs.canonicalize(&config.target_path).ok()).flatten();
+        Self {
+            config: config.clone(),
+            fs,
+            #[cfg(feature = "native")]
+            semaphore: match open_file_limit {
+                Some(limit) if config.per_device => Semaphore::new(fd_limit::max_tasks(limit)),
+                None if config.per_device => Semaphore::new(Semaphore::MAX_PERMITS),
+                _ => Semaphore::new(tasks),
+            },
+            #[cfg(feature = "native")]
+            devices: config.per_device.then(|| async_engine::DeviceSemaphores::new(tasks)),
+            #[cfg(feature = "native")]
+            gate: ((config.largest_first || config.recent_first) && !config.per_device).then(|| Arc::new(priority::PriorityGate::new(tasks))),
+            tasks,
+            open_file_limit,
+            progress_tx,
+            live_top_floor: None,
+            entry_tx: None,
+            error_tx: None,
+            visitor: None,
+            stopped: AtomicBool::new(false),
+            queued_directories: AtomicI64::new(1),
+            #[cfg(feature = "native")]
+            control: None,
+            #[cfg(feature = "native")]
+            metrics: None,
+            timings: config.timings.then(PhaseTimings::default),
+            fs_calls,
+            start_usage: config.resource_usage.then(resources::process_usage).flatten(),
+            tasks_spawned: AtomicU64::new(0),
+            cold_cutoff: config.cold_data_age.and_then(|age| SystemTime::now().checked_sub(age)),
+            spill: config.memory_limit.map(|limit| Mutex::new(SpillStore::new(limit))),
+            canonical_root,
+            followed_links: Mutex::default(),
+            skipped_mounts,
+            chunker: config.dedup_chunk_size.map(Chunker::new),
+            chunks: config.dedup_chunk_size.map(|_| Mutex::default()),
+        }
+    }
 
-            let path = entry.path();
+    /// What the scan cost so far, with `ScannerConfig::resource_usage`. CPU time is
+    /// counted from the start of the scan, peak memory over the life of the process.
+    fn resource_usage(&self) -> Option<ResourceUsage> {
+        let counts = self.fs_calls.as_ref()?;
+        let now = resources::process_usage().unwrap_or_default();
+        let start = self.start_usage.unwrap_or_default();
+        let tasks_spawned = self.tasks_spawned.load(Ordering::Relaxed);
+        Some(ResourceUsage {
+            peak_rss: now.peak_rss,
+            user_cpu: now.user_cpu.saturating_sub(start.user_cpu),
+            system_cpu: now.system_cpu.saturating_sub(start.system_cpu),
+            read_dir_calls: counts.read_dir.load(Ordering::Relaxed),
+            metadata_calls: counts.metadata.load(Ordering::Relaxed),
+            open_calls: counts.probe_open.load(Ordering::Relaxed),
+            tasks_spawned: (tasks_spawned > 0).then_some(tasks_spawned),
+        })
+    }
 
-            if config.verbose {
-                println!("[VERBOSE] Processing entry: {:?}", &path);
+    /// Asks the visitor about an entry; `false` means leave it out of the results.
+    fn visit(&self, path: &Path, metadata: &EntryMetadata) -> bool {
+        let Some(visitor) = &self.visitor else {
+            return true;
+        };
+        let action = if metadata.is_dir() {
+            visitor.visit_dir(path, metadata)
+        } else {
+            visitor.visit_file(path, metadata)
+        };
+        match action {
+            VisitAction::Continue => true,
+            VisitAction::Skip => false,
+            VisitAction::Stop => {
+                self.stopped.store(true, Ordering::Relaxed);
+                false
             }
+        }
+    }
 
-            if !config.include_hidden {
-                if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if file_name.starts_with('.') && file_name != "." && file_name != ".." {
-                        continue;
+    /// Whether the `prune` rule or the filter rules exclude the directory at `path`.
+    fn pruned(&self, path: &Path) -> bool {
+        if self.config.filtered_out(path, true) {
+            return true;
+        }
+        let Some(rule) = &self.config.prune else {
+            return false;
+        };
+        let depth = path.strip_prefix(&self.config.target_path).map_or(0, |p| p.components().count());
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        rule.matches(&DirInfo { path, name: &name, depth })
+    }
+
+    /// Whether the followed symlink at `path`, which resolves to `target`, leads to data
+    /// the scan counts anyway: inside the scanned tree, or to or into the target of an
+    /// earlier link. Such links are recorded, and should be skipped unless
+    /// `count_links_separately` is set.
+    fn shared_link(&self, totals: &mut WalkTotals, path: &Path, target: PathBuf, target_metadata: &EntryMetadata) -> bool {
+        let counted_at = if self.canonical_root.as_ref().is_some_and(|root| target.starts_with(root)) {
+            target.clone()
+        } else {
+            let mut followed = self.followed_links.lock().unwrap_or_else(PoisonError::into_inner);
+            match followed.counted_at(&target) {
+                Some(counted_at) => counted_at,
+                None => {
+                    if target_metadata.is_dir() {
+                        followed.by_link.insert(path.to_path_buf(), target.clone());
                     }
+                    followed.by_target.insert(target, path.to_path_buf());
+                    return false;
                 }
             }
+        };
+        let size = if target_metadata.is_dir() { 0 } else { target_metadata.len };
+        self.record_shared(totals, SharedLink { link: path.to_path_buf(), target, counted_at, is_dir: target_metadata.is_dir(), size })
+    }
 
-            let entry_file_type = match entry.file_type().await {
-                Ok(ft) => ft,
-                Err(e) => {
-                    errors.push(ScanError::IoError { path: path.clone(), source: e });
-                    if let Some(tx) = &progress_tx {
-                        let _ = tx.send(ProgressUpdate::ErrorEncountered);
-                    }
-                    continue;
-                }
+    /// Whether the directory at `path`, below a followed symlink, lies in the scanned tree
+    /// or is the target of another link, as with links to both `/data` and `/data/big`;
+    /// recorded like `shared_link`.
+    fn shared_directory(&self, totals: &mut WalkTotals, path: &Path) -> bool {
+        let (target, counted_at) = {
+            let followed = self.followed_links.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some((link, link_target)) = path.ancestors().skip(1).find_map(|dir| followed.by_link.get_key_value(dir)) else {
+                return false;
             };
-
-            if entry_file_type.is_symlink() {
-                if config.follow_symlinks {
-                    match fs::metadata(&path).await {
-                        Ok(target_metadata) => {
-                            if target_metadata.is_file() {
-                                files_count += 1;
-                                current_size += target_metadata.len();
-                                if let Some(tx) = &progress_tx {
-                                    let _ = tx.send(ProgressUpdate::NewItemFound);
-                                    let _ = tx.send(ProgressUpdate::BytesProcessed(target_metadata.len()));
-                                }
-                            } else if target_metadata.is_dir() {
-                                dirs_count += 1;
-                                if let Some(tx) = &progress_tx {
-                                    let _ = tx.send(ProgressUpdate::NewItemFound);
-                                }
-                                sub_task_paths_to_spawn.push(path.clone());
-                            }
-                        }
-                        Err(e) => {
-                            errors.push(ScanError::MetadataError { path, source: e });
-                            if let Some(tx) = &progress_tx {
-                                let _ = tx.send(ProgressUpdate::ErrorEncountered);
-                            }
-                        }
-                    }
-                }
-            } else if entry_file_type.is_file() {
-                match entry.metadata().await {
-                    Ok(metadata) => {
-                        files_count += 1;
-                        current_size += metadata.len();
-                        if let Some(tx) = &progress_tx {
-                            let _ = tx.send(ProgressUpdate::NewItemFound);
-                            let _ = tx.send(ProgressUpdate::BytesProcessed(metadata.len()));
-                        }
-
-                        // Check for regex pattern match
-                        if let Some(pattern) = &config.file_pattern {
-                            if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                                if pattern.is_match(file_name) {
-                                    matching_files_in_dir.push(path.clone());
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        errors.push(ScanError::MetadataError { path, source: e });
-                        if let Some(tx) = &progress_tx {
-                            let _ = tx.send(ProgressUpdate::ErrorEncountered);
-                        }
-                    }
-                }
-            } else if entry_file_type.is_dir() {
-                dirs_count += 1;
-                if let Some(tx) = &progress_tx {
-                    let _ = tx.send(ProgressUpdate::NewItemFound);
-                }
-                sub_task_paths_to_spawn.push(path.clone());
+            let target = link_target.join(path.strip_prefix(link).unwrap_or(path));
+            match followed.by_target.get(&target) {
+                _ if self.canonical_root.as_ref().is_some_and(|root| target.starts_with(root)) => (target.clone(), target),
+                Some(other) if !path.starts_with(other) => (target, other.clone()),
+                _ => return false,
             }
+        };
+        self.record_shared(totals, SharedLink { link: path.to_path_buf(), target, counted_at, is_dir: true, size: 0 })
+    }
+
+    fn record_shared(&self, totals: &mut WalkTotals, link: SharedLink) -> bool {
+        if link.is_dir {
+            totals.shared_link_directories += 1;
+        } else {
+            totals.shared_link_files += 1;
+            totals.shared_link_size += link.size;
+        }
+        totals.shared_links.push(link);
+        keep_first_shared_links(&mut totals.shared_links);
+        !self.config.count_links_separately
+    }
+
+    /// Whether the permission filter lets a file with `metadata` into the results.
+    fn wanted_mode(&self, metadata: &EntryMetadata) -> bool {
+        self.config.permissions.as_ref().is_none_or(|filter| filter.allows(metadata.mode))
+    }
+
+    /// The filesystem type of the directory at `path`, when it's a mount point
+    /// `skip_fs_types` leaves out.
+    fn skipped_mount(&self, path: &Path) -> Option<&str> {
+        self.skipped_mounts.as_ref()?.fs_type(path)
+    }
+
+    /// Which cache the directory at `path` is, when `skip_caches` leaves it out.
+    fn skipped_cache(&self, path: &Path) -> Option<&'static str> {
+        if self.config.skip_caches { caches::cache_kind(path) } else { None }
+    }
+
+    /// Records an error, passing it on to the `ScanHooks::errors` stream too, unless it
+    /// is under `ignore_errors_under`; returns whether it was recorded.
+    fn record_error(&self, totals: &mut WalkTotals, error: ScanError) -> bool {
+        if self.config.ignore_errors_under.as_ref().is_some_and(|ignored| ignored.covers(error.path())) {
+            totals.ignored_errors += 1;
+            return false;
+        }
+        if let Some(tx) = &self.error_tx {
+            let _ = tx.send(ErrorEvent::new(&error));
+        }
+        totals.errors.push(error);
+        true
+    }
+
+    /// Counts an entry of a directory listing that no longer exists, or a directory that
+    /// was gone before it could be listed.
+    fn entry_vanished(&self, totals: &mut WalkTotals, path: &Path) {
+        let dir = path.parent().unwrap_or(path);
+        *totals.churn.entry(dir.to_path_buf()).or_default() += 1;
+        totals.vanished += 1;
+        if self.config.rescan_changed > 0 {
+            totals.vanished_entries.push(path.to_path_buf());
+        }
+    }
+
+    /// Records an error with one entry of a directory listing; an entry that vanished
+    /// since the listing only counts as churn.
+    fn entry_error(&self, totals: &mut WalkTotals, error: ScanError) {
+        if let ScanError::IoError { path, source } | ScanError::MetadataError { path, source } = &error
+            && source.kind() == std::io::ErrorKind::NotFound
+        {
+            self.entry_vanished(totals, path);
+            return;
+        }
+        if self.record_error(totals, error)
+            && let Some(tx) = &self.progress_tx
+        {
+            let _ = tx.send(ProgressUpdate::ErrorEncountered);
+        }
+    }
+
+    /// Records a directory that could not be listed. Running out of file descriptors is
+    /// only counted, as it tends to fail thousands of directories for the same reason.
+    fn directory_error(&self, totals: &mut WalkTotals, path: &Path, error: std::io::Error) {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            self.entry_vanished(totals, path);
+            return;
+        } else if fd_limit::is_exhausted(&error) {
+            totals.descriptors_exhausted += 1;
+        } else if !self.record_error(totals, ScanError::IoError { path: path.to_path_buf(), source: error }) {
+            return;
+        }
+        if let Some(tx) = &self.progress_tx {
+            let _ = tx.send(ProgressUpdate::ErrorEncountered);
+        }
+    }
+
+    /// Records a failed attempt to spill collections to disk; only the first failure is
+    /// reported, as everything stays in memory afterwards.
+    fn spill_failed(&self, totals: &mut WalkTotals, added: std::io::Result<()>) {
+        if let Err(source) = added {
+            self.record_error(totals, ScanError::IoError { path: std::env::temp_dir(), source });
         }
+    }
 
-        if config.verbose {
-            println!("[VERBOSE] Releasing permit for: {:?}, collected {} sub-paths to spawn", &current_path, sub_task_paths_to_spawn.len());
+    /// Counts `items` entries found, with `bytes` in them, towards the next progress
+    /// update; updates go out once per directory rather than per entry.
+    fn items_found(&self, totals: &mut WalkTotals, items: u64, bytes: u64) {
+        if self.progress_tx.is_none() {
+            return;
         }
-        drop(permit); // If we don't drop the permit, the semaphore will never release causing a deadlock
+        totals.unreported_items += items;
+        totals.unreported_bytes += bytes;
+        if totals.unreported_items >= PROGRESS_BATCH {
+            self.report_progress(totals);
+        }
+    }
+
+    /// Sends what `items_found` counted since the last update.
+    fn report_progress(&self, totals: &mut WalkTotals) {
+        if totals.unreported_items > 0
+            && let Some(tx) = &self.progress_tx
+        {
+            let _ = tx.send(ProgressUpdate::ItemsFound { items: totals.unreported_items, bytes: totals.unreported_bytes });
+        }
+        totals.unreported_items = 0;
+        totals.unreported_bytes = 0;
+    }
 
-        let mut tasks = Vec::new();
-        for sub_path in sub_task_paths_to_spawn {
-            if config.verbose {
-                println!("[VERBOSE] Spawning task for sub-path: {:?} (parent: {:?})", &sub_path, &current_path);
+    /// Marks one directory as read after it queued `discovered` sub-directories.
+    fn directory_read(&self, discovered: usize) {
+        let change = discovered as i64 - 1;
+        let queued = self.queued_directories.fetch_add(change, Ordering::Relaxed) + change;
+        if let Some(tx) = &self.progress_tx {
+            let _ = tx.send(ProgressUpdate::QueueDepth(queued.max(0) as u64));
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed) || self.cancelled()
+    }
+
+    fn cancelled(&self) -> bool {
+        #[cfg(feature = "native")]
+        return self.control.as_ref().is_some_and(|c| c.is_cancelled());
+        #[cfg(not(feature = "native"))]
+        false
+    }
+
+    /// Runs `op`, charging the elapsed time to `phase` when `--timings` is on.
+    fn timed_sync<T>(&self, phase: Phase, op: impl FnOnce() -> T) -> T {
+        match &self.timings {
+            Some(timings) => {
+                let start = Instant::now();
+                let output = op();
+                timings.record(phase, start.elapsed());
+                output
             }
-            let task_config = Arc::clone(&config);
-            let task_semaphore = Arc::clone(&semaphore);
-            let task_progress_tx = progress_tx.clone();
-            tasks.push(tokio::spawn(walk_directory_recursive(
-                sub_path,
-                task_config,
-                task_semaphore,
-                task_progress_tx,
-            )));
-        }
-
-        for task_handle in tasks {
-            match task_handle.await {
-                Ok((sub_files, sub_dirs, sub_size, sub_errors, sub_matching_files)) => {
-                    files_count += sub_files;
-                    dirs_count += sub_dirs;
-                    current_size += sub_size;
-                    errors.extend(sub_errors);
-                    matching_files_in_dir.extend(sub_matching_files);
-                }
-                Err(join_error) => {
-                    eprintln!("Task panicked or was cancelled for a sub-path of {:?}: {:?}", &current_path, join_error);
-                    if let Some(tx) = &progress_tx {
-                        let _ = tx.send(ProgressUpdate::ErrorEncountered);
-                    }
-                }
+            None => op(),
+        }
+    }
+
+    /// Awaits `fut`, charging the elapsed time to `phase` when `--timings` is on.
+    #[cfg(feature = "native")]
+    async fn timed<F: Future>(&self, phase: Phase, fut: F) -> F::Output {
+        match &self.timings {
+            Some(timings) => {
+                let start = Instant::now();
+                let output = fut.await;
+                timings.record(phase, start.elapsed());
+                output
             }
+            None => fut.await,
         }
-        (files_count, dirs_count, current_size, errors, matching_files_in_dir)
-    })
+    }
+}
+
+/// 64-bit FNV-1a of `bytes`, for hashes that have to be the same in every run.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Checks that the scan root exists on `fs` and is a directory.
+fn check_root(fs: &dyn ScanFs, root_path: &Path) -> Result<(), ScanError> {
+    match fs.metadata(root_path) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => Err(ScanError::NotADirectory { path: root_path.to_path_buf() }),
+        Err(e) => Err(ScanError::IoError { path: root_path.to_path_buf(), source: e }),
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.') && n != "." && n != "..")
 }
 
-/// Scanner Engine
-/// - Walks a directory tree recursively
-/// - Reports progress using a channel
-/// - Returns a ScanResult
-/// 
-pub async fn run_scan(config: &ScannerConfig) -> Result<ScanResult, anyhow::Error> {
-    let start_time = Instant::now();
+/// When the file was last read or written. atime is ignored when it predates mtime,
+/// which happens on `noatime` mounts where it is never updated.
+fn last_used(metadata: &EntryMetadata) -> Option<SystemTime> {
+    match (metadata.accessed, metadata.modified) {
+        (Some(accessed), Some(modified)) => Some(accessed.max(modified)),
+        (accessed, modified) => modified.or(accessed),
+    }
+}
+
+/// Accounts for a regular file (or the target of a followed symlink).
+fn record_file(
+    totals: &mut WalkTotals,
+    ctx: &WalkContext,
+    path: &Path,
+    metadata: &EntryMetadata,
+    repo: Option<&RepoContext>,
+) {
+    let size = metadata.len;
+    totals.files += 1;
+    totals.add_file_size(size, repo);
+    ctx.items_found(totals, 1, size);
+    if let Some(tx) = &ctx.progress_tx
+        && ctx.live_top_floor.as_ref().is_some_and(|floor| size > floor.load(Ordering::Relaxed))
+    {
+        let _ = tx.send(ProgressUpdate::FileFound { path: path.to_path_buf(), size });
+    }
 
-    let root_path = PathBuf::from(&config.target_path);
-    // Check if the root path is a directory
-    match fs::metadata(&root_path).await {
-        Ok(meta) => {
-            if !meta.is_dir() {
-                return Err(ScanError::NotADirectory { path: root_path }.into());
+    // Check for regex pattern match
+    if let Some(pattern) = &ctx.config.file_pattern
+        && let Some(file_name) = path.file_name().and_then(|n| n.to_str())
+        && pattern.is_match(file_name)
+    {
+        let matched = MatchedFile { path: path.to_path_buf(), size };
+        match &ctx.spill {
+            Some(store) => {
+                let added = store.lock().unwrap_or_else(PoisonError::into_inner).add_match(matched);
+                ctx.spill_failed(totals, added);
             }
-        }
-        Err(e) => {
-            return Err(ScanError::IoError{ path: root_path, source: e }.into());
+            None => totals.matching_files.push(matched),
         }
     }
 
-    let (progress_tx, progress_rx) = mpsc::unbounded_channel();
-    let mut progress_reporter_handle = None;
+    if let Some(cutoff) = ctx.cold_cutoff
+        && last_used(metadata).is_some_and(|t| t < cutoff)
+    {
+        totals.cold_files += 1;
+        totals.cold_size += size;
+    }
 
-    if config.progress_updates {
-        let reporter = ProgressReporter::new();
-        progress_reporter_handle = Some(tokio::spawn(async move {
-            reporter.run(progress_rx).await;
-        }));
-    } else {
-        // Drop the receiver if not used, so sender doesn't wait indefinitely or panic.
-        drop(progress_rx);
+    if ctx.config.subvolumes
+        && let Some(device) = metadata.device
+    {
+        let usage = totals.subvolumes.entry(device).or_default();
+        usage.0 += 1;
+        usage.1 += size;
     }
-    
-    let progress_tx_option = if config.progress_updates { Some(progress_tx) } else { None };
 
-    let semaphore = Arc::new(Semaphore::new(config.max_concurrent_tasks));
-    let arc_config = Arc::new(config.clone());
+    if let Some(local_size) = metadata.placeholder {
+        totals.placeholders.files += 1;
+        totals.placeholders.logical_size += size;
+        totals.placeholders.local_size += local_size;
+    }
+
+    if ctx.config.by_owner
+        && let Some(uid) = metadata.owner
+    {
+        let usage = totals.owners.entry(uid).or_insert_with(|| OwnerUsage { uid, ..Default::default() });
+        usage.files += 1;
+        usage.size += size;
+    }
+
+    if let Some(categories) = &ctx.config.categories {
+        let usage = totals.categories.entry(Arc::clone(categories.classify(path))).or_default();
+        usage.0 += 1;
+        usage.1 += size;
+    }
 
-    // Send initial NewItemFound for the root directory itself if progress is enabled
-    if let Some(tx) = &progress_tx_option {
-        let _ = tx.send(ProgressUpdate::NewItemFound);
+    if let Some(class) = ctx.config.regenerable.as_ref().and_then(|regenerable| regenerable.classify(path)) {
+        let usage = totals.regenerable.entry(Arc::clone(class)).or_default();
+        usage.0 += 1;
+        usage.1 += size;
     }
 
-    let (files, sub_dirs, size, scan_errors, matching_files) = walk_directory_recursive(
-        root_path,
-        arc_config,
-        semaphore,
-        progress_tx_option.clone(),
-    ).await;
+    for &block_size in &ctx.config.target_block_sizes {
+        let usage = totals.target_usage.entry(block_size).or_default();
+        usage.0 += size.div_ceil(block_size) * block_size;
+        usage.1 += u64::from(size > 0 && size < block_size);
+    }
 
-    // Signal scan completion
-    if let Some(tx) = progress_tx_option {
-        let _ = tx.send(ProgressUpdate::ScanCompleted);
-        if let Some(handle) = progress_reporter_handle {
-            let _ = handle.await;
+    let entry = || FileEntry { path: path.to_path_buf(), size, modified: metadata.modified, owner: metadata.owner };
+    if let Some(count) = ctx.config.top_per_extension {
+        let extension = extension::last_extension(path);
+        let usage = totals.extensions.entry(extension.clone())
+            .or_insert_with(|| ExtensionUsage { extension, files: 0, size: 0, largest: Vec::new() });
+        usage.files += 1;
+        usage.size += size;
+        // Kept sorted, so the file only goes in if it beats the smallest one kept
+        let position = usage.largest.partition_point(|kept| kept.size > size || (kept.size == size && kept.path.as_path() < path));
+        if position < count.get() {
+            usage.largest.insert(position, entry());
+            usage.largest.truncate(count.get());
+        }
+    }
+    if let Some(tx) = &ctx.entry_tx {
+        let _ = tx.send(entry());
+    }
+    if ctx.config.collect_entries {
+        match &ctx.spill {
+            Some(store) => {
+                let added = store.lock().unwrap_or_else(PoisonError::into_inner).add_entry(entry());
+                ctx.spill_failed(totals, added);
+            }
+            None => totals.entries.push(entry()),
         }
     }
+}
 
-    let scan_duration = start_time.elapsed();
+/// Accounts for a file's SELinux `context` under its type, noting it as mislabeled if it
+/// has none or its type isn't among the expected ones.
+fn record_label(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64, context: Option<String>) {
+    let Some(expected) = &ctx.config.selinux else {
+        return;
+    };
+    // user:role:type:level
+    let label_type = context.as_deref().and_then(|c| c.split(':').nth(2)).unwrap_or(UNLABELED);
+    let usage = totals.labels.entry(label_type.to_string()).or_default();
+    usage.0 += 1;
+    usage.1 += size;
+    if context.is_none() || !(expected.is_empty() || expected.iter().any(|t| t == label_type)) {
+        totals.mislabeled_files += 1;
+        totals.mislabeled.push(MislabeledFile { path: path.to_path_buf(), context });
+        if totals.mislabeled.len() >= 2 * MISLABELED_SHOWN {
+            keep_first_mislabeled(&mut totals.mislabeled);
+        }
+    }
+}
 
-    let result = ScanResult {
-        total_files: files,
-        total_directories: sub_dirs + 1, 
-        total_size: size,
-        scan_duration,
-        errors: scan_errors,
-        matching_files,
+/// How much of a text file of `size` bytes at `path` to read for
+/// `ScannerConfig::sample_encodings` and `ScannerConfig::line_endings`; 0 to leave it be.
+fn sample_len(ctx: &WalkContext, path: &Path, size: u64) -> usize {
+    let config = &ctx.config;
+    if size == 0 || !(config.sample_encodings || config.line_endings) || !encoding::is_text_file(path) {
+        return 0;
+    }
+    let wanted = if config.line_endings { LINE_ENDING_SAMPLE_LEN } else { SAMPLE_LEN };
+    wanted.min(usize::try_from(size).unwrap_or(usize::MAX))
+}
+
+/// Counts the encoding and line endings of a text file of `size` bytes starting with
+/// `head` under its directory.
+fn record_sample(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64, head: &[u8]) {
+    let dir = path.parent().unwrap_or(path);
+    if ctx.config.sample_encodings {
+        let start = &head[..head.len().min(SAMPLE_LEN)];
+        if let Some((encoding, bom)) = encoding::detect(start, size > start.len() as u64) {
+            totals.encodings.entry(dir.to_path_buf()).or_default().add(encoding, bom);
+        }
+    }
+    if ctx.config.line_endings
+        && let Some(ending) = line_endings::classify(head)
+    {
+        totals.line_endings.entry(dir.to_path_buf()).or_default().add(ending);
+    }
+}
+
+/// Adds the extent count of a file of `size` bytes for `ScannerConfig::fragmentation`.
+fn record_extents(totals: &mut WalkTotals, path: &Path, size: u64, extents: u64) {
+    totals.extent_files += 1;
+    totals.extents += extents;
+    if extents > 1 && size / extents < FRAGMENTED_EXTENT_SIZE {
+        totals.fragmented.push(FragmentedFile { path: path.to_path_buf(), size, extents });
+    }
+}
+
+/// Adds the chunks of a file read for `ScannerConfig::dedup_chunk_size` to the scan's.
+fn record_chunks(ctx: &WalkContext, chunks: &[chunking::ChunkId]) {
+    if let Some(index) = &ctx.chunks {
+        index.lock().unwrap_or_else(PoisonError::into_inner).add(chunks);
+    }
+}
+
+/// The file among a directory's `entries` whose first line labels the directory, for
+/// `ScannerConfig::directory_labels`.
+fn label_file<'a>(ctx: &WalkContext, entries: impl IntoIterator<Item = &'a FsEntry>) -> Option<&'a Path> {
+    if !ctx.config.directory_labels {
+        return None;
+    }
+    entries.into_iter()
+        .filter(|entry| entry.file_type == EntryType::File)
+        .filter_map(|entry| {
+            let name = entry.path.file_name()?.to_str()?;
+            let rank = LABEL_FILES.iter().position(|f| f.eq_ignore_ascii_case(name))?;
+            Some((rank, entry.path.as_path()))
+        })
+        .min_by_key(|&(rank, _)| rank)
+        .map(|(_, path)| path)
+}
+
+/// Takes the first non-empty line of a label file starting with `head` as the label of
+/// `dir`; a Markdown heading's `#`s are dropped.
+fn record_directory_label(totals: &mut WalkTotals, dir: &Path, head: &[u8]) {
+    let text = String::from_utf8_lossy(head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head));
+    let Some(line) = text.lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+    else {
+        return;
     };
+    let label: String = line.chars().take(MAX_LABEL_LEN).collect();
+    totals.directory_labels.push((dir.to_path_buf(), label));
+}
+
+/// Sorts `files` by path and keeps the first `MISLABELED_SHOWN`.
+fn keep_first_mislabeled(files: &mut Vec<MislabeledFile>) {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files.truncate(MISLABELED_SHOWN);
+}
 
-    if !config.progress_updates {
-        println!("Scanner Engine: Scan complete.");
+/// Whether probing a file failed because another process holds it open exclusively
+/// (Windows sharing/lock violations) or holds a lock on it (Unix `flock`, `EWOULDBLOCK`).
+fn is_lock_error(e: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+        matches!(e.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION))
+    }
+    #[cfg(not(windows))]
+    {
+        e.kind() == std::io::ErrorKind::WouldBlock
     }
-    Ok(result)
 }
+
@@ -0,0 +1,122 @@
+//! Access control lists beyond the mode bits, for `ScannerConfig::acls`: POSIX ACLs on
+//! Linux, DACLs on Windows. Only what an entry didn't inherit from its parent directory is
+//! reported, since that is what a security review of a tree needs to look at.
+
+use std::io;
+use std::path::Path;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::xattr::read_xattr;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn explicit_acl(path: &Path) -> io::Result<Option<String>> {
+    let access = named_entries(read_xattr(path, c"system.posix_acl_access")?);
+    let default = named_entries(read_xattr(path, c"system.posix_acl_default")?);
+    if access.is_empty() && default.is_empty() {
+        return Ok(None);
+    }
+    // New entries copy their parent's default ACL, so only a difference to it was set by hand
+    let inherited = match path.parent() {
+        Some(parent) => named_entries(read_xattr(parent, c"system.posix_acl_default")?),
+        None => Vec::new(),
+    };
+    let mut parts = Vec::new();
+    if access != inherited {
+        parts.extend(access.iter().map(describe));
+    }
+    if !default.is_empty() && default != inherited {
+        parts.extend(default.iter().map(|entry| format!("default:{}", describe(entry))));
+    }
+    Ok((!parts.is_empty()).then(|| parts.join(",")))
+}
+
+#[cfg(windows)]
+pub(super) fn explicit_acl(path: &Path) -> io::Result<Option<String>> {
+    use std::ptr;
+    use windows_sys::Win32::Security::{
+        GetAce, GetSecurityDescriptorControl, GetSecurityDescriptorDacl, ACE_HEADER, ACL, DACL_SECURITY_INFORMATION,
+        INHERITED_ACE, SE_DACL_PROTECTED,
+    };
+    const ACCESS_DENIED_ACE_TYPE: u8 = 1;
+
+    let mut buffer = crate::owners::security_descriptor(path, DACL_SECURITY_INFORMATION)?;
+    let descriptor = buffer.as_mut_ptr().cast();
+    let (mut control, mut revision) = (0u16, 0u32);
+    let (mut present, mut defaulted, mut dacl) = (0, 0, ptr::null_mut::<ACL>());
+    // SAFETY: `descriptor` was filled in by GetFileSecurityW and the out-params are valid
+    let ok = unsafe {
+        GetSecurityDescriptorControl(descriptor, &mut control, &mut revision) != 0
+            && GetSecurityDescriptorDacl(descriptor, &mut present, &mut dacl, &mut defaulted) != 0
+    };
+    if !ok {
+        return Err(io::Error::last_os_error());
+    }
+    let mut parts = Vec::new();
+    if control & SE_DACL_PROTECTED != 0 {
+        parts.push("inheritance disabled".to_string());
+    }
+    if present != 0 && !dacl.is_null() {
+        let (mut explicit, mut denied) = (0, 0);
+        // SAFETY: `dacl` points into `descriptor`, which outlives this loop
+        for index in 0..u32::from(unsafe { (*dacl).AceCount }) {
+            let mut ace = ptr::null_mut();
+            // SAFETY: `index` is below the ACL's entry count
+            if unsafe { GetAce(dacl, index, &mut ace) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: every ACE starts with an ACE_HEADER
+            let header = unsafe { &*ace.cast::<ACE_HEADER>() };
+            if u32::from(header.AceFlags) & INHERITED_ACE == 0 {
+                explicit += 1;
+                if header.AceType == ACCESS_DENIED_ACE_TYPE {
+                    denied += 1;
+                }
+            }
+        }
+        if explicit > 0 {
+            let plural = if explicit == 1 { "y" } else { "ies" };
+            parts.push(format!("{} explicit entr{} ({} deny)", explicit, plural, denied));
+        }
+    }
+    Ok((!parts.is_empty()).then(|| parts.join(", ")))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub(super) fn explicit_acl(_path: &Path) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ACL_USER: u16 = 0x02;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ACL_GROUP: u16 = 0x08;
+
+/// A named user or group entry: tag, permission bits and id.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type AclEntry = (u16, u16, u32);
+
+/// The named user and group entries of an ACL in the kernel's xattr format: a 4-byte
+/// version, then 8 bytes per entry (tag, permissions, id; little endian). The owner,
+/// group, other and mask entries only repeat the mode bits.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn named_entries(xattr: Option<Vec<u8>>) -> Vec<AclEntry> {
+    let Some(xattr) = xattr else {
+        return Vec::new();
+    };
+    xattr.get(4..).unwrap_or_default()
+        .chunks_exact(8)
+        .map(|e| {
+            let tag = u16::from_le_bytes([e[0], e[1]]);
+            let perm = u16::from_le_bytes([e[2], e[3]]);
+            (tag, perm, u32::from_le_bytes([e[4], e[5], e[6], e[7]]))
+        })
+        .filter(|&(tag, ..)| tag == ACL_USER || tag == ACL_GROUP)
+        .collect()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn describe(&(tag, perm, id): &AclEntry) -> String {
+    let kind = if tag == ACL_USER { "user" } else { "group" };
+    let bit = |mask, c| if perm & mask != 0 { c } else { '-' };
+    format!("{}:{}:{}{}{}", kind, id, bit(4, 'r'), bit(2, 'w'), bit(1, 'x'))
+}
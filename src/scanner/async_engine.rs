@@ -0,0 +1,699 @@
+use std::cmp::Reverse;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime};
+use tokio::fs;
+use tokio::sync::{mpsc, watch};
+
+use super::{
+    caches, check_root, subvolumes, is_hidden, is_lock_error, label_file, record_directory_label, record_chunks, record_extents, record_file, record_label, record_sample, sample_len, sync_engine, Engine, EntryMetadata, EntryType,
+    ErrorEvent, ExplicitAcl, FileEntry, ImmutableFile, LockedFile, RealFs, RepoContext, ScanError, ScanControl, ScanFs, ScanResult, ScanVisitor, ScannerConfig, SkippedMount,
+    WalkContext, WalkTotals, LABEL_READ_LEN,
+};
+use super::priority::PriorityGate;
+use crate::hotkeys::HotkeyListener;
+use crate::metrics::ScanMetrics;
+use crate::progress::{LiveTop, ProgressReporter, ProgressSnapshot, ProgressUpdate};
+use crate::progress_socket;
+use crate::timings::Phase;
+
+/// Optional hooks through which an embedding application follows or steers a running scan.
+#[derive(Default)]
+pub struct ScanHooks {
+    /// Receives running totals as the scan progresses
+    pub progress: Option<watch::Sender<ProgressSnapshot>>,
+    /// Receives every scanned file as soon as it is recorded
+    pub entries: Option<mpsc::UnboundedSender<FileEntry>>,
+    /// Receives every error as soon as it is recorded
+    pub errors: Option<mpsc::UnboundedSender<ErrorEvent>>,
+    /// Decides per entry whether it is counted, skipped or ends the scan
+    pub visitor: Option<Arc<dyn ScanVisitor>>,
+    /// Pauses, resumes or cancels the scan from outside
+    pub control: Option<ScanControl>,
+}
+
+/// Separate concurrency budgets per device for `ScannerConfig::per_device`, so tasks
+/// stuck on a slow USB drive cannot take all permits from an NVMe disk, or the other way
+/// round. Entries on filesystems that report no device share one budget.
+pub(super) struct DeviceSemaphores {
+    permits: usize,
+    semaphores: Mutex<HashMap<Option<u64>, Arc<PriorityGate>>>,
+}
+
+impl DeviceSemaphores {
+    pub(super) fn new(permits: usize) -> Self {
+        Self { permits, semaphores: Mutex::default() }
+    }
+
+    fn get(&self, device: Option<u64>) -> Arc<PriorityGate> {
+        let mut semaphores = self.semaphores.lock().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(semaphores.entry(device).or_insert_with(|| Arc::new(PriorityGate::new(self.permits))))
+    }
+}
+
+/// How much data the directory at `path` probably holds, for `largest_first`: its size
+/// in `size_hints`, or else the size of the directory itself.
+fn size_estimate(ctx: &WalkContext, path: &Path, metadata: Option<&EntryMetadata>) -> u64 {
+    let hinted = ctx.config.size_hints.as_ref().and_then(|hints| {
+        hints.directory_size(path.strip_prefix(&ctx.config.target_path).ok()?)
+    });
+    hinted.or(metadata.map(|m| m.len)).unwrap_or(0)
+}
+
+/// Where the directory at `path` goes in line for a permit, highest first: its size
+/// estimate with `largest_first`, its modification time in seconds since the epoch with
+/// `recent_first` (directories without one go last), or else all the same.
+fn queue_priority(ctx: &WalkContext, path: &Path, metadata: Option<&EntryMetadata>) -> u64 {
+    if ctx.config.largest_first {
+        size_estimate(ctx, path, metadata)
+    } else if ctx.config.recent_first {
+        metadata.and_then(|m| m.modified)
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs())
+    } else {
+        0
+    }
+}
+
+/// Runs a blocking call on the scan's filesystem on tokio's blocking thread pool.
+async fn blocking<T: Send + 'static>(
+    ctx: &WalkContext,
+    path: &Path,
+    op: impl FnOnce(&dyn ScanFs, &Path) -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    let fs = Arc::clone(&ctx.fs);
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || op(fs.as_ref(), &path))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+}
+
+/// Whether the file passes the `--filter-tag` tests; tags that can't be read count as a
+/// failure.
+async fn has_tags(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path) -> bool {
+    let Some(filter) = &ctx.config.tags else {
+        return true;
+    };
+    let tags = match filter.database() {
+        Some(database) => Ok(database.tags(path)),
+        None => ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.file_tags(path))).await,
+    };
+    match tags {
+        Ok(tags) => filter.allows(&tags),
+        Err(e) => {
+            ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e });
+            false
+        }
+    }
+}
+
+/// Tries to open the file for reading when `--locked-files` is on.
+async fn probe_lock(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.detect_locked_files {
+        return;
+    }
+    if let Err(e) = ctx.timed(Phase::LockProbe, blocking(ctx, path, |fs, path| fs.probe_open(path))).await
+        && is_lock_error(&e)
+    {
+        totals.locked_files.push(LockedFile { path: path.to_path_buf(), size, reason: e.to_string() });
+    }
+}
+
+/// Reads the owner of a file whose metadata has none when `--by-owner` is on.
+async fn look_up_owner(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, metadata: &mut EntryMetadata) {
+    if !ctx.config.by_owner || metadata.owner.is_some() {
+        return;
+    }
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.owner(path))).await {
+        Ok(owner) => metadata.owner = owner,
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Reads the entry's access control list when `--acls` is on.
+async fn check_acl(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, is_dir: bool) {
+    if !ctx.config.acls {
+        return;
+    }
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.explicit_acl(path))).await {
+        Ok(Some(description)) => totals.acls.push(ExplicitAcl { path: path.to_path_buf(), is_dir, description }),
+        Ok(None) => {}
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Reads the file's SELinux context when `--selinux` is on.
+async fn read_label(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if ctx.config.selinux.is_none() {
+        return;
+    }
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.security_context(path))).await {
+        Ok(context) => record_label(totals, ctx, path, size, context),
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Reads the file's attributes when `--immutable` is on.
+async fn check_attributes(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.detect_immutable {
+        return;
+    }
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.file_attributes(path))).await {
+        Ok(attributes) if attributes.is_empty() => {}
+        Ok(attributes) => totals.immutable_files.push(ImmutableFile { path: path.to_path_buf(), size, attributes }),
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Reads the start of a text file when `--encodings` or `--line-endings` is on.
+async fn sample_text(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    let len = sample_len(ctx, path, size);
+    if len == 0 {
+        return;
+    }
+    let read_head = move |fs: &dyn ScanFs, path: &Path| {
+        let mut head = vec![0; len];
+        let read = fs.read_head(path, &mut head)?;
+        head.truncate(read);
+        Ok(head)
+    };
+    match ctx.timed(Phase::Sampling, blocking(ctx, path, read_head)).await {
+        Ok(head) => record_sample(totals, ctx, path, size, &head),
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Reads the whole file into content-defined chunks when `--dedup-estimate` is on.
+async fn chunk_file(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    let Some(chunker) = ctx.chunker.filter(|_| size > 0) else {
+        return;
+    };
+    let chunk = move |fs: &dyn ScanFs, path: &Path| chunker.chunks(fs.open_read(path)?, size);
+    match ctx.timed(Phase::Hashing, blocking(ctx, path, chunk)).await {
+        Ok(chunks) => record_chunks(ctx, &chunks),
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Counts the file's extents when `--fragmentation` is on and it is large enough.
+async fn count_extents(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.fragmentation.is_some_and(|min_size| size > 0 && size >= min_size) {
+        return;
+    }
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.extent_count(path))).await {
+        Ok(Some(extents)) => record_extents(totals, path, size, extents),
+        Ok(None) => {}
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Whether to leave out the followed symlink at `path`, as it leads to data counted anyway;
+/// one that can't be resolved is followed.
+async fn skip_shared_link(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, target_metadata: &EntryMetadata) -> bool {
+    match ctx.timed(Phase::Metadata, blocking(ctx, path, |fs, path| fs.canonicalize(path))).await {
+        Ok(target) => ctx.shared_link(totals, path, target, target_metadata),
+        Err(_) => false,
+    }
+}
+
+/// Roots with at most this many sub-directories get one progress bar per sub-directory.
+const MAX_SUBTREE_BARS: usize = 12;
+
+/// `subtree` is the index of the root's child this directory lies under, when the
+/// progress display tracks children separately. `device` is only known with `per_device`,
+/// `priority` (the size estimate, or the modification time) only with `largest_first` or
+/// `recent_first`.
+fn walk_directory_recursive(
+    current_path: PathBuf,
+    ctx: Arc<WalkContext>,
+    mut repo: Option<RepoContext>,
+    subtree: Option<u32>,
+    device: Option<u64>,
+    priority: u64,
+) -> Pin<Box<dyn Future<Output = WalkTotals> + Send + 'static>> {
+    Box::pin(async move {
+        let config = &ctx.config;
+        let progress_tx = &ctx.progress_tx;
+        let _task_guard = ctx.metrics.as_ref().map(|m| m.task_started());
+        ctx.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(control) = &ctx.control {
+            control.wait_while_paused().await;
+        }
+        let wait_guard = ctx.metrics.as_ref().map(|m| m.waiting_for_permit());
+        let device_permit = match &ctx.devices {
+            Some(devices) => Some(ctx.timed(Phase::SemaphoreWait, devices.get(device).acquire(priority)).await),
+            None => None,
+        };
+        let gate_permit = match &ctx.gate {
+            Some(gate) => Some(ctx.timed(Phase::SemaphoreWait, Arc::clone(gate).acquire(priority)).await),
+            None => None,
+        };
+        let permit = match &gate_permit {
+            Some(_) => None,
+            None => match ctx.timed(Phase::SemaphoreWait, ctx.semaphore.acquire()).await { // Acquire semaphore
+                Ok(p) => Some(p),
+                Err(_) => return WalkTotals::default(),
+            },
+        };
+        drop(wait_guard);
+
+        if config.verbose {
+            println!("[VERBOSE] Reading directory (permit acquired): {:?}", &current_path);
+        }
+
+        let mut totals = WalkTotals::default();
+        let mut sub_task_paths_to_spawn = Vec::new();
+        // Measured after the permit is released, off the runtime's worker threads
+        let mut caches = Vec::new();
+
+        // A directory holding a `.git` entry starts a new (possibly nested) repository
+        if config.by_repo
+            && !repo.as_ref().is_some_and(|r| r.in_git_dir)
+            && ctx.timed(Phase::Metadata, blocking(&ctx, &current_path.join(".git"), |fs, path| fs.symlink_metadata(path))).await.is_ok()
+        {
+            repo = Some(totals.start_repository(&current_path));
+        }
+        if config.subvolumes
+            && let Ok(metadata) = ctx.timed(Phase::Metadata, blocking(&ctx, &current_path, |fs, path| fs.metadata(path))).await
+            && let Some(device) = metadata.device
+        {
+            totals.subvolume_directory(device, &current_path);
+        }
+
+        let mut entries = match ctx.timed(Phase::Enumeration, blocking(&ctx, &current_path, |fs, path| fs.read_dir(path))).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                ctx.directory_error(&mut totals, &current_path, e);
+                ctx.directory_read(0);
+                return totals;
+            }
+        };
+
+        totals.children = entries.len() as u64;
+        if let Some(label_file) = label_file(&ctx, entries.iter().filter_map(|e| e.as_ref().ok())) {
+            let read_label = |fs: &dyn ScanFs, path: &Path| {
+                let mut head = vec![0; LABEL_READ_LEN];
+                let read = fs.read_head(path, &mut head)?;
+                head.truncate(read);
+                Ok(head)
+            };
+            // A label is a nicety; an unreadable README is not worth an error
+            if let Ok(head) = ctx.timed(Phase::Sampling, blocking(&ctx, label_file, read_label)).await {
+                record_directory_label(&mut totals, &current_path, &head);
+            }
+        }
+        // Paths listed so far, so that another listing only adds what's new
+        let mut listed = HashSet::new();
+        let mut rescans = 0;
+        loop {
+            if rescans > 0 {
+                entries.retain(|entry| !matches!(entry, Ok(e) if listed.contains(&e.path)));
+                totals.recovered += entries.iter().filter(|entry| entry.is_ok()).count() as u64;
+            }
+            if config.rescan_changed > 0 {
+                listed.extend(entries.iter().filter_map(|entry| Some(entry.as_ref().ok()?.path.clone())));
+            }
+            for entry_result in entries {
+                if ctx.stopped() {
+                    break;
+                }
+                let entry = match entry_result {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        ctx.entry_error(&mut totals, ScanError::IoError { path: current_path.clone(), source: e });
+                        continue;
+                    }
+                };
+
+                let path = entry.path;
+
+                if config.verbose {
+                    println!("[VERBOSE] Processing entry: {:?}", &path);
+                }
+
+                if !config.include_hidden && is_hidden(&path) {
+                    continue;
+                }
+
+                if entry.file_type == EntryType::Symlink {
+                    if config.follow_symlinks {
+                        match ctx.timed(Phase::Metadata, blocking(&ctx, &path, |fs, path| fs.metadata(path))).await {
+                            Ok(target_metadata) if !ctx.visit(&path, &target_metadata) => {}
+                            Ok(mut target_metadata) => {
+                                if target_metadata.is_file() {
+                                    if !ctx.config.wanted_file(&path)
+                                        || !ctx.wanted_mode(&target_metadata)
+                                        || !has_tags(&mut totals, &ctx, &path).await
+                                        || skip_shared_link(&mut totals, &ctx, &path, &target_metadata).await
+                                    {
+                                        continue;
+                                    }
+                                    look_up_owner(&mut totals, &ctx, &path, &mut target_metadata).await;
+                                    record_file(&mut totals, &ctx, &path, &target_metadata, repo.as_ref());
+                                    probe_lock(&mut totals, &ctx, &path, target_metadata.len).await;
+                                } else if target_metadata.is_dir() && !ctx.pruned(&path) {
+                                    if skip_shared_link(&mut totals, &ctx, &path, &target_metadata).await {
+                                        continue;
+                                    }
+                                    totals.dirs += 1;
+                                    ctx.items_found(&mut totals, 1, 0);
+                                    let sub_priority = queue_priority(&ctx, &path, Some(&target_metadata));
+                                    sub_task_paths_to_spawn.push((path.clone(), target_metadata.device.or(device), sub_priority));
+                                }
+                            }
+                            Err(e) => {
+                                ctx.entry_error(&mut totals, ScanError::MetadataError { path, source: e });
+                            }
+                        }
+                    }
+                } else if entry.file_type == EntryType::File {
+                    if !ctx.config.wanted_file(&path) {
+                        continue;
+                    }
+                    match ctx.timed(Phase::Metadata, blocking(&ctx, &path, |fs, path| fs.symlink_metadata(path))).await {
+                        Ok(metadata) if !ctx.visit(&path, &metadata) || !ctx.wanted_mode(&metadata) => {}
+                        Ok(mut metadata) => {
+                            if !has_tags(&mut totals, &ctx, &path).await {
+                                continue;
+                            }
+                            look_up_owner(&mut totals, &ctx, &path, &mut metadata).await;
+                            record_file(&mut totals, &ctx, &path, &metadata, repo.as_ref());
+                            probe_lock(&mut totals, &ctx, &path, metadata.len).await;
+                            check_acl(&mut totals, &ctx, &path, false).await;
+                            read_label(&mut totals, &ctx, &path, metadata.len).await;
+                            check_attributes(&mut totals, &ctx, &path, metadata.len).await;
+                            sample_text(&mut totals, &ctx, &path, metadata.len).await;
+                            chunk_file(&mut totals, &ctx, &path, metadata.len).await;
+                            count_extents(&mut totals, &ctx, &path, metadata.len).await;
+                        }
+                        Err(e) => {
+                            ctx.entry_error(&mut totals, ScanError::MetadataError { path, source: e });
+                        }
+                    }
+                } else if entry.file_type == EntryType::Dir && !ctx.pruned(&path) {
+                    if let Some(fs_type) = ctx.skipped_mount(&path) {
+                        totals.skipped_mounts.push(SkippedMount { fs_type: fs_type.to_string(), path });
+                        continue;
+                    }
+                    if config.skip_snapshots
+                        && subvolumes::snapshot_candidate(&path)
+                        && ctx.timed(Phase::Metadata, blocking(&ctx, &path, |fs, path| Ok(subvolumes::is_snapshot_dir(fs, path)))).await.unwrap_or(false)
+                    {
+                        totals.skipped_snapshots.push(path);
+                        continue;
+                    }
+                    if let Some(kind) = ctx.skipped_cache(&path) {
+                        caches.push((path, kind));
+                        continue;
+                    }
+                    if config.follow_symlinks && ctx.shared_directory(&mut totals, &path) {
+                        continue;
+                    }
+                    // Directory metadata costs an extra call, so only fetch it for a visitor, to
+                    // find the directory's device, to estimate its size or for its mtime
+                    let mut sub_device = device;
+                    let mut metadata = None;
+                    let estimate_from_metadata = config.largest_first && config.size_hints.is_none();
+                    if ctx.visitor.is_some() || ctx.devices.is_some() || estimate_from_metadata || config.recent_first {
+                        match ctx.timed(Phase::Metadata, blocking(&ctx, &path, |fs, path| fs.symlink_metadata(path))).await {
+                            Ok(dir_metadata) if !ctx.visit(&path, &dir_metadata) => continue,
+                            Ok(dir_metadata) => {
+                                sub_device = dir_metadata.device.or(device);
+                                metadata = Some(dir_metadata);
+                            }
+                            Err(e) => {
+                                ctx.entry_error(&mut totals, ScanError::MetadataError { path, source: e });
+                                continue;
+                            }
+                        }
+                    }
+                    totals.dirs += 1;
+                    ctx.items_found(&mut totals, 1, 0);
+                    check_acl(&mut totals, &ctx, &path, true).await;
+                    let sub_priority = queue_priority(&ctx, &path, metadata.as_ref());
+                    sub_task_paths_to_spawn.push((path.clone(), sub_device, sub_priority));
+                }
+            }
+            if totals.vanished_entries.is_empty() || rescans == config.rescan_changed || ctx.stopped() {
+                break;
+            }
+            // What vanished may be back under the same name, e.g. replaced by a rename
+            for path in totals.vanished_entries.drain(..) {
+                listed.remove(&path);
+            }
+            rescans += 1;
+            totals.rescans += 1;
+            entries = match ctx.timed(Phase::Enumeration, blocking(&ctx, &current_path, |fs, path| fs.read_dir(path))).await {
+                Ok(entries) => entries,
+                Err(_) => break,
+            };
+        }
+
+        // Must run before children are merged in
+        totals.finish_directory(&current_path, &ctx);
+
+        if config.verbose {
+            println!("[VERBOSE] Releasing permit for: {:?}, collected {} sub-paths to spawn", &current_path, sub_task_paths_to_spawn.len());
+        }
+        drop(permit); // If we don't drop the permit, the semaphore will never release causing a deadlock
+        drop(gate_permit);
+        drop(device_permit);
+
+        for (cache_path, kind) in caches {
+            let fs = Arc::clone(&ctx.fs);
+            if let Ok(usage) = tokio::task::spawn_blocking(move || caches::measure(fs.as_ref(), cache_path, kind)).await {
+                totals.skipped_caches.push(usage);
+            }
+        }
+
+        if ctx.stopped() {
+            sub_task_paths_to_spawn.clear();
+        }
+        ctx.directory_read(sub_task_paths_to_spawn.len());
+
+        if config.largest_first || config.recent_first {
+            // Spawned first, so also first in line among directories of equal priority
+            sub_task_paths_to_spawn.sort_by_key(|(_, _, priority)| Reverse(*priority));
+        }
+
+        let is_root = current_path == config.target_path;
+        let track_subtrees = is_root && (2..=MAX_SUBTREE_BARS).contains(&sub_task_paths_to_spawn.len());
+        if let Some(tx) = &progress_tx {
+            if let Some(index) = subtree {
+                // Only this directory's own entries; children report for themselves
+                let _ = tx.send(ProgressUpdate::SubtreeProgress { index, items: totals.files + totals.dirs, bytes: totals.size });
+            }
+            if track_subtrees {
+                for (index, (sub_path, _, _)) in sub_task_paths_to_spawn.iter().enumerate() {
+                    let name = sub_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    let _ = tx.send(ProgressUpdate::SubtreeStarted { index: index as u32, name });
+                }
+            }
+        }
+
+        let mut tasks = Vec::new();
+        for (index, (sub_path, sub_device, sub_priority)) in sub_task_paths_to_spawn.into_iter().enumerate() {
+            if config.verbose {
+                println!("[VERBOSE] Spawning task for sub-path: {:?} (parent: {:?})", &sub_path, &current_path);
+            }
+            let task_repo = repo.as_ref().map(|r| r.for_child(&sub_path));
+            let task_subtree = if track_subtrees { Some(index as u32) } else { subtree };
+            tasks.push(tokio::spawn(walk_directory_recursive(
+                sub_path,
+                Arc::clone(&ctx),
+                task_repo,
+                task_subtree,
+                sub_device,
+                sub_priority,
+            )));
+        }
+
+        for task_handle in tasks {
+            match task_handle.await {
+                Ok(sub_totals) => totals.merge(sub_totals, &ctx),
+                Err(join_error) => {
+                    eprintln!("Task panicked or was cancelled for a sub-path of {:?}: {:?}", &current_path, join_error);
+                    if let Some(tx) = &progress_tx {
+                        let _ = tx.send(ProgressUpdate::ErrorEncountered);
+                    }
+                }
+            }
+        }
+        totals.finish_subtree(&current_path);
+
+        if let (Some(tx), Some(index)) = (&progress_tx, subtree)
+            && current_path.parent() == Some(config.target_path.as_path())
+        {
+            let _ = tx.send(ProgressUpdate::SubtreeFinished(index));
+        }
+        totals
+    })
+}
+
+/// Runs a scan in which `visitor` decides, per file and directory, whether the entry is
+/// counted, skipped (with everything below it) or ends the scan.
+pub async fn scan_with_visitor(
+    config: &ScannerConfig,
+    visitor: impl ScanVisitor,
+) -> Result<ScanResult, anyhow::Error> {
+    run_scan_with(config, ScanHooks { visitor: Some(Arc::new(visitor)), ..Default::default() }).await
+}
+
+/// Checks that the scan root exists and is a directory.
+pub async fn ensure_directory(root_path: &Path) -> Result<(), ScanError> {
+    match fs::metadata(root_path).await {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => Err(ScanError::NotADirectory { path: root_path.to_path_buf() }),
+        Err(e) => Err(ScanError::IoError { path: root_path.to_path_buf(), source: e }),
+    }
+}
+
+/// Scanner Engine
+/// - Walks a directory tree recursively
+/// - Reports progress using a channel
+/// - Returns a ScanResult
+/// 
+pub async fn run_scan(config: &ScannerConfig) -> Result<ScanResult, anyhow::Error> {
+    run_scan_with(config, ScanHooks::default()).await
+}
+
+/// Like `run_scan`, additionally feeding progress and file entries to `hooks`. With
+/// `Engine::Sync` the walk runs on a blocking thread while progress is still reported
+/// from the runtime.
+pub async fn run_scan_with(config: &ScannerConfig, hooks: ScanHooks) -> Result<ScanResult, anyhow::Error> {
+    let started = SystemTime::now();
+    let start_time = Instant::now();
+
+    let root_path = PathBuf::from(&config.target_path);
+    match &config.filesystem {
+        Some(filesystem) => check_root(filesystem.as_ref(), &root_path)?,
+        None if config.serial => check_root(&RealFs, &root_path)?,
+        None => ensure_directory(&root_path).await?,
+    }
+
+    let metrics = config.debug_metrics.then(|| Arc::new(ScanMetrics::default()));
+
+    let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+    let mut progress_rx = Some(progress_rx);
+    // Drawn between directories by the walk itself in a serial scan
+    let mut serial_display = None;
+    let mut progress_reporter_handle = None;
+    let mut progress_socket_handle = None;
+    let mut live_top_floor = None;
+    let wants_progress = config.progress_updates || config.progress_socket.is_some() || hooks.progress.is_some();
+
+    if wants_progress {
+        let mut snapshot_tx = hooks.progress;
+        if let Some(socket_path) = &config.progress_socket {
+            let snapshot_rx = snapshot_tx
+                .get_or_insert_with(|| watch::channel(ProgressSnapshot::default()).0)
+                .subscribe();
+            let socket_path = socket_path.clone();
+            progress_socket_handle = Some(tokio::spawn(async move {
+                if let Err(e) = progress_socket::serve(socket_path.clone(), snapshot_rx).await {
+                    eprintln!("{}", crate::t!("Warning: progress socket {} failed: {}", format!("{:?}", socket_path), e));
+                }
+            }));
+        }
+        let mut reporter = ProgressReporter::new(config.progress_updates, snapshot_tx, metrics.clone())
+            .with_formatter(config.formatter);
+        if let Some(count) = config.live_top.filter(|_| config.progress_updates) {
+            let live_top = LiveTop::new(root_path.clone(), count.get());
+            live_top_floor = Some(live_top.floor());
+            reporter = reporter.with_live_top(live_top);
+        }
+        if config.serial {
+            serial_display = Some(reporter.start());
+        } else if let Some(rx) = progress_rx.take() {
+            progress_reporter_handle = Some(tokio::spawn(async move {
+                reporter.run(rx).await;
+            }));
+        }
+    } else {
+        // Drop the receiver if not used, so sender doesn't wait indefinitely or panic.
+        progress_rx = None;
+    }
+
+    let progress_tx_option = if wants_progress { Some(progress_tx) } else { None };
+
+    // Count the root directory itself if progress is enabled
+    if let Some(tx) = &progress_tx_option {
+        let _ = tx.send(ProgressUpdate::ItemsFound { items: 1, bytes: 0 });
+    }
+
+    let use_hotkeys = config.hotkeys && std::io::stdin().is_terminal();
+    let control = hooks.control.or_else(|| use_hotkeys.then(ScanControl::default));
+    let hotkeys = match &control {
+        Some(control) if use_hotkeys => match HotkeyListener::start(control.clone(), progress_tx_option.clone()) {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                eprintln!("{}", crate::t!("Warning: Cannot read hotkeys from the terminal: {}", e));
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let ctx = Arc::new(WalkContext {
+        entry_tx: hooks.entries,
+        error_tx: hooks.errors,
+        live_top_floor,
+        visitor: hooks.visitor,
+        control: control.clone(),
+        metrics: metrics.clone(),
+        ..WalkContext::new(config, progress_tx_option.clone())
+    });
+    let mut draw_progress = || {
+        if let (Some(display), Some(rx)) = (&mut serial_display, &mut progress_rx) {
+            display.drain(rx);
+        }
+    };
+    let totals = match config.engine {
+        _ if config.serial => sync_engine::walk_sequential(&ctx, root_path, &mut draw_progress),
+        Engine::Async => {
+            let device = match &ctx.devices {
+                Some(_) => blocking(&ctx, &root_path, |fs, path| fs.metadata(path)).await.ok().and_then(|m| m.device),
+                None => None,
+            };
+            walk_directory_recursive(root_path, Arc::clone(&ctx), None, None, device, 0).await
+        }
+        Engine::Sync => {
+            let walker = Arc::clone(&ctx);
+            tokio::task::spawn_blocking(move || sync_engine::walk(&walker))
+                .await
+                .map_err(|e| anyhow::anyhow!("Scan thread panicked: {}", e))?
+        }
+    };
+    // Leave raw mode before anything else is printed
+    drop(hotkeys);
+
+    draw_progress();
+    if let Some(display) = serial_display {
+        display.finish();
+    }
+
+    // Signal scan completion
+    if let Some(tx) = progress_tx_option {
+        let _ = tx.send(ProgressUpdate::ScanCompleted);
+        if let Some(handle) = progress_reporter_handle {
+            let _ = handle.await;
+        }
+        // Let socket clients receive the final snapshot before returning
+        if let Some(handle) = progress_socket_handle {
+            let _ = handle.await;
+        }
+    }
+
+    let result = totals.into_result(&ctx, started, start_time.elapsed(), metrics.map(|m| m.snapshot()));
+
+    if config.verbose {
+        eprintln!("Scanner Engine: Scan complete.");
+    }
+    Ok(result)
+}
@@ -0,0 +1,80 @@
+//! File attributes that keep cleanup jobs from modifying or deleting a file even with the
+//! right permissions, for `ScannerConfig::detect_immutable`.
+
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAttribute {
+    /// `chattr +i`: can't be changed, renamed, linked to or deleted, even by root
+    Immutable,
+    /// `chattr +a`: can only be appended to
+    AppendOnly,
+    /// Windows `FILE_ATTRIBUTE_READONLY`; deleting it fails until the attribute is cleared
+    ReadOnly,
+    /// Windows `FILE_ATTRIBUTE_SYSTEM`
+    System,
+}
+
+impl FileAttribute {
+    pub fn label(self) -> &'static str {
+        match self {
+            FileAttribute::Immutable => "immutable",
+            FileAttribute::AppendOnly => "append-only",
+            FileAttribute::ReadOnly => "read-only",
+            FileAttribute::System => "system",
+        }
+    }
+}
+
+/// Reads the attributes from `statx`, which reports them without opening the file (the
+/// `FS_IOC_GETFLAGS` ioctl would need an open descriptor).
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub(super) fn file_attributes(path: &Path) -> io::Result<Vec<FileAttribute>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statx>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stats` is a valid out-param; attributes come
+    // back whatever the mask asks for
+    let status = unsafe {
+        libc::statx(libc::AT_FDCWD, c_path.as_ptr(), libc::AT_SYMLINK_NOFOLLOW, 0, stats.as_mut_ptr())
+    };
+    if status != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statx succeeded, so it filled in the struct
+    let stats = unsafe { stats.assume_init() };
+    let set = |flag: libc::c_int| stats.stx_attributes_mask & stats.stx_attributes & flag as u64 != 0;
+    let mut attributes = Vec::new();
+    if set(libc::STATX_ATTR_IMMUTABLE) {
+        attributes.push(FileAttribute::Immutable);
+    }
+    if set(libc::STATX_ATTR_APPEND) {
+        attributes.push(FileAttribute::AppendOnly);
+    }
+    Ok(attributes)
+}
+
+#[cfg(windows)]
+pub(super) fn file_attributes(path: &Path) -> io::Result<Vec<FileAttribute>> {
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM};
+
+    let flags = std::fs::symlink_metadata(path)?.file_attributes();
+    let mut attributes = Vec::new();
+    if flags & FILE_ATTRIBUTE_READONLY != 0 {
+        attributes.push(FileAttribute::ReadOnly);
+    }
+    if flags & FILE_ATTRIBUTE_SYSTEM != 0 {
+        attributes.push(FileAttribute::System);
+    }
+    Ok(attributes)
+}
+
+#[cfg(not(any(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")), windows)))]
+pub(super) fn file_attributes(_path: &Path) -> io::Result<Vec<FileAttribute>> {
+    Ok(Vec::new())
+}
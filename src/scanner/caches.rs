@@ -0,0 +1,72 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::{CacheUsage, EntryType, ScanFs};
+
+/// Cache directories skipped by `--skip-caches`, as the trailing components of their
+/// path (compared case-insensitively), with the label they are reported under.
+const CACHE_DIRECTORIES: &[(&str, &str)] = &[
+    ("pip", ".cache/pip"),
+    ("pip", "Library/Caches/pip"),
+    ("pip", "AppData/Local/pip/Cache"),
+    ("npm", ".npm/_cacache"),
+    ("npm", "AppData/Local/npm-cache"),
+    ("yarn", ".cache/yarn"),
+    ("yarn", "Library/Caches/Yarn"),
+    ("yarn", "AppData/Local/Yarn/Cache"),
+    ("pnpm", ".local/share/pnpm/store"),
+    ("cargo", ".cargo/registry/cache"),
+    ("cargo", ".cargo/registry/src"),
+    ("cargo", ".cargo/git/checkouts"),
+    ("go", "go/pkg/mod/cache"),
+    ("gradle", ".gradle/caches"),
+    ("maven", ".m2/repository"),
+    ("nuget", ".nuget/packages"),
+    ("chrome", ".cache/google-chrome"),
+    ("chrome", ".cache/chromium"),
+    ("chrome", "Library/Caches/Google/Chrome"),
+    ("chrome", "Google/Chrome/User Data/Default/Cache"),
+    ("chrome", "Google/Chrome/User Data/Default/Code Cache"),
+    ("firefox", ".cache/mozilla/firefox"),
+    ("firefox", "Library/Caches/Firefox"),
+    ("firefox", "AppData/Local/Mozilla/Firefox/Profiles"),
+    ("edge", "Microsoft/Edge/User Data/Default/Cache"),
+    ("thumbnails", ".cache/thumbnails"),
+    ("steam shaders", "steamapps/shadercache"),
+];
+
+/// Label of the cache `path` is, if it is one of `CACHE_DIRECTORIES`.
+pub(super) fn cache_kind(path: &Path) -> Option<&'static str> {
+    CACHE_DIRECTORIES.iter()
+        .find(|(_, suffix)| ends_with_components(path, suffix))
+        .map(|&(kind, _)| kind)
+}
+
+fn ends_with_components(path: &Path, suffix: &str) -> bool {
+    let mut components = path.components().rev();
+    suffix.rsplit('/').all(|expected| {
+        matches!(components.next(), Some(Component::Normal(name)) if name.eq_ignore_ascii_case(expected))
+    })
+}
+
+/// Adds up the files below a skipped cache without recording them individually.
+/// Unreadable entries are left out of the estimate.
+/// Symlinks are not followed.
+pub(super) fn measure(fs: &dyn ScanFs, path: PathBuf, kind: &'static str) -> CacheUsage {
+    let (mut files, mut size) = (0, 0);
+    let mut pending = vec![path.clone()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs.read_dir(&dir).into_iter().flatten().filter_map(Result::ok) {
+            match entry.file_type {
+                EntryType::Dir => pending.push(entry.path),
+                EntryType::File => {
+                    if let Ok(metadata) = fs.symlink_metadata(&entry.path) {
+                        files += 1;
+                        size += metadata.len;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    CacheUsage { path, kind: kind.to_string(), files, size }
+}
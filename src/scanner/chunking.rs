@@ -0,0 +1,140 @@
+//! Content-defined chunking (FastCDC) of file contents, for `ScannerConfig::dedup_chunk_size`:
+//! backup tools such as restic and borg split files the same way and store each distinct
+//! chunk once, so the share of distinct chunks predicts how well a tree will dedupe.
+//! Cut points follow the contents, not offsets, so data shifted by an insertion still
+//! yields the same chunks after the edit.
+
+use std::collections::HashSet;
+use std::io::{self, Read};
+
+/// Bounds of the average chunk size; restic uses 1 MiB, borg 2 MiB by default.
+pub const MIN_DEDUP_CHUNK_SIZE: u64 = 4 << 10;
+pub const MAX_DEDUP_CHUNK_SIZE: u64 = 16 << 20;
+
+/// Random values per byte for the gear hash, from SplitMix64 with a fixed seed so chunk
+/// boundaries are the same in every run.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Chunk size limits and cut masks for an average chunk size.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Chunker {
+    min: usize,
+    avg: usize,
+    max: usize,
+    /// Harder to match, used before the average size so chunks rarely end early
+    mask_small: u64,
+    /// Easier to match, used after it so chunks rarely reach the maximum
+    mask_large: u64,
+}
+
+impl Chunker {
+    /// Chunks of `avg` bytes on average (a power of two of at least `MIN_DEDUP_CHUNK_SIZE`),
+    /// between a quarter of that and eight times as much.
+    pub(super) fn new(avg: u64) -> Self {
+        let bits = avg.trailing_zeros();
+        let avg = 1usize << bits;
+        // The gear hash shifts left, so its top bits depend on the most bytes
+        let mask = |bits: u32| !0u64 << (64 - bits);
+        Chunker { min: avg / 4, avg, max: avg * 8, mask_small: mask(bits + 2), mask_large: mask(bits - 2) }
+    }
+
+    /// Length of the chunk starting `data`, which holds at least `max` bytes unless the
+    /// file ends sooner.
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        let normal = end.min(self.avg);
+        let mut hash = 0u64;
+        for (i, &byte) in data.iter().enumerate().take(end).skip(self.min) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if i < normal { self.mask_small } else { self.mask_large };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Fingerprints of the chunks of everything `reader` yields, in order. `size` is the
+    /// expected length, so small files don't get a buffer for the largest chunks.
+    pub(super) fn chunks(&self, mut reader: impl Read, size: u64) -> io::Result<Vec<ChunkId>> {
+        let mut chunks = Vec::new();
+        let len = usize::try_from(size).unwrap_or(usize::MAX).saturating_add(1).min(self.max * 2);
+        let mut buf = vec![0u8; len];
+        let (mut start, mut filled, mut eof) = (0, 0, false);
+        loop {
+            // Keep at least `max` bytes ahead unless the file is done
+            if !eof && filled - start < self.max {
+                buf.copy_within(start..filled, 0);
+                filled -= start;
+                start = 0;
+                while filled < buf.len() {
+                    match reader.read(&mut buf[filled..]) {
+                        Ok(0) => {
+                            eof = true;
+                            break;
+                        }
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            if start == filled {
+                return Ok(chunks);
+            }
+            let len = self.cut(&buf[start..filled]);
+            let chunk = &buf[start..start + len];
+            chunks.push(ChunkId { hash: super::stable_hash(chunk), len: len as u32 });
+            start += len;
+        }
+    }
+}
+
+/// A chunk by its hash and length, which together stand in for its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct ChunkId {
+    hash: u64,
+    len: u32,
+}
+
+/// The chunks seen so far in the scan, shared by every walker.
+#[derive(Debug, Default)]
+pub(super) struct ChunkIndex {
+    seen: HashSet<ChunkId>,
+    pub files: u64,
+    pub bytes: u64,
+    pub chunks: u64,
+    pub unique_chunks: u64,
+    pub unique_bytes: u64,
+}
+
+impl ChunkIndex {
+    /// Adds the chunks of one file.
+    pub(super) fn add(&mut self, chunks: &[ChunkId]) {
+        self.files += 1;
+        for &chunk in chunks {
+            self.chunks += 1;
+            self.bytes += u64::from(chunk.len);
+            if self.seen.insert(chunk) {
+                self.unique_chunks += 1;
+                self.unique_bytes += u64::from(chunk.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,674 @@
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{DirInfo, ExtensionFilter, FilterRules, IgnoredErrors, PermissionFilter, PruneRule, ScanFs, MAX_DEDUP_CHUNK_SIZE, MIN_DEDUP_CHUNK_SIZE};
+use crate::baseline::Baseline;
+use crate::categories::Categories;
+use crate::regenerable::Regenerable;
+use crate::formatting::Formatter;
+use crate::tags::TagFilter;
+
+/// How a scan walks the tree; both read the same configuration and produce the same
+/// results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// One tokio task per directory, blocking calls on tokio's blocking pool; needs the
+    /// `native` feature
+    Async,
+    /// Plain `std::fs` calls, on a rayon thread pool with the `rayon` feature and on the
+    /// calling thread otherwise
+    Sync,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        if cfg!(feature = "native") { Engine::Async } else { Engine::Sync }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScannerConfig {
+    pub target_path: PathBuf,
+    pub engine: Engine,
+    /// Walk the tree on the calling thread, one directory at a time in sorted order, with
+    /// progress drawn in between instead of by a task of its own; `engine`,
+    /// `max_concurrent_tasks`, `per_device`, `largest_first` and `recent_first` don't apply
+    pub serial: bool,
+    pub max_concurrent_tasks: usize,
+    pub follow_symlinks: bool,
+    /// Follow symlinks to data the scan counts anyway, counting it once per link, rather
+    /// than only reporting them
+    pub count_links_separately: bool,
+    pub include_hidden: bool,
+    pub progress_updates: bool,
+    pub progress_socket: Option<PathBuf>,
+    /// Draw this many of the largest files and directories found so far below the progress
+    /// spinner, which needs `progress_updates`
+    pub live_top: Option<NonZeroUsize>,
+    /// How sizes and counts are shown in the progress output
+    pub formatter: Formatter,
+    pub verbose: bool,
+    pub file_pattern: Option<Regex>,
+    pub by_repo: bool,
+    pub debug_metrics: bool,
+    pub timings: bool,
+    /// Report peak memory, CPU time and filesystem calls of the scan
+    pub resource_usage: bool,
+    pub collect_entries: bool,
+    /// Bytes the collected entries, matching files and cold directories may take in
+    /// memory before they are spilled to temporary files
+    pub memory_limit: Option<u64>,
+    /// Report files not accessed (or modified) for at least this long
+    pub cold_data_age: Option<Duration>,
+    pub by_owner: bool,
+    /// Aggregate usage per file category
+    pub categories: Option<Categories>,
+    /// Split usage into regenerable data and user data
+    pub regenerable: Option<Regenerable>,
+    /// Count the entries directly inside each directory, for the busiest ones
+    pub inode_report: bool,
+    /// Histogram of how many immediate children directories have
+    pub fan_out: bool,
+    /// Count the extents of files of at least this many bytes and flag fragmented ones;
+    /// Linux only
+    pub fragmentation: Option<u64>,
+    /// Usage per subvolume or dataset, telling them apart by device (one more call per
+    /// directory)
+    pub subvolumes: bool,
+    /// Identify the disk behind the scan root (model, serial, capacity); Linux only
+    pub device_info: bool,
+    /// Model the usage on filesystems or object stores allocating space in units of each
+    /// of these sizes, for migration planning
+    pub target_block_sizes: Vec<u64>,
+    /// Keep this many of the largest files per extension, for the heaviest extensions
+    pub top_per_extension: Option<NonZeroUsize>,
+    /// Try opening every file to find ones locked by other processes
+    pub detect_locked_files: bool,
+    /// Look for files and directories with access control lists not inherited from their parent
+    pub acls: bool,
+    /// Collect SELinux contexts of files; those without one, or with a type not in the list
+    /// (if it isn't empty), are reported as mislabeled
+    pub selinux: Option<Vec<String>>,
+    /// Look for immutable, append-only (Linux), read-only and system (Windows) files
+    pub detect_immutable: bool,
+    /// Read the start of text files to report their encodings per directory
+    pub sample_encodings: bool,
+    /// Read text files to report LF, CRLF and mixed line endings per directory
+    pub line_endings: bool,
+    /// Read every file, split into content-defined chunks of this average size, to
+    /// estimate how well the tree would dedupe in a chunking backup tool
+    pub dedup_chunk_size: Option<u64>,
+    /// Label directories in reports with the first line of their `.dirinfo` or README
+    pub directory_labels: bool,
+    /// Allow- and deny-lists of file extensions, see `wanted_file`
+    pub extensions: Option<ExtensionFilter>,
+    /// Files failing these tests on their permission bits are left out of the results
+    pub permissions: Option<PermissionFilter>,
+    /// Files failing these tests on their `--tag` tags are left out of the results
+    pub tags: Option<TagFilter>,
+    /// Errors at these paths are counted in `ScanResult::ignored_errors` instead of listed
+    pub ignore_errors_under: Option<IgnoredErrors>,
+    /// Leave well-known cache directories out of the totals and report their size instead
+    pub skip_caches: bool,
+    /// Mounts of these filesystem types (or their subtypes, `fuse` for `fuse.sshfs`) are
+    /// neither counted nor descended into; needs a mount table (Linux, macOS, FreeBSD)
+    pub skip_fs_types: Vec<String>,
+    /// Leave out ZFS `.zfs` directories and snapper `.snapshots` subvolumes, whose
+    /// snapshots would count the same data again, and list them instead
+    pub skip_snapshots: bool,
+    /// Directories matching this rule are neither counted nor descended into
+    pub prune: Option<PruneRule>,
+    /// Files and directories these rsync filter rules exclude are neither counted nor
+    /// descended into, as an rsync job copying the root would leave them out
+    pub filter_rules: Option<FilterRules>,
+    /// Read `p` (pause/resume) and `q` (cancel) from the terminal while scanning
+    pub hotkeys: bool,
+    /// Filesystem to scan instead of the real one, e.g. a `MemoryFs` in tests
+    pub filesystem: Option<Arc<dyn ScanFs>>,
+    /// Give every device its own `max_concurrent_tasks` budget, so a slow drive only holds
+    /// up directories on itself; the async engine stats each directory to find its device
+    pub per_device: bool,
+    /// Read the directories waiting for a permit in descending order of estimated size, so
+    /// totals converge early and a cancelled scan still covers most of the data (async engine)
+    pub largest_first: bool,
+    /// Read the directories waiting for a permit newest modification time first, so a
+    /// time-boxed or cancelled scan covers what changed recently (async engine)
+    pub recent_first: bool,
+    /// Directory sizes from an earlier scan, the estimates for `largest_first`; without them
+    /// the size of each directory itself stands in, which grows with its number of entries
+    pub size_hints: Option<Arc<Baseline>>,
+    /// List a directory up to this many more times while entries vanish from it during the
+    /// scan, counting the entries only the new listings hold, e.g. files renamed into place
+    pub rescan_changed: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Target path is empty")]
+    EmptyPath,
+
+    #[error("Cannot resolve target path {path:?}: {source}")]
+    UnresolvablePath { path: PathBuf, source: std::io::Error },
+
+    #[error("At least one concurrent task is required")]
+    NoConcurrency,
+
+    #[error("Invalid file pattern {pattern:?}: {source}")]
+    InvalidPattern { pattern: String, source: regex::Error },
+
+    #[error("Cold data age must be greater than zero")]
+    ZeroColdDataAge,
+
+    #[error("A cold data age of {0} days is too long")]
+    ColdDataAgeTooLong(u64),
+
+    #[error("Progress socket requires the native engine")]
+    ProgressSocketUnavailable,
+
+    #[error("Skipping filesystem types needs a mount table, which this platform doesn't have")]
+    FsTypesUnsupported,
+
+    #[error("Counting extents needs FIEMAP, which only Linux has")]
+    FragmentationUnsupported,
+
+    #[error("Reading the largest and the most recently modified directories first can't be combined")]
+    ConflictingOrder,
+
+    #[error("A serial scan can't serve a progress socket, which needs a task of its own")]
+    SerialProgressSocket,
+
+    #[error("The average chunk size must be a power of two from 4 KiB to 16 MiB, got {0} bytes")]
+    InvalidChunkSize(u64),
+
+    #[error("The async engine is not available in this build (enable the `native` feature)")]
+    AsyncEngineUnavailable,
+}
+
+impl ScannerConfig {
+    /// Starts a configuration for scanning `target_path` with the CLI's defaults:
+    /// two tasks per CPU (see `cpu_limit::available_cpus`), hidden files included, no progress output and no reports.
+    pub fn builder(target_path: impl Into<PathBuf>) -> ScannerConfigBuilder {
+        ScannerConfigBuilder {
+            config: ScannerConfig {
+                target_path: target_path.into(),
+                engine: Engine::default(),
+                serial: false,
+                max_concurrent_tasks: crate::cpu_limit::available_cpus() * 2,
+                follow_symlinks: false,
+                count_links_separately: false,
+                include_hidden: true,
+                progress_updates: false,
+                progress_socket: None,
+                live_top: None,
+                formatter: Formatter::default(),
+                verbose: false,
+                file_pattern: None,
+                by_repo: false,
+                debug_metrics: false,
+                timings: false,
+                resource_usage: false,
+                collect_entries: false,
+                memory_limit: None,
+                cold_data_age: None,
+                by_owner: false,
+                categories: None,
+                regenerable: None,
+                inode_report: false,
+                fan_out: false,
+                subvolumes: false,
+                device_info: false,
+                fragmentation: None,
+                target_block_sizes: Vec::new(),
+                top_per_extension: None,
+                detect_locked_files: false,
+                acls: false,
+                selinux: None,
+                detect_immutable: false,
+                sample_encodings: false,
+                line_endings: false,
+                dedup_chunk_size: None,
+                directory_labels: false,
+                extensions: None,
+                permissions: None,
+                tags: None,
+                ignore_errors_under: None,
+                skip_caches: false,
+                skip_snapshots: false,
+                skip_fs_types: Vec::new(),
+                prune: None,
+                filter_rules: None,
+                hotkeys: false,
+                filesystem: None,
+                per_device: false,
+                largest_first: false,
+                recent_first: false,
+                size_hints: None,
+                rescan_changed: 0,
+            },
+            pattern: None,
+        }
+    }
+
+    /// A hash of the settings that decide what a scan counts and reports, so reports of
+    /// scans made with different settings can be told apart. Settings that only change how
+    /// the scan runs (engine, concurrency, progress output, memory limit) and the target
+    /// path are left out. Unlike `std`'s hashers, the hash is the same in every run.
+    pub fn config_hash(&self) -> String {
+        format!("{:016x}", super::stable_hash(self.counted_settings().as_bytes()))
+    }
+
+    /// The settings `config_hash` covers, one `name=value` line each.
+    pub fn counted_settings(&self) -> String {
+        format!(
+            "follow_symlinks={:?}\ncount_links_separately={:?}\ninclude_hidden={:?}\nfile_pattern={:?}\nby_repo={:?}\ncollect_entries={:?}\n\
+             cold_data_age={:?}\nby_owner={:?}\ncategories={:?}\nregenerable={:?}\ninode_report={:?}\nfan_out={:?}\nsubvolumes={:?}\nfragmentation={:?}\ntarget_block_sizes={:?}\n\
+             top_per_extension={:?}\ndetect_locked_files={:?}\nacls={:?}\nselinux={:?}\ndetect_immutable={:?}\n\
+             sample_encodings={:?}\nline_endings={:?}\ndedup_chunk_size={:?}\ndirectory_labels={:?}\nextensions={:?}\npermissions={:?}\n\
+             tags={:?}\nignore_errors_under={:?}\nskip_caches={:?}\nskip_fs_types={:?}\nskip_snapshots={:?}\nprune={:?}\nfilter_rules={:?}\n",
+            self.follow_symlinks,
+            self.count_links_separately,
+            self.include_hidden,
+            self.file_pattern.as_ref().map(Regex::as_str),
+            self.by_repo,
+            self.collect_entries,
+            self.cold_data_age,
+            self.by_owner,
+            self.categories.as_ref().map(Categories::assignments),
+            self.regenerable.as_ref().map(Regenerable::describe),
+            self.inode_report,
+            self.fan_out,
+            self.subvolumes,
+            self.fragmentation,
+            self.target_block_sizes,
+            self.top_per_extension,
+            self.detect_locked_files,
+            self.acls,
+            self.selinux,
+            self.detect_immutable,
+            self.sample_encodings,
+            self.line_endings,
+            self.dedup_chunk_size,
+            self.directory_labels,
+            self.extensions,
+            self.permissions,
+            self.tags.as_ref().map(|t| t.tests().collect::<Vec<_>>()),
+            self.ignore_errors_under,
+            self.skip_caches,
+            self.skip_fs_types,
+            self.skip_snapshots,
+            self.prune,
+            self.filter_rules.as_ref().map(FilterRules::describe),
+        )
+    }
+
+    /// Whether the extension filter and the filter rules let the file at `path` into the
+    /// results. Every engine, and the sampling estimate, asks this before stat'ing a file.
+    pub fn wanted_file(&self, path: &Path) -> bool {
+        self.extensions.as_ref().is_none_or(|filter| filter.allows(path)) && !self.filtered_out(path, false)
+    }
+
+    /// Whether the rsync filter rules leave out the entry at `path`.
+    pub(crate) fn filtered_out(&self, path: &Path, is_dir: bool) -> bool {
+        self.filter_rules.as_ref().is_some_and(|rules| {
+            rules.excludes(path, path.strip_prefix(&self.target_path).unwrap_or(path), is_dir)
+        })
+    }
+}
+
+/// Validating builder for `ScannerConfig`, see `ScannerConfig::builder`.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ScannerConfigBuilder {
+    config: ScannerConfig,
+    /// Compiled in `build` so an invalid pattern surfaces as a `ConfigError`
+    pattern: Option<String>,
+}
+
+impl ScannerConfigBuilder {
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.config.engine = engine;
+        self
+    }
+
+    pub fn serial(mut self, enabled: bool) -> Self {
+        self.config.serial = enabled;
+        self
+    }
+
+    /// Concurrent walker tasks, or threads for the rayon-backed `Engine::Sync`.
+    pub fn max_concurrent_tasks(mut self, tasks: usize) -> Self {
+        self.config.max_concurrent_tasks = tasks;
+        self
+    }
+
+    pub fn per_device(mut self, enabled: bool) -> Self {
+        self.config.per_device = enabled;
+        self
+    }
+
+    pub fn largest_first(mut self, enabled: bool) -> Self {
+        self.config.largest_first = enabled;
+        self
+    }
+
+    pub fn recent_first(mut self, enabled: bool) -> Self {
+        self.config.recent_first = enabled;
+        self
+    }
+
+    pub fn size_hints(mut self, hints: Option<Arc<Baseline>>) -> Self {
+        self.config.size_hints = hints;
+        self
+    }
+
+    pub fn rescan_changed(mut self, times: u32) -> Self {
+        self.config.rescan_changed = times;
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.config.follow_symlinks = follow;
+        self
+    }
+
+    pub fn count_links_separately(mut self, enabled: bool) -> Self {
+        self.config.count_links_separately = enabled;
+        self
+    }
+
+    pub fn include_hidden(mut self, include: bool) -> Self {
+        self.config.include_hidden = include;
+        self
+    }
+
+    pub fn progress_updates(mut self, show: bool) -> Self {
+        self.config.progress_updates = show;
+        self
+    }
+
+    pub fn progress_socket(mut self, path: Option<PathBuf>) -> Self {
+        self.config.progress_socket = path;
+        self
+    }
+
+    pub fn live_top(mut self, count: Option<NonZeroUsize>) -> Self {
+        self.config.live_top = count;
+        self
+    }
+
+    pub fn formatter(mut self, formatter: Formatter) -> Self {
+        self.config.formatter = formatter;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.config.verbose = verbose;
+        self
+    }
+
+    /// Regex matched against file names; replaces any earlier `file_pattern`.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = Some(pattern.into());
+        self.config.file_pattern = None;
+        self
+    }
+
+    /// Already compiled file name pattern; replaces any earlier `pattern`.
+    pub fn file_pattern(mut self, pattern: Option<Regex>) -> Self {
+        self.pattern = None;
+        self.config.file_pattern = pattern;
+        self
+    }
+
+    pub fn by_repo(mut self, by_repo: bool) -> Self {
+        self.config.by_repo = by_repo;
+        self
+    }
+
+    pub fn debug_metrics(mut self, enabled: bool) -> Self {
+        self.config.debug_metrics = enabled;
+        self
+    }
+
+    pub fn timings(mut self, enabled: bool) -> Self {
+        self.config.timings = enabled;
+        self
+    }
+
+    pub fn resource_usage(mut self, enabled: bool) -> Self {
+        self.config.resource_usage = enabled;
+        self
+    }
+
+    pub fn collect_entries(mut self, collect: bool) -> Self {
+        self.config.collect_entries = collect;
+        self
+    }
+
+    pub fn memory_limit(mut self, limit: Option<u64>) -> Self {
+        self.config.memory_limit = limit;
+        self
+    }
+
+    pub fn cold_data_age(mut self, age: Option<Duration>) -> Self {
+        self.config.cold_data_age = age;
+        self
+    }
+
+    pub fn by_owner(mut self, by_owner: bool) -> Self {
+        self.config.by_owner = by_owner;
+        self
+    }
+
+    /// `Categories::builtin()` or a loaded category file enables `--by-category` totals.
+    pub fn categories(mut self, categories: Option<Categories>) -> Self {
+        self.config.categories = categories;
+        self
+    }
+
+    /// `Regenerable::builtin()` or loaded rules enable the regenerable/user data split.
+    pub fn regenerable(mut self, regenerable: Option<Regenerable>) -> Self {
+        self.config.regenerable = regenerable;
+        self
+    }
+
+    pub fn inode_report(mut self, enabled: bool) -> Self {
+        self.config.inode_report = enabled;
+        self
+    }
+
+    pub fn fan_out(mut self, enabled: bool) -> Self {
+        self.config.fan_out = enabled;
+        self
+    }
+
+    pub fn fragmentation(mut self, min_size: Option<u64>) -> Self {
+        self.config.fragmentation = min_size;
+        self
+    }
+
+    pub fn subvolumes(mut self, enabled: bool) -> Self {
+        self.config.subvolumes = enabled;
+        self
+    }
+
+    pub fn device_info(mut self, enabled: bool) -> Self {
+        self.config.device_info = enabled;
+        self
+    }
+
+    /// Block sizes of zero are left out.
+    pub fn target_block_sizes(mut self, sizes: Vec<u64>) -> Self {
+        self.config.target_block_sizes = sizes.into_iter().filter(|&size| size > 0).collect();
+        self.config.target_block_sizes.sort_unstable();
+        self.config.target_block_sizes.dedup();
+        self
+    }
+
+    pub fn top_per_extension(mut self, count: Option<NonZeroUsize>) -> Self {
+        self.config.top_per_extension = count;
+        self
+    }
+
+    pub fn detect_locked_files(mut self, detect: bool) -> Self {
+        self.config.detect_locked_files = detect;
+        self
+    }
+
+    pub fn acls(mut self, detect: bool) -> Self {
+        self.config.acls = detect;
+        self
+    }
+
+    pub fn detect_immutable(mut self, detect: bool) -> Self {
+        self.config.detect_immutable = detect;
+        self
+    }
+
+    pub fn sample_encodings(mut self, sample: bool) -> Self {
+        self.config.sample_encodings = sample;
+        self
+    }
+
+    pub fn line_endings(mut self, classify: bool) -> Self {
+        self.config.line_endings = classify;
+        self
+    }
+
+    /// Estimate deduplication with chunks of this average size, a power of two from
+    /// `MIN_DEDUP_CHUNK_SIZE` to `MAX_DEDUP_CHUNK_SIZE`.
+    pub fn dedup_chunk_size(mut self, avg: Option<u64>) -> Self {
+        self.config.dedup_chunk_size = avg;
+        self
+    }
+
+    pub fn directory_labels(mut self, read: bool) -> Self {
+        self.config.directory_labels = read;
+        self
+    }
+
+    /// Collect SELinux contexts, expecting one of `expected_types` (any, if empty).
+    pub fn selinux(mut self, expected_types: Option<Vec<String>>) -> Self {
+        self.config.selinux = expected_types;
+        self
+    }
+
+    pub fn extensions(mut self, filter: Option<ExtensionFilter>) -> Self {
+        self.config.extensions = filter;
+        self
+    }
+
+    pub fn permissions(mut self, filter: Option<PermissionFilter>) -> Self {
+        self.config.permissions = filter.filter(|f| !f.is_empty());
+        self
+    }
+
+    pub fn tags(mut self, filter: Option<TagFilter>) -> Self {
+        self.config.tags = filter.filter(|f| !f.is_empty());
+        self
+    }
+
+    pub fn ignore_errors_under(mut self, ignored: Option<IgnoredErrors>) -> Self {
+        self.config.ignore_errors_under = ignored.filter(|i| !i.is_empty());
+        self
+    }
+
+    pub fn skip_caches(mut self, skip: bool) -> Self {
+        self.config.skip_caches = skip;
+        self
+    }
+
+    pub fn skip_fs_types(mut self, fs_types: Vec<String>) -> Self {
+        self.config.skip_fs_types = fs_types;
+        self
+    }
+
+    pub fn skip_snapshots(mut self, skip: bool) -> Self {
+        self.config.skip_snapshots = skip;
+        self
+    }
+
+    pub fn prune(mut self, rule: Option<PruneRule>) -> Self {
+        self.config.prune = rule;
+        self
+    }
+
+    /// Shorthand for `prune` with a closure, e.g. `.prune_if(|dir| dir.name == "cache")`.
+    pub fn prune_if(self, predicate: impl Fn(&DirInfo<'_>) -> bool + Send + Sync + 'static) -> Self {
+        self.prune(Some(PruneRule::new(predicate)))
+    }
+
+    pub fn filter_rules(mut self, rules: Option<FilterRules>) -> Self {
+        self.config.filter_rules = rules;
+        self
+    }
+
+    /// Only takes effect with the native engine and an interactive terminal.
+    pub fn hotkeys(mut self, enabled: bool) -> Self {
+        self.config.hotkeys = enabled;
+        self
+    }
+
+    /// Scans `filesystem` instead of the operating system's. The target path is still
+    /// made absolute, but not checked against the real filesystem.
+    pub fn filesystem(mut self, filesystem: impl ScanFs) -> Self {
+        self.config.filesystem = Some(Arc::new(filesystem));
+        self
+    }
+
+    /// Validates the settings and resolves a relative target path against the current
+    /// directory, so the configuration means the same thing wherever it is used later.
+    pub fn build(self) -> Result<ScannerConfig, ConfigError> {
+        let mut config = self.config;
+
+        if config.target_path.as_os_str().is_empty() {
+            return Err(ConfigError::EmptyPath);
+        }
+        config.target_path = std::path::absolute(&config.target_path)
+            .map_err(|source| ConfigError::UnresolvablePath { path: config.target_path.clone(), source })?;
+
+        if config.max_concurrent_tasks == 0 {
+            return Err(ConfigError::NoConcurrency);
+        }
+        if let Some(pattern) = self.pattern {
+            config.file_pattern = Some(
+                Regex::new(&pattern).map_err(|source| ConfigError::InvalidPattern { pattern, source })?,
+            );
+        }
+        if config.cold_data_age == Some(Duration::ZERO) {
+            return Err(ConfigError::ZeroColdDataAge);
+        }
+        if cfg!(not(feature = "native")) && config.progress_socket.is_some() {
+            return Err(ConfigError::ProgressSocketUnavailable);
+        }
+        if !config.skip_fs_types.is_empty() && !super::mounts::SUPPORTED {
+            return Err(ConfigError::FsTypesUnsupported);
+        }
+        if config.fragmentation.is_some() && !super::fiemap::SUPPORTED {
+            return Err(ConfigError::FragmentationUnsupported);
+        }
+        if config.largest_first && config.recent_first {
+            return Err(ConfigError::ConflictingOrder);
+        }
+        if config.serial && config.progress_socket.is_some() {
+            return Err(ConfigError::SerialProgressSocket);
+        }
+        if let Some(avg) = config.dedup_chunk_size
+            && (!avg.is_power_of_two() || !(MIN_DEDUP_CHUNK_SIZE..=MAX_DEDUP_CHUNK_SIZE).contains(&avg))
+        {
+            return Err(ConfigError::InvalidChunkSize(avg));
+        }
+        if cfg!(not(feature = "native")) && config.engine == Engine::Async && !config.serial {
+            return Err(ConfigError::AsyncEngineUnavailable);
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// How often threads of the synchronous engine check whether a pause has ended.
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+/// Lets another task or thread pause, resume or cancel a running scan.
+///
+/// Pausing stops walker tasks from acquiring new permits; directories already being
+/// read are finished. Cancelling ends the scan with the results gathered so far.
+#[derive(Debug, Clone)]
+pub struct ScanControl {
+    paused: Arc<watch::Sender<bool>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Default for ScanControl {
+    fn default() -> Self {
+        Self { paused: Arc::new(watch::Sender::new(false)), cancelled: Arc::default() }
+    }
+}
+
+impl ScanControl {
+    /// Pauses a running scan or resumes a paused one, returning whether it is now paused.
+    pub fn toggle_pause(&self) -> bool {
+        let mut paused = false;
+        self.paused.send_modify(|p| {
+            *p = !*p;
+            paused = *p;
+        });
+        paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        // Paused tasks have to wake up to notice
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(super) async fn wait_while_paused(&self) {
+        let _ = self.paused.subscribe().wait_for(|paused| !paused).await;
+    }
+
+    /// Blocking counterpart of `wait_while_paused`, for the synchronous engine's threads.
+    pub(super) fn block_while_paused(&self) {
+        while self.is_paused() {
+            std::thread::sleep(PAUSE_POLL);
+        }
+    }
+}
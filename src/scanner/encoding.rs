@@ -0,0 +1,111 @@
+//! Guessing the encoding of text files from their first bytes, for
+//! `ScannerConfig::sample_encodings`: the audit that precedes moving an old Windows share
+//! to UTF-8.
+
+use std::path::Path;
+
+/// Bytes read from the start of each sampled file.
+pub const SAMPLE_LEN: usize = 4096;
+
+/// Extensions of the files that are sampled; anything else is assumed to be binary.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "text", "log", "csv", "tsv", "ini", "cfg", "conf", "inf", "reg", "xml", "html", "htm", "json", "md",
+    "rst", "sql", "bat", "cmd", "ps1", "vbs", "js", "css", "srt", "sub", "yaml", "yml", "properties", "c", "h",
+    "cpp", "hpp", "cs", "java", "py", "sh", "pl", "php", "asp", "rtf", "tex", "nfo", "diz", "rs", "go", "ts",
+    "tsx", "jsx", "toml", "rb", "kt", "swift", "m", "scala", "lua", "gradle", "cmake", "mk", "vue", "svg",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Only 7-bit bytes, so valid in UTF-8 and every legacy code page alike
+    Ascii,
+    Utf8,
+    Utf16,
+    Utf32,
+    /// 8-bit text that isn't UTF-8: Windows-1252, ISO-8859-x, Shift JIS, ...
+    Legacy,
+}
+
+/// Sampled text files of one directory, by encoding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodingCounts {
+    pub ascii: u64,
+    pub utf8: u64,
+    pub utf16: u64,
+    pub utf32: u64,
+    pub legacy: u64,
+    /// Files starting with a byte order mark, whatever their encoding
+    pub with_bom: u64,
+}
+
+impl EncodingCounts {
+    pub fn files(&self) -> u64 {
+        self.ascii + self.utf8 + self.utf16 + self.utf32 + self.legacy
+    }
+
+    /// Files that a UTF-8 tool would misread: UTF-16, UTF-32 and legacy code pages.
+    pub fn non_utf8(&self) -> u64 {
+        self.utf16 + self.utf32 + self.legacy
+    }
+
+    pub(super) fn add(&mut self, encoding: TextEncoding, bom: bool) {
+        *match encoding {
+            TextEncoding::Ascii => &mut self.ascii,
+            TextEncoding::Utf8 => &mut self.utf8,
+            TextEncoding::Utf16 => &mut self.utf16,
+            TextEncoding::Utf32 => &mut self.utf32,
+            TextEncoding::Legacy => &mut self.legacy,
+        } += 1;
+        self.with_bom += bom as u64;
+    }
+
+    pub fn merge(&mut self, other: &EncodingCounts) {
+        self.ascii += other.ascii;
+        self.utf8 += other.utf8;
+        self.utf16 += other.utf16;
+        self.utf32 += other.utf32;
+        self.legacy += other.legacy;
+        self.with_bom += other.with_bom;
+    }
+}
+
+/// Whether the file at `path` is worth sampling, going by its extension.
+pub(super) fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TEXT_EXTENSIONS.iter().any(|t| t.eq_ignore_ascii_case(e)))
+}
+
+/// The encoding of a file starting with `head`, and whether it has a byte order mark;
+/// `None` if it looks binary after all. `truncated` says there is more of the file, so a
+/// multi-byte character cut off at the end doesn't count against UTF-8.
+pub(super) fn detect(head: &[u8], truncated: bool) -> Option<(TextEncoding, bool)> {
+    // UTF-32 LE's mark starts with UTF-16 LE's, so it has to be checked first
+    const BOMS: [(&[u8], TextEncoding); 5] = [
+        (&[0xFF, 0xFE, 0, 0], TextEncoding::Utf32),
+        (&[0, 0, 0xFE, 0xFF], TextEncoding::Utf32),
+        (&[0xEF, 0xBB, 0xBF], TextEncoding::Utf8),
+        (&[0xFF, 0xFE], TextEncoding::Utf16),
+        (&[0xFE, 0xFF], TextEncoding::Utf16),
+    ];
+    if let Some((_, encoding)) = BOMS.iter().find(|(bom, _)| head.starts_with(bom)) {
+        return Some((*encoding, true));
+    }
+    if head.contains(&0) {
+        // Mostly-ASCII UTF-16 without a mark has a zero in (nearly) every other byte
+        let zeros = |offset: usize| head.iter().skip(offset).step_by(2).filter(|&&b| b == 0).count();
+        let half = head.len() / 2;
+        let (even, odd) = (zeros(0), zeros(1));
+        return (half > 0 && even.max(odd) * 10 >= half * 8 && even.min(odd) * 10 < half)
+            .then_some((TextEncoding::Utf16, false));
+    }
+    if head.is_ascii() {
+        return Some((TextEncoding::Ascii, false));
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => Some((TextEncoding::Utf8, false)),
+        // error_len is None when the input ends inside a character
+        Err(e) if truncated && e.error_len().is_none() => Some((TextEncoding::Utf8, false)),
+        Err(_) => Some((TextEncoding::Legacy, false)),
+    }
+}
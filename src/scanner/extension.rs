@@ -0,0 +1,54 @@
+use std::path::Path;
+
+/// Allow- and deny-lists of file extensions, compared case-insensitively against the end
+/// of the file name, so multi-part extensions such as `tar.gz` work too. Files left out
+/// are neither counted nor stat'ed.
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl ExtensionFilter {
+    /// Extensions may be given with or without the leading dot; empty ones are ignored.
+    /// An empty `allow` list allows every extension.
+    pub fn new<S: AsRef<str>>(allow: impl IntoIterator<Item = S>, deny: impl IntoIterator<Item = S>) -> Self {
+        Self { allow: normalize(allow), deny: normalize(deny) }
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        let name = name.as_encoded_bytes();
+        (self.allow.is_empty() || self.allow.iter().any(|ext| has_extension(name, ext)))
+            && !self.deny.iter().any(|ext| has_extension(name, ext))
+    }
+}
+
+fn normalize<S: AsRef<str>>(extensions: impl IntoIterator<Item = S>) -> Vec<String> {
+    extensions.into_iter()
+        .map(|ext| ext.as_ref().trim().trim_start_matches('.').to_string())
+        .filter(|ext| !ext.is_empty())
+        .collect()
+}
+
+/// Whether `name` ends with `.` + `ext` and has something before that dot.
+fn has_extension(name: &[u8], ext: &str) -> bool {
+    let ext = ext.as_bytes();
+    name.len() > ext.len() + 1
+        && name[name.len() - ext.len() - 1] == b'.'
+        && name[name.len() - ext.len()..].eq_ignore_ascii_case(ext)
+}
+
+/// Lower-case last extension of the file at `path`, without the dot; empty when it has
+/// none. Dot files such as `.bashrc` have no extension.
+pub(crate) fn last_extension(path: &Path) -> String {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return String::new();
+    };
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name[dot + 1..].to_ascii_lowercase(),
+        _ => String::new(),
+    }
+}
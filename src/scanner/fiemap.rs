@@ -0,0 +1,47 @@
+//! Extent counts from the `FS_IOC_FIEMAP` ioctl, for `ScannerConfig::fragmentation`. A file
+//! in many small extents takes a seek per extent to read through, which is what makes
+//! sequential reads slow on spinning disks.
+
+use std::io;
+use std::path::Path;
+
+/// Whether this platform can count extents.
+pub(super) const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// The number of extents the file at `path` is stored in, or `None` if its filesystem
+/// can't tell (tmpfs, network filesystems, ...).
+#[cfg(target_os = "linux")]
+pub(super) fn extent_count(path: &Path) -> io::Result<Option<u64>> {
+    use std::os::fd::AsRawFd;
+
+    /// `struct fiemap` without the extent array after it; asking for no extents makes the
+    /// kernel only count them
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+    }
+    /// `_IOWR('f', 11, struct fiemap)`
+    const FS_IOC_FIEMAP: u32 = 0xc020_660b;
+
+    let file = std::fs::File::open(path)?;
+    let mut fiemap = Fiemap { start: 0, length: u64::MAX, flags: 0, mapped_extents: 0, extent_count: 0, reserved: 0 };
+    // SAFETY: `fiemap` is a valid `struct fiemap` with room for the zero extents it asks for
+    if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as libc::Ioctl, &mut fiemap) } != 0 {
+        let error = io::Error::last_os_error();
+        return match error.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::ENOTTY) => Ok(None),
+            _ => Err(error),
+        };
+    }
+    Ok(Some(u64::from(fiemap.mapped_extents)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn extent_count(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
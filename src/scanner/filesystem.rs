@@ -0,0 +1,434 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::FileAttribute;
+use crate::owners;
+
+/// Type of a directory entry, without following symlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    /// Sockets, devices, FIFOs
+    Other,
+}
+
+impl From<fs::FileType> for EntryType {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Dir
+        } else if file_type.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        }
+    }
+}
+
+/// The parts of a file's metadata the scanner uses. Unlike `std::fs::Metadata` it can be
+/// built by hand, so `ScanFs` implementations need not be backed by a real filesystem.
+#[derive(Debug, Clone)]
+pub struct EntryMetadata {
+    pub file_type: EntryType,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    /// Numeric owner, where the metadata has one; see `ScanFs::owner` for the others
+    pub owner: Option<u32>,
+    /// Device (mounted filesystem) holding the entry, where the platform reports one
+    pub device: Option<u64>,
+    /// Unix permission bits, with setuid, setgid and sticky, where the platform has them
+    pub mode: Option<u32>,
+    /// For placeholders of files kept in the cloud (OneDrive Files On-Demand, Dropbox
+    /// online-only, other recall-on-access stubs), the bytes of their data stored locally
+    pub placeholder: Option<u64>,
+}
+
+impl EntryMetadata {
+    pub fn is_file(&self) -> bool {
+        self.file_type == EntryType::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == EntryType::Dir
+    }
+}
+
+impl From<&fs::Metadata> for EntryMetadata {
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self {
+            file_type: metadata.file_type().into(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+            owner: owners::owner_id(metadata),
+            device: device_id(metadata),
+            mode: mode_bits(metadata),
+            placeholder: placeholder_local_size(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn device_id(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn mode_bits(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_bits(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Windows marks cloud placeholders with recall attributes, and archived files as
+/// offline; none of their data is taken to be local.
+#[cfg(windows)]
+fn placeholder_local_size(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_RECALL_ON_OPEN,
+    };
+    let stub = FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS | FILE_ATTRIBUTE_RECALL_ON_OPEN;
+    (metadata.is_file() && metadata.file_attributes() & stub != 0).then_some(0)
+}
+
+/// File Provider on macOS flags the files it has evicted as dataless; whatever blocks
+/// they still have are local.
+#[cfg(target_os = "macos")]
+fn placeholder_local_size(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::macos::fs::MetadataExt as _;
+    use std::os::unix::fs::MetadataExt;
+    const SF_DATALESS: u32 = 0x4000_0000;
+    (metadata.is_file() && metadata.st_flags() & SF_DATALESS != 0).then(|| (metadata.blocks() * 512).min(metadata.len()))
+}
+
+/// Other systems have no marker for placeholders: a file without blocks may just as well
+/// be sparse, so none is taken for one.
+#[cfg(not(any(target_os = "macos", windows)))]
+fn placeholder_local_size(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// One entry of a directory listing.
+#[derive(Debug, Clone)]
+pub struct FsEntry {
+    pub path: PathBuf,
+    pub file_type: EntryType,
+}
+
+/// The filesystem operations a scan performs. `RealFs` is used unless
+/// `ScannerConfig::filesystem` says otherwise; `MemoryFs` (feature `memory-fs`)
+/// simulates trees with permission errors, slow directories and symlink loops.
+///
+/// Calls block; the concurrent engine runs them on tokio's blocking thread pool.
+pub trait ScanFs: Debug + Send + Sync + 'static {
+    /// Lists `path`; an entry that cannot be read is an `Err` in the list, while the
+    /// other entries are still returned.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<FsEntry>>>;
+
+    /// Metadata of `path`, following symlinks.
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata>;
+
+    /// Metadata of `path` itself, even if it is a symlink.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryMetadata>;
+
+    /// Opens `path` for reading and closes it again, for `--locked-files`; on Unix also
+    /// takes and drops an exclusive `flock`, failing with `WouldBlock` if another process
+    /// holds one.
+    fn probe_open(&self, path: &Path) -> io::Result<()>;
+
+    /// Describes the access control entries of `path` that it didn't inherit from its
+    /// parent directory, for `--acls`; `None` if there are none. Filesystems without ACLs
+    /// can keep this default.
+    fn explicit_acl(&self, _path: &Path) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// The SELinux context of `path`, for `--selinux`; `None` if it has no label. Filesystems
+    /// without labels can keep this default.
+    fn security_context(&self, _path: &Path) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Attributes of `path` that keep it from being deleted or rewritten, for
+    /// `--immutable`. Filesystems without such attributes can keep this default.
+    fn file_attributes(&self, _path: &Path) -> io::Result<Vec<FileAttribute>> {
+        Ok(Vec::new())
+    }
+
+    /// How many extents the file at `path` is stored in, for `--fragmentation`; `None` if
+    /// the filesystem can't tell. Filesystems without extents can keep this default.
+    fn extent_count(&self, _path: &Path) -> io::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// The owner of `path` for `--by-owner` when its metadata had none, as on Windows
+    /// where owners live in security descriptors. Filesystems without owners can keep
+    /// this default.
+    fn owner(&self, _path: &Path) -> io::Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// Reads the start of the file at `path` into `buf`, returning how much was read (less
+    /// than `buf.len()` only at the end of the file), for `--encodings` and `--line-endings`. Filesystems
+    /// without contents can keep this default, which reads nothing.
+    fn read_head(&self, _path: &Path, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    /// Opens the file at `path` to read all of it, for `--dedup-estimate`. Filesystems
+    /// without contents can keep this default, which reads nothing.
+    fn open_read(&self, _path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(io::empty()))
+    }
+
+    /// The tags `--tag` recorded on `path` itself, for `--filter-tag`. Filesystems without
+    /// a place for them can keep this default, which finds none.
+    fn file_tags(&self, _path: &Path) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// `path` made absolute with every symlink resolved, to tell which followed links lead
+    /// to the same place. Filesystems without symlinks can keep this default, which fails.
+    fn canonicalize(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Lets a caller keep a handle on the filesystem it passed to a scan, e.g. to read a
+/// `MemoryFs`'s counters afterwards.
+impl<T: ScanFs> ScanFs for Arc<T> {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<FsEntry>>> {
+        (**self).read_dir(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        (**self).metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        (**self).symlink_metadata(path)
+    }
+
+    fn probe_open(&self, path: &Path) -> io::Result<()> {
+        (**self).probe_open(path)
+    }
+
+    fn explicit_acl(&self, path: &Path) -> io::Result<Option<String>> {
+        (**self).explicit_acl(path)
+    }
+
+    fn security_context(&self, path: &Path) -> io::Result<Option<String>> {
+        (**self).security_context(path)
+    }
+
+    fn file_attributes(&self, path: &Path) -> io::Result<Vec<FileAttribute>> {
+        (**self).file_attributes(path)
+    }
+
+    fn extent_count(&self, path: &Path) -> io::Result<Option<u64>> {
+        (**self).extent_count(path)
+    }
+
+    fn owner(&self, path: &Path) -> io::Result<Option<u32>> {
+        (**self).owner(path)
+    }
+
+    fn read_head(&self, path: &Path, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_head(path, buf)
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        (**self).open_read(path)
+    }
+
+    fn file_tags(&self, path: &Path) -> io::Result<Vec<String>> {
+        (**self).file_tags(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        (**self).canonicalize(path)
+    }
+}
+
+/// The operating system's filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl ScanFs for RealFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<FsEntry>>> {
+        Ok(fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(FsEntry { path: entry.path(), file_type: entry.file_type()?.into() })
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        fs::metadata(path).map(|m| EntryMetadata::from(&m))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        fs::symlink_metadata(path).map(|m| EntryMetadata::from(&m))
+    }
+
+    fn probe_open(&self, path: &Path) -> io::Result<()> {
+        let file = fs::File::open(path)?;
+        // Unix opens files whatever locks they hold, so ask for an exclusive lock without
+        // waiting; it goes again when the file is closed
+        #[cfg(unix)]
+        file.try_lock()?;
+        drop(file);
+        Ok(())
+    }
+
+    fn explicit_acl(&self, path: &Path) -> io::Result<Option<String>> {
+        super::acl::explicit_acl(path)
+    }
+
+    fn security_context(&self, path: &Path) -> io::Result<Option<String>> {
+        super::xattr::security_context(path)
+    }
+
+    fn file_attributes(&self, path: &Path) -> io::Result<Vec<FileAttribute>> {
+        super::attributes::file_attributes(path)
+    }
+
+    fn extent_count(&self, path: &Path) -> io::Result<Option<u64>> {
+        super::fiemap::extent_count(path)
+    }
+
+    fn owner(&self, path: &Path) -> io::Result<Option<u32>> {
+        owners::owner_of(path)
+    }
+
+    fn read_head(&self, path: &Path, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = fs::File::open(path)?;
+        let mut filled = 0;
+        while filled < buf.len() {
+            match file.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn file_tags(&self, path: &Path) -> io::Result<Vec<String>> {
+        crate::tags::read_native(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// Calls made through a `CountingFs`.
+#[derive(Debug, Default)]
+pub(crate) struct FsCallCounts {
+    pub read_dir: AtomicU64,
+    /// `metadata` and `symlink_metadata`, and reads of ACLs, labels and attributes
+    pub metadata: AtomicU64,
+    /// `probe_open`, `read_head` and `open_read`
+    pub probe_open: AtomicU64,
+}
+
+/// Counts the calls a scan makes into another `ScanFs`, for `--resource-usage`.
+#[derive(Debug)]
+pub(crate) struct CountingFs {
+    pub inner: Arc<dyn ScanFs>,
+    pub counts: Arc<FsCallCounts>,
+}
+
+impl ScanFs for CountingFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<FsEntry>>> {
+        self.counts.read_dir.fetch_add(1, Ordering::Relaxed);
+        self.inner.read_dir(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.symlink_metadata(path)
+    }
+
+    fn probe_open(&self, path: &Path) -> io::Result<()> {
+        self.counts.probe_open.fetch_add(1, Ordering::Relaxed);
+        self.inner.probe_open(path)
+    }
+
+    fn explicit_acl(&self, path: &Path) -> io::Result<Option<String>> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.explicit_acl(path)
+    }
+
+    fn security_context(&self, path: &Path) -> io::Result<Option<String>> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.security_context(path)
+    }
+
+    fn file_attributes(&self, path: &Path) -> io::Result<Vec<FileAttribute>> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.file_attributes(path)
+    }
+
+    fn extent_count(&self, path: &Path) -> io::Result<Option<u64>> {
+        self.counts.probe_open.fetch_add(1, Ordering::Relaxed);
+        self.inner.extent_count(path)
+    }
+
+    fn owner(&self, path: &Path) -> io::Result<Option<u32>> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.owner(path)
+    }
+
+    fn read_head(&self, path: &Path, buf: &mut [u8]) -> io::Result<usize> {
+        self.counts.probe_open.fetch_add(1, Ordering::Relaxed);
+        self.inner.read_head(path, buf)
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        self.counts.probe_open.fetch_add(1, Ordering::Relaxed);
+        self.inner.open_read(path)
+    }
+
+    fn file_tags(&self, path: &Path) -> io::Result<Vec<String>> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.file_tags(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.counts.metadata.fetch_add(1, Ordering::Relaxed);
+        self.inner.canonicalize(path)
+    }
+}
@@ -0,0 +1,310 @@
+//! rsync filter rules (`--filter-file`), so a scan sees the tree the way an rsync job
+//! copying the scan root (`rsync -a ROOT/ DEST`) would: the first rule matching a file or
+//! directory decides, anything no rule matches is included, and an excluded directory
+//! is not descended into, whatever rules for its contents say.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use thiserror::Error;
+
+/// An ordered list of include and exclude rules, as read from rsync filter files.
+#[derive(Clone, Default)]
+pub struct FilterRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    include: bool,
+    /// The pattern without a leading `/`, a trailing `/` or a trailing `/***`
+    pattern: Vec<u8>,
+    /// Leading `/`: matched against the whole path from the scan root
+    anchored: bool,
+    /// Trailing `/`: only matches directories
+    dir_only: bool,
+    /// Trailing `/***`: matches the directory and everything below it
+    with_contents: bool,
+    /// Holds a `/` or `**`, so it is matched against the path instead of the name
+    full_path: bool,
+    /// `!` modifier: applies to what the pattern does not match
+    negate: bool,
+    /// `/` modifier: matched against the absolute path
+    absolute: bool,
+    /// The rule as written, for `Debug` output
+    source: String,
+}
+
+#[derive(Debug, Error)]
+pub enum FilterRuleError {
+    #[error("Cannot read filter file {path:?}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("Filter file {path:?}, line {line}: {message}")]
+    Invalid { path: PathBuf, line: usize, message: String },
+}
+
+impl FilterRules {
+    /// Appends the rules of the filter file at `path`, following its `merge` rules.
+    pub fn add_file(&mut self, path: &Path) -> Result<(), FilterRuleError> {
+        self.merge(path, None, 0)
+    }
+
+    /// Whether an rsync job with these rules leaves out the file or directory at `path`,
+    /// `relative` to the scan root.
+    pub fn excludes(&self, path: &Path, relative: &Path, is_dir: bool) -> bool {
+        let relative = slash_path(relative);
+        let mut absolute = None;
+        for rule in &self.rules {
+            let matched = if rule.absolute {
+                let absolute = absolute.get_or_insert_with(|| slash_path(path));
+                rule.matches(absolute.strip_prefix(b"/").unwrap_or(absolute), is_dir)
+            } else {
+                rule.matches(&relative, is_dir)
+            };
+            if matched {
+                return !rule.include;
+            }
+        }
+        false
+    }
+
+    /// Every rule as written, in the order they are checked.
+    pub fn describe(&self) -> Vec<&str> {
+        self.rules.iter().map(|rule| rule.source.as_str()).collect()
+    }
+
+    /// `force` makes every rule of a `merge` with the `+` or `-` modifier an include or
+    /// an exclude.
+    fn merge(&mut self, path: &Path, force: Option<bool>, depth: usize) -> Result<(), FilterRuleError> {
+        // rsync gives up on merge files including each other too
+        if depth > 16 {
+            return Err(FilterRuleError::Invalid { path: path.to_path_buf(), line: 0, message: "merge files nest too deep".into() });
+        }
+        let text = std::fs::read(path).map_err(|source| FilterRuleError::Io { path: path.to_path_buf(), source })?;
+        for (i, line) in text.split(|&b| b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let invalid = |message: String| FilterRuleError::Invalid { path: path.to_path_buf(), line: i + 1, message };
+            match parse_line(line, force).map_err(invalid)? {
+                Parsed::Nothing => {}
+                Parsed::Clear => self.rules.clear(),
+                Parsed::Rule(rule) => self.rules.push(rule),
+                Parsed::Merge { file, force } => self.merge(&file, force, depth + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FilterRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FilterRules").field(&self.describe()).finish()
+    }
+}
+
+enum Parsed {
+    /// A blank line, a comment or a rule only the receiving side applies
+    Nothing,
+    Clear,
+    Rule(Rule),
+    Merge { file: PathBuf, force: Option<bool> },
+}
+
+/// Parses one line of a filter file: `RULE[,MODIFIERS] PATTERN` with the long rule names,
+/// or the short ones with the modifiers right after them (`-! *.tmp`).
+fn parse_line(line: &[u8], force: Option<bool>) -> Result<Parsed, String> {
+    if line.iter().all(u8::is_ascii_whitespace) || line.starts_with(b"#") || line.starts_with(b";") {
+        return Ok(Parsed::Nothing);
+    }
+    let text = String::from_utf8_lossy(line);
+    // Inside a `merge` with `+` or `-`, every line is a pattern
+    if let Some(include) = force {
+        return Rule::parse(include, "", line, &text);
+    }
+    let (head, pattern) = match line.iter().position(|&b| b == b' ' || b == b'_') {
+        Some(end) => (&line[..end], &line[end + 1..]),
+        None => (line, &b""[..]),
+    };
+    let head = std::str::from_utf8(head).map_err(|_| format!("unknown rule {:?}", text))?;
+    const LONG: &[(&str, &str)] = &[
+        ("include", "+"), ("exclude", "-"), ("show", "S"), ("hide", "H"), ("protect", "P"), ("risk", "R"),
+        ("merge", "."), ("dir-merge", ":"), ("clear", "!"),
+    ];
+    let (rule, modifiers) = match LONG.iter().find(|(name, _)| head == *name || head.starts_with(&format!("{},", name))) {
+        Some((name, short)) => (*short, &head[name.len()..]),
+        None => head.split_at(head.chars().next().map_or(0, char::len_utf8)),
+    };
+    let modifiers = modifiers.strip_prefix(',').unwrap_or(modifiers);
+    match rule {
+        // Hiding and showing are include and exclude for the sending side only
+        "+" | "S" => Rule::parse(true, modifiers, pattern, &text),
+        "-" | "H" => Rule::parse(false, modifiers, pattern, &text),
+        // Protecting and risking only concern deletions on the receiving side
+        "P" | "R" => Ok(Parsed::Nothing),
+        "!" if pattern.is_empty() => Ok(Parsed::Clear),
+        "." => {
+            let force = match modifiers {
+                "" | "e" => None,
+                "+" | "+e" | "e+" => Some(true),
+                "-" | "-e" | "e-" => Some(false),
+                _ => return Err(format!("merge modifiers {:?} are not supported", modifiers)),
+            };
+            let file = std::str::from_utf8(pattern).map_err(|_| "merge file name is not UTF-8".to_string())?;
+            Ok(Parsed::Merge { file: PathBuf::from(file), force })
+        }
+        ":" => Err("per-directory merge files (dir-merge) are not supported".into()),
+        _ => Err(format!("unknown rule {:?}", text)),
+    }
+}
+
+impl Rule {
+    /// The rule for `pattern`, unless its modifiers make it one a scan has no use for.
+    fn parse(include: bool, modifiers: &str, pattern: &[u8], source: &str) -> Result<Parsed, String> {
+        let (mut negate, mut absolute) = (false, false);
+        for modifier in modifiers.chars() {
+            match modifier {
+                '!' => negate = true,
+                '/' => absolute = true,
+                // Sending side only, or perishable: both apply to what gets copied
+                's' | 'p' => {}
+                // Receiving side only, or extended attribute names: a scan never sees them
+                'r' | 'x' => return Ok(Parsed::Nothing),
+                _ => return Err(format!("modifier {:?} is not supported", modifier)),
+            }
+        }
+        if pattern.is_empty() {
+            return Err(format!("{:?} has no pattern", source));
+        }
+        let mut pattern = pattern;
+        let anchored = absolute || pattern.starts_with(b"/");
+        pattern = pattern.strip_prefix(b"/").unwrap_or(pattern);
+        let with_contents = pattern.ends_with(b"/***");
+        if with_contents {
+            pattern = &pattern[..pattern.len() - 4];
+        }
+        let dir_only = with_contents || pattern.ends_with(b"/");
+        pattern = pattern.strip_suffix(b"/").unwrap_or(pattern);
+        let full_path = anchored || with_contents || pattern.contains(&b'/') || pattern.windows(2).any(|w| w == b"**");
+        Ok(Parsed::Rule(Rule {
+            include,
+            pattern: pattern.to_vec(),
+            anchored,
+            dir_only,
+            with_contents,
+            full_path,
+            negate,
+            absolute,
+            source: source.to_string(),
+        }))
+    }
+
+    /// Whether the rule applies to the entry at `path` (`/`-separated, from the root the
+    /// rule is anchored at).
+    fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        self.matches_pattern(path, is_dir) != self.negate
+    }
+
+    fn matches_pattern(&self, path: &[u8], is_dir: bool) -> bool {
+        if self.with_contents {
+            // Everything below the directory, whatever it is
+            let mut below = self.pattern.clone();
+            below.extend_from_slice(b"/**");
+            if self.matches_name(&below, path) {
+                return true;
+            }
+        }
+        (is_dir || !self.dir_only) && self.matches_name(&self.pattern, path)
+    }
+
+    fn matches_name(&self, pattern: &[u8], path: &[u8]) -> bool {
+        if !self.full_path {
+            let name = path.rsplit(|&b| b == b'/').next().unwrap_or(path);
+            return wildmatch(pattern, name);
+        }
+        if self.anchored {
+            return wildmatch(pattern, path);
+        }
+        // Unanchored patterns with a `/` match the end of the path at a `/`
+        wildmatch(pattern, path)
+            || path.iter().enumerate().any(|(i, &b)| b == b'/' && wildmatch(pattern, &path[i + 1..]))
+    }
+}
+
+/// `path` with `/` between its components, without a root or prefix unless it is absolute.
+fn slash_path(path: &Path) -> Vec<u8> {
+    let mut joined = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => {
+                if !joined.is_empty() && joined != b"/" {
+                    joined.push(b'/');
+                }
+                joined.extend_from_slice(name.as_encoded_bytes());
+            }
+            Component::RootDir => joined.push(b'/'),
+            _ => {}
+        }
+    }
+    joined
+}
+
+/// Matches `text` against an rsync wildcard pattern: `*` matches within a path component,
+/// `**` across them, `?` one character other than `/`, `[...]` one of a set, and `\`
+/// escapes the next character. Patterns without `*`, `?` or `[` are compared literally.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    if !pattern.iter().any(|b| matches!(b, b'*' | b'?' | b'[')) {
+        return pattern == text;
+    }
+    matches_from(pattern, text)
+}
+
+fn matches_from(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = pattern.iter().position(|&b| b != b'*').map_or(&b""[..], |i| &pattern[i..]);
+            (0..=text.len()).any(|i| matches_from(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let component = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+            (0..=component).any(|i| matches_from(rest, &text[i..]))
+        }
+        Some(b'?') => text.first().is_some_and(|&c| c != b'/') && matches_from(&pattern[1..], &text[1..]),
+        Some(b'[') => match (text.first(), class(&pattern[1..])) {
+            (Some(&c), Some((set, len))) => c != b'/' && set(c) && matches_from(&pattern[len + 1..], &text[1..]),
+            (Some(&c), None) => c == b'[' && matches_from(&pattern[1..], &text[1..]),
+            (None, _) => false,
+        },
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && matches_from(&pattern[2..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && matches_from(&pattern[1..], &text[1..]),
+    }
+}
+
+/// The set a `[...]` class (`pattern` starting after the `[`) matches, with the length of
+/// the class up to and including the `]`; `None` if it isn't closed.
+fn class(pattern: &[u8]) -> Option<(impl Fn(u8) -> bool + '_, usize)> {
+    let negated = matches!(pattern.first(), Some(b'!' | b'^'));
+    let start = usize::from(negated);
+    // A `]` right at the start is part of the set
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&b| b == b']')?;
+    let set = &pattern[start..end];
+    let contains = move |c: u8| {
+        let mut i = 0;
+        while i < set.len() {
+            if set.get(i + 1) == Some(&b'-') && i + 2 < set.len() {
+                if (set[i]..=set[i + 2]).contains(&c) {
+                    return true;
+                }
+                i += 3;
+            } else {
+                if set[i] == c {
+                    return true;
+                }
+                i += 1;
+            }
+        }
+        false
+    };
+    Some((move |c: u8| contains(c) != negated, end + 1))
+}
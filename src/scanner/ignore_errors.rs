@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+/// Places whose errors are only counted, for `ScannerConfig::ignore_errors_under`, as on
+/// servers the same unreadable paths (`/proc`, `/run`, ...) would fill every report.
+/// Entries are path prefixes, or patterns when they contain `*` (within one component),
+/// `**` (across components) or `?`, e.g. `/home/*/.cache`. Both cover everything below.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoredErrors {
+    prefixes: Vec<PathBuf>,
+    patterns: Vec<Regex>,
+}
+
+impl IgnoredErrors {
+    /// Empty entries are ignored.
+    pub fn new<S: AsRef<str>>(entries: impl IntoIterator<Item = S>) -> Self {
+        let mut ignored = Self::default();
+        for entry in entries {
+            let entry = entry.as_ref().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            if entry.contains(['*', '?']) {
+                ignored.patterns.push(pattern_regex(entry));
+            } else {
+                ignored.prefixes.push(PathBuf::from(entry));
+            }
+        }
+        ignored
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Whether errors at `path` are to be left out of the results.
+    pub fn covers(&self, path: &Path) -> bool {
+        if self.prefixes.iter().any(|prefix| path.starts_with(prefix)) {
+            return true;
+        }
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let path = path.to_string_lossy();
+        self.patterns.iter().any(|pattern| pattern.is_match(&path))
+    }
+}
+
+/// Regex matching the paths at or below those matched by the wildcard `pattern`.
+fn pattern_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => regex.push_str(".*"),
+            '*' => regex.push_str(r"[^/\\]*"),
+            '?' => regex.push_str(r"[^/\\]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push_str(r"(?:[/\\]|$)");
+    // Everything but the wildcards is escaped, so the regex is always valid
+    Regex::new(&regex).expect("escaped wildcard pattern")
+}
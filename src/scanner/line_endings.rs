@@ -0,0 +1,69 @@
+//! Classifying the line endings of text files, for `ScannerConfig::line_endings`:
+//! repository hygiene checks on top of the scanner's traversal.
+
+/// Bytes read from each file; source files are rarely longer, and for those that are the
+/// start decides.
+pub const LINE_ENDING_SAMPLE_LEN: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    /// Both LF and CRLF lines
+    Mixed,
+    /// A single line without a line break
+    None,
+}
+
+/// Sampled text files of one directory, by line ending.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineEndingCounts {
+    pub lf: u64,
+    pub crlf: u64,
+    pub mixed: u64,
+    pub none: u64,
+}
+
+impl LineEndingCounts {
+    pub fn files(&self) -> u64 {
+        self.lf + self.crlf + self.mixed + self.none
+    }
+
+    pub(super) fn add(&mut self, ending: LineEnding) {
+        *match ending {
+            LineEnding::Lf => &mut self.lf,
+            LineEnding::Crlf => &mut self.crlf,
+            LineEnding::Mixed => &mut self.mixed,
+            LineEnding::None => &mut self.none,
+        } += 1;
+    }
+
+    pub fn merge(&mut self, other: &LineEndingCounts) {
+        self.lf += other.lf;
+        self.crlf += other.crlf;
+        self.mixed += other.mixed;
+        self.none += other.none;
+    }
+}
+
+/// The line endings of a file starting with `head`; `None` for binary files (and UTF-16
+/// or UTF-32 text, whose line breaks aren't single bytes).
+pub(super) fn classify(head: &[u8]) -> Option<LineEnding> {
+    if head.contains(&0) {
+        return None;
+    }
+    let (mut lf, mut crlf) = (0u64, 0u64);
+    for (i, _) in head.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+        if i > 0 && head[i - 1] == b'\r' {
+            crlf += 1;
+        } else {
+            lf += 1;
+        }
+    }
+    Some(match (lf, crlf) {
+        (0, 0) => LineEnding::None,
+        (_, 0) => LineEnding::Lf,
+        (0, _) => LineEnding::Crlf,
+        _ => LineEnding::Mixed,
+    })
+}
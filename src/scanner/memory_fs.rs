@@ -0,0 +1,307 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use super::filesystem::{EntryMetadata, EntryType, FsEntry, ScanFs};
+
+/// Symlinks followed while resolving one path before giving up, as Linux does.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Longest path accepted, so a walk through a symlink loop ends like on a real system.
+const MAX_PATH_LEN: usize = 4096;
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    Dir,
+    File { len: u64 },
+    Symlink { target: PathBuf },
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    kind: NodeKind,
+    modified: SystemTime,
+}
+
+/// In-memory, deterministic `ScanFs` for testing the engine and code embedding it.
+///
+/// Trees are built with absolute paths; missing parent directories are created. Faults
+/// are injected per path:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use disk_scanner::scanner::{MemoryFs, ScannerConfig};
+/// let fs = MemoryFs::new()
+///     .file("/data/a.log", 4096)
+///     .file("/data/private/key", 32)
+///     .deny("/data/private")                              // read_dir fails
+///     .slow("/data/archive", Duration::from_millis(50))   // read_dir sleeps first
+///     .symlink("/data/loop", "/data");                    // loops with --follow-symlinks
+/// let config = ScannerConfig::builder("/data").filesystem(fs).build();
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: HashMap<PathBuf, Node>,
+    children: HashMap<PathBuf, BTreeSet<OsString>>,
+    denied: HashSet<PathBuf>,
+    locked: HashSet<PathBuf>,
+    broken_metadata: HashSet<PathBuf>,
+    delays: HashMap<PathBuf, Duration>,
+    read_dir_calls: AtomicUsize,
+    reads_in_flight: AtomicUsize,
+    peak_reads_in_flight: AtomicUsize,
+}
+
+/// Drops the in-flight count of a `read_dir` call when it returns.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an empty directory.
+    pub fn dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.insert(path.as_ref(), NodeKind::Dir);
+        self
+    }
+
+    /// Adds a file of `len` bytes.
+    pub fn file(mut self, path: impl AsRef<Path>, len: u64) -> Self {
+        self.insert(path.as_ref(), NodeKind::File { len });
+        self
+    }
+
+    /// Adds a symlink; a relative `target` is resolved against the link's directory.
+    pub fn symlink(mut self, path: impl AsRef<Path>, target: impl Into<PathBuf>) -> Self {
+        self.insert(path.as_ref(), NodeKind::Symlink { target: target.into() });
+        self
+    }
+
+    /// Sets the modification (and access) time of an existing entry.
+    pub fn modified(mut self, path: impl AsRef<Path>, time: SystemTime) -> Self {
+        if let Some(node) = self.nodes.get_mut(&normalize(path.as_ref())) {
+            node.modified = time;
+        }
+        self
+    }
+
+    /// Listing this directory, or opening this file, fails with "permission denied".
+    pub fn deny(mut self, path: impl AsRef<Path>) -> Self {
+        self.denied.insert(normalize(path.as_ref()));
+        self
+    }
+
+    /// Opening this file fails as if another process held a lock on it.
+    pub fn lock(mut self, path: impl AsRef<Path>) -> Self {
+        self.locked.insert(normalize(path.as_ref()));
+        self
+    }
+
+    /// Reading this entry's metadata fails, as when a file vanishes mid-scan.
+    pub fn break_metadata(mut self, path: impl AsRef<Path>) -> Self {
+        self.broken_metadata.insert(normalize(path.as_ref()));
+        self
+    }
+
+    /// Listing this directory blocks for `delay` first.
+    pub fn slow(mut self, path: impl AsRef<Path>, delay: Duration) -> Self {
+        self.delays.insert(normalize(path.as_ref()), delay);
+        self
+    }
+
+    /// How many times a directory was listed.
+    pub fn read_dir_calls(&self) -> usize {
+        self.read_dir_calls.load(Ordering::SeqCst)
+    }
+
+    /// The most directory listings that were in progress at the same time.
+    pub fn peak_concurrent_reads(&self) -> usize {
+        self.peak_reads_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// A tree with one of every fault: `root/docs` (3 files), an unreadable
+    /// `root/private`, a slow `root/archive`, a locked file, a file whose metadata
+    /// cannot be read, a symlink loop (`root/docs/up` -> `root`) and a dangling link.
+    pub fn fixture(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        MemoryFs::new()
+            .file(root.join("docs/report.pdf"), 200_000)
+            .file(root.join("docs/notes.txt"), 1_500)
+            .file(root.join("docs/draft.txt"), 800)
+            .file(root.join("private/secret.key"), 64)
+            .deny(root.join("private"))
+            .file(root.join("archive/2023.tar.gz"), 5_000_000)
+            .slow(root.join("archive"), Duration::from_millis(20))
+            .file(root.join("db/data.mdb"), 1_000_000)
+            .lock(root.join("db/data.mdb"))
+            .file(root.join("tmp/vanishing"), 10)
+            .break_metadata(root.join("tmp/vanishing"))
+            .symlink(root.join("docs/up"), "..")
+            .symlink(root.join("tmp/dangling"), root.join("missing"))
+    }
+
+    /// A balanced tree `depth` levels deep with `fan_out` sub-directories and
+    /// `files_per_dir` files of `file_len` bytes in every directory, for exercising
+    /// concurrency limits.
+    pub fn fixture_wide(root: impl AsRef<Path>, depth: u32, fan_out: u32, files_per_dir: u32, file_len: u64) -> Self {
+        fn fill(fs: MemoryFs, dir: &Path, depth: u32, fan_out: u32, files: u32, len: u64) -> MemoryFs {
+            let mut fs = fs.dir(dir);
+            for i in 0..files {
+                fs = fs.file(dir.join(format!("file{}", i)), len);
+            }
+            if depth > 0 {
+                for i in 0..fan_out {
+                    fs = fill(fs, &dir.join(format!("dir{}", i)), depth - 1, fan_out, files, len);
+                }
+            }
+            fs
+        }
+        fill(MemoryFs::new(), root.as_ref(), depth, fan_out, files_per_dir, file_len)
+    }
+
+    fn insert(&mut self, path: &Path, kind: NodeKind) {
+        let path = normalize(path);
+        let mut child = path.clone();
+        while let Some(parent) = child.parent().map(Path::to_path_buf) {
+            if let Some(name) = child.file_name() {
+                self.children.entry(parent.clone()).or_default().insert(name.to_owned());
+            }
+            self.nodes.entry(parent.clone()).or_insert_with(|| Node { kind: NodeKind::Dir, modified: SystemTime::UNIX_EPOCH });
+            child = parent;
+        }
+        self.nodes.insert(path, Node { kind, modified: SystemTime::UNIX_EPOCH });
+    }
+
+    /// Resolves `path` to the path of the node it names, following symlinks in every
+    /// component but the last, and in the last one too when `follow_last` is set.
+    fn resolve<'a>(&'a self, path: &'a Path, follow_last: bool) -> io::Result<(PathBuf, &'a Node)> {
+        if path.as_os_str().len() > MAX_PATH_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "File name too long"));
+        }
+        let mut pending: Vec<Component<'a>> = path.components().rev().collect();
+        let mut current = PathBuf::new();
+        let mut hops = 0;
+        while let Some(component) = pending.pop() {
+            match component {
+                Component::Prefix(_) => current = PathBuf::from(component.as_os_str()),
+                Component::RootDir => {
+                    let prefix = match current.components().next() {
+                        Some(prefix @ Component::Prefix(_)) => PathBuf::from(prefix.as_os_str()),
+                        _ => PathBuf::new(),
+                    };
+                    current = prefix.join(component.as_os_str());
+                }
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    current.pop();
+                }
+                Component::Normal(name) => {
+                    let candidate = current.join(name);
+                    let node = self.nodes.get(&candidate).ok_or_else(|| not_found(path))?;
+                    let last = pending.is_empty();
+                    match &node.kind {
+                        NodeKind::Symlink { target } if !last || follow_last => {
+                            hops += 1;
+                            if hops > MAX_SYMLINK_HOPS {
+                                return Err(io::Error::other("Too many levels of symbolic links"));
+                            }
+                            pending.extend(target.components().rev());
+                        }
+                        NodeKind::Dir => current = candidate,
+                        _ if last => current = candidate,
+                        _ => return Err(io::Error::new(ErrorKind::NotADirectory, "Not a directory")),
+                    }
+                }
+            }
+        }
+        let node = self.nodes.get(&current).ok_or_else(|| not_found(path))?;
+        Ok((current, node))
+    }
+
+    fn entry_metadata(&self, path: &Path, follow: bool) -> io::Result<EntryMetadata> {
+        let (resolved, node) = self.resolve(path, follow)?;
+        if self.broken_metadata.contains(&resolved) {
+            return Err(io::Error::other("Simulated metadata failure"));
+        }
+        let (file_type, len) = match node.kind {
+            NodeKind::Dir => (EntryType::Dir, 0),
+            NodeKind::File { len } => (EntryType::File, len),
+            NodeKind::Symlink { ref target } => (EntryType::Symlink, target.as_os_str().len() as u64),
+        };
+        Ok(EntryMetadata { file_type, len, modified: Some(node.modified), accessed: Some(node.modified), owner: None, device: None, mode: None, placeholder: None })
+    }
+}
+
+impl ScanFs for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<FsEntry>>> {
+        self.read_dir_calls.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.reads_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        let _guard = InFlight(&self.reads_in_flight);
+        self.peak_reads_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+        let (resolved, node) = self.resolve(path, true)?;
+        if !matches!(node.kind, NodeKind::Dir) {
+            return Err(io::Error::new(ErrorKind::NotADirectory, "Not a directory"));
+        }
+        if let Some(delay) = self.delays.get(&resolved) {
+            std::thread::sleep(*delay);
+        }
+        if self.denied.contains(&resolved) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "Permission denied"));
+        }
+        Ok(self.children.get(&resolved).into_iter().flatten()
+            .map(|name| {
+                let file_type = match self.nodes[&resolved.join(name)].kind {
+                    NodeKind::Dir => EntryType::Dir,
+                    NodeKind::File { .. } => EntryType::File,
+                    NodeKind::Symlink { .. } => EntryType::Symlink,
+                };
+                Ok(FsEntry { path: path.join(name), file_type })
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        self.entry_metadata(path, true)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        self.entry_metadata(path, false)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve(path, true).map(|(resolved, _)| resolved)
+    }
+
+    fn probe_open(&self, path: &Path) -> io::Result<()> {
+        let (resolved, _) = self.resolve(path, true)?;
+        if self.denied.contains(&resolved) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "Permission denied"));
+        }
+        if self.locked.contains(&resolved) {
+            #[cfg(windows)]
+            return Err(io::Error::from_raw_os_error(32)); // ERROR_SHARING_VIOLATION
+            #[cfg(not(windows))]
+            return Err(io::Error::new(ErrorKind::WouldBlock, "Resource temporarily unavailable"));
+        }
+        Ok(())
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("No such file or directory: {:?}", path))
+}
@@ -0,0 +1,146 @@
+//! Mount points below the scan root whose filesystem type `--skip-fs-type` leaves out,
+//! read from the system's mount table once per scan. A directory is checked against it
+//! before it is stat'ed or listed, so a hung network mount can't stall the scan.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The mounts to skip, by their path as the walk reaches them.
+#[derive(Debug, Default)]
+pub(super) struct SkippedMounts {
+    types: HashMap<PathBuf, String>,
+}
+
+impl SkippedMounts {
+    /// The mounts below `root` of one of `fs_types`. A type also matches subtypes, so
+    /// `fuse` takes in `fuse.sshfs`.
+    pub(super) fn load(root: &Path, fs_types: &[String]) -> io::Result<Self> {
+        // Mount points are listed with symlinks resolved, while the walk goes by `root`
+        let canonical_root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        // Of mounts stacked on one mount point, the last one listed is the one visible
+        let table: HashMap<PathBuf, String> = mount_table()?.into_iter().map(|mount| (mount.mount_point, mount.fs_type)).collect();
+        let types = table.into_iter()
+            .filter(|(_, fs_type)| fs_types.iter().any(|skipped| matches_type(fs_type, skipped)))
+            .filter_map(|(mount_point, fs_type)| {
+                let below = mount_point.strip_prefix(&canonical_root).ok()?;
+                (!below.as_os_str().is_empty()).then(|| (root.join(below), fs_type))
+            })
+            .collect();
+        Ok(Self { types })
+    }
+
+    /// The filesystem type of the directory at `path`, if it is a mount to skip.
+    pub(super) fn fs_type(&self, path: &Path) -> Option<&str> {
+        self.types.get(path).map(String::as_str)
+    }
+}
+
+fn matches_type(fs_type: &str, skipped: &str) -> bool {
+    fs_type == skipped || fs_type.strip_prefix(skipped).is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// What is mounted on the mount point holding `path`.
+pub(super) fn mount_source(path: &Path) -> io::Result<Option<PathBuf>> {
+    let path = std::fs::canonicalize(path)?;
+    // The deepest mount point above `path`, and of those stacked there the last one listed
+    let mut holding: Option<Mount> = None;
+    for mount in mount_table()? {
+        if path.starts_with(&mount.mount_point)
+            && holding.as_ref().is_none_or(|held| mount.mount_point.components().count() >= held.mount_point.components().count())
+        {
+            holding = Some(mount);
+        }
+    }
+    Ok(holding.map(|mount| PathBuf::from(mount.source)))
+}
+
+/// One line of the mount table.
+struct Mount {
+    mount_point: PathBuf,
+    fs_type: String,
+    /// What is mounted, such as `/dev/sda1`, or a name like `tmpfs` or `server:/export`
+    source: String,
+}
+
+/// Every mount, from `/proc/self/mountinfo`.
+#[cfg(target_os = "linux")]
+fn mount_table() -> io::Result<Vec<Mount>> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    Ok(mountinfo.lines()
+        .filter_map(|line| {
+            // `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`: the
+            // mount point is the fifth field, the type and source the first after the `-`
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mount_point = mount.split(' ').nth(4)?;
+            let mut filesystem = filesystem.split(' ');
+            let fs_type = filesystem.next()?;
+            let source = filesystem.next()?;
+            Some(Mount { mount_point: PathBuf::from(unescape(mount_point)), fs_type: fs_type.to_string(), source: unescape(source) })
+        })
+        .collect())
+}
+
+/// Undoes the octal escapes (`\040` for a space) of paths in the mount table.
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match octal {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Every mount, from `getmntinfo`.
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn mount_table() -> io::Result<Vec<Mount>> {
+    use std::ffi::CStr;
+
+    let mut mounts: *mut libc::statfs = std::ptr::null_mut();
+    // SAFETY: getmntinfo points `mounts` at `count` statfs structs it owns
+    let count = unsafe { libc::getmntinfo(&mut mounts, libc::MNT_NOWAIT) };
+    if count <= 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: see above; the buffer stays valid until the next call on this thread
+    let mounts = unsafe { std::slice::from_raw_parts(mounts, count as usize) };
+    Ok(mounts.iter()
+        .map(|mount| {
+            // SAFETY: the names are NUL-terminated within their arrays
+            let (mount_point, fs_type, source) = unsafe {
+                (
+                    CStr::from_ptr(mount.f_mntonname.as_ptr()),
+                    CStr::from_ptr(mount.f_fstypename.as_ptr()),
+                    CStr::from_ptr(mount.f_mntfromname.as_ptr()),
+                )
+            };
+            Mount {
+                mount_point: PathBuf::from(mount_point.to_string_lossy().into_owned()),
+                fs_type: fs_type.to_string_lossy().into_owned(),
+                source: source.to_string_lossy().into_owned(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+fn mount_table() -> io::Result<Vec<Mount>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Whether this platform has a mount table to skip filesystem types by.
+pub(super) const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos", target_os = "freebsd"));
@@ -0,0 +1,113 @@
+use thiserror::Error;
+
+/// Tests on Unix permission bits, as `find -perm` takes them; a file is counted only when
+/// it passes all of them. Files whose mode is unknown (no Unix permissions, or a `ScanFs`
+/// that doesn't report them) are left out.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionFilter {
+    tests: Vec<PermissionTest>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PermissionTest {
+    bits: u32,
+    kind: Match,
+    /// Passes when the mode does not match instead
+    negated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Match {
+    /// The permission bits are exactly these
+    Exact,
+    /// All of these bits are set (`-MODE`)
+    All,
+    /// Any of these bits is set (`/MODE`)
+    Any,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid permission test {test:?}: {reason}")]
+pub struct PermissionParseError {
+    test: String,
+    reason: &'static str,
+}
+
+impl PermissionFilter {
+    /// Adds a test: `MODE` for exactly these bits, `-MODE` for all of them, `/MODE` for
+    /// any of them, each negated with a leading `!`. Modes are octal (`4000`, `022`) or
+    /// symbolic clauses starting from no bits (`o+w`, `u=rwx,g+s`).
+    pub fn add(&mut self, test: &str) -> Result<(), PermissionParseError> {
+        let error = |reason| PermissionParseError { test: test.to_string(), reason };
+        let trimmed = test.trim();
+        let (negated, rest) = match trimmed.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let (kind, mode) = match rest.as_bytes().first() {
+            Some(b'-') => (Match::All, &rest[1..]),
+            Some(b'/') => (Match::Any, &rest[1..]),
+            Some(_) => (Match::Exact, rest),
+            None => return Err(error("empty mode")),
+        };
+        let bits = parse_mode(mode).map_err(error)?;
+        self.tests.push(PermissionTest { bits, kind, negated });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tests.is_empty()
+    }
+
+    /// Whether a file with permission bits `mode` passes every test.
+    pub fn allows(&self, mode: Option<u32>) -> bool {
+        let Some(mode) = mode else {
+            return false;
+        };
+        let mode = mode & 0o7777;
+        self.tests.iter().all(|test| {
+            let matched = match test.kind {
+                Match::Exact => mode == test.bits,
+                Match::All => mode & test.bits == test.bits,
+                // As in find, `/000` matches every file
+                Match::Any => test.bits == 0 || mode & test.bits != 0,
+            };
+            matched != test.negated
+        })
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, &'static str> {
+    if mode.is_empty() {
+        return Err("empty mode");
+    }
+    if mode.bytes().all(|b| b.is_ascii_digit()) {
+        return u32::from_str_radix(mode, 8).ok()
+            .filter(|bits| *bits <= 0o7777)
+            .ok_or("octal modes have at most four digits 0-7");
+    }
+    let mut bits = 0;
+    for clause in mode.split(',') {
+        let split = clause.find(['+', '=']).ok_or("symbolic clauses look like u+w or go=rx")?;
+        let who = match &clause[..split] {
+            "" => 0o7777,
+            who => who.chars().try_fold(0, |mask, c| match c {
+                'u' => Ok(mask | 0o4700),
+                'g' => Ok(mask | 0o2070),
+                'o' => Ok(mask | 0o1007),
+                'a' => Ok(mask | 0o7777),
+                _ => Err("who must be one or more of u, g, o and a"),
+            })?,
+        };
+        let what = clause[split + 1..].chars().try_fold(0, |mask, c| match c {
+            'r' => Ok(mask | 0o444),
+            'w' => Ok(mask | 0o222),
+            'x' => Ok(mask | 0o111),
+            's' => Ok(mask | 0o6000),
+            't' => Ok(mask | 0o1000),
+            _ => Err("permissions must be one or more of r, w, x, s and t"),
+        })?;
+        bits |= who & what;
+    }
+    Ok(bits)
+}
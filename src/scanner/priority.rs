@@ -0,0 +1,97 @@
+//! A semaphore whose waiters are served by priority, for `ScannerConfig::largest_first`
+//! and `ScannerConfig::recent_first`.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tokio::sync::oneshot;
+
+/// Hands out a fixed number of permits, always to the waiter with the highest priority
+/// next, in arrival order among equals. With every priority the same it is a plain fair
+/// semaphore.
+#[derive(Debug)]
+pub(super) struct PriorityGate {
+    state: Mutex<GateState>,
+}
+
+#[derive(Debug)]
+struct GateState {
+    available: usize,
+    waiting: BinaryHeap<Waiter>,
+    arrivals: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: u64,
+    arrival: u64,
+    wake: oneshot::Sender<()>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.arrival.cmp(&self.arrival))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+/// Returns its permit to the gate when dropped.
+#[derive(Debug)]
+pub(super) struct GatePermit {
+    gate: Arc<PriorityGate>,
+}
+
+impl PriorityGate {
+    pub(super) fn new(permits: usize) -> Self {
+        Self { state: Mutex::new(GateState { available: permits, waiting: BinaryHeap::new(), arrivals: 0 }) }
+    }
+
+    /// Waits for a permit. Walker tasks always run to completion, so a waiter is never
+    /// dropped after being handed a permit, which would lose that permit.
+    pub(super) async fn acquire(self: Arc<Self>, priority: u64) -> GatePermit {
+        let woken = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.available > 0 {
+                state.available -= 1;
+                None
+            } else {
+                let (wake, woken) = oneshot::channel();
+                let arrival = state.arrivals;
+                state.arrivals += 1;
+                state.waiting.push(Waiter { priority, arrival, wake });
+                Some(woken)
+            }
+        };
+        if let Some(woken) = woken {
+            // The permit is handed over by the dropped `GatePermit`
+            let _ = woken.await;
+        }
+        GatePermit { gate: self }
+    }
+}
+
+impl Drop for GatePermit {
+    fn drop(&mut self) {
+        let mut state = self.gate.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use regex::Regex;
+use thiserror::Error;
+
+/// A directory about to be descended into, as seen by a `PruneRule`.
+#[derive(Debug, Clone, Copy)]
+pub struct DirInfo<'a> {
+    pub path: &'a Path,
+    pub name: &'a str,
+    /// 1 for direct children of the scan root
+    pub depth: usize,
+}
+
+/// Decides which directories the walker does not descend into. Pruned directories are
+/// left out of the results entirely.
+#[derive(Clone)]
+pub struct PruneRule {
+    predicate: Arc<dyn Fn(&DirInfo<'_>) -> bool + Send + Sync>,
+    /// The `--prune-if` expression, for `Debug` output
+    source: Option<String>,
+}
+
+impl PruneRule {
+    pub fn new(predicate: impl Fn(&DirInfo<'_>) -> bool + Send + Sync + 'static) -> Self {
+        Self { predicate: Arc::new(predicate), source: None }
+    }
+
+    /// Parses a `--prune-if` expression such as `dir.name == "cache" && dir.depth > 2`.
+    ///
+    /// Fields are `dir.name`, `dir.path` (strings) and `dir.depth` (integer). Strings
+    /// compare with `==`, `!=` and `=~` (regex), integers with `==`, `!=`, `<`, `<=`,
+    /// `>`, `>=`. Conditions combine with `&&`, `||`, `!` and parentheses.
+    pub fn parse(source: &str) -> Result<Self, PruneParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(PruneParseError(format!("unexpected {} after the end of the expression", token)));
+        }
+        Ok(Self {
+            predicate: Arc::new(move |dir: &DirInfo<'_>| expr.eval(dir)),
+            source: Some(source.to_string()),
+        })
+    }
+
+    pub fn matches(&self, dir: &DirInfo<'_>) -> bool {
+        (self.predicate)(dir)
+    }
+}
+
+impl fmt::Debug for PruneRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => f.debug_tuple("PruneRule").field(source).finish(),
+            None => f.write_str("PruneRule(<closure>)"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid prune expression: {0}")]
+pub struct PruneParseError(String);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Field(Field),
+    Str(String),
+    Int(u64),
+    Op(CmpOp),
+    Match,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Field(field) => write!(f, "field {}", field.label()),
+            Token::Str(s) => write!(f, "string {:?}", s),
+            Token::Int(n) => write!(f, "number {}", n),
+            Token::Op(op) => write!(f, "operator {:?}", op),
+            Token::Match => f.write_str("operator =~"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Not => f.write_str("!"),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Path,
+    Depth,
+}
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::Name => "dir.name",
+            Field::Path => "dir.path",
+            Field::Depth => "dir.depth",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn compare<T: PartialOrd + ?Sized>(self, left: &T, right: &T) -> bool {
+        match self {
+            CmpOp::Eq => left == right,
+            CmpOp::Ne => left != right,
+            CmpOp::Lt => left < right,
+            CmpOp::Le => left <= right,
+            CmpOp::Gt => left > right,
+            CmpOp::Ge => left >= right,
+        }
+    }
+}
+
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text { field: Field, op: CmpOp, value: String },
+    Number { op: CmpOp, value: u64 },
+    Matches { field: Field, regex: Regex },
+}
+
+impl Expr {
+    fn eval(&self, dir: &DirInfo<'_>) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(dir) && right.eval(dir),
+            Expr::Or(left, right) => left.eval(dir) || right.eval(dir),
+            Expr::Not(inner) => !inner.eval(dir),
+            Expr::Text { field, op, value } => op.compare(text_field(dir, *field).as_ref(), value.as_str()),
+            Expr::Number { op, value } => op.compare(&(dir.depth as u64), value),
+            Expr::Matches { field, regex } => regex.is_match(&text_field(dir, *field)),
+        }
+    }
+}
+
+fn text_field<'a>(dir: &DirInfo<'a>, field: Field) -> std::borrow::Cow<'a, str> {
+    match field {
+        Field::Path => dir.path.to_string_lossy(),
+        _ => dir.name.into(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, PruneParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(PruneParseError("unterminated string".into())),
+                    },
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err(PruneParseError("unterminated string".into())),
+                }
+            }
+            tokens.push(Token::Str(value));
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(i, d)) = chars.peek()
+                && d.is_ascii_digit()
+            {
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            let value = source[start..end]
+                .parse()
+                .map_err(|_| PruneParseError(format!("number {} is too large", &source[start..end])))?;
+            tokens.push(Token::Int(value));
+            continue;
+        }
+        if c.is_ascii_alphabetic() {
+            let mut end = start;
+            while let Some(&(i, ch)) = chars.peek()
+                && (ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
+            {
+                end = i + ch.len_utf8();
+                chars.next();
+            }
+            let field = match &source[start..end] {
+                "dir.name" => Field::Name,
+                "dir.path" => Field::Path,
+                "dir.depth" => Field::Depth,
+                other => {
+                    return Err(PruneParseError(format!(
+                        "unknown field {:?} (expected dir.name, dir.path or dir.depth)",
+                        other
+                    )));
+                }
+            };
+            tokens.push(Token::Field(field));
+            continue;
+        }
+
+        chars.next();
+        let next = chars.peek().map(|&(_, n)| n);
+        let (token, two_chars) = match (c, next) {
+            ('=', Some('=')) => (Token::Op(CmpOp::Eq), true),
+            ('=', Some('~')) => (Token::Match, true),
+            ('!', Some('=')) => (Token::Op(CmpOp::Ne), true),
+            ('<', Some('=')) => (Token::Op(CmpOp::Le), true),
+            ('>', Some('=')) => (Token::Op(CmpOp::Ge), true),
+            ('&', Some('&')) => (Token::And, true),
+            ('|', Some('|')) => (Token::Or, true),
+            ('<', _) => (Token::Op(CmpOp::Lt), false),
+            ('>', _) => (Token::Op(CmpOp::Gt), false),
+            ('!', _) => (Token::Not, false),
+            ('(', _) => (Token::Open, false),
+            (')', _) => (Token::Close, false),
+            _ => return Err(PruneParseError(format!("unexpected character {:?} at offset {}", c, start))),
+        };
+        if two_chars {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over `or := and ("||" and)*`, `and := unary ("&&" unary)*`,
+/// `unary := "!" unary | "(" or ")" | field op literal`.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, PruneParseError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, PruneParseError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, PruneParseError> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(PruneParseError("missing closing parenthesis".into()));
+                }
+                Ok(expr)
+            }
+            Some(Token::Field(field)) => self.comparison(field),
+            Some(token) => Err(PruneParseError(format!("expected a condition, found {}", token))),
+            None => Err(PruneParseError("expected a condition, found the end of the expression".into())),
+        }
+    }
+
+    fn comparison(&mut self, field: Field) -> Result<Expr, PruneParseError> {
+        let op = self.next();
+        let value = self.next();
+        match (field, op, value) {
+            (Field::Depth, Some(Token::Op(op)), Some(Token::Int(value))) => Ok(Expr::Number { op, value }),
+            (Field::Name | Field::Path, Some(Token::Op(op @ (CmpOp::Eq | CmpOp::Ne))), Some(Token::Str(value))) => {
+                Ok(Expr::Text { field, op, value })
+            }
+            (Field::Name | Field::Path, Some(Token::Match), Some(Token::Str(pattern))) => {
+                let regex = Regex::new(&pattern).map_err(|e| PruneParseError(e.to_string()))?;
+                Ok(Expr::Matches { field, regex })
+            }
+            (Field::Depth, ..) => Err(PruneParseError("dir.depth must be compared with an integer".into())),
+            _ => Err(PruneParseError(format!(
+                "{} must be compared with ==, != or =~ and a quoted string",
+                field.label()
+            ))),
+        }
+    }
+}
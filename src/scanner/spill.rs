@@ -0,0 +1,400 @@
+//! Moving the per-file collections of a scan to temporary files once they outgrow
+//! `ScannerConfig::memory_limit`, so scans of huge trees fit on small machines.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Take, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tempfile::NamedTempFile;
+
+use super::{ColdDirectory, FileEntry, MatchedFile};
+
+/// Items a `SpillList` can move to disk and read back.
+pub trait Spill: Clone {
+    /// Roughly the bytes the item occupies in memory, heap allocations included.
+    fn footprint(&self) -> usize;
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()>;
+    fn read_from(input: &mut impl Read) -> io::Result<Self>;
+}
+
+/// A list whose items stay in memory until the scan's memory limit moves them to a
+/// temporary file, deleted once the list is dropped. Lists with an order keep it: every
+/// run written out is sorted, and iterating merges the runs with what is still in memory.
+#[derive(Debug)]
+pub struct SpillList<T> {
+    memory: Vec<T>,
+    /// `Spill::footprint` of everything in `memory`
+    memory_bytes: usize,
+    file: Option<NamedTempFile>,
+    runs: Vec<Run>,
+    order: Option<fn(&T, &T) -> Ordering>,
+}
+
+/// Items written to the spill file in one go.
+#[derive(Debug)]
+struct Run {
+    offset: u64,
+    bytes: u64,
+    items: usize,
+}
+
+impl<T> Default for SpillList<T> {
+    fn default() -> Self {
+        Self { memory: Vec::new(), memory_bytes: 0, file: None, runs: Vec::new(), order: None }
+    }
+}
+
+impl<T> From<Vec<T>> for SpillList<T> {
+    fn from(memory: Vec<T>) -> Self {
+        Self { memory, ..Default::default() }
+    }
+}
+
+impl<T: Spill> SpillList<T> {
+    /// An empty list kept sorted by `order`.
+    pub(crate) fn ordered(order: fn(&T, &T) -> Ordering) -> Self {
+        Self { order: Some(order), ..Default::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.spilled()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Items that were moved to disk.
+    pub fn spilled(&self) -> usize {
+        self.runs.iter().map(|run| run.items).sum()
+    }
+
+    /// Adds `item`, returning its footprint.
+    pub(crate) fn push(&mut self, item: T) -> usize {
+        let bytes = item.footprint();
+        self.memory.push(item);
+        self.memory_bytes += bytes;
+        bytes
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.memory_bytes
+    }
+
+    /// Writes everything held in memory to the spill file as one run, returning the
+    /// bytes freed. On failure the items stay in memory.
+    pub(crate) fn spill(&mut self) -> io::Result<usize> {
+        if self.memory.is_empty() {
+            return Ok(0);
+        }
+        self.sort();
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(tempfile::Builder::new().prefix("disk_scanner-").suffix(".spill").tempfile()?),
+        };
+        // A run that failed half way leaves unreferenced bytes behind, so always append
+        let offset = file.as_file_mut().seek(SeekFrom::End(0))?;
+        let mut out = BufWriter::new(file.as_file_mut());
+        for item in &self.memory {
+            item.write_to(&mut out)?;
+        }
+        let end = out.stream_position()?;
+        out.flush()?;
+        drop(out);
+        self.runs.push(Run { offset, bytes: end - offset, items: self.memory.len() });
+        self.memory = Vec::new();
+        Ok(std::mem::take(&mut self.memory_bytes))
+    }
+
+    /// Sorts what is in memory, once nothing more is added.
+    pub(crate) fn sort(&mut self) {
+        if let Some(order) = self.order {
+            self.memory.sort_by(order);
+        }
+    }
+
+    /// Every item, in order for ordered lists; spilled items are read back one at a
+    /// time, so iterating never needs more memory than the list already holds.
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<Cow<'_, T>>> + '_ {
+        let mut sources: Vec<Source<'_, T>> = self.runs.iter().map(|run| Source::Run { run, reader: None, left: run.items }).collect();
+        sources.push(Source::Memory(self.memory.iter()));
+        Iter { file: self.file.as_ref(), heads: sources.iter().map(|_| None).collect(), sources, order: self.order, failed: false }
+    }
+
+    /// Every item, read back into memory.
+    pub fn to_vec(&self) -> io::Result<Vec<T>> {
+        self.iter().map(|item| item.map(Cow::into_owned)).collect()
+    }
+}
+
+enum Source<'a, T> {
+    Run { run: &'a Run, reader: Option<BufReader<Take<File>>>, left: usize },
+    Memory(std::slice::Iter<'a, T>),
+}
+
+impl<'a, T: Spill> Source<'a, T> {
+    fn next(&mut self, file: Option<&NamedTempFile>) -> Option<io::Result<Cow<'a, T>>> {
+        match self {
+            Source::Memory(items) => items.next().map(|item| Ok(Cow::Borrowed(item))),
+            Source::Run { left: 0, .. } => None,
+            Source::Run { run, reader, left } => {
+                if reader.is_none() {
+                    // Every source gets a handle of its own, so runs can be read side by side
+                    let opened = file.ok_or_else(|| io::Error::other("spill file is gone")).and_then(|file| {
+                        let mut handle = file.reopen()?;
+                        handle.seek(SeekFrom::Start(run.offset))?;
+                        Ok(BufReader::new(handle.take(run.bytes)))
+                    });
+                    match opened {
+                        Ok(opened) => *reader = Some(opened),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                *left -= 1;
+                Some(T::read_from(reader.as_mut()?).map(Cow::Owned))
+            }
+        }
+    }
+}
+
+struct Iter<'a, T: Clone> {
+    file: Option<&'a NamedTempFile>,
+    sources: Vec<Source<'a, T>>,
+    /// The next item of each source, while merging an ordered list
+    heads: Vec<Option<Cow<'a, T>>>,
+    order: Option<fn(&T, &T) -> Ordering>,
+    /// Set after an error, which ends the iteration
+    failed: bool,
+}
+
+impl<'a, T: Spill> Iterator for Iter<'a, T> {
+    type Item = io::Result<Cow<'a, T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let Some(order) = self.order else {
+            // Unordered: one source after the other
+            while let Some(source) = self.sources.first_mut() {
+                match source.next(self.file) {
+                    Some(item) => {
+                        self.failed = item.is_err();
+                        return Some(item);
+                    }
+                    None => {
+                        self.sources.remove(0);
+                    }
+                }
+            }
+            return None;
+        };
+        for (source, head) in self.sources.iter_mut().zip(&mut self.heads) {
+            if head.is_none() {
+                match source.next(self.file) {
+                    Some(Ok(item)) => *head = Some(item),
+                    Some(Err(e)) => {
+                        self.failed = true;
+                        return Some(Err(e));
+                    }
+                    None => {}
+                }
+            }
+        }
+        // Ties go to the earlier source, so equal items keep the order they were added in
+        let smallest = self.heads.iter().enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|item| (i, item)))
+            .reduce(|best, next| if order(next.1, best.1) == Ordering::Less { next } else { best })
+            .map(|(i, _)| i)?;
+        self.heads[smallest].take().map(Ok)
+    }
+}
+
+/// The spilled collections of a scan with `ScannerConfig::memory_limit`, shared by its
+/// walker tasks. Whenever they hold more than the limit the largest one is spilled.
+#[derive(Debug)]
+pub(crate) struct SpillStore {
+    limit: u64,
+    /// Bytes held in memory by all three lists
+    used: u64,
+    /// Set once spilling failed; everything is kept in memory from then on
+    failed: bool,
+    pub(crate) entries: SpillList<FileEntry>,
+    pub(crate) matching_files: SpillList<MatchedFile>,
+    pub(crate) cold_directories: SpillList<ColdDirectory>,
+}
+
+impl SpillStore {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: 0,
+            failed: false,
+            entries: SpillList::default(),
+            matching_files: SpillList::default(),
+            cold_directories: SpillList::ordered(larger_first),
+        }
+    }
+
+    pub(crate) fn add_entry(&mut self, entry: FileEntry) -> io::Result<()> {
+        self.used += self.entries.push(entry) as u64;
+        self.enforce()
+    }
+
+    pub(crate) fn add_match(&mut self, matched: MatchedFile) -> io::Result<()> {
+        self.used += self.matching_files.push(matched) as u64;
+        self.enforce()
+    }
+
+    pub(crate) fn add_cold_directory(&mut self, directory: ColdDirectory) -> io::Result<()> {
+        self.used += self.cold_directories.push(directory) as u64;
+        self.enforce()
+    }
+
+    fn enforce(&mut self) -> io::Result<()> {
+        if self.used <= self.limit || self.failed {
+            return Ok(());
+        }
+        let largest = [self.entries.memory_bytes(), self.matching_files.memory_bytes(), self.cold_directories.memory_bytes()];
+        let spilled = match largest.iter().enumerate().max_by_key(|(_, bytes)| **bytes).map(|(i, _)| i) {
+            Some(0) => self.entries.spill(),
+            Some(1) => self.matching_files.spill(),
+            _ => self.cold_directories.spill(),
+        };
+        match spilled {
+            Ok(freed) => {
+                self.used -= freed as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.failed = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// The lists, ready to be handed out with the scan result.
+    pub(crate) fn take(&mut self) -> (SpillList<FileEntry>, SpillList<MatchedFile>, SpillList<ColdDirectory>) {
+        let mut cold_directories = std::mem::replace(&mut self.cold_directories, SpillList::ordered(larger_first));
+        cold_directories.sort();
+        self.used = 0;
+        (std::mem::take(&mut self.entries), std::mem::take(&mut self.matching_files), cold_directories)
+    }
+}
+
+/// Order of `ColdDataReport::directories`.
+pub(crate) fn larger_first(a: &ColdDirectory, b: &ColdDirectory) -> Ordering {
+    b.size.cmp(&a.size)
+}
+
+impl Spill for FileEntry {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.path.capacity()
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_path(out, &self.path)?;
+        out.write_all(&self.size.to_le_bytes())?;
+        write_time(out, self.modified)?;
+        match self.owner {
+            Some(uid) => {
+                out.write_all(&[1])?;
+                out.write_all(&uid.to_le_bytes())
+            }
+            None => out.write_all(&[0]),
+        }
+    }
+
+    fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        Ok(FileEntry {
+            path: read_path(input)?,
+            size: read_u64(input)?,
+            modified: read_time(input)?,
+            owner: match read_array::<1>(input)? {
+                [0] => None,
+                _ => Some(u32::from_le_bytes(read_array(input)?)),
+            },
+        })
+    }
+}
+
+impl Spill for MatchedFile {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.path.capacity()
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_path(out, &self.path)?;
+        out.write_all(&self.size.to_le_bytes())
+    }
+
+    fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        Ok(MatchedFile { path: read_path(input)?, size: read_u64(input)? })
+    }
+}
+
+impl Spill for ColdDirectory {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.path.capacity()
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_path(out, &self.path)?;
+        out.write_all(&self.files.to_le_bytes())?;
+        out.write_all(&self.size.to_le_bytes())
+    }
+
+    fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        Ok(ColdDirectory { path: read_path(input)?, files: read_u64(input)?, size: read_u64(input)? })
+    }
+}
+
+fn write_path(out: &mut impl Write, path: &std::path::Path) -> io::Result<()> {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+fn read_path(input: &mut impl Read) -> io::Result<PathBuf> {
+    let mut bytes = vec![0; read_u64(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    // SAFETY: the bytes come from `as_encoded_bytes` in `write_path`, called by this same
+    // process, which is all `from_encoded_bytes_unchecked` asks for
+    Ok(PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(bytes) }))
+}
+
+/// A tag (0: none, 1: after the epoch, 2: before it) and the distance to the epoch.
+fn write_time(out: &mut impl Write, time: Option<SystemTime>) -> io::Result<()> {
+    let (tag, distance) = match time.map(|t| t.duration_since(UNIX_EPOCH)) {
+        None => (0, Duration::ZERO),
+        Some(Ok(after)) => (1, after),
+        Some(Err(before)) => (2, before.duration()),
+    };
+    out.write_all(&[tag])?;
+    out.write_all(&distance.as_secs().to_le_bytes())?;
+    out.write_all(&distance.subsec_nanos().to_le_bytes())
+}
+
+fn read_time(input: &mut impl Read) -> io::Result<Option<SystemTime>> {
+    let [tag] = read_array::<1>(input)?;
+    let distance = Duration::new(read_u64(input)?, u32::from_le_bytes(read_array(input)?));
+    Ok(match tag {
+        0 => None,
+        1 => UNIX_EPOCH.checked_add(distance),
+        _ => UNIX_EPOCH.checked_sub(distance),
+    })
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(input)?))
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,26 @@
+//! Subvolume and dataset boundaries for `ScannerConfig::subvolumes` and
+//! `ScannerConfig::skip_snapshots`. A btrfs subvolume or ZFS dataset shows up as a
+//! directory on another device than its parent, and its snapshots as more such
+//! directories holding the same blocks again.
+
+use std::path::Path;
+
+use super::ScanFs;
+
+/// Whether `path` may be a snapshot directory, going by its name alone: ZFS's `.zfs`
+/// control directory, or snapper's `.snapshots`.
+pub(super) fn snapshot_candidate(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == ".zfs" || name == ".snapshots")
+}
+
+/// Whether the directory at `path` holds snapshots of the tree around it. `.zfs` is
+/// reserved on ZFS; `.snapshots` only counts as a subvolume of its own, so an ordinary
+/// directory of that name is still scanned.
+pub(super) fn is_snapshot_dir(fs: &dyn ScanFs, path: &Path) -> bool {
+    if path.file_name().is_some_and(|name| name == ".zfs") {
+        return true;
+    }
+    let device = |path: &Path| fs.metadata(path).ok().and_then(|metadata| metadata.device);
+    snapshot_candidate(path)
+        && path.parent().and_then(device).zip(device(path)).is_some_and(|(parent, own)| parent != own)
+}
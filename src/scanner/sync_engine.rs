@@ -0,0 +1,407 @@
+//! Synchronous scan engine on top of the blocking `ScanFs` calls, `Engine::Sync`.
+//!
+//! With the `rayon` feature directories are read in parallel on a rayon pool of
+//! `max_concurrent_tasks` threads; for local disks this is often faster than the tokio
+//! engine, as no call has to hop over to a blocking thread. Without it, e.g. in a
+//! `wasm32-wasip1` build running inside a plugin host that exposes a preopened directory,
+//! it walks one directory at a time. Either way entries are processed in sorted order and
+//! children merged in that order, so results are deterministic.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use super::{
+    caches, check_root, subvolumes, trash, is_hidden, is_lock_error, label_file, record_directory_label, record_chunks, record_extents, record_file, record_label, record_sample, sample_len, EntryMetadata, EntryType, ExplicitAcl, ImmutableFile, LockedFile, RepoContext,
+    ScanError, ScanResult, ScannerConfig, SkippedMount, WalkContext, WalkTotals, LABEL_READ_LEN,
+};
+use crate::timings::Phase;
+
+/// Stack of each rayon worker. Every level of the tree nests a few frames, so this is
+/// what keeps very deep trees from overflowing it; only touched pages are committed.
+#[cfg(feature = "rayon")]
+const WORKER_STACK: usize = 64 << 20;
+
+/// Whether the file passes the `--filter-tag` tests; tags that can't be read count as a
+/// failure.
+fn has_tags(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path) -> bool {
+    let Some(filter) = &ctx.config.tags else {
+        return true;
+    };
+    let tags = match filter.database() {
+        Some(database) => Ok(database.tags(path)),
+        None => ctx.timed_sync(Phase::Metadata, || ctx.fs.file_tags(path)),
+    };
+    match tags {
+        Ok(tags) => filter.allows(&tags),
+        Err(e) => {
+            ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e });
+            false
+        }
+    }
+}
+
+fn probe_lock(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.detect_locked_files {
+        return;
+    }
+    if let Err(e) = ctx.timed_sync(Phase::LockProbe, || ctx.fs.probe_open(path))
+        && is_lock_error(&e)
+    {
+        totals.locked_files.push(LockedFile { path: path.to_path_buf(), size, reason: e.to_string() });
+    }
+}
+
+/// Reads the owner of a file whose metadata has none when `--by-owner` is on.
+fn look_up_owner(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, metadata: &mut EntryMetadata) {
+    if !ctx.config.by_owner || metadata.owner.is_some() {
+        return;
+    }
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.owner(path)) {
+        Ok(owner) => metadata.owner = owner,
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn check_acl(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, is_dir: bool) {
+    if !ctx.config.acls {
+        return;
+    }
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.explicit_acl(path)) {
+        Ok(Some(description)) => totals.acls.push(ExplicitAcl { path: path.to_path_buf(), is_dir, description }),
+        Ok(None) => {}
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn read_label(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if ctx.config.selinux.is_none() {
+        return;
+    }
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.security_context(path)) {
+        Ok(context) => record_label(totals, ctx, path, size, context),
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn check_attributes(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.detect_immutable {
+        return;
+    }
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.file_attributes(path)) {
+        Ok(attributes) if attributes.is_empty() => {}
+        Ok(attributes) => totals.immutable_files.push(ImmutableFile { path: path.to_path_buf(), size, attributes }),
+        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn sample_text(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    let len = sample_len(ctx, path, size);
+    if len == 0 {
+        return;
+    }
+    let mut head = vec![0; len];
+    match ctx.timed_sync(Phase::Sampling, || ctx.fs.read_head(path, &mut head)) {
+        Ok(read) => record_sample(totals, ctx, path, size, &head[..read]),
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn chunk_file(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    let Some(chunker) = ctx.chunker.filter(|_| size > 0) else {
+        return;
+    };
+    match ctx.timed_sync(Phase::Hashing, || chunker.chunks(ctx.fs.open_read(path)?, size)) {
+        Ok(chunks) => record_chunks(ctx, &chunks),
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+fn count_extents(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, size: u64) {
+    if !ctx.config.fragmentation.is_some_and(|min_size| size > 0 && size >= min_size) {
+        return;
+    }
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.extent_count(path)) {
+        Ok(Some(extents)) => record_extents(totals, path, size, extents),
+        Ok(None) => {}
+        Err(e) => ctx.entry_error(totals, ScanError::IoError { path: path.to_path_buf(), source: e }),
+    }
+}
+
+/// Whether to leave out the followed symlink at `path`, as it leads to data counted anyway;
+/// one that can't be resolved is followed.
+fn skip_shared_link(totals: &mut WalkTotals, ctx: &WalkContext, path: &Path, target_metadata: &EntryMetadata) -> bool {
+    match ctx.timed_sync(Phase::Metadata, || ctx.fs.canonicalize(path)) {
+        Ok(target) => ctx.shared_link(totals, path, target, target_metadata),
+        Err(_) => false,
+    }
+}
+
+fn directory_found(totals: &mut WalkTotals, ctx: &WalkContext) {
+    totals.dirs += 1;
+    ctx.items_found(totals, 1, 0);
+}
+
+/// Processes the entries of one directory into `totals`, returning the sub-directories to visit.
+fn scan_directory(
+    ctx: &WalkContext,
+    current_path: &Path,
+    mut repo: Option<RepoContext>,
+    totals: &mut WalkTotals,
+) -> Vec<(PathBuf, Option<RepoContext>)> {
+    let config = &ctx.config;
+
+    #[cfg(feature = "native")]
+    if let Some(control) = &ctx.control {
+        control.block_while_paused();
+    }
+
+    if config.by_repo
+        && !repo.as_ref().is_some_and(|r| r.in_git_dir)
+        && ctx.timed_sync(Phase::Metadata, || ctx.fs.symlink_metadata(&current_path.join(".git"))).is_ok()
+    {
+        repo = Some(totals.start_repository(current_path));
+    }
+    if config.subvolumes
+        && let Ok(metadata) = ctx.timed_sync(Phase::Metadata, || ctx.fs.metadata(current_path))
+        && let Some(device) = metadata.device
+    {
+        totals.subvolume_directory(device, current_path);
+    }
+
+    let mut listing = match ctx.timed_sync(Phase::Enumeration, || ctx.fs.read_dir(current_path)) {
+        Ok(listing) => listing,
+        Err(e) => {
+            ctx.directory_error(totals, current_path, e);
+            ctx.directory_read(0);
+            return Vec::new();
+        }
+    };
+
+    let mut sub_dirs = Vec::new();
+    // Paths listed so far, so that another listing only adds what's new
+    let mut listed = HashSet::new();
+    let mut rescans = 0;
+    loop {
+        let mut entries = Vec::new();
+        for entry_result in listing {
+            match entry_result {
+                Ok(entry) => entries.push(entry),
+                Err(e) => ctx.entry_error(totals, ScanError::IoError { path: current_path.to_path_buf(), source: e }),
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        if rescans == 0 {
+            totals.children = entries.len() as u64;
+            if let Some(label_file) = label_file(ctx, &entries) {
+                let mut head = vec![0; LABEL_READ_LEN];
+                // A label is a nicety; an unreadable README is not worth an error
+                if let Ok(read) = ctx.timed_sync(Phase::Sampling, || ctx.fs.read_head(label_file, &mut head)) {
+                    record_directory_label(totals, current_path, &head[..read]);
+                }
+            }
+        } else {
+            entries.retain(|entry| !listed.contains(&entry.path));
+            totals.recovered += entries.len() as u64;
+        }
+        if config.rescan_changed > 0 {
+            listed.extend(entries.iter().map(|entry| entry.path.clone()));
+        }
+        for entry in entries {
+            if ctx.stopped() {
+                break;
+            }
+            let path = entry.path;
+            if config.verbose {
+                println!("[VERBOSE] Processing entry: {:?}", &path);
+            }
+            if !config.include_hidden && is_hidden(&path) {
+                continue;
+            }
+
+            if entry.file_type == EntryType::Symlink {
+                if config.follow_symlinks {
+                    match ctx.timed_sync(Phase::Metadata, || ctx.fs.metadata(&path)) {
+                        Ok(target_metadata) if !ctx.visit(&path, &target_metadata) => {}
+                        Ok(target_metadata) if target_metadata.is_file() && !(ctx.config.wanted_file(&path) && ctx.wanted_mode(&target_metadata)) => {}
+                        Ok(target_metadata) if target_metadata.is_file() && !has_tags(totals, ctx, &path) => {}
+                        Ok(target_metadata) if target_metadata.is_file() && skip_shared_link(totals, ctx, &path, &target_metadata) => {}
+                        Ok(mut target_metadata) if target_metadata.is_file() => {
+                            look_up_owner(totals, ctx, &path, &mut target_metadata);
+                            record_file(totals, ctx, &path, &target_metadata, repo.as_ref());
+                            probe_lock(totals, ctx, &path, target_metadata.len);
+                        }
+                        Ok(target_metadata) if target_metadata.is_dir() && !ctx.pruned(&path) => {
+                            if skip_shared_link(totals, ctx, &path, &target_metadata) {
+                                continue;
+                            }
+                            directory_found(totals, ctx);
+                            sub_dirs.push(path);
+                        }
+                        Ok(_) => {}
+                        Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path, source: e }),
+                    }
+                }
+            } else if entry.file_type == EntryType::File {
+                if !ctx.config.wanted_file(&path) {
+                    continue;
+                }
+                match ctx.timed_sync(Phase::Metadata, || ctx.fs.symlink_metadata(&path)) {
+                    Ok(metadata) if !ctx.visit(&path, &metadata) || !ctx.wanted_mode(&metadata) => {}
+                    Ok(_) if !has_tags(totals, ctx, &path) => {}
+                    Ok(mut metadata) => {
+                        look_up_owner(totals, ctx, &path, &mut metadata);
+                        record_file(totals, ctx, &path, &metadata, repo.as_ref());
+                        probe_lock(totals, ctx, &path, metadata.len);
+                        check_acl(totals, ctx, &path, false);
+                        read_label(totals, ctx, &path, metadata.len);
+                        check_attributes(totals, ctx, &path, metadata.len);
+                        sample_text(totals, ctx, &path, metadata.len);
+                        chunk_file(totals, ctx, &path, metadata.len);
+                        count_extents(totals, ctx, &path, metadata.len);
+                    }
+                    Err(e) => ctx.entry_error(totals, ScanError::MetadataError { path, source: e }),
+                }
+            } else if entry.file_type == EntryType::Dir && !ctx.pruned(&path) {
+                if let Some(fs_type) = ctx.skipped_mount(&path) {
+                    totals.skipped_mounts.push(SkippedMount { fs_type: fs_type.to_string(), path });
+                    continue;
+                }
+                if config.skip_snapshots
+                    && subvolumes::snapshot_candidate(&path)
+                    && ctx.timed_sync(Phase::Metadata, || subvolumes::is_snapshot_dir(ctx.fs.as_ref(), &path))
+                {
+                    totals.skipped_snapshots.push(path);
+                    continue;
+                }
+                if let Some(kind) = ctx.skipped_cache(&path) {
+                    totals.skipped_caches.push(caches::measure(ctx.fs.as_ref(), path, kind));
+                    continue;
+                }
+                if config.follow_symlinks && ctx.shared_directory(totals, &path) {
+                    continue;
+                }
+                // Directory metadata costs an extra call, so only fetch it for a visitor
+                if ctx.visitor.is_some() {
+                    match ctx.timed_sync(Phase::Metadata, || ctx.fs.symlink_metadata(&path)) {
+                        Ok(metadata) if !ctx.visit(&path, &metadata) => continue,
+                        Ok(_) => {}
+                        Err(e) => {
+                            ctx.entry_error(totals, ScanError::MetadataError { path, source: e });
+                            continue;
+                        }
+                    }
+                }
+                directory_found(totals, ctx);
+                check_acl(totals, ctx, &path, true);
+                sub_dirs.push(path);
+            }
+        }
+        if totals.vanished_entries.is_empty() || rescans == config.rescan_changed || ctx.stopped() {
+            break;
+        }
+        // What vanished may be back under the same name, e.g. replaced by a rename
+        for path in totals.vanished_entries.drain(..) {
+            listed.remove(&path);
+        }
+        rescans += 1;
+        totals.rescans += 1;
+        listing = match ctx.timed_sync(Phase::Enumeration, || ctx.fs.read_dir(current_path)) {
+            Ok(listing) => listing,
+            Err(_) => break,
+        };
+    }
+
+    // Must run before children are merged in
+    totals.finish_directory(current_path, ctx);
+    if ctx.stopped() {
+        sub_dirs.clear();
+    }
+    ctx.directory_read(sub_dirs.len());
+
+    sub_dirs.into_iter()
+        .map(|sub_path| {
+            let sub_repo = repo.as_ref().map(|r| r.for_child(&sub_path));
+            (sub_path, sub_repo)
+        })
+        .collect()
+}
+
+/// Walks the tree below `ctx.config.target_path`, on a rayon pool when available and the
+/// scan isn't `ScannerConfig::serial`.
+pub(super) fn walk(ctx: &WalkContext) -> WalkTotals {
+    let root_path = ctx.config.target_path.clone();
+    #[cfg(feature = "rayon")]
+    if !ctx.config.serial {
+        match rayon::ThreadPoolBuilder::new()
+            .num_threads(ctx.tasks)
+            .stack_size(WORKER_STACK)
+            .thread_name(|i| format!("scan-worker-{}", i))
+            .build()
+        {
+            Ok(pool) => return pool.install(|| walk_parallel(ctx, root_path, None)),
+            Err(e) => eprintln!("{}", crate::t!("Warning: cannot start scan threads ({}); scanning on one thread", e)),
+        }
+    }
+    walk_sequential(ctx, root_path, || {})
+}
+
+/// Reads `dir`, then its sub-directories in parallel, merging them back in sorted order.
+#[cfg(feature = "rayon")]
+fn walk_parallel(ctx: &WalkContext, dir: PathBuf, repo: Option<RepoContext>) -> WalkTotals {
+    let mut totals = WalkTotals::default();
+    let sub_dirs = scan_directory(ctx, &dir, repo, &mut totals);
+    let children: Vec<WalkTotals> = sub_dirs.into_par_iter()
+        .map(|(sub_path, sub_repo)| walk_parallel(ctx, sub_path, sub_repo))
+        .collect();
+    for child in children {
+        totals.merge(child, ctx);
+    }
+    totals.finish_subtree(&dir);
+    totals
+}
+
+/// Reads one directory at a time on the calling thread, depth first in sorted order,
+/// calling `after_directory` after each.
+pub(super) fn walk_sequential(ctx: &WalkContext, root_path: PathBuf, mut after_directory: impl FnMut()) -> WalkTotals {
+    walk_stack(ctx, root_path, None, &mut after_directory)
+}
+
+/// `walk_sequential` from `start`. A trash directory below it gets a walk of its own, so
+/// its totals cover just its subtree for `WalkTotals::finish_subtree`.
+fn walk_stack(ctx: &WalkContext, start: PathBuf, repo: Option<RepoContext>, after_directory: &mut dyn FnMut()) -> WalkTotals {
+    let mut totals = WalkTotals::default();
+    // Explicit stack instead of recursion so very deep trees can't overflow it
+    let mut pending = vec![(start.clone(), repo)];
+    while let Some((dir, repo)) = pending.pop() {
+        if dir != start && trash::trash_kind(&dir).is_some() {
+            let trash_totals = walk_stack(ctx, dir, repo, after_directory);
+            totals.merge(trash_totals, ctx);
+            continue;
+        }
+        let mut dir_totals = WalkTotals::default();
+        let sub_dirs = scan_directory(ctx, &dir, repo, &mut dir_totals);
+        totals.merge(dir_totals, ctx);
+        after_directory();
+        // Reversed so the stack pops them in sorted order
+        pending.extend(sub_dirs.into_iter().rev());
+    }
+    totals.finish_subtree(&start);
+    totals
+}
+
+/// Synchronous counterpart of `run_scan`: same configuration and results, no runtime,
+/// no progress reporting. Always uses `Engine::Sync`, whatever the configuration says.
+pub fn run_scan_sync(config: &ScannerConfig) -> Result<ScanResult, anyhow::Error> {
+    let started = SystemTime::now();
+    let start_time = Instant::now();
+
+    let ctx = WalkContext::new(config, None);
+    check_root(ctx.fs.as_ref(), &config.target_path)?;
+    let totals = walk(&ctx);
+
+    Ok(totals.into_result(&ctx, started, start_time.elapsed(), None))
+}
@@ -0,0 +1,122 @@
+//! Trash and recycle-bin directories, recognized by name and place as the walk reaches
+//! them: in the user's home, or at the top of a volume. Their contents count like any other
+//! files; they are only reported separately, as a full disk is often just a trash nobody
+//! emptied.
+
+use std::path::{Path, PathBuf};
+
+/// How a trash directory keeps the trashed items.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// Directly inside it: the macOS `~/.Trash`
+    Flat,
+    /// In a directory per user: macOS `.Trashes` and `$RECYCLE.BIN`
+    PerUser,
+    /// In `files` with their `.trashinfo` in `info`: the freedesktop.org home trash and
+    /// per-volume `.Trash-$uid`
+    FreeDesktop,
+    /// In a freedesktop.org trash per user: the shared `.Trash` at the top of a volume
+    SharedFreeDesktop,
+}
+
+/// Which trash the directory at `path` is, with the label it is reported under. Only the
+/// name is looked at for most directories; a matching one must also be in its place.
+fn layout(path: &Path) -> Option<(&'static str, Layout)> {
+    let name = path.file_name()?.to_str()?;
+    let at_volume_root = || path.parent().is_some_and(is_volume_root);
+    if (name.eq_ignore_ascii_case("$RECYCLE.BIN") || name.eq_ignore_ascii_case("RECYCLER")) && at_volume_root() {
+        Some(("recycle bin", Layout::PerUser))
+    } else if name == ".Trashes" && at_volume_root() {
+        Some(("macOS volume", Layout::PerUser))
+    } else if name == ".Trash" && cfg!(target_os = "macos") && is(path, std::env::home_dir().map(|home| home.join(".Trash"))) {
+        Some(("macOS", Layout::Flat))
+    } else if name == ".Trash" && at_volume_root() {
+        Some(("freedesktop shared", Layout::SharedFreeDesktop))
+    } else if volume_trash_owner(name).is_some() && at_volume_root() {
+        Some(("freedesktop volume", Layout::FreeDesktop))
+    } else if name == "Trash" && is(path, data_home().map(|data| data.join("Trash"))) {
+        Some(("freedesktop", Layout::FreeDesktop))
+    } else {
+        None
+    }
+}
+
+/// Whether `path` (as the walk has it, symlinks resolved) is the directory `expected` names.
+fn is(path: &Path, expected: Option<PathBuf>) -> bool {
+    expected.and_then(|expected| std::fs::canonicalize(expected).ok()).is_some_and(|expected| expected == path)
+}
+
+/// Where the freedesktop.org home trash lives: `$XDG_DATA_HOME`, by default `~/.local/share`.
+fn data_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| Some(std::env::home_dir()?.join(".local/share")))
+}
+
+/// The uid in the name of a per-volume freedesktop.org trash, `.Trash-$uid`.
+fn volume_trash_owner(name: &str) -> Option<&str> {
+    name.strip_prefix(".Trash-").filter(|uid| !uid.is_empty() && uid.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Whether `directory` is the top of a volume: the root of the filesystem or of a drive,
+/// or a mount point.
+fn is_volume_root(directory: &Path) -> bool {
+    let Some(parent) = directory.parent() else {
+        return true;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(directory), std::fs::metadata(parent)) {
+            (Ok(directory), Ok(parent)) => directory.dev() != parent.dev(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = parent;
+        false
+    }
+}
+
+/// The name of the invoking user's own bin in a per-user trash: their uid on Unix, their
+/// SID on Windows.
+fn own_bin_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        Some(uzers::get_current_uid().to_string())
+    }
+    #[cfg(windows)]
+    {
+        crate::owners::current_user_sid()
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        None
+    }
+}
+
+/// Label of the trash `path` is, if it is one.
+pub(super) fn trash_kind(path: &Path) -> Option<&'static str> {
+    layout(path).map(|(kind, _)| kind)
+}
+
+/// The directories whose entries are the invoking user's trashed items in the trash at
+/// `path`; other users' bins are never among them.
+pub(super) fn bins(path: &Path) -> Vec<PathBuf> {
+    let own = own_bin_name();
+    let freedesktop = |trash: &Path| vec![trash.join("files"), trash.join("info")];
+    match layout(path) {
+        Some((_, Layout::Flat)) => vec![path.to_path_buf()],
+        Some((_, Layout::PerUser)) => own.map(|user| path.join(user)).filter(|bin| bin.is_dir()).into_iter().collect(),
+        Some((_, Layout::FreeDesktop)) => {
+            match path.file_name().and_then(|name| name.to_str()).and_then(volume_trash_owner) {
+                Some(uid) if own.as_deref() != Some(uid) => Vec::new(),
+                _ => freedesktop(path),
+            }
+        }
+        Some((_, Layout::SharedFreeDesktop)) => own.map(|user| freedesktop(&path.join(user))).unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use super::EntryMetadata;
+
+/// What the walker does with an entry after a `ScanVisitor` has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    /// Count the entry and, for a directory, descend into it
+    Continue,
+    /// Leave the entry out of the results; a skipped directory is not descended into
+    Skip,
+    /// Skip the entry and end the scan; the result covers what was counted so far
+    Stop,
+}
+
+/// Custom per-entry processing on top of the concurrent walker, see `scan_with_visitor`.
+///
+/// Methods are called from many tasks at once, in no particular order. The scan root
+/// itself is not visited.
+pub trait ScanVisitor: Send + Sync + 'static {
+    fn visit_file(&self, _path: &Path, _metadata: &EntryMetadata) -> VisitAction {
+        VisitAction::Continue
+    }
+
+    fn visit_dir(&self, _path: &Path, _metadata: &EntryMetadata) -> VisitAction {
+        VisitAction::Continue
+    }
+}
@@ -0,0 +1,73 @@
+//! Extended attributes, where ACLs, SELinux labels and `--tag` tags live on Linux.
+
+use std::io;
+use std::path::Path;
+
+/// The SELinux context of `path` itself, e.g. `system_u:object_r:httpd_sys_content_t:s0`,
+/// or `None` if it has no label (or SELinux labels aren't supported here).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn security_context(path: &Path) -> io::Result<Option<String>> {
+    Ok(read_xattr(path, c"security.selinux")?.map(|value| {
+        let value = value.strip_suffix(b"\0").unwrap_or(&value);
+        String::from_utf8_lossy(value).into_owned()
+    }))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn security_context(_path: &Path) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+/// The extended attribute `name` of `path` itself (not a symlink's target), or `None`
+/// where it isn't set or the filesystem has no extended attributes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn read_xattr(path: &Path, name: &std::ffi::CStr) -> io::Result<Option<Vec<u8>>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    loop {
+        // SAFETY: both strings are NUL-terminated; a null buffer of length 0 asks for the size
+        let size = unsafe { libc::lgetxattr(c_path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return missing_xattr(io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; size as usize];
+        // SAFETY: `value` has room for `value.len()` bytes
+        let read = unsafe { libc::lgetxattr(c_path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len()) };
+        if read >= 0 {
+            value.truncate(read as usize);
+            return Ok(Some(value));
+        }
+        let e = io::Error::last_os_error();
+        // The ACL grew between the two calls
+        if e.raw_os_error() != Some(libc::ERANGE) {
+            return missing_xattr(e);
+        }
+    }
+}
+
+/// Sets the extended attribute `name` of `path` itself (not a symlink's target) to `value`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn write_xattr(path: &Path, name: &std::ffi::CStr, value: &[u8]) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: both strings are NUL-terminated and `value` holds `value.len()` bytes
+    let status = unsafe { libc::lsetxattr(c_path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+    if status != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn missing_xattr(e: io::Error) -> io::Result<Option<Vec<u8>>> {
+    match e.raw_os_error() {
+        Some(libc::ENODATA | libc::EOPNOTSUPP) => Ok(None),
+        _ => Err(e),
+    }
+}
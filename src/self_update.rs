@@ -0,0 +1,177 @@
+//! `self-update`: replaces the running binary with the build for this platform from the
+//! latest GitHub release, for machines without a package manager that ships the tool.
+//!
+//! A release carries, per platform, the binary `disk_scanner-<os>-<arch>[.exe]`, its
+//! SHA-256 checksum as `<binary>.sha256` (`sha256sum` output) and, for builds made with a
+//! release key, an Ed25519 signature of the binary as `<binary>.sig`.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+
+use crate::cli::SelfUpdateArgs;
+
+/// Ed25519 public key the release binaries are signed with, in hex, set when building
+/// releases. Builds without it only install with `--allow-unsigned`.
+const RELEASE_KEY: Option<&str> = option_env!("DISK_SCANNER_RELEASE_KEY");
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Largest download accepted, well above any release binary.
+const MAX_DOWNLOAD: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets.iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+/// Name of the release binary for the platform this was built for.
+fn asset_name() -> String {
+    format!("disk_scanner-{}-{}{}", std::env::consts::OS, std::env::consts::ARCH, std::env::consts::EXE_SUFFIX)
+}
+
+/// `1.2.3` (or `v1.2.3`) as numbers to compare; anything after a `-` or `+` is ignored.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    core.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>> {
+    let response = agent.get(url).call().with_context(|| format!("Failed to download {}", url))?;
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_DOWNLOAD + 1).read_to_end(&mut body)
+        .with_context(|| format!("Failed to download {}", url))?;
+    if body.len() as u64 > MAX_DOWNLOAD {
+        bail!("{} is larger than {} bytes", url, MAX_DOWNLOAD);
+    }
+    Ok(body)
+}
+
+/// Checks `binary` against the `.sha256` asset, and the `.sig` asset when built with a
+/// release key.
+fn verify(agent: &ureq::Agent, release: &Release, name: &str, binary: &[u8]) -> Result<()> {
+    let checksum = download(agent, &release.asset(&format!("{}.sha256", name))?.browser_download_url)?;
+    let expected = String::from_utf8_lossy(&checksum)
+        .split_whitespace()
+        .next()
+        .and_then(decode_hex)
+        .ok_or_else(|| anyhow!("{}.sha256 holds no SHA-256 checksum", name))?;
+    if digest(&SHA256, binary).as_ref() != expected.as_slice() {
+        bail!("Checksum mismatch for {} of release {}", name, release.tag_name);
+    }
+    match RELEASE_KEY {
+        Some(key) => {
+            let key = decode_hex(key).ok_or_else(|| anyhow!("The release key built in isn't hex"))?;
+            let signature = download(agent, &release.asset(&format!("{}.sig", name))?.browser_download_url)?;
+            UnparsedPublicKey::new(&ED25519, key)
+                .verify(binary, &signature)
+                .map_err(|_| anyhow!("Bad signature on {} of release {}", name, release.tag_name))?;
+        }
+        None => eprintln!("Warning: --allow-unsigned: only the checksum of {} was verified, not who built it", name),
+    }
+    Ok(())
+}
+
+/// Puts `binary` in place of the executable at `current`, keeping its permissions.
+fn replace(current: &Path, binary: &[u8]) -> Result<()> {
+    let dir = current.parent().ok_or_else(|| anyhow!("{:?} has no parent directory", current))?;
+    let mut new = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create the new binary in {:?}", dir))?;
+    new.write_all(binary)?;
+    new.as_file().sync_all()?;
+    fs::set_permissions(new.path(), fs::metadata(current)?.permissions())?;
+    // A running executable can't be replaced on Windows, only renamed out of the way
+    #[cfg(windows)]
+    let old = {
+        let old = current.with_extension("exe.old");
+        let _ = fs::remove_file(&old);
+        fs::rename(current, &old).with_context(|| format!("Failed to move {:?} aside", current))?;
+        old
+    };
+    if let Err(e) = new.persist(current) {
+        // Otherwise there would be no executable left at `current`
+        #[cfg(windows)]
+        if let Err(restore) = fs::rename(&old, current) {
+            return Err(e.error).with_context(|| {
+                format!("Failed to replace {:?}, and to move the old binary back from {:?}: {}", current, old, restore)
+            });
+        }
+        return Err(e.error).with_context(|| format!("Failed to replace {:?}", current));
+    }
+    Ok(())
+}
+
+/// Runs the `self-update` subcommand.
+pub fn run(args: &SelfUpdateArgs) -> Result<()> {
+    let current_version = env!("CARGO_PKG_VERSION");
+    let agent = ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("disk_scanner/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let url = format!("https://api.github.com/repos/{}/releases/latest", args.repo);
+    let response = agent.get(&url)
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .with_context(|| format!("Failed to look up the latest release of {}", args.repo))?;
+    let release: Release = serde_json::from_reader(response.into_reader())
+        .with_context(|| format!("Unexpected release data from {}", url))?;
+
+    let latest = parse_version(&release.tag_name)
+        .ok_or_else(|| anyhow!("Latest release tag {:?} isn't a version", release.tag_name))?;
+    let current = parse_version(current_version).unwrap_or_default();
+    if latest <= current && !args.force {
+        println!("disk_scanner {} is up to date (latest release: {})", current_version, release.tag_name);
+        return Ok(());
+    }
+    if args.check {
+        println!("disk_scanner {} can be updated to {}", current_version, release.tag_name);
+        return Ok(());
+    }
+
+    if RELEASE_KEY.is_none() && !args.allow_unsigned {
+        bail!(
+            "This build has no release key to check the signature of {} with; build with DISK_SCANNER_RELEASE_KEY, \
+             or pass --allow-unsigned to install after checking only its checksum",
+            release.tag_name
+        );
+    }
+    let name = asset_name();
+    let binary = download(&agent, &release.asset(&name)?.browser_download_url)?;
+    verify(&agent, &release, &name, &binary)?;
+    let current_exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Failed to find the running executable")?;
+    replace(&current_exe, &binary)?;
+    println!("Updated {:?} from {} to {}", current_exe, current_version, release.tag_name);
+    Ok(())
+}
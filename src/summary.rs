@@ -0,0 +1,1014 @@
+use std::path::Path;
+use std::time::Instant;
+
+use disk_scanner::baseline::DirectoryGrowth;
+use disk_scanner::cost::CostModel;
+use disk_scanner::formatting::Formatter;
+use disk_scanner::history::WhatsNew;
+use disk_scanner::quota::QuotaViolation;
+use disk_scanner::scanner::{
+    ChurnReport, EncodingCounts, LineEndingCounts, OpenFileLimit, ScanResult, FAN_OUT_BOUNDS, FRAGMENTED_EXTENT_SIZE, PATHOLOGICAL_ENTRIES, UNLABELED,
+};
+use disk_scanner::t;
+
+/// How many of the coldest directories the summary lists.
+const COLD_DIRECTORIES_SHOWN: usize = 20;
+
+/// How many directories with non-UTF-8 text `--encodings` lists.
+const ENCODING_DIRECTORIES_SHOWN: usize = 20;
+
+/// How many directories with inconsistent line endings `--line-endings` lists.
+const LINE_ENDING_DIRECTORIES_SHOWN: usize = 20;
+
+/// How many of the files behind the growth `--whats-new` lists.
+pub const WHATS_NEW_SHOWN: usize = 20;
+
+/// Which optional sections to print, mirroring the flags that collected them.
+pub struct SummaryOptions {
+    pub by_repo: bool,
+    pub by_owner: bool,
+    pub by_category: bool,
+    pub inodes: bool,
+    pub quotas: bool,
+    pub locked_files: bool,
+    pub acls: bool,
+    pub selinux: bool,
+    pub immutable: bool,
+    pub encodings: bool,
+    pub line_endings: bool,
+    pub cost_model: Option<CostModel>,
+    pub verbose: bool,
+    pub color: bool,
+    pub formatter: Formatter,
+}
+
+/// ANSI styling that collapses to plain text when color is off.
+#[derive(Clone, Copy)]
+pub struct Palette {
+    pub enabled: bool,
+}
+
+impl Palette {
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn title(self, text: &str) -> String {
+        self.paint("1;36", text)
+    }
+
+    pub fn header(self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    pub fn good(self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    pub fn warning(self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    pub fn error(self, text: &str) -> String {
+        self.paint("31", text)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Column-aligned rows under an optional header line; empty headers print no header.
+pub struct Table {
+    columns: Vec<(&'static str, Align)>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: &[(&'static str, Align)]) -> Self {
+        Self { columns: columns.to_vec(), rows: Vec::new() }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    pub fn print(&self, palette: Palette) {
+        let mut widths: Vec<usize> = self.columns.iter().map(|(header, _)| header.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(visible_len(cell));
+            }
+        }
+
+        if self.columns.iter().any(|(header, _)| !header.is_empty()) {
+            let headers: Vec<String> = self.columns.iter().map(|(header, _)| header.to_string()).collect();
+            println!("{}", palette.header(&self.format_row(&headers, &widths)));
+        }
+        for row in &self.rows {
+            println!("{}", self.format_row(row, &widths));
+        }
+    }
+
+    fn format_row(&self, cells: &[String], widths: &[usize]) -> String {
+        let last = cells.len().saturating_sub(1);
+        let mut line = String::from(" ");
+        for (i, ((cell, width), (_, align))) in cells.iter().zip(widths).zip(&self.columns).enumerate() {
+            let padding = " ".repeat(width - visible_len(cell));
+            line.push(' ');
+            match align {
+                Align::Right => {
+                    line.push_str(&padding);
+                    line.push_str(cell);
+                }
+                Align::Left => {
+                    line.push_str(cell);
+                    // No trailing padding after the last column
+                    if i != last {
+                        line.push_str(&padding);
+                    }
+                }
+            }
+            line.push(' ');
+        }
+        line.trim_end().to_string()
+    }
+}
+
+/// Width of `text` on screen, ignoring ANSI color sequences.
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => len += 1,
+        }
+    }
+    len
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+/// `dir` for a table, followed by its label from `--dir-labels` if it has one.
+fn directory(result: &ScanResult, dir: &Path) -> String {
+    match result.directory_label(dir) {
+        Some(label) => format!("{} ({})", dir.display(), label),
+        None => dir.display().to_string(),
+    }
+}
+
+pub fn section(palette: Palette, title: &str) {
+    println!("\n{}", palette.title(title));
+}
+
+/// Prints the text summary of a finished scan. `output_start` is when output began, for
+/// the "Output writing" line of `--timings`.
+pub fn print(
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    growth: Option<&[DirectoryGrowth]>,
+    whats_new: Option<&WhatsNew>,
+    options: &SummaryOptions,
+    output_start: Instant,
+) {
+    let palette = Palette { enabled: options.color };
+    let formatter = options.formatter;
+
+    if result.cancelled {
+        println!("\n{}", palette.warning(t!("Scan cancelled; the results below cover only part of the tree.")));
+    }
+    if let Some(open_files) = &result.open_files {
+        for warning in open_file_warnings(open_files) {
+            println!("\n{}", palette.warning(&warning));
+        }
+    }
+    if let Some(warning) = churn_warning(&result.churn) {
+        println!("\n{}", palette.warning(&warning));
+    }
+
+    section(palette, t!("Summary"));
+    let mut totals = Table::new(&[("", Align::Left), ("", Align::Right)]);
+    totals.row(vec![t!("Files").into(), formatter.count(result.total_files)]);
+    totals.row(vec![t!("Directories").into(), formatter.count(result.total_directories)]);
+    totals.row(vec![t!("Size").into(), formatter.size(result.total_size)]);
+    totals.row(vec![t!("Duration").into(), format!("{:.2?}", result.scan_duration)]);
+    if let Some(device) = &result.device {
+        let mut names = vec![device.disk.clone()];
+        names.extend(device.model.clone());
+        totals.row(vec![t!("Device").into(), format!("{} ({})", device.source.display(), names.join(", "))]);
+        if let Some(serial) = &device.serial {
+            totals.row(vec![t!("Serial number").into(), serial.clone()]);
+        }
+        let capacity = formatter.size(device.capacity);
+        totals.row(vec![t!("Disk capacity").into(), match device.rotational {
+            Some(true) => t!("{}, spinning", capacity),
+            Some(false) => t!("{}, solid state", capacity),
+            None => capacity,
+        }]);
+    }
+    if let Some(volume) = &result.volume {
+        totals.row(vec![
+            t!("Volume used").into(),
+            t!("{} of {} ({}%)", formatter.size(volume.used()), formatter.size(volume.total), format!("{:.1}", percent(volume.used(), volume.total))),
+        ]);
+        totals.row(vec![t!("Volume free").into(), formatter.size(volume.available)]);
+        totals.row(vec![t!("Share of volume").into(), format!("{:.1}%", percent(result.total_size, volume.total))]);
+    }
+    if !result.errors.is_empty() {
+        totals.row(vec![t!("Errors").into(), palette.warning(&formatter.count(result.errors.len() as u64))]);
+    }
+    if let Some(placeholders) = &result.placeholders {
+        totals.row(vec![
+            t!("Cloud placeholders").into(),
+            t!("{} files, {} in all", formatter.count(placeholders.files), formatter.size(placeholders.logical_size)),
+        ]);
+        totals.row(vec![
+            t!("Size stored locally").into(),
+            formatter.size(result.total_size.saturating_sub(placeholders.logical_size) + placeholders.local_size),
+        ]);
+    }
+    if result.ignored_errors > 0 {
+        totals.row(vec![t!("Ignored errors").into(), formatter.count(result.ignored_errors)]);
+    }
+    if result.churn.vanished > 0 {
+        totals.row(vec![t!("Vanished during scan").into(), palette.warning(&formatter.count(result.churn.vanished))]);
+    }
+    totals.print(palette);
+
+    if let Some(snapshots) = &result.snapshots {
+        section(palette, &t!("Local snapshots on {} ({})", snapshots.mount_point.display(), snapshots.snapshots.len()));
+        let mut table = Table::new(&[(t!("PURGEABLE"), Align::Left), (t!("SNAPSHOT"), Align::Left)]);
+        for snapshot in &snapshots.snapshots {
+            table.row(vec![if snapshot.purgeable { t!("yes") } else { t!("no") }.into(), snapshot.name.clone()]);
+        }
+        table.print(palette);
+        if let Some(unaccounted) = snapshots.unaccounted {
+            println!("{}", t!("  The volume takes up {} more than its files, much of it possibly held by these snapshots.", formatter.size(unaccounted)));
+        }
+        println!(
+            "{}",
+            palette.warning(t!("  Deleting files frees no space these snapshots still hold; tmutil thinlocalsnapshots or deletelocalsnapshots releases it."))
+        );
+    }
+
+    if !result.matching_files.is_empty() {
+        section(palette, &t!("Matching files ({})", result.matching_files.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("PATH"), Align::Left)]);
+        for matched in result.matching_files.iter() {
+            match matched {
+                Ok(matched) => table.row(vec![formatter.size(matched.size), matched.path.display().to_string()]),
+                Err(e) => eprintln!("{}", t!("Warning: failed to read back matching files spilled to disk: {}", e)),
+            }
+        }
+        table.print(palette);
+    }
+
+    if options.by_repo {
+        section(palette, &t!("Repositories ({})", result.repositories.len()));
+        let mut table = Table::new(&[
+            (t!("TOTAL"), Align::Right),
+            (t!("WORKING TREE"), Align::Right),
+            (t!(".GIT"), Align::Right),
+            (t!("REPOSITORY"), Align::Left),
+        ]);
+        for repo in &result.repositories {
+            table.row(vec![
+                formatter.size(repo.total_size()),
+                formatter.size(repo.working_tree_size),
+                formatter.size(repo.git_dir_size),
+                directory(result, &repo.root),
+            ]);
+        }
+        table.print(palette);
+    }
+
+    if options.by_owner {
+        section(palette, &t!("Owners ({})", result.owners.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("FILES"), Align::Right), (t!("OWNER"), Align::Left)]);
+        for owner in &result.owners {
+            table.row(vec![formatter.size(owner.size), formatter.count(owner.files), owner.display_name()]);
+        }
+        table.print(palette);
+    }
+
+    if options.by_category {
+        section(palette, &t!("Categories ({})", result.categories.len()));
+        let mut table = Table::new(&[
+            (t!("SIZE"), Align::Right),
+            (t!("SHARE"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("CATEGORY"), Align::Left),
+        ]);
+        for category in &result.categories {
+            table.row(vec![
+                formatter.size(category.size),
+                format!("{:.1}%", percent(category.size, result.total_size)),
+                formatter.count(category.files),
+                category.name.clone(),
+            ]);
+        }
+        table.print(palette);
+    }
+
+    if let Some(regenerable) = &result.regenerable {
+        section(
+            palette,
+            &t!(
+                "Regenerable data: {} reclaimable if deleted, {} of user data",
+                formatter.size(regenerable.size),
+                formatter.size(result.total_size.saturating_sub(regenerable.size))
+            ),
+        );
+        let mut table = Table::new(&[
+            (t!("SIZE"), Align::Right),
+            (t!("SHARE"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("CLASS"), Align::Left),
+        ]);
+        for class in &regenerable.classes {
+            table.row(vec![
+                formatter.size(class.size),
+                format!("{:.1}%", percent(class.size, result.total_size)),
+                formatter.count(class.files),
+                class.name.clone(),
+            ]);
+        }
+        let user_files = result.total_files.saturating_sub(regenerable.files);
+        let user_size = result.total_size.saturating_sub(regenerable.size);
+        table.row(vec![
+            formatter.size(user_size),
+            format!("{:.1}%", percent(user_size, result.total_size)),
+            formatter.count(user_files),
+            t!("user data").to_string(),
+        ]);
+        table.print(palette);
+    }
+
+    if let Some(dedup) = &result.dedup {
+        section(
+            palette,
+            &t!(
+                "Dedup estimate: {} of file data would store as {} ({}x)",
+                formatter.size(dedup.bytes),
+                formatter.size(dedup.unique_bytes),
+                format!("{:.2}", dedup.ratio())
+            ),
+        );
+        let mut table = Table::new(&[("", Align::Left), ("", Align::Right)]);
+        table.row(vec![t!("Files read").into(), formatter.count(dedup.files)]);
+        table.row(vec![t!("Average chunk size").into(), formatter.size(dedup.chunk_size)]);
+        table.row(vec![t!("Chunks").into(), formatter.count(dedup.chunks)]);
+        table.row(vec![
+            t!("Distinct chunks").into(),
+            t!("{} ({}%)", formatter.count(dedup.unique_chunks), format!("{:.1}", percent(dedup.unique_chunks, dedup.chunks))),
+        ]);
+        table.row(vec![t!("Saved by deduplication").into(), formatter.size(dedup.bytes - dedup.unique_bytes)]);
+        table.print(palette);
+    }
+
+    if !result.target_usage.is_empty() {
+        section(palette, &t!("Size on target: {} of file data in {} files", formatter.size(result.total_size), formatter.count(result.total_files)));
+        let mut table = Table::new(&[
+            (t!("BLOCK SIZE"), Align::Right),
+            (t!("ALLOCATED"), Align::Right),
+            (t!("OVERHEAD"), Align::Right),
+            (t!("FILES UNDER ONE BLOCK"), Align::Right),
+        ]);
+        for usage in &result.target_usage {
+            let overhead = usage.overhead(result.total_size);
+            table.row(vec![
+                formatter.size(usage.block_size),
+                formatter.size(usage.allocated),
+                t!("{} ({}%)", formatter.size(overhead), format!("{:.1}", percent(overhead, result.total_size))),
+                formatter.count(usage.small_files),
+            ]);
+        }
+        table.print(palette);
+    }
+
+    if let Some(fragmentation) = &result.fragmentation {
+        section(
+            palette,
+            &t!(
+                "Fragmentation: {} of {} files of {} or more in extents averaging under {}",
+                fragmentation.fragmented,
+                fragmentation.files,
+                formatter.size(fragmentation.min_size),
+                formatter.size(FRAGMENTED_EXTENT_SIZE)
+            ),
+        );
+        let mut table = Table::new(&[
+            (t!("EXTENTS"), Align::Right),
+            (t!("AVERAGE"), Align::Right),
+            (t!("SIZE"), Align::Right),
+            (t!("FILE"), Align::Left),
+        ]);
+        for file in &fragmentation.most_fragmented {
+            table.row(vec![
+                formatter.count(file.extents),
+                formatter.size(file.mean_extent_size()),
+                formatter.size(file.size),
+                file.path.display().to_string(),
+            ]);
+        }
+        table.print(palette);
+        if fragmentation.fragmented > fragmentation.most_fragmented.len() as u64 {
+            println!("{}", t!("  ... and {} more", fragmentation.fragmented - fragmentation.most_fragmented.len() as u64));
+        }
+    }
+
+    if !result.subvolumes.is_empty() {
+        section(palette, &t!("Subvolumes ({})", result.subvolumes.len()));
+        let mut table = Table::new(&[
+            (t!("SIZE"), Align::Right),
+            (t!("SHARE"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("SUBVOLUME"), Align::Left),
+        ]);
+        for subvolume in &result.subvolumes {
+            table.row(vec![
+                formatter.size(subvolume.size),
+                format!("{:.1}%", percent(subvolume.size, result.total_size)),
+                formatter.count(subvolume.files),
+                directory(result, &subvolume.root),
+            ]);
+        }
+        table.print(palette);
+    }
+
+    if let Some(model) = &options.cost_model {
+        let cost = model.estimate(result);
+        let money = |amount| formatter.money(amount, &model.currency);
+        section(palette, &t!("Estimated cost on {}: {} per month", model.name, money(cost.storage_per_month)));
+        let mut table = Table::new(&[("", Align::Left), ("", Align::Right)]);
+        table.row(vec![t!("Stored data").into(), formatter.size(cost.bytes)]);
+        table.row(vec![t!("Objects").into(), formatter.count(cost.objects)]);
+        table.row(vec![t!("Storage per month").into(), money(cost.storage_per_month)]);
+        table.row(vec![t!("Uploading every object (once)").into(), money(cost.upload)]);
+        table.row(vec![t!("Downloading everything (once)").into(), money(cost.egress)]);
+        table.print(palette);
+    }
+
+    let inodes = result.volume.as_ref().and_then(|v| v.inodes.as_ref());
+    if options.inodes {
+        section(palette, t!("Inodes"));
+        let mut usage = Table::new(&[("", Align::Left), ("", Align::Right)]);
+        let in_tree = result.total_files + result.total_directories;
+        match inodes {
+            Some(inodes) => {
+                let used = t!("{} of {} ({}%)", inodes.used(), inodes.total, format!("{:.1}", percent(inodes.used(), inodes.total)));
+                let cell = if inodes.available * 10 < inodes.total { palette.warning(&used) } else { used };
+                usage.row(vec![t!("Volume inodes used").into(), cell]);
+                usage.row(vec![
+                    t!("Inodes in this tree").into(),
+                    t!("{} ({}% of the volume)", in_tree, format!("{:.1}", percent(in_tree, inodes.total))),
+                ]);
+            }
+            None => {
+                usage.row(vec![t!("Volume inodes").into(), t!("no fixed limit").into()]);
+                usage.row(vec![t!("Inodes in this tree").into(), formatter.count(in_tree)]);
+            }
+        }
+        usage.print(palette);
+    }
+
+    if let Some(fan_out) = &result.fan_out {
+        section(
+            palette,
+            &t!(
+                "Fan-out: {} children per directory on average, at most {}",
+                format!("{:.1}", fan_out.mean_children()),
+                fan_out.max_children
+            ),
+        );
+        let mut table = Table::new(&[
+            (t!("CHILDREN"), Align::Right),
+            (t!("DIRECTORIES"), Align::Right),
+            (t!("SHARE"), Align::Right),
+            ("", Align::Left),
+        ]);
+        let widest = fan_out.buckets.iter().copied().max().unwrap_or(0);
+        let mut lower = 0;
+        for (i, &count) in fan_out.buckets.iter().enumerate() {
+            let range = match FAN_OUT_BOUNDS.get(i) {
+                Some(&upper) if upper - lower == 1 => lower.to_string(),
+                Some(&upper) => format!("{}-{}", lower, upper - 1),
+                None => format!("{}+", lower),
+            };
+            lower = FAN_OUT_BOUNDS.get(i).copied().unwrap_or(lower);
+            let bar = "#".repeat(if widest == 0 { 0 } else { (count * 40).div_ceil(widest) as usize });
+            table.row(vec![
+                range,
+                formatter.count(count),
+                format!("{:.1}%", percent(count, fan_out.directories)),
+                bar,
+            ]);
+        }
+        table.print(palette);
+    }
+
+    if options.inodes || result.fan_out.is_some() {
+        section(palette, &t!("Directories with the most entries ({})", result.busiest_directories.len()));
+        let mut table = Table::new(&[
+            (t!("ENTRIES"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("DIRS"), Align::Right),
+            (t!("PATH"), Align::Left),
+        ]);
+        let mut pathological = 0;
+        for dir in &result.busiest_directories {
+            let entries = formatter.count(dir.children);
+            let entries = if dir.is_pathological(inodes) {
+                pathological += 1;
+                palette.error(&entries)
+            } else {
+                entries
+            };
+            table.row(vec![entries, formatter.count(dir.files), formatter.count(dir.directories), directory(result, &dir.path)]);
+        }
+        table.print(palette);
+        if pathological > 0 {
+            println!(
+                "{}",
+                palette.warning(&t!(
+                    "  {} directories hold {} or more entries, or 1% of the volume's inodes; expect slow listings and backups.",
+                    pathological, PATHOLOGICAL_ENTRIES
+                ))
+            );
+        }
+    }
+
+    if !result.extensions.is_empty() {
+        section(palette, &t!("Largest files per extension ({} heaviest extensions)", result.extensions.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("FILE"), Align::Left)]);
+        for usage in &result.extensions {
+            let name = if usage.extension.is_empty() { t!("(no extension)").to_string() } else { format!(".{}", usage.extension) };
+            table.row(vec![
+                palette.header(&formatter.size(usage.size)),
+                palette.header(&t!("{} in {} files", name, usage.files)),
+            ]);
+            for file in &usage.largest {
+                table.row(vec![formatter.size(file.size), format!("  {}", file.path.display())]);
+            }
+        }
+        table.print(palette);
+    }
+
+    if options.quotas {
+        if quota_violations.is_empty() {
+            println!("\n{}", palette.good(t!("All owners are within quota.")));
+        } else {
+            section(palette, &t!("Over quota ({})", quota_violations.len()));
+            let mut table = Table::new(&[
+                (t!("OWNER"), Align::Left),
+                (t!("USED"), Align::Right),
+                (t!("LIMIT"), Align::Right),
+                (t!("OVER"), Align::Right),
+            ]);
+            for v in quota_violations {
+                table.row(vec![v.owner.clone(), formatter.size(v.used), formatter.size(v.limit), palette.error(&formatter.size(v.used - v.limit))]);
+            }
+            table.print(palette);
+        }
+    }
+
+    if let Some(growth) = growth {
+        if growth.is_empty() {
+            println!("\n{}", palette.good(t!("No directory grew beyond the alert threshold.")));
+        } else {
+            section(palette, &t!("Grown since baseline ({})", growth.len()));
+            let mut table = Table::new(&[
+                (t!("BEFORE"), Align::Right),
+                (t!("AFTER"), Align::Right),
+                (t!("GROWTH"), Align::Right),
+                (t!("DIRECTORY"), Align::Left),
+            ]);
+            for g in growth {
+                table.row(vec![
+                    formatter.size(g.before),
+                    formatter.size(g.after),
+                    palette.error(&format!("+{:.1}%", g.fraction() * 100.0)),
+                    directory(result, &g.path),
+                ]);
+            }
+            table.print(palette);
+        }
+    }
+
+    if let Some(changes) = whats_new {
+        let since = changes.previous_scan
+            .map(|t| format!(" ({})", formatter.timestamp(t)))
+            .unwrap_or_default();
+        section(
+            palette,
+            &t!("What's new since the previous scan{}: {} in {} files", since, formatter.size(changes.total_growth), changes.files.len()),
+        );
+        let mut table = Table::new(&[
+            (t!("GROWTH"), Align::Right),
+            (t!("SHARE"), Align::Right),
+            (t!("BEFORE"), Align::Right),
+            (t!("PATH"), Align::Left),
+        ]);
+        for file in changes.files.iter().take(WHATS_NEW_SHOWN) {
+            table.row(vec![
+                palette.warning(&format!("+{}", formatter.size(file.delta()))),
+                format!("{:.1}%", percent(file.delta(), changes.total_growth)),
+                file.before.map_or_else(|| t!("new").to_string(), |before| formatter.size(before)),
+                file.path.display().to_string(),
+            ]);
+        }
+        table.print(palette);
+        if changes.files.len() > WHATS_NEW_SHOWN {
+            println!("{}", t!("  ... and {} more", changes.files.len() - WHATS_NEW_SHOWN));
+        }
+    }
+
+    if options.locked_files {
+        section(palette, &t!("Locked files ({})", result.locked_files.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("PATH"), Align::Left), (t!("REASON"), Align::Left)]);
+        for locked in &result.locked_files {
+            table.row(vec![formatter.size(locked.size), locked.path.display().to_string(), locked.reason.clone()]);
+        }
+        table.print(palette);
+    }
+
+    if options.encodings {
+        let mut total = EncodingCounts::default();
+        for dir in &result.encodings {
+            total.merge(&dir.counts);
+        }
+        section(palette, &t!("Text encodings ({} files sampled)", total.files()));
+        println!(
+            "{}",
+            t!(
+                "  ASCII {}, UTF-8 {}, UTF-16 {}, UTF-32 {}, legacy code pages {}; {} with a byte order mark",
+                total.ascii, total.utf8, total.utf16, total.utf32, total.legacy, total.with_bom,
+            )
+        );
+        // Directories a UTF-8 migration has to touch, most affected files first
+        let mut affected: Vec<_> = result.encodings.iter()
+            .filter(|d| d.counts.non_utf8() > 0 || d.counts.with_bom > 0)
+            .collect();
+        affected.sort_by(|a, b| {
+            (b.counts.non_utf8(), b.counts.with_bom).cmp(&(a.counts.non_utf8(), a.counts.with_bom))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        if !affected.is_empty() {
+            let mut table = Table::new(&[
+                (t!("FILES"), Align::Right),
+                (t!("UTF-8"), Align::Right),
+                (t!("UTF-16"), Align::Right),
+                (t!("UTF-32"), Align::Right),
+                (t!("LEGACY"), Align::Right),
+                (t!("BOM"), Align::Right),
+                (t!("DIRECTORY"), Align::Left),
+            ]);
+            for dir in affected.iter().take(ENCODING_DIRECTORIES_SHOWN) {
+                let c = &dir.counts;
+                table.row(vec![
+                    c.files().to_string(),
+                    (c.ascii + c.utf8).to_string(),
+                    c.utf16.to_string(),
+                    c.utf32.to_string(),
+                    c.legacy.to_string(),
+                    c.with_bom.to_string(),
+                    directory(result, &dir.path),
+                ]);
+            }
+            table.print(palette);
+            if affected.len() > ENCODING_DIRECTORIES_SHOWN {
+                println!("{}", t!("  ... and {} more directories", affected.len() - ENCODING_DIRECTORIES_SHOWN));
+            }
+        }
+    }
+
+    if options.line_endings {
+        let mut total = LineEndingCounts::default();
+        for dir in &result.line_endings {
+            total.merge(&dir.counts);
+        }
+        section(palette, &t!("Line endings ({} files)", total.files()));
+        println!("{}", t!("  LF {}, CRLF {}, mixed {}, no line break {}", total.lf, total.crlf, total.mixed, total.none));
+        // Directories with mixed files, or with both LF and CRLF files side by side
+        let mut inconsistent: Vec<_> = result.line_endings.iter()
+            .filter(|d| d.counts.mixed > 0 || (d.counts.lf > 0 && d.counts.crlf > 0))
+            .collect();
+        inconsistent.sort_by(|a, b| b.counts.mixed.cmp(&a.counts.mixed).then_with(|| a.path.cmp(&b.path)));
+        if !inconsistent.is_empty() {
+            let mut table = Table::new(&[
+                (t!("FILES"), Align::Right),
+                (t!("LF"), Align::Right),
+                (t!("CRLF"), Align::Right),
+                (t!("MIXED"), Align::Right),
+                (t!("DIRECTORY"), Align::Left),
+            ]);
+            for dir in inconsistent.iter().take(LINE_ENDING_DIRECTORIES_SHOWN) {
+                let c = &dir.counts;
+                let mixed = if c.mixed > 0 { palette.warning(&c.mixed.to_string()) } else { c.mixed.to_string() };
+                table.row(vec![
+                    c.files().to_string(),
+                    c.lf.to_string(),
+                    c.crlf.to_string(),
+                    mixed,
+                    directory(result, &dir.path),
+                ]);
+            }
+            table.print(palette);
+            if inconsistent.len() > LINE_ENDING_DIRECTORIES_SHOWN {
+                println!("{}", t!("  ... and {} more directories", inconsistent.len() - LINE_ENDING_DIRECTORIES_SHOWN));
+            }
+        }
+    }
+
+    if options.immutable {
+        section(palette, &t!("Immutable files ({})", result.immutable_files.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("PATH"), Align::Left), (t!("ATTRIBUTES"), Align::Left)]);
+        for file in &result.immutable_files {
+            let attributes: Vec<&str> = file.attributes.iter().map(|a| a.label()).collect();
+            table.row(vec![formatter.size(file.size), file.path.display().to_string(), attributes.join(", ")]);
+        }
+        table.print(palette);
+    }
+
+    if options.acls {
+        section(palette, &t!("Explicit ACLs ({})", result.acls.len()));
+        let mut table = Table::new(&[(t!("TYPE"), Align::Left), (t!("PATH"), Align::Left), (t!("ACL"), Align::Left)]);
+        for acl in &result.acls {
+            let kind = if acl.is_dir { t!("dir") } else { t!("file") };
+            table.row(vec![kind.to_string(), acl.path.display().to_string(), acl.description.clone()]);
+        }
+        table.print(palette);
+    }
+
+    if options.selinux && let Some(selinux) = &result.selinux {
+        section(palette, &t!("SELinux types ({})", selinux.types.len()));
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("FILES"), Align::Right), (t!("TYPE"), Align::Left)]);
+        for usage in &selinux.types {
+            table.row(vec![formatter.size(usage.size), formatter.count(usage.files), usage.label_type.clone()]);
+        }
+        table.print(palette);
+
+        if selinux.mislabeled_files > 0 {
+            section(palette, &t!("Mislabeled files ({})", selinux.mislabeled_files));
+            let mut table = Table::new(&[(t!("CONTEXT"), Align::Left), (t!("PATH"), Align::Left)]);
+            for file in &selinux.mislabeled {
+                let context = file.context.clone().unwrap_or_else(|| UNLABELED.to_string());
+                table.row(vec![context, file.path.display().to_string()]);
+            }
+            table.print(palette);
+            if selinux.mislabeled_files > selinux.mislabeled.len() as u64 {
+                println!("{}", t!("  ... and {} more", selinux.mislabeled_files - selinux.mislabeled.len() as u64));
+            }
+        }
+    }
+
+    if !result.skipped_caches.is_empty() {
+        let reclaimable: u64 = result.skipped_caches.iter().map(|c| c.size).sum();
+        section(
+            palette,
+            &t!("Skipped caches ({}): {} reclaimable", result.skipped_caches.len(), formatter.size(reclaimable)),
+        );
+        let mut table = Table::new(&[
+            (t!("SIZE"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("CACHE"), Align::Left),
+            (t!("PATH"), Align::Left),
+        ]);
+        for cache in &result.skipped_caches {
+            table.row(vec![formatter.size(cache.size), formatter.count(cache.files), cache.kind.clone(), directory(result, &cache.path)]);
+        }
+        table.print(palette);
+    }
+
+    if !result.skipped_mounts.is_empty() {
+        section(palette, &t!("Skipped mounts ({})", result.skipped_mounts.len()));
+        let mut table = Table::new(&[(t!("TYPE"), Align::Left), (t!("PATH"), Align::Left)]);
+        for mount in &result.skipped_mounts {
+            table.row(vec![mount.fs_type.clone(), directory(result, &mount.path)]);
+        }
+        table.print(palette);
+    }
+
+    if !result.skipped_snapshots.is_empty() {
+        section(palette, &t!("Skipped snapshot directories ({})", result.skipped_snapshots.len()));
+        for path in &result.skipped_snapshots {
+            println!("  {}", directory(result, path));
+        }
+    }
+
+    if !result.trash.is_empty() {
+        let trashed: u64 = result.trash.iter().map(|t| t.size).sum();
+        section(palette, &t!("Trash ({}): {} reclaimable by emptying it", result.trash.len(), formatter.size(trashed)));
+        let mut table = Table::new(&[
+            (t!("SIZE"), Align::Right),
+            (t!("FILES"), Align::Right),
+            (t!("TRASH"), Align::Left),
+            (t!("PATH"), Align::Left),
+        ]);
+        for trash in &result.trash {
+            table.row(vec![formatter.size(trash.size), formatter.count(trash.files), trash.kind.clone(), directory(result, &trash.path)]);
+        }
+        table.print(palette);
+    }
+
+    if let Some(cold) = &result.cold_data {
+        section(
+            palette,
+            &t!(
+                "Cold data (unused for {} days): {} files, {}",
+                cold.age.as_secs() / (24 * 60 * 60),
+                formatter.count(cold.files),
+                formatter.size(cold.size)
+            ),
+        );
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("FILES"), Align::Right), (t!("DIRECTORY"), Align::Left)]);
+        for dir in cold.directories.iter().take(COLD_DIRECTORIES_SHOWN) {
+            match dir {
+                Ok(dir) => table.row(vec![formatter.size(dir.size), formatter.count(dir.files), directory(result, &dir.path)]),
+                Err(e) => eprintln!("{}", t!("Warning: failed to read back cold directories spilled to disk: {}", e)),
+            }
+        }
+        table.print(palette);
+    }
+
+    if let Some(shared) = result.shared_links.as_ref().filter(|s| s.files + s.directories > 0) {
+        let title = if shared.counted_again {
+            t!("Symlinks counted again ({} directories, {} files, {})", shared.directories, shared.files, formatter.size(shared.file_size))
+        } else {
+            t!("Symlinks to data counted elsewhere ({} directories, {} files, {})", shared.directories, shared.files, formatter.size(shared.file_size))
+        };
+        section(palette, &title);
+        let mut table = Table::new(&[(t!("SIZE"), Align::Right), (t!("LINK"), Align::Left), (t!("COUNTED AT"), Align::Left)]);
+        for link in &shared.links {
+            let size = if link.is_dir { t!("(directory)").to_string() } else { formatter.size(link.size) };
+            table.row(vec![size, link.link.display().to_string(), link.counted_at.display().to_string()]);
+        }
+        table.print(palette);
+    }
+
+    if !result.churn.directories.is_empty() {
+        section(palette, &t!("Changed during scan ({})", result.churn.directories.len()));
+        let mut table = Table::new(&[(t!("VANISHED"), Align::Right), (t!("DIRECTORY"), Align::Left)]);
+        for dir in &result.churn.directories {
+            table.row(vec![formatter.count(dir.vanished), directory(result, &dir.path)]);
+        }
+        table.print(palette);
+    }
+
+    if let Some(m) = &result.metrics {
+        section(palette, t!("Runtime metrics"));
+        let mut table = Table::new(&[("", Align::Left), ("", Align::Right)]);
+        table.row(vec![t!("Tasks spawned").into(), m.tasks_spawned.to_string()]);
+        table.row(vec![t!("Peak tasks in flight").into(), m.peak_tasks_in_flight.to_string()]);
+        table.row(vec![t!("Peak semaphore waiters").into(), m.peak_semaphore_waiters.to_string()]);
+        table.row(vec![t!("Peak progress channel depth").into(), m.peak_channel_depth.to_string()]);
+        table.print(palette);
+    }
+
+    if let Some(usage) = &result.resource_usage {
+        section(palette, t!("Resource usage"));
+        let mut table = Table::new(&[("", Align::Left), ("", Align::Right)]);
+        if let Some(rss) = usage.peak_rss {
+            table.row(vec![t!("Peak memory (RSS)").into(), formatter.size(rss)]);
+        }
+        table.row(vec![t!("CPU time (user)").into(), format!("{:.2?}", usage.user_cpu)]);
+        table.row(vec![t!("CPU time (system)").into(), format!("{:.2?}", usage.system_cpu)]);
+        table.row(vec![t!("Directory listings").into(), usage.read_dir_calls.to_string()]);
+        table.row(vec![t!("Metadata calls").into(), usage.metadata_calls.to_string()]);
+        if usage.open_calls > 0 {
+            table.row(vec![t!("Files opened").into(), usage.open_calls.to_string()]);
+        }
+        if let Some(tasks) = usage.tasks_spawned {
+            table.row(vec![t!("Tasks spawned").into(), tasks.to_string()]);
+        }
+        table.print(palette);
+    }
+
+    if !result.errors.is_empty() && options.verbose {
+        section(palette, &t!("Errors encountered ({})", result.errors.len()));
+        for err in &result.errors {
+            println!("  {}", palette.error(&err.to_string()));
+        }
+    }
+
+    if let Some(phases) = &result.timings {
+        let output_time = output_start.elapsed();
+        section(palette, &t!("Timings (cumulative across tasks, wall clock {})", format!("{:.2?}", result.scan_duration)));
+        let mut table = Table::new(&[(t!("PHASE"), Align::Left), (t!("TIME"), Align::Right), (t!("CALLS"), Align::Right)]);
+        for timing in phases {
+            table.row(vec![timing.phase.to_string(), format!("{:.2?}", timing.total), timing.calls.to_string()]);
+        }
+        table.row(vec![t!("Output writing").into(), format!("{:.2?}", output_time), String::new()]);
+        table.print(palette);
+    }
+}
+
+/// Explains how the open file limit held the scan back, one sentence per effect.
+pub fn open_file_warnings(open_files: &OpenFileLimit) -> Vec<String> {
+    let limit = open_files.limit.map(|l| format!(" ({})", l)).unwrap_or_default();
+    let mut warnings = Vec::new();
+    if open_files.tasks < open_files.requested_tasks {
+        warnings.push(t!(
+            "Open file limit{} allows reading only {} directories at once instead of {}; raise it with `ulimit -n` for a faster scan.",
+            limit, open_files.tasks, open_files.requested_tasks
+        ));
+    }
+    if open_files.unreadable_directories > 0 {
+        warnings.push(t!(
+            "{} directories were not read because the process ran out of file descriptors{}; raise the limit with `ulimit -n` and scan again.",
+            open_files.unreadable_directories, limit
+        ));
+    }
+    warnings
+}
+
+/// Explains that entries changed under the scan, and what `--rescan-changed` did about it.
+pub fn churn_warning(churn: &ChurnReport) -> Option<String> {
+    if churn.vanished == 0 {
+        return None;
+    }
+    Some(if churn.rescans > 0 {
+        t!(
+            "{} entries vanished while the scan ran; {} more listings of their directories found {} new entries, but the totals may still mix the tree from before and after the changes.",
+            churn.vanished, churn.rescans, churn.recovered
+        )
+    } else {
+        t!(
+            "{} entries vanished while the scan ran, so the totals mix the tree from before and after the changes; --rescan-changed N lists the changing directories again.",
+            churn.vanished
+        )
+    })
+}
+
+/// The `--single-dir-summary` line: the size for people, then the totals for parsers.
+pub fn print_single_dir(result: &ScanResult, formatter: Formatter) {
+    let mut line = format!(
+        "size={:?} bytes={} files={} directories={} errors={}",
+        formatter.size(result.total_size),
+        result.total_size,
+        result.total_files,
+        result.total_directories,
+        result.errors.len(),
+    );
+    if result.cancelled {
+        line.push_str(" cancelled=true");
+    }
+    println!("{}", line);
+}
+
+/// Prints the whole result as one `key=value` line for `--quiet`, e.g.
+/// `path="/data" files=12 directories=3 bytes=4096 errors=0 over_quota=0 duration_ms=8`.
+/// With a baseline, `grown=N` counts the directories over the growth threshold;
+/// `unread_directories=N` counts those left out because file descriptors ran out, and
+/// `vanished=N` the entries that disappeared during the scan.
+pub fn print_line(
+    root: &Path,
+    result: &ScanResult,
+    quota_violations: &[QuotaViolation],
+    growth: Option<&[DirectoryGrowth]>,
+) {
+    let mut line = format!(
+        "path={:?} files={} directories={} bytes={} errors={} over_quota={} duration_ms={}",
+        root,
+        result.total_files,
+        result.total_directories,
+        result.total_size,
+        result.errors.len(),
+        quota_violations.len(),
+        result.scan_duration.as_millis()
+    );
+    if let Some(growth) = growth {
+        line.push_str(&format!(" grown={}", growth.len()));
+    }
+    if let Some(open_files) = result.open_files.as_ref().filter(|o| o.unreadable_directories > 0) {
+        line.push_str(&format!(" unread_directories={}", open_files.unreadable_directories));
+    }
+    if result.churn.vanished > 0 {
+        line.push_str(&format!(" vanished={}", result.churn.vanished));
+    }
+    if result.cancelled {
+        line.push_str(" cancelled=true");
+    }
+    println!("{}", line);
+}
+
+/// Whether the summary should be colored: not with `--no-color`, a non-empty `NO_COLOR`
+/// (https://no-color.org) or when stdout is not a terminal.
+pub fn use_color(no_color_flag: bool) -> bool {
+    use std::io::IsTerminal;
+
+    !no_color_flag
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && std::io::stdout().is_terminal()
+}
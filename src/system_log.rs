@@ -0,0 +1,211 @@
+use std::io;
+use clap::ValueEnum;
+
+#[cfg(any(unix, windows))]
+const IDENTIFIER: &str = "disk_scanner";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogTarget {
+    /// Local syslog daemon via /dev/log
+    Syslog,
+    /// systemd-journald native protocol
+    Journald,
+    /// Windows Application event log
+    EventLog,
+}
+
+/// Syslog severities used by the scanner.
+#[derive(Debug, Clone, Copy)]
+pub enum Priority {
+    Err = 3,
+    Warning = 4,
+    Info = 6,
+}
+
+enum Sink {
+    #[cfg(unix)]
+    Datagram { target: LogTarget, socket: std::os::unix::net::UnixDatagram },
+    #[cfg(windows)]
+    EventLog(event_log::EventSource),
+}
+
+/// Sends scan summaries and errors to the system log.
+pub struct SystemLogger {
+    sink: Sink,
+}
+
+// Elsewhere (e.g. WASI) `Sink` has no variants and every target is rejected
+#[cfg_attr(not(any(unix, windows)), allow(unreachable_code, unused_variables))]
+impl SystemLogger {
+    pub fn connect(target: LogTarget) -> io::Result<Self> {
+        let sink = match target {
+            #[cfg(unix)]
+            LogTarget::Syslog | LogTarget::Journald => {
+                let socket_path = if target == LogTarget::Syslog { "/dev/log" } else { "/run/systemd/journal/socket" };
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(socket_path)?;
+                Sink::Datagram { target, socket }
+            }
+            #[cfg(windows)]
+            LogTarget::EventLog => Sink::EventLog(event_log::EventSource::register()?),
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{:?} logging is not available on this platform", target),
+                ));
+            }
+        };
+        Ok(Self { sink })
+    }
+
+    pub fn log(&self, priority: Priority, message: &str) -> io::Result<()> {
+        match &self.sink {
+            #[cfg(unix)]
+            Sink::Datagram { target, socket } => {
+                // Neither format below can carry raw newlines
+                let message = message.replace('\n', " ");
+                let datagram = match target {
+                    LogTarget::Journald => format!(
+                        "PRIORITY={}\nSYSLOG_IDENTIFIER={}\nMESSAGE={}\n",
+                        priority as u8, IDENTIFIER, message
+                    ),
+                    // Facility "user" (1), RFC 3164 style as accepted by local daemons
+                    _ => format!("<{}>{}[{}]: {}", 8 + priority as u8, IDENTIFIER, std::process::id(), message),
+                };
+                socket.send(datagram.as_bytes()).map(|_| ())
+            }
+            #[cfg(windows)]
+            Sink::EventLog(source) => source.report(priority, message),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod event_log {
+    use std::io;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW,
+        EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+    use windows_sys::Win32::System::Registry::{
+        RegCloseKey, RegCreateKeyExW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE,
+        KEY_SET_VALUE, REG_DWORD, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+    };
+
+    use super::{Priority, IDENTIFIER};
+
+    const SOURCE_KEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application\disk_scanner";
+    /// Ships with the .NET Framework and maps every event id to the message text ("%1").
+    const MESSAGE_FILE: &str = r"%SystemRoot%\Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+    const EVENT_ID: u32 = 1000;
+    /// Error, warning and information events.
+    const TYPES_SUPPORTED: u32 = 0x7;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Registers the event source so Event Viewer renders our messages. This needs
+    /// administrator rights once; without them events are still logged, just less
+    /// nicely rendered, so failures are ignored.
+    fn register_source() {
+        let key_name = wide(SOURCE_KEY);
+        let mut key: HKEY = ptr::null_mut();
+        // SAFETY: all pointers reference live, NUL-terminated buffers or valid out-params
+        unsafe {
+            let status = RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                key_name.as_ptr(),
+                0,
+                ptr::null(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_SET_VALUE,
+                ptr::null(),
+                &mut key,
+                ptr::null_mut(),
+            );
+            if status != 0 {
+                return;
+            }
+            let value_name = wide("EventMessageFile");
+            let message_file = wide(MESSAGE_FILE);
+            RegSetValueExW(
+                key,
+                value_name.as_ptr(),
+                0,
+                REG_EXPAND_SZ,
+                message_file.as_ptr().cast(),
+                (message_file.len() * 2) as u32,
+            );
+            let value_name = wide("TypesSupported");
+            RegSetValueExW(
+                key,
+                value_name.as_ptr(),
+                0,
+                REG_DWORD,
+                (&TYPES_SUPPORTED as *const u32).cast(),
+                4,
+            );
+            RegCloseKey(key);
+        }
+    }
+
+    pub struct EventSource {
+        handle: HANDLE,
+    }
+
+    impl EventSource {
+        pub fn register() -> io::Result<Self> {
+            register_source();
+            let name = wide(IDENTIFIER);
+            // SAFETY: `name` is NUL-terminated and outlives the call
+            let handle = unsafe { RegisterEventSourceW(ptr::null(), name.as_ptr()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { handle })
+        }
+
+        pub fn report(&self, priority: Priority, message: &str) -> io::Result<()> {
+            let event_type = match priority {
+                Priority::Err => EVENTLOG_ERROR_TYPE,
+                Priority::Warning => EVENTLOG_WARNING_TYPE,
+                Priority::Info => EVENTLOG_INFORMATION_TYPE,
+            };
+            let text = wide(message);
+            let strings = [text.as_ptr()];
+            // SAFETY: `strings` holds one valid NUL-terminated string for the duration of the call
+            let ok = unsafe {
+                ReportEventW(
+                    self.handle,
+                    event_type,
+                    0,
+                    EVENT_ID,
+                    ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    ptr::null(),
+                )
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for EventSource {
+        fn drop(&mut self) {
+            // SAFETY: the handle came from RegisterEventSourceW and is released once
+            unsafe {
+                DeregisterEventSource(self.handle);
+            }
+        }
+    }
+}
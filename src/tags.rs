@@ -0,0 +1,192 @@
+//! Tags on files for curation over several runs: `--tag reviewed` records a tag on the
+//! files a scan matched, and `--filter-tag reviewed` (or `'!reviewed'`) picks them out
+//! again later. Tags live on the files themselves where the platform allows it, in a user
+//! extended attribute on Linux or an alternate data stream on NTFS, and otherwise in a
+//! sidecar database (`--tag-db`).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Extended attribute holding a file's tags, comma-separated.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const TAG_XATTR: &std::ffi::CStr = c"user.disk_scanner.tags";
+
+/// Alternate data stream holding a file's tags, comma-separated.
+#[cfg(windows)]
+const TAG_STREAM: &str = ":disk_scanner.tags";
+
+/// Whether tags can be kept on the files themselves here; elsewhere they need a
+/// `TagDatabase`.
+pub const NATIVE_TAGS: bool = cfg!(any(target_os = "linux", target_os = "android", windows));
+
+#[derive(Debug, Error)]
+#[error("Invalid tag {0:?}: tags can't be empty, contain commas or whitespace, or start with '!'")]
+pub struct InvalidTag(pub String);
+
+/// Checks that `tag` survives being stored comma-separated and used in `--filter-tag`.
+pub fn validate(tag: &str) -> Result<(), InvalidTag> {
+    if tag.is_empty() || tag.starts_with('!') || tag.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(InvalidTag(tag.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+fn split(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect()
+}
+
+/// The tags recorded on the file at `path` itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn read_native(path: &Path) -> io::Result<Vec<String>> {
+    let value = crate::scanner::xattr::read_xattr(path, TAG_XATTR)?;
+    Ok(value.map(|v| split(&String::from_utf8_lossy(&v))).unwrap_or_default())
+}
+
+#[cfg(windows)]
+pub fn read_native(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(stream(path)) {
+        Ok(value) => Ok(split(&value)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub fn read_native(_path: &Path) -> io::Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+/// Replaces the tags recorded on the file at `path` itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn write_native(path: &Path, tags: &BTreeSet<String>) -> io::Result<()> {
+    crate::scanner::xattr::write_xattr(path, TAG_XATTR, join(tags).as_bytes())
+}
+
+#[cfg(windows)]
+pub fn write_native(path: &Path, tags: &BTreeSet<String>) -> io::Result<()> {
+    fs::write(stream(path), join(tags))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub fn write_native(_path: &Path, _tags: &BTreeSet<String>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "tags need a tag database on this platform"))
+}
+
+#[cfg(windows)]
+fn stream(path: &Path) -> PathBuf {
+    let mut stream = path.as_os_str().to_os_string();
+    stream.push(TAG_STREAM);
+    PathBuf::from(stream)
+}
+
+pub fn join(tags: &BTreeSet<String>) -> String {
+    tags.iter().map(String::as_str).collect::<Vec<_>>().join(",")
+}
+
+/// Tags kept in a JSON file mapping absolute paths to their tags, for filesystems without
+/// extended attributes or alternate data streams (FAT, many network shares) and for trees
+/// that shouldn't be written to. A plain JSON file rather than sqlite keeps the tool free
+/// of a C dependency (and building for WASI); it is read whole and rewritten on save.
+#[derive(Debug, Default)]
+pub struct TagDatabase {
+    path: PathBuf,
+    files: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl TagDatabase {
+    /// Loads the database at `path`; one that doesn't exist yet starts out empty.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(TagDatabase { path: path.to_path_buf(), files: read(path)? })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn tags(&self, file: &Path) -> Vec<String> {
+        self.files.get(file).map(|tags| tags.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Adds `tag` to `file`, returning whether it was new.
+    pub fn insert(&mut self, file: &Path, tag: &str) -> bool {
+        self.files.entry(file.to_path_buf()).or_default().insert(tag.to_string())
+    }
+
+    /// Writes the database back, replacing the old file only once the new one is complete.
+    /// Tags other runs saved since `open` are kept: the file is locked (`<path>.lock`) and
+    /// read again, and as tags are only ever added, the two are merged.
+    pub fn save(&self) -> io::Result<()> {
+        let _lock = crate::history::lock(&self.path)?;
+        let mut files = read(&self.path)?;
+        for (file, tags) in &self.files {
+            files.entry(file.clone()).or_default().extend(tags.iter().cloned());
+        }
+        let temp = self.path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        serde_json::to_writer_pretty(&mut writer, &files)?;
+        writer.write_all(b"\n")?;
+        // On disk before the rename, or a crash could leave the database empty
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(&temp, &self.path)
+    }
+}
+
+/// The tag database at `path`, empty if there is none yet.
+fn read(path: &Path) -> io::Result<BTreeMap<PathBuf, BTreeSet<String>>> {
+    match File::open(path) {
+        Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tag tests for `--filter-tag`: files must have every required tag and none of the
+/// excluded ones.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    required: Vec<String>,
+    excluded: Vec<String>,
+    database: Option<Arc<TagDatabase>>,
+}
+
+impl TagFilter {
+    /// Adds a test: `TAG` for files that have the tag, `!TAG` for files that don't.
+    pub fn add(&mut self, test: &str) -> Result<(), InvalidTag> {
+        let (tags, tag) = match test.strip_prefix('!') {
+            Some(tag) => (&mut self.excluded, tag),
+            None => (&mut self.required, test),
+        };
+        validate(tag)?;
+        tags.push(tag.to_string());
+        Ok(())
+    }
+
+    /// Reads the tags from `database` instead of the files themselves.
+    pub fn with_database(mut self, database: TagDatabase) -> Self {
+        self.database = Some(Arc::new(database));
+        self
+    }
+
+    pub fn database(&self) -> Option<&TagDatabase> {
+        self.database.as_deref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.excluded.is_empty()
+    }
+
+    /// The tests as given to `add`, required tags first.
+    pub fn tests(&self) -> impl Iterator<Item = String> + '_ {
+        self.required.iter().cloned().chain(self.excluded.iter().map(|tag| format!("!{}", tag)))
+    }
+
+    /// Whether a file with `tags` passes every test.
+    pub fn allows(&self, tags: &[String]) -> bool {
+        self.required.iter().all(|t| tags.contains(t)) && !self.excluded.iter().any(|t| tags.contains(t))
+    }
+}
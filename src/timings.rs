@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Scan phases tracked by `--timings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// `read_dir`, iterating entries and reading their file types
+    Enumeration,
+    /// `stat`-like calls on files, directories and symlink targets
+    Metadata,
+    /// Reading file contents to split them into content-defined chunks (--dedup-estimate)
+    Hashing,
+    /// Waiting for a concurrency permit
+    SemaphoreWait,
+    /// Opening files to detect locks
+    LockProbe,
+    /// Reading the start of files: encodings, line endings and directory labels
+    Sampling,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Enumeration,
+        Phase::Metadata,
+        Phase::Hashing,
+        Phase::SemaphoreWait,
+        Phase::LockProbe,
+        Phase::Sampling,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::Enumeration => "Directory enumeration",
+            Phase::Metadata => "Metadata calls",
+            Phase::Hashing => "Hashing",
+            Phase::SemaphoreWait => "Semaphore wait",
+            Phase::LockProbe => "Lock probes",
+            Phase::Sampling => "Encoding samples",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Default)]
+struct PhaseCounter {
+    nanos: AtomicU64,
+    calls: AtomicU64,
+}
+
+/// Cumulative time spent per phase, summed over all concurrent tasks.
+#[derive(Debug, Default)]
+pub struct PhaseTimings {
+    phases: [PhaseCounter; Phase::ALL.len()],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    pub total: Duration,
+    pub calls: u64,
+}
+
+impl PhaseTimings {
+    pub fn record(&self, phase: Phase, elapsed: Duration) {
+        let counter = &self.phases[phase.index()];
+        counter.nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        counter.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Vec<PhaseTiming> {
+        Phase::ALL.iter()
+            .map(|&phase| {
+                let counter = &self.phases[phase.index()];
+                PhaseTiming {
+                    phase: phase.label(),
+                    total: Duration::from_nanos(counter.nanos.load(Ordering::Relaxed)),
+                    calls: counter.calls.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+}
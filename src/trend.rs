@@ -0,0 +1,131 @@
+use std::collections::BTreeSet;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use disk_scanner::formatting::Formatter;
+use disk_scanner::history::{self, HistoryRecord};
+
+use crate::cli::TrendArgs;
+use crate::summary::{self, Align, Palette, Table};
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Column and row label for the whole tree, next to the top-level directories.
+const TOTAL_LABEL: &str = "(total)";
+
+/// Runs the `trend` subcommand.
+pub fn run(args: &TrendArgs) -> Result<()> {
+    let records = history::load(&args.history)?;
+    let root = match &args.path {
+        Some(path) => std::path::absolute(path)?,
+        None => match records.last() {
+            Some(latest) => latest.root.clone(),
+            None => bail!("History {:?} has no scans yet", args.history),
+        },
+    };
+    let records: Vec<&HistoryRecord> = records.iter().filter(|r| r.root == root).collect();
+    if records.is_empty() {
+        bail!("History {:?} has no scans of {:?}", args.history, root);
+    }
+
+    let directories: BTreeSet<&str> = records.iter()
+        .flat_map(|r| r.directories.keys().map(String::as_str))
+        .collect();
+    if args.csv {
+        print_csv(&records, &directories);
+    } else {
+        print_sparklines(&records, &directories);
+    }
+    Ok(())
+}
+
+fn date(timestamp: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string()
+}
+
+fn directory_size(record: &HistoryRecord, directory: &str) -> u64 {
+    record.directories.get(directory).copied().unwrap_or(0)
+}
+
+/// One row per scan and one column per directory, sizes in bytes.
+fn print_csv(records: &[&HistoryRecord], directories: &BTreeSet<&str>) {
+    let header: Vec<String> = ["timestamp", TOTAL_LABEL].into_iter()
+        .chain(directories.iter().copied())
+        .map(csv_field)
+        .collect();
+    println!("{}", header.join(","));
+    for record in records {
+        let mut row = vec![date(record.timestamp), record.total_size.to_string()];
+        row.extend(directories.iter().map(|d| directory_size(record, d).to_string()));
+        println!("{}", row.join(","));
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn print_sparklines(records: &[&HistoryRecord], directories: &BTreeSet<&str>) {
+    let palette = Palette { enabled: summary::use_color(false) };
+    let formatter = Formatter::default();
+    let (first, last) = (records[0], records[records.len() - 1]);
+    summary::section(
+        palette,
+        &format!(
+            "Trend of {} ({} scans, {} to {})",
+            last.root.display(),
+            records.len(),
+            date(first.timestamp),
+            date(last.timestamp)
+        ),
+    );
+
+    let mut series: Vec<(&str, Vec<u64>)> = directories.iter()
+        .map(|&d| (d, records.iter().map(|r| directory_size(r, d)).collect()))
+        .collect();
+    series.sort_by_key(|(_, sizes)| std::cmp::Reverse(sizes[sizes.len() - 1]));
+    series.insert(0, (TOTAL_LABEL, records.iter().map(|r| r.total_size).collect()));
+
+    let mut table = Table::new(&[
+        ("DIRECTORY", Align::Left),
+        ("FIRST", Align::Right),
+        ("LAST", Align::Right),
+        ("CHANGE", Align::Right),
+        ("TREND", Align::Left),
+    ]);
+    for (name, sizes) in series {
+        let (before, after) = (sizes[0], sizes[sizes.len() - 1]);
+        let change = match (before, after) {
+            (0, 0) => "+0.0%".to_string(),
+            (0, _) => "new".to_string(),
+            _ => format!("{:+.1}%", (after as f64 - before as f64) * 100.0 / before as f64),
+        };
+        let change = match after.cmp(&before) {
+            std::cmp::Ordering::Greater => palette.warning(&change),
+            std::cmp::Ordering::Less => palette.good(&change),
+            std::cmp::Ordering::Equal => change,
+        };
+        table.row(vec![name.to_string(), formatter.size(before), formatter.size(after), change, sparkline(&sizes)]);
+    }
+    table.print(palette);
+}
+
+/// Scales `values` between their minimum and maximum onto block characters.
+fn sparkline(values: &[u64]) -> String {
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    values.iter()
+        .map(|&v| {
+            if max == min {
+                SPARK_LEVELS[SPARK_LEVELS.len() / 2]
+            } else {
+                let level = (v - min) as f64 / (max - min) as f64 * (SPARK_LEVELS.len() - 1) as f64;
+                SPARK_LEVELS[level.round() as usize]
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,28 @@
+/// Parses a human size such as "512MB", "1.5TiB", "10G" or "4096".
+///
+/// Like `du`, "KB"/"MB"/"GB"/"TB" are powers of 1000 while "K"/"KiB", "M"/"MiB", ...
+/// are powers of 1024. A bare number is a byte count.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("'{}' is not a size like 10GB", value))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1000,
+        "MB" => 1000u64.pow(2),
+        "GB" => 1000u64.pow(3),
+        "TB" => 1000u64.pow(4),
+        "PB" => 1000u64.pow(5),
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        "G" | "GIB" => 1 << 30,
+        "T" | "TIB" => 1 << 40,
+        "P" | "PIB" => 1 << 50,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, value)),
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
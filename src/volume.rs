@@ -0,0 +1,244 @@
+//! Capacity of the filesystem hosting the scan root.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Capacity of one mounted volume, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeUsage {
+    pub total: u64,
+    /// Unused space, including any reserved for the superuser
+    pub free: u64,
+    /// Space the current user can still write to
+    pub available: u64,
+    /// File slots, on filesystems with a fixed number of them
+    pub inodes: Option<InodeUsage>,
+}
+
+/// Inode counts of one volume; running out of them stops file creation just like a
+/// full disk does.
+#[derive(Debug, Clone, Copy)]
+pub struct InodeUsage {
+    pub total: u64,
+    pub free: u64,
+    /// Inodes the current user can still allocate
+    pub available: u64,
+}
+
+impl InodeUsage {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+impl VolumeUsage {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+#[cfg(unix)]
+pub fn volume_usage(path: &Path) -> io::Result<VolumeUsage> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stats` is a valid out-param
+    if unsafe { libc::statvfs(c_path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so it filled in the struct
+    let stats = unsafe { stats.assume_init() };
+    // The field types differ between platforms (e.g. 32-bit block counts on macOS)
+    #[allow(clippy::unnecessary_cast)]
+    let fragment = stats.f_frsize as u64;
+    #[allow(clippy::unnecessary_cast)]
+    Ok(VolumeUsage {
+        total: stats.f_blocks as u64 * fragment,
+        free: stats.f_bfree as u64 * fragment,
+        available: stats.f_bavail as u64 * fragment,
+        // Filesystems that allocate inodes on demand (btrfs, ZFS, ...) report none
+        inodes: (stats.f_files > 0).then_some(InodeUsage {
+            total: stats.f_files as u64,
+            free: stats.f_ffree as u64,
+            available: stats.f_favail as u64,
+        }),
+    })
+}
+
+#[cfg(windows)]
+pub fn volume_usage(path: &Path) -> io::Result<VolumeUsage> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+    // SAFETY: `wide` is NUL-terminated and the out-params are valid u64s
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // NTFS has no fixed inode table
+    Ok(VolumeUsage { total, free, available, inodes: None })
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn volume_usage(_path: &Path) -> io::Result<VolumeUsage> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "volume capacity is not available on this platform"))
+}
+
+/// Local APFS snapshots of the volume holding a path, such as Time Machine's hourly ones.
+/// Blocks a snapshot still references stay allocated after the files using them are
+/// deleted, so deleting files may not free any space until the snapshots go.
+#[derive(Debug, Clone)]
+pub struct LocalSnapshots {
+    /// Where the volume is mounted
+    pub mount_point: PathBuf,
+    /// Oldest first
+    pub snapshots: Vec<Snapshot>,
+    /// What the volume itself takes up in its container, snapshots included
+    pub volume_used: Option<u64>,
+    /// For a scan of the whole volume, what it takes up beyond the files counted: space
+    /// held by the snapshots, plus filesystem metadata and what clones and compression
+    /// make file sizes differ by
+    pub unaccounted: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub name: String,
+    /// macOS deletes purgeable snapshots by itself when the space is needed
+    pub purgeable: bool,
+}
+
+/// The local snapshots of the APFS volume holding `path`, from `diskutil`, for a scan of
+/// `path` that counted `counted` bytes; `None` where there are none, or off macOS.
+#[cfg(target_os = "macos")]
+pub fn local_snapshots(path: &Path, counted: u64) -> io::Result<Option<LocalSnapshots>> {
+    use std::ffi::{CStr, CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::process::Command;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stats` is a valid out-param
+    if unsafe { libc::statfs(c_path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statfs succeeded, so it filled in the struct
+    let stats = unsafe { stats.assume_init() };
+    // SAFETY: the kernel NUL-terminates both names
+    let (fs_type, mount_point) = unsafe { (CStr::from_ptr(stats.f_fstypename.as_ptr()), CStr::from_ptr(stats.f_mntonname.as_ptr())) };
+    if fs_type.to_bytes() != b"apfs" {
+        return Ok(None);
+    }
+    let mount_point = PathBuf::from(OsStr::from_bytes(mount_point.to_bytes()));
+
+    let diskutil = |args: &[&str]| -> io::Result<String> {
+        let output = Command::new("diskutil").args(args).arg(&mount_point).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let snapshots = parse_snapshots(&diskutil(&["apfs", "listSnapshots"])?);
+    if snapshots.is_empty() {
+        return Ok(None);
+    }
+    let volume_used = diskutil(&["info"]).ok().and_then(|info| parse_used_space(&info));
+    let unaccounted = volume_used.filter(|_| path == mount_point).map(|used| used.saturating_sub(counted));
+    Ok(Some(LocalSnapshots { mount_point, snapshots, volume_used, unaccounted }))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn local_snapshots(_path: &Path, _counted: u64) -> io::Result<Option<LocalSnapshots>> {
+    Ok(None)
+}
+
+/// The snapshots in `diskutil apfs listSnapshots` output, which lists each as an indented
+/// block of `Name:`, `XID:` and `Purgeable:` lines, oldest first.
+#[cfg(target_os = "macos")]
+fn parse_snapshots(output: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim_start_matches(['|', ' ']);
+        if let Some(name) = line.strip_prefix("Name:") {
+            snapshots.push(Snapshot { name: name.trim().to_string(), purgeable: false });
+        } else if let Some(purgeable) = line.strip_prefix("Purgeable:")
+            && let Some(snapshot) = snapshots.last_mut()
+        {
+            snapshot.purgeable = purgeable.trim().eq_ignore_ascii_case("yes");
+        }
+    }
+    snapshots
+}
+
+/// The bytes of `Volume Used Space:   52.4 GB (52412620800 Bytes) (...)` in `diskutil info`.
+#[cfg(target_os = "macos")]
+fn parse_used_space(info: &str) -> Option<u64> {
+    let line = info.lines().find_map(|line| line.trim().strip_prefix("Volume Used Space:"))?;
+    let (_, bytes) = line.split_once('(')?;
+    bytes.split_whitespace().next()?.parse().ok()
+}
+
+/// The disk behind the volume holding a path, so an archived report says which physical
+/// disk it describes.
+#[derive(Debug, Clone)]
+pub struct BlockDevice {
+    /// The mounted device, such as `/dev/sda2`
+    pub source: PathBuf,
+    /// The whole disk the device is a partition of, or the device itself, such as `sda`
+    pub disk: String,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// Size of the whole disk in bytes
+    pub capacity: u64,
+    /// Whether the disk spins, where the kernel knows
+    pub rotational: Option<bool>,
+}
+
+/// The block device mounted from `source`, from sysfs; `None` where `source` is no local
+/// block device (network and virtual filesystems), or off Linux.
+#[cfg(target_os = "linux")]
+pub fn block_device(source: &Path) -> io::Result<Option<BlockDevice>> {
+    // `/dev/root` and `/dev/disk/by-uuid/...` are symlinks to the node named in sysfs
+    let Ok(node) = std::fs::canonicalize(source) else {
+        return Ok(None);
+    };
+    let Some(name) = node.strip_prefix("/dev").ok().and_then(|name| name.to_str()) else {
+        return Ok(None);
+    };
+    let device = match std::fs::canonicalize(Path::new("/sys/class/block").join(name)) {
+        Ok(device) => device,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // A partition's directory sits inside the one of its disk
+    let disk = match device.parent() {
+        Some(parent) if device.join("partition").exists() => parent.to_path_buf(),
+        _ => device,
+    };
+    let read = |file: &str| {
+        std::fs::read_to_string(disk.join(file)).ok()
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    let sectors: u64 = read("size").and_then(|size| size.parse().ok()).unwrap_or(0);
+    Ok(Some(BlockDevice {
+        source: source.to_path_buf(),
+        disk: disk.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        model: read("device/model"),
+        // NVMe and SCSI disks have it on the device, virtio ones on the disk
+        serial: read("device/serial").or_else(|| read("serial")),
+        // Always in 512-byte units, whatever the disk's sector size
+        capacity: sectors * 512,
+        rotational: read("queue/rotational").map(|rotational| rotational == "1"),
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn block_device(_source: &Path) -> io::Result<Option<BlockDevice>> {
+    Ok(None)
+}